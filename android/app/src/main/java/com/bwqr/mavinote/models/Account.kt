@@ -58,4 +58,51 @@ data class Mavinote(val email: String, val token: String) {
             return account
         }
     }
-}
\ No newline at end of file
+}
+
+sealed class Verification {
+    class Code(val code: String) : Verification()
+    object Accepted : Verification()
+
+    companion object : Deserialize<Verification> {
+        override fun deserialize(deserializer: Deserializer): Verification {
+            return when (val index = deserializer.deserialize_variant_index()) {
+                0 -> Code(deserializer.deserialize_str())
+                1 -> Accepted
+                else -> throw DeserializationError("Unknown variant index for Verification: $index")
+            }
+        }
+    }
+}
+
+enum class Encryption {
+    Disabled,
+    Locked,
+    Unlocked;
+
+    companion object : Deserialize<Encryption> {
+        override fun deserialize(deserializer: Deserializer): Encryption {
+            return when (val index = deserializer.deserialize_variant_index()) {
+                0 -> Disabled
+                1 -> Locked
+                2 -> Unlocked
+                else -> throw DeserializationError("Unknown variant index for Encryption: $index")
+            }
+        }
+    }
+}
+
+enum class ConflictPolicy {
+    Merge,
+    Copy;
+
+    companion object : Deserialize<ConflictPolicy> {
+        override fun deserialize(deserializer: Deserializer): ConflictPolicy {
+            return when (val index = deserializer.deserialize_variant_index()) {
+                0 -> Merge
+                1 -> Copy
+                else -> throw DeserializationError("Unknown variant index for ConflictPolicy: $index")
+            }
+        }
+    }
+}
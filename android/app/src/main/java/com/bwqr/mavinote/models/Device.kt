@@ -1,6 +1,7 @@
 package com.bwqr.mavinote.models
 
 import com.bwqr.mavinote.reax.Deserialize
+import com.novi.serde.DeserializationError
 import com.novi.serde.Deserializer
 
 data class Device(
@@ -8,6 +9,8 @@ data class Device(
     val accountId: Int,
     val pubkey: String,
     val createdAt: String,
+    val trust: Trust,
+    val fingerprint: String,
 ) {
     companion object : Deserialize<Device> {
         override fun deserialize(deserializer: Deserializer): Device {
@@ -18,6 +21,32 @@ data class Device(
                 deserializer.deserialize_i32(),
                 deserializer.deserialize_str(),
                 deserializer.deserialize_str(),
+                Trust.deserialize(deserializer),
+                deserializer.deserialize_str(),
+            )
+
+            deserializer.decrease_container_depth()
+
+            return device
+        }
+    }
+}
+
+data class DirectoryDevice(
+    val id: Int,
+    val accountId: Int,
+    val email: String,
+    val pubkey: String,
+) {
+    companion object : Deserialize<DirectoryDevice> {
+        override fun deserialize(deserializer: Deserializer): DirectoryDevice {
+            deserializer.increase_container_depth()
+
+            val device = DirectoryDevice(
+                deserializer.deserialize_i32(),
+                deserializer.deserialize_i32(),
+                deserializer.deserialize_str(),
+                deserializer.deserialize_str(),
             )
 
             deserializer.decrease_container_depth()
@@ -25,4 +54,47 @@ data class Device(
             return device
         }
     }
+}
+
+enum class Trust {
+    Unverified,
+    Verified,
+    Changed;
+
+    companion object {
+        fun deserialize(deserializer: Deserializer): Trust {
+            val index = deserializer.deserialize_variant_index()
+
+            return when (index) {
+                0 -> Unverified
+                1 -> Verified
+                2 -> Changed
+                else -> throw DeserializationError("Unknown variant index for Trust: $index")
+            }
+        }
+    }
+}
+
+data class TrustEvent(
+    val accountId: Int,
+    val deviceId: Int,
+    val trust: Trust,
+    val fingerprint: String,
+) {
+    companion object : Deserialize<TrustEvent> {
+        override fun deserialize(deserializer: Deserializer): TrustEvent {
+            deserializer.increase_container_depth()
+
+            val event = TrustEvent(
+                deserializer.deserialize_i32(),
+                deserializer.deserialize_i32(),
+                Trust.deserialize(deserializer),
+                deserializer.deserialize_str(),
+            )
+
+            deserializer.decrease_container_depth()
+
+            return event
+        }
+    }
 }
\ No newline at end of file
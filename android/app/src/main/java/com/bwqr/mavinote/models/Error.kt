@@ -22,6 +22,7 @@ open class NoteError : Error() {
                 2 -> DatabaseError.deserialize(deserializer)
                 3 -> CryptoError.deserialize(deserializer)
                 4 -> UnreachableError.deserialize(deserializer)
+                5 -> LockedError
                 else -> throw DeserializationError("Unknown variant index for Error: $index")
             }
         }
@@ -30,6 +31,15 @@ open class NoteError : Error() {
     fun handle() {
         when (this) {
             is MavinoteError.NoConnection -> Bus.message("No Internet Connection")
+            is MavinoteError.QuotaExceeded -> Bus.message(
+                when (this.quota) {
+                    MavinoteError.Quota.Storage -> "Storage quota of the account is exceeded"
+                    MavinoteError.Quota.Notes -> "Note limit of the account is reached"
+                    MavinoteError.Quota.Folders -> "Folder limit of the account is reached"
+                    MavinoteError.Quota.NoteSize -> "Note is too large"
+                }
+            )
+            is LockedError -> Bus.message("Notes are locked, enter your pin to unlock them")
             is MavinoteError.DeviceDeleted -> {
                 val accountId = this.accountId
                 GlobalScope.launch {
@@ -60,6 +70,26 @@ sealed class MavinoteError : NoteError() {
     object UnexpectedResponse : MavinoteError()
     class DeviceDeleted(val accountId: Int) : MavinoteError()
     class Unknown(override val message: String) : MavinoteError()
+    class QuotaExceeded(val quota: Quota) : MavinoteError()
+
+    enum class Quota {
+        Storage,
+        Notes,
+        Folders,
+        NoteSize;
+
+        companion object {
+            fun deserialize(deserializer: Deserializer): Quota {
+                return when (val index = deserializer.deserialize_variant_index()) {
+                    0 -> Storage
+                    1 -> Notes
+                    2 -> Folders
+                    3 -> NoteSize
+                    else -> throw DeserializationError("Unknown variant index for Quota: $index")
+                }
+            }
+        }
+    }
 
     companion object {
         fun deserialize(deserializer: Deserializer): MavinoteError {
@@ -70,6 +100,7 @@ sealed class MavinoteError : NoteError() {
                 3 -> UnexpectedResponse
                 4 -> DeviceDeleted(deserializer.deserialize_i32())
                 5 -> Unknown(deserializer.deserialize_str())
+                6 -> QuotaExceeded(Quota.deserialize(deserializer))
                 else -> throw DeserializationError("Unknown variant index for MavinoteError: $index")
             }
         }
@@ -78,11 +109,15 @@ sealed class MavinoteError : NoteError() {
 
 sealed class StorageError : NoteError() {
     object EmailAlreadyExists : StorageError()
+    class File(override val message: String) : StorageError()
+    class SecretStore(override val message: String) : StorageError()
 
     companion object {
         fun deserialize(deserializer: Deserializer): StorageError {
             return when (val index = deserializer.deserialize_variant_index()) {
                 0 -> EmailAlreadyExists
+                1 -> File(deserializer.deserialize_str())
+                2 -> SecretStore(deserializer.deserialize_str())
                 else -> throw DeserializationError("Unknown variant index for StorageError: $index")
             }
         }
@@ -102,6 +137,9 @@ sealed class CryptoError: NoteError() {
     object InvalidLength : CryptoError()
     object Decrypt : CryptoError()
     object Encrypt : CryptoError()
+    object DirectoryMismatch : CryptoError()
+    object FingerprintMismatch : CryptoError()
+    object CommitmentMismatch : CryptoError()
 
     companion object {
         fun deserialize(deserializer: Deserializer): CryptoError {
@@ -110,6 +148,9 @@ sealed class CryptoError: NoteError() {
                 1 -> InvalidLength
                 2 -> Decrypt
                 3 -> Encrypt
+                4 -> DirectoryMismatch
+                5 -> FingerprintMismatch
+                6 -> CommitmentMismatch
                 else -> throw DeserializationError("Unknown variant index for CryptoError: $index")
             }
         }
@@ -122,4 +163,6 @@ data class UnreachableError(override val message: String) : NoteError() {
             return UnreachableError(deserializer.deserialize_str())
         }
     }
-}
\ No newline at end of file
+}
+
+object LockedError : NoteError()
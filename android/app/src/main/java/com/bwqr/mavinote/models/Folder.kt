@@ -3,14 +3,18 @@ package com.bwqr.mavinote.models
 import com.bwqr.mavinote.reax.DeInt
 import com.bwqr.mavinote.reax.DeOption
 import com.bwqr.mavinote.reax.Deserialize
+import com.novi.serde.DeserializationError
 import com.novi.serde.Deserializer
 
 data class Folder constructor(
     val id: Int,
     val accountId: Int,
     val remoteId: Int?,
+    val parentId: Int?,
     val name: String,
-    val state: State
+    val state: State,
+    val shared: Boolean,
+    val role: Role?
 ) {
     companion object : Deserialize<Folder> {
         override fun deserialize(deserializer: Deserializer): Folder {
@@ -20,8 +24,11 @@ data class Folder constructor(
                 deserializer.deserialize_i32(),
                 deserializer.deserialize_i32(),
                 DeOption(DeInt).deserialize(deserializer),
+                DeOption(DeInt).deserialize(deserializer),
                 deserializer.deserialize_str(),
-                State.deserialize(deserializer)
+                State.deserialize(deserializer),
+                deserializer.deserialize_bool(),
+                DeOption(Role).deserialize(deserializer)
             )
 
             deserializer.decrease_container_depth()
@@ -29,4 +36,43 @@ data class Folder constructor(
             return folder
         }
     }
-}
\ No newline at end of file
+}
+
+data class FolderMember(
+    val userId: Int,
+    val email: String,
+    val role: Role,
+) {
+    companion object : Deserialize<FolderMember> {
+        override fun deserialize(deserializer: Deserializer): FolderMember {
+            deserializer.increase_container_depth()
+
+            val member = FolderMember(
+                deserializer.deserialize_i32(),
+                deserializer.deserialize_str(),
+                Role.deserialize(deserializer),
+            )
+
+            deserializer.decrease_container_depth()
+
+            return member
+        }
+    }
+}
+
+enum class Role {
+    Read,
+    Write;
+
+    companion object : Deserialize<Role> {
+        override fun deserialize(deserializer: Deserializer): Role {
+            val index = deserializer.deserialize_variant_index()
+
+            return when (index) {
+                0 -> Read
+                1 -> Write
+                else -> throw DeserializationError("Unknown variant index for Role: $index")
+            }
+        }
+    }
+}
@@ -1,6 +1,7 @@
 package com.bwqr.mavinote.models
 
 import com.bwqr.mavinote.reax.DeInt
+import com.bwqr.mavinote.reax.DeList
 import com.bwqr.mavinote.reax.DeOption
 import com.bwqr.mavinote.reax.Deserialize
 import com.novi.serde.DeserializationError
@@ -36,10 +37,61 @@ data class Note constructor(
     }
 }
 
+data class Commit(
+    val noteId: Int,
+    val commit: Int,
+    val deviceId: Int,
+    val createdAt: String,
+) {
+    companion object : Deserialize<Commit> {
+        override fun deserialize(deserializer: Deserializer): Commit {
+            deserializer.increase_container_depth()
+
+            val commit = Commit(
+                deserializer.deserialize_i32(),
+                deserializer.deserialize_i32(),
+                deserializer.deserialize_i32(),
+                deserializer.deserialize_str(),
+            )
+
+            deserializer.decrease_container_depth()
+
+            return commit
+        }
+    }
+}
+
+data class CommitNote(
+    val noteId: Int,
+    val commit: Int,
+    val name: String,
+    val text: String,
+    val createdAt: String,
+) {
+    companion object : Deserialize<CommitNote> {
+        override fun deserialize(deserializer: Deserializer): CommitNote {
+            deserializer.increase_container_depth()
+
+            val commitNote = CommitNote(
+                deserializer.deserialize_i32(),
+                deserializer.deserialize_i32(),
+                deserializer.deserialize_str(),
+                deserializer.deserialize_str(),
+                deserializer.deserialize_str(),
+            )
+
+            deserializer.decrease_container_depth()
+
+            return commitNote
+        }
+    }
+}
+
 enum class State {
     Clean,
     Modified,
-    Deleted;
+    Deleted,
+    Trashed;
 
     companion object {
         fun deserialize(deserializer: Deserializer): State {
@@ -49,8 +101,197 @@ enum class State {
                 0 -> Clean
                 1 -> Modified
                 2 -> Deleted
+                3 -> Trashed
                 else -> throw DeserializationError("Unknown variant index for State: $index")
             }
         }
     }
-}
\ No newline at end of file
+}
+
+data class Attachment(
+    val id: Int,
+    val noteId: Int,
+    val name: String,
+) {
+    companion object : Deserialize<Attachment> {
+        override fun deserialize(deserializer: Deserializer): Attachment {
+            deserializer.increase_container_depth()
+
+            val attachment = Attachment(
+                deserializer.deserialize_i32(),
+                deserializer.deserialize_i32(),
+                deserializer.deserialize_str(),
+            )
+
+            deserializer.decrease_container_depth()
+
+            return attachment
+        }
+    }
+}
+
+data class SearchResult(
+    val noteId: Int,
+    val folderId: Int,
+    val name: String,
+    val snippet: List<SnippetPart>,
+) {
+    companion object : Deserialize<SearchResult> {
+        override fun deserialize(deserializer: Deserializer): SearchResult {
+            deserializer.increase_container_depth()
+
+            val result = SearchResult(
+                deserializer.deserialize_i32(),
+                deserializer.deserialize_i32(),
+                deserializer.deserialize_str(),
+                DeList(SnippetPart).deserialize(deserializer),
+            )
+
+            deserializer.decrease_container_depth()
+
+            return result
+        }
+    }
+}
+
+data class SnippetPart(
+    val text: String,
+    val highlighted: Boolean,
+) {
+    companion object : Deserialize<SnippetPart> {
+        override fun deserialize(deserializer: Deserializer): SnippetPart {
+            deserializer.increase_container_depth()
+
+            val part = SnippetPart(
+                deserializer.deserialize_str(),
+                deserializer.deserialize_bool(),
+            )
+
+            deserializer.decrease_container_depth()
+
+            return part
+        }
+    }
+}
+
+data class Conflict(
+    val id: Int,
+    val note: Note,
+    val copy: Note,
+    val remoteCommit: Int,
+    val createdAt: String,
+) {
+    companion object : Deserialize<Conflict> {
+        override fun deserialize(deserializer: Deserializer): Conflict {
+            deserializer.increase_container_depth()
+
+            val conflict = Conflict(
+                deserializer.deserialize_i32(),
+                Note.deserialize(deserializer),
+                Note.deserialize(deserializer),
+                deserializer.deserialize_i32(),
+                deserializer.deserialize_str(),
+            )
+
+            deserializer.decrease_container_depth()
+
+            return conflict
+        }
+    }
+}
+
+enum class OperationKind {
+    CreateFolder,
+    RenameFolder,
+    MoveFolder,
+    DeleteFolder,
+    CreateNote,
+    UpdateNote,
+    MoveNote,
+    DeleteNote;
+
+    companion object {
+        fun deserialize(deserializer: Deserializer): OperationKind {
+            val index = deserializer.deserialize_variant_index()
+
+            return when (index) {
+                0 -> CreateFolder
+                1 -> RenameFolder
+                2 -> MoveFolder
+                3 -> DeleteFolder
+                4 -> CreateNote
+                5 -> UpdateNote
+                6 -> MoveNote
+                7 -> DeleteNote
+                else -> throw DeserializationError("Unknown variant index for OperationKind: $index")
+            }
+        }
+    }
+}
+
+data class FailedOperation(
+    val id: Int,
+    val accountId: Int,
+    val kind: OperationKind,
+    val name: String,
+    val error: String,
+) {
+    companion object : Deserialize<FailedOperation> {
+        override fun deserialize(deserializer: Deserializer): FailedOperation {
+            deserializer.increase_container_depth()
+
+            val operation = FailedOperation(
+                deserializer.deserialize_i32(),
+                deserializer.deserialize_i32(),
+                OperationKind.deserialize(deserializer),
+                deserializer.deserialize_str(),
+                deserializer.deserialize_str(),
+            )
+
+            deserializer.decrease_container_depth()
+
+            return operation
+        }
+    }
+}
+
+data class NoteRevision(
+    val id: Int,
+    val noteId: Int,
+    val name: String,
+    val text: String,
+    val createdAt: String,
+) {
+    companion object : Deserialize<NoteRevision> {
+        override fun deserialize(deserializer: Deserializer): NoteRevision {
+            deserializer.increase_container_depth()
+
+            val revision = NoteRevision(
+                deserializer.deserialize_i32(),
+                deserializer.deserialize_i32(),
+                deserializer.deserialize_str(),
+                deserializer.deserialize_str(),
+                deserializer.deserialize_str(),
+            )
+
+            deserializer.decrease_container_depth()
+
+            return revision
+        }
+    }
+}
+
+sealed class RevisionRetention {
+    class Count(val count: Int) : RevisionRetention()
+    class Days(val days: Int) : RevisionRetention()
+
+    companion object : Deserialize<RevisionRetention> {
+        override fun deserialize(deserializer: Deserializer): RevisionRetention {
+            return when (val index = deserializer.deserialize_variant_index()) {
+                0 -> Count(deserializer.deserialize_i32())
+                1 -> Days(deserializer.deserialize_i32())
+                else -> throw DeserializationError("Unknown variant index for RevisionRetention: $index")
+            }
+        }
+    }
+}
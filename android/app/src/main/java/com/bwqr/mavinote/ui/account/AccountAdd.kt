@@ -38,6 +38,7 @@ import com.bwqr.mavinote.R
 import com.bwqr.mavinote.models.MavinoteError
 import com.bwqr.mavinote.models.NoteError
 import com.bwqr.mavinote.models.StorageError
+import com.bwqr.mavinote.models.Verification
 import com.bwqr.mavinote.ui.Screen
 import com.bwqr.mavinote.ui.theme.MavinoteTheme
 import com.bwqr.mavinote.ui.util.ErrorText
@@ -272,6 +273,7 @@ fun ShowPublicKey(email: String, token: String, onAccountAdd: () -> Unit) {
 
     var error by remember { mutableStateOf<String?>(null) }
     var publicKey by remember { mutableStateOf<String?>(null) }
+    var verificationCode by remember { mutableStateOf<String?>(null) }
 
     LaunchedEffect(key1 = 1) {
         try {
@@ -283,9 +285,15 @@ fun ShowPublicKey(email: String, token: String, onAccountAdd: () -> Unit) {
 
     LaunchedEffect(key1 = 1) {
         try {
-            AccountViewModel.waitVerification(token)
-            AccountViewModel.addAccount(email)
-            onAccountAdd()
+            AccountViewModel.waitVerification(token).collect {
+                when (it) {
+                    is Verification.Code -> verificationCode = it.code
+                    is Verification.Accepted -> {
+                        AccountViewModel.addAccount(email)
+                        onAccountAdd()
+                    }
+                }
+            }
         } catch (e: NoteError) {
             when {
                 e is MavinoteError.Message && e.message == "ws_failed" -> {
@@ -323,6 +331,14 @@ fun ShowPublicKey(email: String, token: String, onAccountAdd: () -> Unit) {
             )
             Text(it, fontWeight = FontWeight.Bold)
         }
+
+        verificationCode?.let {
+            Text(
+                "The other device displays a verification code. Add this device there only if it matches the code below.",
+                modifier = Modifier.padding(0.dp, 16.dp, 0.dp, 12.dp),
+            )
+            Text(it, fontWeight = FontWeight.Bold)
+        }
     }
 
     error?.let {
@@ -17,6 +17,7 @@ import androidx.compose.runtime.remember
 import androidx.compose.runtime.rememberCoroutineScope
 import androidx.compose.runtime.setValue
 import androidx.compose.ui.Modifier
+import androidx.compose.ui.text.font.FontWeight
 import androidx.compose.ui.tooling.preview.Preview
 import androidx.compose.ui.unit.dp
 import androidx.navigation.NavController
@@ -33,12 +34,51 @@ fun DeviceAdd(navController: NavController, accountId: Int) {
     var inProgress by remember { mutableStateOf(false) }
     var validationErrors by remember { mutableStateOf(setOf<ValidationErrors>()) }
     var error by remember { mutableStateOf<String?>(null) }
+    var verificationCode by remember { mutableStateOf<String?>(null) }
+
+    val handleError = { e: NoteError ->
+        when {
+            e is MavinoteError.Message && e.message == "item_not_found" -> {
+                error = "Public Key is not found"
+            }
+            e is MavinoteError.Message && e.message == "device_already_exists" -> {
+                error = "Device with this public key is already added"
+            }
+            e is MavinoteError.Message && e.message == "expired_pubkey" -> {
+                error = "5 minutes waiting is timed out. Please try the steps on new device again."
+            }
+            e is MavinoteError.Message && e.message == "verification_not_started" -> {
+                verificationCode = null
+                error = "Verification is restarted on the new device. Please verify the device again."
+            }
+            else -> e.handle()
+        }
+    }
 
     DeviceAddView(
         inProgress,
         error,
         validationErrors,
+        verificationCode,
         { error = null },
+        { pubkey ->
+            if (inProgress) {
+                return@DeviceAddView
+            }
+
+            inProgress = true
+
+            scope.launch {
+                try {
+                    AccountViewModel.addDevice(accountId, pubkey)
+                    navController.navigateUp()
+                } catch (e: NoteError) {
+                    handleError(e)
+                } finally {
+                    inProgress = false
+                }
+            }
+        },
     ) { pubkey ->
         if (inProgress) {
             return@DeviceAddView
@@ -60,21 +100,9 @@ fun DeviceAdd(navController: NavController, accountId: Int) {
 
         scope.launch {
             try {
-                AccountViewModel.addDevice(accountId, pubkey)
-                navController.navigateUp()
+                verificationCode = AccountViewModel.startDeviceVerification(accountId, pubkey)
             } catch (e: NoteError) {
-                when {
-                    e is MavinoteError.Message && e.message == "item_not_found" -> {
-                        error = "Public Key is not found"
-                    }
-                    e is MavinoteError.Message && e.message == "device_already_exists" -> {
-                        error = "Device with this public key is already added"
-                    }
-                    e is MavinoteError.Message && e.message == "expired_pubkey" -> {
-                        error = "5 minutes waiting is timed out. Please try the steps on new device again."
-                    }
-                    else -> e.handle()
-                }
+                handleError(e)
             } finally {
                 inProgress = false
             }
@@ -91,8 +119,10 @@ private fun DeviceAddView(
     inProgress: Boolean,
     error: String?,
     validationErrors: Set<ValidationErrors>,
+    verificationCode: String?,
     onDismissError: () -> Unit,
-    onDeviceAdd: (fingerprint: String) -> Unit
+    onDeviceAdd: (pubkey: String) -> Unit,
+    onDeviceVerify: (pubkey: String) -> Unit
 ) {
     val scrollState = rememberScrollState()
 
@@ -117,7 +147,8 @@ private fun DeviceAddView(
                 )
 
                 Text(
-                    "Then you need to type the Public Key of the new device below and tap Add Device.",
+                    "Then you need to type the Public Key of the new device below and tap Verify Device. " +
+                            "Both devices display a verification code, add the device only if the codes match.",
                     modifier = Modifier.padding(0.dp, 8.dp)
                 )
 
@@ -136,14 +167,32 @@ private fun DeviceAddView(
                 if (validationErrors.contains(ValidationErrors.InvalidPubkey)) {
                     ErrorText(error = "Please specify a valid Public Key")
                 }
+
+                verificationCode?.let {
+                    Text(
+                        "Verification Code",
+                        modifier = Modifier.padding(0.dp, 16.dp, 0.dp, 12.dp)
+                    )
+                    Text(it, fontWeight = FontWeight.Bold)
+                }
             }
 
-            Button(
-                modifier = Modifier.fillMaxWidth(),
-                enabled = !inProgress,
-                onClick = { onDeviceAdd(pubkey) },
-            ) {
-                Text("Add Device")
+            if (verificationCode == null) {
+                Button(
+                    modifier = Modifier.fillMaxWidth(),
+                    enabled = !inProgress,
+                    onClick = { onDeviceVerify(pubkey) },
+                ) {
+                    Text("Verify Device")
+                }
+            } else {
+                Button(
+                    modifier = Modifier.fillMaxWidth(),
+                    enabled = !inProgress,
+                    onClick = { onDeviceAdd(pubkey) },
+                ) {
+                    Text("Codes Match, Add Device")
+                }
             }
         }
     }
@@ -164,5 +213,5 @@ fun DeviceAddPreview() {
     val error: String? = null
     val validationErrors = setOf<ValidationErrors>()
 
-    DeviceAddView(inProgress, error, validationErrors, { }) { }
+    DeviceAddView(inProgress, error, validationErrors, null, { }, { }) { }
 }
\ No newline at end of file
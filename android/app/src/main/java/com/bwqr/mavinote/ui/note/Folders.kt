@@ -192,9 +192,9 @@ fun FoldersPreview() {
         AccountWithFolders(
             Account(1, "Default", AccountKind.Local),
             listOf(
-                Folder(1, 1, null, "Favorites", State.Clean),
-                Folder(2, 1, null, "Todos", State.Clean),
-                Folder(3, 1, null, "Hobbies", State.Clean)
+                Folder(1, 1, null, null, "Favorites", State.Clean, false, null),
+                Folder(2, 1, null, null, "Todos", State.Clean, false, null),
+                Folder(3, 1, null, null, "Hobbies", State.Clean, false, null)
             )
         ),
         AccountWithFolders(
@@ -203,7 +203,7 @@ fun FoldersPreview() {
         ),
         AccountWithFolders(
             Account(2, "Remote", AccountKind.Mavinote),
-            listOf(Folder(1, 2, null, "Race Cars", State.Clean))
+            listOf(Folder(1, 2, null, null, "Race Cars", State.Clean, false, null))
         ),
     )
 
@@ -208,7 +208,7 @@ fun NotesFab(navController: NavController, folderId: Int) {
 fun NotesPreview() {
     val navController = rememberNavController()
 
-    val folder = Folder(1, 1, null, "Can Long Typed Title Fit Here Or Cannot Fit Here", State.Clean)
+    val folder = Folder(1, 1, null, null, "Can Long Typed Title Fit Here Or Cannot Fit Here", State.Clean, false, null)
 
     val notes = listOf(
         Note(1, folder.id, null, 1, "Downtown", "Going to downtown", State.Clean),
@@ -227,7 +227,7 @@ fun NotesPreview() {
 fun EmptyNotesPreview() {
     val navController = rememberNavController()
 
-    val folder = Folder(1, 1, null, "Todos", State.Clean)
+    val folder = Folder(1, 1, null, null, "Todos", State.Clean, false, null)
 
     MavinoteTheme {
         NotesView(navController, folder, listOf()) {}
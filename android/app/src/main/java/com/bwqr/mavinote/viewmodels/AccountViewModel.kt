@@ -1,8 +1,13 @@
 package com.bwqr.mavinote.viewmodels
 
 import com.bwqr.mavinote.models.Account
+import com.bwqr.mavinote.models.ConflictPolicy
 import com.bwqr.mavinote.models.Device
+import com.bwqr.mavinote.models.DirectoryDevice
+import com.bwqr.mavinote.models.Encryption
 import com.bwqr.mavinote.models.Mavinote
+import com.bwqr.mavinote.models.TrustEvent
+import com.bwqr.mavinote.models.Verification
 import com.bwqr.mavinote.reax.DeBool
 import com.bwqr.mavinote.reax.DeList
 import com.bwqr.mavinote.reax.DeOption
@@ -31,8 +36,8 @@ class AccountViewModel {
         suspend fun requestVerification(email: String): String =
             Runtime.runOnce(DeString) { _requestVerification(it, email) }
 
-        suspend fun waitVerification(token: String) =
-            Runtime.runOnceUnit { _waitVerification(it, token) }
+        fun waitVerification(token: String): Flow<Verification> =
+            Runtime.runStream(Verification) { _waitVerification(it, token) }
 
         suspend fun sendVerificationCode(email: String) =
             Runtime.runOnceUnit { _sendVerificationCode(it, email) }
@@ -61,6 +66,56 @@ class AccountViewModel {
         suspend fun welcomeShown(): Boolean = Runtime.runOnce(DeBool) { _welcomeShown(it) }
 
         suspend fun updateWelcomeShown(shown: Boolean): Unit = Runtime.runOnceUnit { _updateWelcomeShown(it, shown) }
+
+        suspend fun directoryDevices(accountId: Int, email: String): List<DirectoryDevice> =
+            Runtime.runOnce(DeList(DirectoryDevice)) { _directoryDevices(it, accountId, email) }
+
+        suspend fun updateDirectory(accountId: Int, listed: Boolean): Unit =
+            Runtime.runOnceUnit { _updateDirectory(it, accountId, listed) }
+
+        suspend fun rotateIdentityKey(): Unit =
+            Runtime.runOnceUnit { _rotateIdentityKey(it) }
+
+        suspend fun verifyDevice(accountId: Int, deviceId: Int, fingerprint: String): Unit =
+            Runtime.runOnceUnit { _verifyDevice(it, accountId, deviceId, fingerprint) }
+
+        fun trustEvents(): Flow<TrustEvent> = Runtime.runStream(TrustEvent) { _trustEvents(it) }
+
+        suspend fun startDeviceVerification(accountId: Int, pubkey: String): String =
+            Runtime.runOnce(DeString) { _startDeviceVerification(it, accountId, pubkey) }
+
+        suspend fun createRecoveryBundle(accountId: Int, passphrase: String): Unit =
+            Runtime.runOnceUnit { _createRecoveryBundle(it, accountId, passphrase) }
+
+        suspend fun deleteRecoveryBundle(accountId: Int): Unit =
+            Runtime.runOnceUnit { _deleteRecoveryBundle(it, accountId) }
+
+        suspend fun sendRecoveryCode(email: String): Unit =
+            Runtime.runOnceUnit { _sendRecoveryCode(it, email) }
+
+        suspend fun recoverAccount(email: String, code: String, passphrase: String): Unit =
+            Runtime.runOnceUnit { _recoverAccount(it, email, code, passphrase) }
+
+        suspend fun encryption(): Encryption =
+            Runtime.runOnce(Encryption) { _encryption(it) }
+
+        suspend fun enableEncryption(pin: String): Unit =
+            Runtime.runOnceUnit { _enableEncryption(it, pin) }
+
+        suspend fun disableEncryption(pin: String): Unit =
+            Runtime.runOnceUnit { _disableEncryption(it, pin) }
+
+        suspend fun unlock(pin: String): Unit =
+            Runtime.runOnceUnit { _unlock(it, pin) }
+
+        suspend fun lock(): Unit =
+            Runtime.runOnceUnit { _lock(it) }
+
+        suspend fun conflictPolicy(accountId: Int): ConflictPolicy =
+            Runtime.runOnce(ConflictPolicy) { _conflictPolicy(it, accountId) }
+
+        suspend fun updateConflictPolicy(accountId: Int, keepCopies: Boolean): Unit =
+            Runtime.runOnceUnit { _updateConflictPolicy(it, accountId, keepCopies) }
     }
 }
 
@@ -71,7 +126,7 @@ private external fun _devices(onceId: Int, accountId: Int): Long
 private external fun _addDevice(onceId: Int, accountId: Int, fingerprint: String): Long
 private external fun _deleteDevice(onceId: Int, accountId: Int, deviceId: Int): Long
 private external fun _requestVerification(onceId: Int, email: String): Long
-private external fun _waitVerification(onceId: Int, token: String): Long
+private external fun _waitVerification(streamId: Int, token: String): Long
 private external fun _sendVerificationCode(onceId: Int, email: String): Long
 private external fun _signUp(onceId: Int, email: String, code: String): Long
 private external fun _addAccount(onceId: Int, email: String): Long
@@ -81,4 +136,21 @@ private external fun _closeAccount(onceId: Int, accountId: Int, code: String): L
 private external fun _publicKey(onceId: Int): Long
 private external fun _listenNotifications(streamId: Int, accountId: Int): Long
 private external fun _welcomeShown(onceId: Int): Long
-private external fun _updateWelcomeShown(onceId: Int, shown: Boolean): Long
\ No newline at end of file
+private external fun _updateWelcomeShown(onceId: Int, shown: Boolean): Long
+private external fun _directoryDevices(onceId: Int, accountId: Int, email: String): Long
+private external fun _updateDirectory(onceId: Int, accountId: Int, listed: Boolean): Long
+private external fun _rotateIdentityKey(onceId: Int): Long
+private external fun _verifyDevice(onceId: Int, accountId: Int, deviceId: Int, fingerprint: String): Long
+private external fun _trustEvents(streamId: Int): Long
+private external fun _startDeviceVerification(onceId: Int, accountId: Int, pubkey: String): Long
+private external fun _createRecoveryBundle(onceId: Int, accountId: Int, passphrase: String): Long
+private external fun _deleteRecoveryBundle(onceId: Int, accountId: Int): Long
+private external fun _sendRecoveryCode(onceId: Int, email: String): Long
+private external fun _recoverAccount(onceId: Int, email: String, code: String, passphrase: String): Long
+private external fun _encryption(onceId: Int): Long
+private external fun _enableEncryption(onceId: Int, pin: String): Long
+private external fun _disableEncryption(onceId: Int, pin: String): Long
+private external fun _unlock(onceId: Int, pin: String): Long
+private external fun _lock(onceId: Int): Long
+private external fun _conflictPolicy(onceId: Int, accountId: Int): Long
+private external fun _updateConflictPolicy(onceId: Int, accountId: Int, keepCopies: Boolean): Long
\ No newline at end of file
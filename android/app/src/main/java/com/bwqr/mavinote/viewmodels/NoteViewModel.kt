@@ -1,10 +1,21 @@
 package com.bwqr.mavinote.viewmodels
 
+import com.bwqr.mavinote.models.Attachment
+import com.bwqr.mavinote.models.Commit
+import com.bwqr.mavinote.models.CommitNote
+import com.bwqr.mavinote.models.Conflict
+import com.bwqr.mavinote.models.FailedOperation
 import com.bwqr.mavinote.models.Folder
+import com.bwqr.mavinote.models.FolderMember
 import com.bwqr.mavinote.models.Note
+import com.bwqr.mavinote.models.NoteRevision
+import com.bwqr.mavinote.models.RevisionRetention
+import com.bwqr.mavinote.models.SearchResult
+import com.bwqr.mavinote.reax.DeBool
 import com.bwqr.mavinote.reax.DeInt
 import com.bwqr.mavinote.reax.DeList
 import com.bwqr.mavinote.reax.DeOption
+import com.bwqr.mavinote.reax.DeString
 import com.bwqr.mavinote.reax.Runtime
 import kotlinx.coroutines.flow.Flow
 
@@ -20,8 +31,14 @@ class NoteViewModel {
         suspend fun folder(folderId: Int): Folder? =
             Runtime.runOnce(DeOption(Folder)) { _folder(it, folderId) }
 
-        suspend fun createFolder(accountId: Int, name: String): Unit =
-            Runtime.runOnceUnit { _createFolder(it, accountId, name) }
+        suspend fun createFolder(accountId: Int, name: String, parentId: Int? = null): Unit =
+            Runtime.runOnceUnit { _createFolder(it, accountId, parentId ?: 0, name) }
+
+        suspend fun moveFolder(folderId: Int, parentId: Int?): Unit =
+            Runtime.runOnceUnit { _moveFolder(it, folderId, parentId ?: 0) }
+
+        suspend fun renameFolder(folderId: Int, name: String): Unit =
+            Runtime.runOnceUnit { _renameFolder(it, folderId, name) }
 
         suspend fun deleteFolder(folderId: Int): Unit =
             Runtime.runOnceUnit { _deleteFolder(it, folderId) }
@@ -40,6 +57,96 @@ class NoteViewModel {
 
         suspend fun deleteNote(noteId: Int): Unit =
             Runtime.runOnceUnit { _deleteNote(it, noteId) }
+
+        suspend fun moveNote(noteId: Int, folderId: Int): Unit =
+            Runtime.runOnceUnit { _moveNote(it, noteId, folderId) }
+
+        suspend fun noteCommits(noteId: Int): List<Commit> =
+            Runtime.runOnce(DeList(Commit)) { _noteCommits(it, noteId) }
+
+        suspend fun noteCommit(noteId: Int, commit: Int): CommitNote? =
+            Runtime.runOnce(DeOption(CommitNote)) { _noteCommit(it, noteId, commit) }
+
+        suspend fun restoreCommit(noteId: Int, commit: Int): Unit =
+            Runtime.runOnceUnit { _restoreCommit(it, noteId, commit) }
+
+        suspend fun attachments(noteId: Int): List<Attachment> =
+            Runtime.runOnce(DeList(Attachment)) { _attachments(it, noteId) }
+
+        suspend fun addAttachment(noteId: Int, path: String): Attachment =
+            Runtime.runOnce(Attachment) { _addAttachment(it, noteId, path) }
+
+        suspend fun attachmentPath(noteId: Int, attachmentId: Int): String =
+            Runtime.runOnce(DeString) { _attachmentPath(it, noteId, attachmentId) }
+
+        suspend fun deleteAttachment(noteId: Int, attachmentId: Int): Unit =
+            Runtime.runOnceUnit { _deleteAttachment(it, noteId, attachmentId) }
+
+        suspend fun trash(): List<Note> =
+            Runtime.runOnce(DeList(Note)) { _trash(it) }
+
+        suspend fun restoreNote(noteId: Int): Unit =
+            Runtime.runOnceUnit { _restoreNote(it, noteId) }
+
+        suspend fun folderMembers(folderId: Int): List<FolderMember> =
+            Runtime.runOnce(DeList(FolderMember)) { _folderMembers(it, folderId) }
+
+        suspend fun shareFolder(folderId: Int, email: String, write: Boolean): Unit =
+            Runtime.runOnceUnit { _shareFolder(it, folderId, email, write) }
+
+        suspend fun removeFolderMember(folderId: Int, userId: Int): Unit =
+            Runtime.runOnceUnit { _removeFolderMember(it, folderId, userId) }
+
+        fun search(query: String, accountId: Int): Flow<SearchResult> =
+            Runtime.runStream(SearchResult) { _search(it, query, accountId) }
+
+        fun conflicts(): Flow<List<Conflict>> =
+            Runtime.runStream(DeList(Conflict)) { _conflicts(it) }
+
+        suspend fun resolveConflict(conflictId: Int): Unit =
+            Runtime.runOnceUnit { _resolveConflict(it, conflictId) }
+
+        suspend fun discardConflict(conflictId: Int): Unit =
+            Runtime.runOnceUnit { _discardConflict(it, conflictId) }
+
+        fun failedOperations(): Flow<List<FailedOperation>> =
+            Runtime.runStream(DeList(FailedOperation)) { _failedOperations(it) }
+
+        suspend fun retryFailedOperation(operationId: Int): Unit =
+            Runtime.runOnceUnit { _retryFailedOperation(it, operationId) }
+
+        suspend fun discardFailedOperation(operationId: Int): Unit =
+            Runtime.runOnceUnit { _discardFailedOperation(it, operationId) }
+
+        suspend fun noteRevisions(noteId: Int): List<NoteRevision> =
+            Runtime.runOnce(DeList(NoteRevision)) { _noteRevisions(it, noteId) }
+
+        suspend fun restoreRevision(revisionId: Int): Unit =
+            Runtime.runOnceUnit { _restoreRevision(it, revisionId) }
+
+        suspend fun revisionRetention(): RevisionRetention =
+            Runtime.runOnce(RevisionRetention) { _revisionRetention(it) }
+
+        suspend fun updateRevisionRetention(value: Int, days: Boolean): Unit =
+            Runtime.runOnceUnit { _updateRevisionRetention(it, value, days) }
+
+        suspend fun convertNote(noteId: Int): Unit =
+            Runtime.runOnceUnit { _convertNote(it, noteId) }
+
+        suspend fun noteConverted(noteId: Int): Boolean =
+            Runtime.runOnce(DeBool) { _noteConverted(it, noteId) }
+
+        suspend fun trashedFolders(): List<Folder> =
+            Runtime.runOnce(DeList(Folder)) { _trashedFolders(it) }
+
+        suspend fun restoreFolder(folderId: Int): Unit =
+            Runtime.runOnceUnit { _restoreFolder(it, folderId) }
+
+        suspend fun trashRetention(): Int =
+            Runtime.runOnce(DeInt) { _trashRetention(it) }
+
+        suspend fun updateTrashRetention(days: Int): Unit =
+            Runtime.runOnceUnit { _updateTrashRetention(it, days) }
     }
 }
 
@@ -47,10 +154,42 @@ private external fun _init()
 private external fun _sync(onceId: Int): Long
 private external fun _folders(streamId: Int): Long
 private external fun _folder(onceId: Int, folderId: Int): Long
-private external fun _createFolder(onceId: Int, accountId: Int, name: String): Long
+private external fun _createFolder(onceId: Int, accountId: Int, parentId: Int, name: String): Long
+private external fun _moveFolder(onceId: Int, folderId: Int, parentId: Int): Long
+private external fun _renameFolder(onceId: Int, folderId: Int, name: String): Long
 private external fun _deleteFolder(onceId: Int, folderId: Int): Long
 private external fun _noteSummaries(streamId: Int, folderId: Int): Long
 private external fun _note(onceId: Int, noteId: Int): Long
 private external fun _createNote(onceId: Int, folderId: Int, text: String): Long
 private external fun _updateNote(onceId: Int, noteId: Int, text: String): Long
-private external fun _deleteNote(onceId: Int, noteId: Int): Long
\ No newline at end of file
+private external fun _deleteNote(onceId: Int, noteId: Int): Long
+private external fun _moveNote(onceId: Int, noteId: Int, folderId: Int): Long
+private external fun _noteCommits(onceId: Int, noteId: Int): Long
+private external fun _noteCommit(onceId: Int, noteId: Int, commit: Int): Long
+private external fun _restoreCommit(onceId: Int, noteId: Int, commit: Int): Long
+private external fun _attachments(onceId: Int, noteId: Int): Long
+private external fun _addAttachment(onceId: Int, noteId: Int, path: String): Long
+private external fun _attachmentPath(onceId: Int, noteId: Int, attachmentId: Int): Long
+private external fun _deleteAttachment(onceId: Int, noteId: Int, attachmentId: Int): Long
+private external fun _trash(onceId: Int): Long
+private external fun _restoreNote(onceId: Int, noteId: Int): Long
+private external fun _folderMembers(onceId: Int, folderId: Int): Long
+private external fun _shareFolder(onceId: Int, folderId: Int, email: String, write: Boolean): Long
+private external fun _removeFolderMember(onceId: Int, folderId: Int, userId: Int): Long
+private external fun _search(streamId: Int, query: String, accountId: Int): Long
+private external fun _conflicts(streamId: Int): Long
+private external fun _resolveConflict(onceId: Int, conflictId: Int): Long
+private external fun _discardConflict(onceId: Int, conflictId: Int): Long
+private external fun _failedOperations(streamId: Int): Long
+private external fun _retryFailedOperation(onceId: Int, operationId: Int): Long
+private external fun _discardFailedOperation(onceId: Int, operationId: Int): Long
+private external fun _noteRevisions(onceId: Int, noteId: Int): Long
+private external fun _restoreRevision(onceId: Int, revisionId: Int): Long
+private external fun _revisionRetention(onceId: Int): Long
+private external fun _updateRevisionRetention(onceId: Int, value: Int, days: Boolean): Long
+private external fun _convertNote(onceId: Int, noteId: Int): Long
+private external fun _noteConverted(onceId: Int, noteId: Int): Long
+private external fun _trashedFolders(onceId: Int): Long
+private external fun _restoreFolder(onceId: Int, folderId: Int): Long
+private external fun _trashRetention(onceId: Int): Long
+private external fun _updateTrashRetention(onceId: Int, days: Int): Long
\ No newline at end of file
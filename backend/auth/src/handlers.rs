@@ -1,10 +1,10 @@
 use std::time::{Instant, Duration};
 
 use base::{
-    crypto::Crypto,
+    crypto::{self, Crypto},
     models::{Token, TokenKind, UNEXPECTED_TOKEN_KIND},
     sanitize::Sanitized,
-    schema::{devices, pending_devices, pending_users, users, user_devices},
+    schema::{devices, pending_devices, pending_recoveries, pending_users, recovery_bundles, users, user_devices},
     types::Pool,
     HttpError, HttpMessage,
 };
@@ -23,8 +23,8 @@ use rand::seq::SliceRandom;
 
 use crate::{
     models::PendingUser,
-    requests::{CreatePendingDevice, Login, SendCode, SignUp},
-    responses, templates::VerifyEmail,
+    requests::{CreatePendingDevice, Login, Recover, RevealVerification, SendCode, SignUp},
+    responses, templates::{RecoverAccount, VerifyEmail},
 };
 
 pub async fn login(
@@ -98,7 +98,7 @@ pub async fn sign_up(
 
         let user_id = diesel::insert_into(users::table)
             .values(users::email.eq(pending_user.email))
-            .get_result::<(i32, String, NaiveDateTime)>(&mut conn)
+            .get_result::<(i32, String, NaiveDateTime, i64)>(&mut conn)
             .map_err(|e| match e {
                 diesel::result::Error::DatabaseError(
                     diesel::result::DatabaseErrorKind::UniqueViolation,
@@ -119,9 +119,10 @@ pub async fn sign_up(
             .do_nothing()
             .execute(&mut conn)?;
 
-        let (device_id, _, pass, _) = devices::table
+        let (device_id, pass) = devices::table
             .filter(devices::pubkey.eq(&request.pubkey))
-            .first::<(i32, String, String, NaiveDateTime)>(&mut conn)?;
+            .select((devices::id, devices::password))
+            .first::<(i32, String)>(&mut conn)?;
 
         if pass != password {
             return Err(HttpError::conflict("device_exists_but_passwords_mismatch"));
@@ -192,6 +193,133 @@ pub async fn send_code(
     Ok(Json(HttpMessage::success()))
 }
 
+/// Sends the code which is needed to fetch the recovery bundle of the user. Users without a bundle are told so, since
+/// the emails of users are already revealed by the other endpoints.
+pub async fn send_recovery_code(
+    pool: Data<Pool>,
+    request: Sanitized<Json<SendCode>>,
+    mail_recipient: Data<MailRecipient>,
+) -> Result<Json<HttpMessage>, HttpError> {
+    block(move || -> Result<(), HttpError> {
+        let mut conn = pool.get().unwrap();
+
+        let Some(user_id) = users::table
+            .inner_join(recovery_bundles::table)
+            .filter(users::email.eq(&request.email))
+            .select(users::id)
+            .first::<i32>(&mut conn)
+            .optional()? else {
+                return Err(HttpError::not_found("recovery_bundle_not_found"));
+            };
+
+        let code: String = b"0123456789"
+            .choose_multiple(&mut rand::thread_rng(), 8)
+            .map(|num| char::from(*num))
+            .collect();
+
+        diesel::insert_into(pending_recoveries::table)
+            .values((pending_recoveries::user_id.eq(user_id), pending_recoveries::code.eq(&code)))
+            .on_conflict(pending_recoveries::user_id)
+            .do_update()
+            .set((
+                pending_recoveries::code.eq(&code),
+                pending_recoveries::updated_at.eq(Utc::now().naive_utc()),
+                pending_recoveries::attempts.eq(0),
+            ))
+            .execute(&mut conn)?;
+
+        mail_recipient.do_send(SendMail {
+            to: request.email.clone(),
+            subject: "Recover your Mavinote account".to_string(),
+            html: RecoverAccount { code: &code }.render()?,
+        });
+
+        Ok(())
+    })
+    .await??;
+
+    Ok(Json(HttpMessage::success()))
+}
+
+/// Number of wrong codes after which the recovery code is invalidated and a new one has to be sent
+const MAX_RECOVERY_ATTEMPTS: i32 = 5;
+
+/// Returns the recovery bundle of the user, the device decrypts it with the passphrase and logins as the device which
+/// created it
+pub async fn recover(
+    pool: Data<Pool>,
+    request: Sanitized<Json<Recover>>,
+) -> Result<Json<responses::RecoveryBundle>, HttpError> {
+    let bundle = block(move || -> Result<responses::RecoveryBundle, HttpError> {
+        let mut conn = pool.get().unwrap();
+
+        let user_id = users::table
+            .filter(users::email.eq(&request.email))
+            .select(users::id)
+            .first::<i32>(&mut conn)
+            .optional()?
+            .ok_or(HttpError::not_found("email_not_found"))?;
+
+        // Row is locked so that concurrent guesses cannot exceed the allowed attempts
+        let matched = conn.transaction(|conn| -> Result<bool, HttpError> {
+            let (code, updated_at, attempts) = pending_recoveries::table
+                .filter(pending_recoveries::user_id.eq(user_id))
+                .select((pending_recoveries::code, pending_recoveries::updated_at, pending_recoveries::attempts))
+                .for_update()
+                .first::<(String, NaiveDateTime, i32)>(conn)
+                .map_err(|e| match e {
+                    diesel::result::Error::NotFound => HttpError::not_found("email_not_found"),
+                    _ => e.into(),
+                })?;
+
+            let minutes_since_code_sent = Utc::now()
+                .naive_utc()
+                .signed_duration_since(updated_at)
+                .num_minutes();
+
+            if minutes_since_code_sent > 5 || attempts >= MAX_RECOVERY_ATTEMPTS {
+                return Err(HttpError::unprocessable_entity("expired_code"));
+            }
+
+            if crypto::codes_match(&code, &request.code) {
+                return Ok(true);
+            }
+
+            diesel::update(pending_recoveries::table)
+                .filter(pending_recoveries::user_id.eq(user_id))
+                .set(pending_recoveries::attempts.eq(attempts + 1))
+                .execute(conn)?;
+
+            Ok(false)
+        })?;
+
+        if !matched {
+            return Err(HttpError::unprocessable_entity("invalid_code"));
+        }
+
+        // Code is not consumed, the user may mistype the passphrase and fetch the bundle again until it expires
+        recovery_bundles::table
+            .filter(recovery_bundles::user_id.eq(user_id))
+            .select((
+                recovery_bundles::salt,
+                recovery_bundles::algorithm,
+                recovery_bundles::memory_cost,
+                recovery_bundles::time_cost,
+                recovery_bundles::parallelism,
+                recovery_bundles::nonce,
+                recovery_bundles::ciphertext,
+            ))
+            .first::<responses::RecoveryBundle>(&mut conn)
+            .map_err(|e| match e {
+                diesel::result::Error::NotFound => HttpError::not_found("recovery_bundle_not_found"),
+                _ => e.into(),
+            })
+    })
+    .await??;
+
+    Ok(Json(bundle))
+}
+
 pub async fn request_verification(
     crypto: Data<Crypto>,
     pool: Data<Pool>,
@@ -206,6 +334,10 @@ pub async fn request_verification(
         return Err(HttpError::unprocessable_entity("invalid_password"));
     }
 
+    if !crypto::is_valid_verification_value(&request.commitment) {
+        return Err(HttpError::unprocessable_entity("invalid_commitment"));
+    }
+
     let token = block(move || {
         let mut conn = pool.get().unwrap();
 
@@ -228,9 +360,10 @@ pub async fn request_verification(
             .do_nothing()
             .execute(&mut conn)?;
 
-        let (device_id, _, pass, _) = devices::table
+        let (device_id, pass) = devices::table
             .filter(devices::pubkey.eq(&request.pubkey))
-            .first::<(i32, String, String, NaiveDateTime)>(&mut conn)?;
+            .select((devices::id, devices::password))
+            .first::<(i32, String)>(&mut conn)?;
 
         if pass != password {
             return Err(HttpError::conflict("device_exists_but_passwords_mismatch"));
@@ -251,10 +384,17 @@ pub async fn request_verification(
             .values((
                 pending_devices::user_id.eq(&user_id),
                 pending_devices::device_id.eq(&device_id),
+                pending_devices::commitment.eq(&request.commitment),
             ))
             .on_conflict((pending_devices::user_id, pending_devices::device_id))
             .do_update()
-            .set(pending_devices::updated_at.eq(Utc::now().naive_utc()))
+            .set((
+                pending_devices::updated_at.eq(Utc::now().naive_utc()),
+                pending_devices::verifier_device_id.eq(None::<i32>),
+                pending_devices::commitment.eq(&request.commitment),
+                pending_devices::verifier_nonce.eq(None::<String>),
+                pending_devices::device_nonce.eq(None::<String>),
+            ))
             .execute(&mut conn)?;
 
         crypto
@@ -266,6 +406,41 @@ pub async fn request_verification(
     Ok(Json(responses::Token { token }))
 }
 
+/// Reveals the nonce committed in [`request_verification`]. The pending device reveals it only after receiving the nonce
+/// of the verifier, so neither nonce can be chosen to steer the verification code once the other one is known.
+pub async fn reveal_verification(
+    crypto: Data<Crypto>,
+    pool: Data<Pool>,
+    request: Sanitized<Json<RevealVerification>>,
+) -> Result<Json<HttpMessage>, HttpError> {
+    let token = crypto.decode::<Token>(&request.token)?;
+
+    if token.kind != TokenKind::PendingDevice {
+        return Err(UNEXPECTED_TOKEN_KIND);
+    }
+
+    if !crypto::is_valid_verification_value(&request.nonce) {
+        return Err(HttpError::unprocessable_entity("invalid_nonce"));
+    }
+
+    let updated = block(move || {
+        diesel::update(pending_devices::table)
+            .filter(pending_devices::user_id.eq(token.user_id))
+            .filter(pending_devices::device_id.eq(token.device_id))
+            .filter(pending_devices::verifier_nonce.is_not_null())
+            .filter(pending_devices::device_nonce.is_null())
+            .set(pending_devices::device_nonce.eq(&request.nonce))
+            .execute(&mut pool.get().unwrap())
+    })
+    .await??;
+
+    if updated == 0 {
+        return Err(HttpError::unprocessable_entity("verification_not_started"));
+    }
+
+    Ok(Json(HttpMessage::success()))
+}
+
 pub async fn wait_verification(
     pool: Data<Pool>,
     crypto: Data<Crypto>,
@@ -307,7 +482,8 @@ mod tests {
     use base::{
         crypto::Crypto,
         sanitize::Sanitized,
-        schema::{devices, pending_users, users, user_devices},
+        models::Token,
+        schema::{devices, pending_devices, pending_recoveries, pending_users, recovery_bundles, users, user_devices},
         HttpError,
     };
     use test_helpers::db::create_pool;
@@ -317,7 +493,7 @@ mod tests {
 
     use crate::requests;
 
-    use super::sign_up;
+    use super::{recover, reveal_verification, sign_up};
 
     #[actix_web::test]
     async fn it_returns_invalid_pubkey_error_if_pubkey_is_not_base64_encoded_valid_pubkey_when_sign_up_is_called(
@@ -504,4 +680,169 @@ mod tests {
 
         assert!(user_device.is_ok());
     }
+
+    fn create_recovery_bundle(conn: &mut PgConnection) {
+        let user_id = diesel::insert_into(users::table)
+            .values(users::email.eq("EMAIL"))
+            .returning(users::id)
+            .get_result::<i32>(conn)
+            .unwrap();
+
+        let device_id = diesel::insert_into(devices::table)
+            .values((devices::pubkey.eq("PUBKEY"), devices::password.eq("PASSWORD")))
+            .returning(devices::id)
+            .get_result::<i32>(conn)
+            .unwrap();
+
+        diesel::insert_into(recovery_bundles::table)
+            .values((
+                recovery_bundles::user_id.eq(user_id),
+                recovery_bundles::device_id.eq(device_id),
+                recovery_bundles::salt.eq("SALT"),
+                recovery_bundles::algorithm.eq("argon2id"),
+                recovery_bundles::memory_cost.eq(65536),
+                recovery_bundles::time_cost.eq(3),
+                recovery_bundles::parallelism.eq(4),
+                recovery_bundles::nonce.eq("NONCE"),
+                recovery_bundles::ciphertext.eq("CIPHERTEXT"),
+            ))
+            .execute(conn)
+            .unwrap();
+
+        diesel::insert_into(pending_recoveries::table)
+            .values((pending_recoveries::user_id.eq(user_id), pending_recoveries::code.eq("11223344")))
+            .execute(conn)
+            .unwrap();
+    }
+
+    #[actix_web::test]
+    async fn it_returns_invalid_code_error_if_the_code_for_given_email_is_incorrect_when_recover_is_called() {
+        let pool = create_pool();
+
+        create_recovery_bundle(&mut pool.get().unwrap());
+
+        let request = requests::Recover { email: "EMAIL".to_string(), code: "44332211".to_string() };
+
+        let res = recover(Data::new(pool), Sanitized(Json(request))).await;
+
+        assert_eq!(
+            HttpError::unprocessable_entity("invalid_code"),
+            res.map(|_| ()).unwrap_err()
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_returns_recovery_bundle_when_recover_is_called() {
+        let pool = create_pool();
+
+        create_recovery_bundle(&mut pool.get().unwrap());
+
+        let request = requests::Recover { email: "EMAIL".to_string(), code: "11223344".to_string() };
+
+        let bundle = recover(Data::new(pool), Sanitized(Json(request))).await.unwrap();
+
+        assert_eq!(
+            ("SALT", "NONCE", "CIPHERTEXT"),
+            (bundle.salt.as_str(), bundle.nonce.as_str(), bundle.ciphertext.as_str())
+        );
+        assert_eq!(
+            ("argon2id", 65536, 3, 4),
+            (bundle.algorithm.as_str(), bundle.memory_cost, bundle.time_cost, bundle.parallelism)
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_returns_expired_code_error_if_too_many_codes_are_tried_when_recover_is_called() {
+        let pool = Data::new(create_pool());
+
+        create_recovery_bundle(&mut pool.get().unwrap());
+
+        for _ in 0..super::MAX_RECOVERY_ATTEMPTS {
+            let request = requests::Recover { email: "EMAIL".to_string(), code: "44332211".to_string() };
+
+            let res = recover(pool.clone(), Sanitized(Json(request))).await;
+
+            assert_eq!(
+                HttpError::unprocessable_entity("invalid_code"),
+                res.map(|_| ()).unwrap_err()
+            );
+        }
+
+        let request = requests::Recover { email: "EMAIL".to_string(), code: "11223344".to_string() };
+
+        let res = recover(pool, Sanitized(Json(request))).await;
+
+        assert_eq!(
+            HttpError::unprocessable_entity("expired_code"),
+            res.map(|_| ()).unwrap_err()
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_reveals_nonce_only_after_verifier_sends_its_nonce_when_reveal_verification_is_called() {
+        let pool = create_pool();
+        let crypto = Data::new(Crypto::new("SECRET"));
+
+        let (user_id, device_id) = {
+            let mut conn = pool.get().unwrap();
+
+            let user_id = diesel::insert_into(users::table)
+                .values(users::email.eq("EMAIL"))
+                .returning(users::id)
+                .get_result::<i32>(&mut conn)
+                .unwrap();
+
+            let device_id = diesel::insert_into(devices::table)
+                .values((devices::pubkey.eq("PUBKEY"), devices::password.eq("PASSWORD")))
+                .returning(devices::id)
+                .get_result::<i32>(&mut conn)
+                .unwrap();
+
+            diesel::insert_into(pending_devices::table)
+                .values((
+                    pending_devices::user_id.eq(user_id),
+                    pending_devices::device_id.eq(device_id),
+                    pending_devices::commitment.eq("COMMITMENT"),
+                ))
+                .execute(&mut conn)
+                .unwrap();
+
+            (user_id, device_id)
+        };
+
+        let pool = Data::new(pool);
+        let token = crypto.encode(&Token::pending_device(user_id, device_id)).unwrap();
+        let nonce = BASE64_STANDARD.encode([1; 32]);
+
+        let request = requests::RevealVerification { token: token.clone(), nonce: nonce.clone() };
+        let res = reveal_verification(crypto.clone(), pool.clone(), Sanitized(Json(request))).await;
+
+        assert_eq!(
+            HttpError::unprocessable_entity("verification_not_started"),
+            res.map(|_| ()).unwrap_err()
+        );
+
+        diesel::update(pending_devices::table)
+            .set(pending_devices::verifier_nonce.eq(BASE64_STANDARD.encode([2; 32])))
+            .execute(&mut pool.get().unwrap())
+            .unwrap();
+
+        let request = requests::RevealVerification { token: token.clone(), nonce: nonce.clone() };
+        reveal_verification(crypto.clone(), pool.clone(), Sanitized(Json(request))).await.unwrap();
+
+        let request = requests::RevealVerification { token, nonce: BASE64_STANDARD.encode([3; 32]) };
+        let res = reveal_verification(crypto, pool.clone(), Sanitized(Json(request))).await;
+
+        assert_eq!(
+            HttpError::unprocessable_entity("verification_not_started"),
+            res.map(|_| ()).unwrap_err()
+        );
+
+        let revealed = pending_devices::table
+            .select(pending_devices::device_nonce)
+            .first::<Option<String>>(&mut pool.get().unwrap())
+            .unwrap();
+
+        assert_eq!(Some(nonce), revealed);
+    }
 }
@@ -12,7 +12,10 @@ pub fn register(config: &mut ServiceConfig) {
             .route("sign-up", post().to(handlers::sign_up))
             .route("login", post().to(handlers::login))
             .route("send-code", post().to(handlers::send_code))
+            .route("send-recovery-code", post().to(handlers::send_recovery_code))
+            .route("recover", post().to(handlers::recover))
             .route("request-verification", post().to(handlers::request_verification))
+            .route("reveal-verification", post().to(handlers::reveal_verification))
             .route("wait-verification", get().to(handlers::wait_verification)),
     );
 }
@@ -23,9 +23,22 @@ pub struct SendCode {
     pub email: String,
 }
 
+#[derive(Deserialize, Sanitize)]
+pub struct Recover {
+    pub email: String,
+    pub code: String,
+}
+
 #[derive(Deserialize, Sanitize)]
 pub struct CreatePendingDevice {
     pub email: String,
     pub pubkey: String,
     pub password: String,
+    pub commitment: String,
+}
+
+#[derive(Deserialize, Sanitize)]
+pub struct RevealVerification {
+    pub token: String,
+    pub nonce: String,
 }
@@ -1,3 +1,4 @@
+use diesel::Queryable;
 use serde::{Serialize, Deserialize};
 
 #[derive(Deserialize, Serialize)]
@@ -5,3 +6,14 @@ pub struct Token {
     pub token: String,
 }
 
+#[derive(Queryable, Serialize)]
+pub struct RecoveryBundle {
+    pub salt: String,
+    pub algorithm: String,
+    pub memory_cost: i32,
+    pub time_cost: i32,
+    pub parallelism: i32,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
@@ -5,3 +5,9 @@ use askama::Template;
 pub struct VerifyEmail<'a> {
     pub code: &'a str,
 }
+
+#[derive(Template)]
+#[template(path = "mails/recover-account.html")]
+pub struct RecoverAccount<'a> {
+    pub code: &'a str,
+}
@@ -1,7 +1,10 @@
 use base64::prelude::{Engine, BASE64_STANDARD};
 use jsonwebtoken::{errors::Error as JWTError, DecodingKey, EncodingKey, Header, Validation};
-use ring::hmac;
+use std::sync::Arc;
+
+use ring::{constant_time, hmac, signature::{Ed25519KeyPair, KeyPair}};
 use serde::{de::DeserializeOwned, Serialize};
+use x25519_dalek::{PublicKey, StaticSecret};
 
 #[derive(Clone)]
 pub struct Crypto {
@@ -10,6 +13,8 @@ pub struct Crypto {
     header: Header,
     validation: Validation,
     hmac512_key: hmac::Key,
+    record_key: Arc<Ed25519KeyPair>,
+    rotation_key: StaticSecret,
 }
 
 impl Crypto {
@@ -20,6 +25,8 @@ impl Crypto {
             header: Header::default(),
             validation: Validation::new(jsonwebtoken::Algorithm::HS256),
             hmac512_key: hmac::Key::new(hmac::HMAC_SHA512, secret.as_bytes()),
+            record_key: Arc::new(record_key(secret)),
+            rotation_key: rotation_key(secret),
         }
     }
 
@@ -34,4 +41,70 @@ impl Crypto {
     pub fn sign512(&self, message: &str) -> String {
         BASE64_STANDARD.encode(hmac::sign(&self.hmac512_key, message.as_bytes()))
     }
+
+    /// Signs the records that are published to the clients, which verify them with [`Crypto::record_pubkey`]
+    pub fn sign_record(&self, message: &str) -> String {
+        BASE64_STANDARD.encode(self.record_key.sign(message.as_bytes()))
+    }
+
+    pub fn record_pubkey(&self) -> String {
+        BASE64_STANDARD.encode(self.record_key.public_key())
+    }
+
+    /// Pubkey that the devices agree on a shared secret with to prove the possession of their identity keys
+    pub fn rotation_pubkey(&self) -> String {
+        BASE64_STANDARD.encode(PublicKey::from(&self.rotation_key).as_bytes())
+    }
+
+    /// Verifies that the proof is the HMAC of the new pubkey, keyed with the shared secret of the previous pubkey
+    /// and [`Crypto::rotation_pubkey`]. Only the owner of the previous private key can compute it.
+    pub fn verify_rotation(&self, previous_pubkey: &str, pubkey: &str, proof: &str) -> bool {
+        let Some(previous_pubkey) = parse_pubkey(previous_pubkey) else {
+            return false;
+        };
+
+        let Ok(proof) = BASE64_STANDARD.decode(proof) else {
+            return false;
+        };
+
+        let shared_secret = self.rotation_key.diffie_hellman(&previous_pubkey);
+        let key = hmac::Key::new(hmac::HMAC_SHA256, shared_secret.as_bytes());
+
+        hmac::verify(&key, pubkey.as_bytes(), &proof).is_ok()
+    }
+}
+
+pub fn is_valid_pubkey(pubkey: &str) -> bool {
+    parse_pubkey(pubkey).is_some()
+}
+
+/// Compares the codes in constant time so that the time taken does not reveal how many leading characters match
+pub fn codes_match(code: &str, other: &str) -> bool {
+    constant_time::verify_slices_are_equal(code.as_bytes(), other.as_bytes()).is_ok()
+}
+
+/// Commitments and nonces exchanged while verifying a device are 32 bytes, like the pubkeys
+pub fn is_valid_verification_value(value: &str) -> bool {
+    BASE64_STANDARD.decode(value).map(|bytes| bytes.len() == 32).unwrap_or(false)
+}
+
+fn parse_pubkey(pubkey: &str) -> Option<PublicKey> {
+    let bytes = BASE64_STANDARD.decode(pubkey).ok()?;
+
+    <[u8; 32]>::try_from(bytes).ok().map(PublicKey::from)
+}
+
+/// Derives the signing key from the secret so that it stays the same across restarts
+fn record_key(secret: &str) -> Ed25519KeyPair {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let seed = hmac::sign(&key, b"record_key");
+
+    Ed25519KeyPair::from_seed_unchecked(seed.as_ref()).unwrap()
+}
+
+fn rotation_key(secret: &str) -> StaticSecret {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let seed = hmac::sign(&key, b"rotation_key");
+
+    StaticSecret::from(<[u8; 32]>::try_from(seed.as_ref()).unwrap())
 }
@@ -52,6 +52,22 @@ impl HttpError {
             message: None,
         }
     }
+
+    pub const fn quota_exceeded(error: &'static str) -> Self {
+        HttpError {
+            code: StatusCode::PAYLOAD_TOO_LARGE,
+            error,
+            message: None,
+        }
+    }
+
+    pub const fn too_many_requests(error: &'static str) -> Self {
+        HttpError {
+            code: StatusCode::TOO_MANY_REQUESTS,
+            error,
+            message: None,
+        }
+    }
 }
 
 impl Serialize for HttpError {
@@ -0,0 +1,293 @@
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+    task::{Context, Poll},
+};
+
+use actix_web::{
+    body::{self, EitherBody, MessageBody},
+    dev::{Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{header::{ContentType, CONTENT_LENGTH}, Method, StatusCode},
+    web::{block, BytesMut, Data},
+    Error, HttpMessage, HttpResponse, ResponseError,
+};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use chrono::{Duration, Utc};
+use diesel::{prelude::*, PgConnection};
+use futures::{future::LocalBoxFuture, StreamExt};
+use ring::digest::{Context as DigestContext, SHA256};
+
+use crate::{models::Token, schema::idempotency_keys, types::Pool, HttpError};
+
+pub const IDEMPOTENCY_KEY: &str = "Idempotency-Key";
+
+const MAX_KEY_LEN: usize = 64;
+/// Bodies are read before the handlers run, so they are capped here. It is the default limit of the JSON extractor,
+/// handlers would refuse larger bodies anyway.
+pub const MAX_BODY_LEN: usize = 2 * 1024 * 1024;
+/// Requests that are handled longer than this many seconds are assumed to be interrupted. Whether they are applied is
+/// unknown, so their keys are never reserved again.
+const INTERRUPTED_AFTER: i64 = 60;
+
+const INVALID_IDEMPOTENCY_KEY: HttpError = HttpError::unprocessable_entity("invalid_idempotency_key");
+const IDEMPOTENCY_KEY_REUSED: HttpError = HttpError::unprocessable_entity("idempotency_key_reused");
+const IDEMPOTENCY_KEY_IN_PROGRESS: HttpError = HttpError::conflict("idempotency_key_in_progress");
+const IDEMPOTENCY_OUTCOME_UNKNOWN: HttpError = HttpError::conflict("idempotency_outcome_unknown");
+const IDEMPOTENCY_UNAVAILABLE: HttpError = HttpError {
+    code: StatusCode::INTERNAL_SERVER_ERROR,
+    error: "idempotency_unavailable",
+    message: None,
+};
+const REQUEST_TOO_LARGE: HttpError = HttpError {
+    code: StatusCode::PAYLOAD_TOO_LARGE,
+    error: "request_too_large",
+    message: None,
+};
+const UNREADABLE_REQUEST: HttpError = HttpError::unprocessable_entity("unreadable_request");
+const UNREADABLE_RESPONSE: HttpError = HttpError {
+    code: StatusCode::INTERNAL_SERVER_ERROR,
+    error: "unreadable_response",
+    message: None,
+};
+
+/// Deduplicates the requests that are sent with an idempotency key. First request with a key is handled and its
+/// successful response is stored, replayed requests get the stored response instead of being handled again.
+/// Failed requests are not stored so that they can be retried with the same key. Whenever it cannot be known whether
+/// a request is applied, the request is refused rather than handled again.
+///
+/// It must be wrapped inside [`super::auth_user::AuthUser`] since the keys belong to devices.
+pub struct Idempotency;
+
+impl<S, B> Transform<S, ServiceRequest> for Idempotency
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = IdempotencyMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(IdempotencyMiddleware { service: Rc::new(service) }))
+    }
+}
+
+pub struct IdempotencyMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for IdempotencyMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let key = match parse_key(&req) {
+                Ok(Some(key)) => key,
+                Ok(None) => return service.call(req).await.map(ServiceResponse::map_into_left_body),
+                Err(e) => return Ok(req.error_response(e).map_into_right_body()),
+            };
+
+            let (Some(pool), Some(device_id)) = (
+                req.app_data::<Data<Pool>>().cloned(),
+                req.extensions().get::<Token>().map(|token| token.device_id),
+            ) else {
+                return Ok(req.error_response(IDEMPOTENCY_UNAVAILABLE).map_into_right_body());
+            };
+
+            let declared_len = req.headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|len| len.to_str().ok())
+                .and_then(|len| len.parse::<usize>().ok());
+
+            if declared_len.is_some_and(|len| len > MAX_BODY_LEN) {
+                return Ok(req.error_response(REQUEST_TOO_LARGE).map_into_right_body());
+            }
+
+            // Body is read here to bind it to the key, and given back to the handler afterwards
+            let mut payload = req.take_payload();
+            let mut body = BytesMut::new();
+            while let Some(chunk) = payload.next().await {
+                match chunk {
+                    Ok(chunk) if body.len() + chunk.len() > MAX_BODY_LEN => {
+                        return Ok(req.error_response(REQUEST_TOO_LARGE).map_into_right_body());
+                    },
+                    Ok(chunk) => body.extend_from_slice(&chunk),
+                    Err(_) => return Ok(req.error_response(UNREADABLE_REQUEST).map_into_right_body()),
+                }
+            }
+
+            let body = body.freeze();
+            let request = fingerprint(req.method(), &req.uri().to_string(), &body);
+            req.set_payload(Payload::from(body));
+
+            let reservation = {
+                let (pool, key, request) = (pool.clone(), key.clone(), request.clone());
+
+                block(move || reserve(&mut pool.get().unwrap(), device_id, &key, &request))
+                    .await
+                    .map_err(HttpError::from)
+                    .and_then(|reservation| reservation)
+            };
+
+            match reservation {
+                Ok(Reservation::Reserved) => {},
+                Ok(Reservation::Replay(status, body)) => {
+                    let status = StatusCode::from_u16(status as u16).unwrap_or(StatusCode::OK);
+                    let response = HttpResponse::build(status)
+                        .content_type(ContentType::json())
+                        .body(body);
+
+                    return Ok(req.into_response(response).map_into_right_body());
+                },
+                Err(e) => return Ok(req.error_response(e).map_into_right_body()),
+            }
+
+            let res = service.call(req).await;
+
+            let res = match res {
+                Ok(res) if res.status().is_success() => res,
+                res => {
+                    release_key(pool, device_id, key).await;
+
+                    return res.map(ServiceResponse::map_into_left_body);
+                },
+            };
+
+            let (req, res) = res.into_parts();
+            let status = res.status();
+            let (res, body) = res.into_parts();
+
+            // Request is already applied, so the key is kept without an outcome instead of being released
+            let Ok(bytes) = body::to_bytes(body).await else {
+                return Ok(ServiceResponse::new(req, UNREADABLE_RESPONSE.error_response()).map_into_right_body());
+            };
+
+            let stored = String::from_utf8_lossy(&bytes).into_owned();
+
+            // Response is already produced, a replay after a failure here is refused since the outcome is not stored
+            match block(move || complete(&mut pool.get().unwrap(), device_id, &key, status.as_u16() as i32, &stored)).await {
+                Ok(Err(e)) => log::error!("failed to store idempotent response, {e:?}"),
+                Err(e) => log::error!("failed to store idempotent response, {e:?}"),
+                Ok(Ok(())) => {},
+            }
+
+            Ok(ServiceResponse::new(req, res.set_body(bytes).map_into_boxed_body()).map_into_right_body())
+        })
+    }
+}
+
+enum Reservation {
+    Reserved,
+    Replay(i32, String),
+}
+
+/// Hash of the method, the uri and the body, so that a key is not replayed for another request
+fn fingerprint(method: &Method, uri: &str, body: &[u8]) -> String {
+    let mut context = DigestContext::new(&SHA256);
+    context.update(method.as_str().as_bytes());
+    context.update(b" ");
+    context.update(uri.as_bytes());
+    context.update(b"\n");
+    context.update(body);
+
+    BASE64_STANDARD.encode(context.finish().as_ref())
+}
+
+/// Reads the key of the request, reading requests are never deduplicated
+fn parse_key(req: &ServiceRequest) -> Result<Option<String>, HttpError> {
+    if req.method() == Method::GET {
+        return Ok(None);
+    }
+
+    let Some(key) = req.headers().get(IDEMPOTENCY_KEY) else {
+        return Ok(None);
+    };
+
+    match key.to_str() {
+        Ok(key) if !key.is_empty() && key.len() <= MAX_KEY_LEN => Ok(Some(key.to_string())),
+        _ => Err(INVALID_IDEMPOTENCY_KEY),
+    }
+}
+
+fn reserve(conn: &mut PgConnection, device_id: i32, key: &str, request: &str) -> Result<Reservation, HttpError> {
+    conn.transaction(|conn| {
+        let inserted = diesel::insert_into(idempotency_keys::table)
+            .values((
+                idempotency_keys::device_id.eq(device_id),
+                idempotency_keys::key.eq(key),
+                idempotency_keys::request.eq(request),
+            ))
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+
+        if inserted == 1 {
+            return Ok(Reservation::Reserved);
+        }
+
+        let (stored_request, status, body, created_at) = idempotency_keys::table
+            .filter(idempotency_keys::device_id.eq(device_id))
+            .filter(idempotency_keys::key.eq(key))
+            .select((
+                idempotency_keys::request,
+                idempotency_keys::status,
+                idempotency_keys::body,
+                idempotency_keys::created_at,
+            ))
+            .for_update()
+            .first::<(String, Option<i32>, Option<String>, chrono::NaiveDateTime)>(conn)?;
+
+        if stored_request != request {
+            return Err(IDEMPOTENCY_KEY_REUSED);
+        }
+
+        let interrupted = created_at < Utc::now().naive_utc() - Duration::seconds(INTERRUPTED_AFTER);
+
+        match (status, body) {
+            (Some(status), Some(body)) => Ok(Reservation::Replay(status, body)),
+            _ if interrupted => Err(IDEMPOTENCY_OUTCOME_UNKNOWN),
+            _ => Err(IDEMPOTENCY_KEY_IN_PROGRESS),
+        }
+    })
+}
+
+fn complete(conn: &mut PgConnection, device_id: i32, key: &str, status: i32, body: &str) -> Result<(), diesel::result::Error> {
+    diesel::update(idempotency_keys::table)
+        .filter(idempotency_keys::device_id.eq(device_id))
+        .filter(idempotency_keys::key.eq(key))
+        .set((idempotency_keys::status.eq(status), idempotency_keys::body.eq(body)))
+        .execute(conn)
+        .map(|_| ())
+}
+
+/// Failed requests give up their keys so that they can be retried
+async fn release_key(pool: Data<Pool>, device_id: i32, key: String) {
+    match block(move || release(&mut pool.get().unwrap(), device_id, &key)).await {
+        Ok(Err(e)) => log::error!("failed to release idempotency key, {e:?}"),
+        Err(e) => log::error!("failed to release idempotency key, {e:?}"),
+        Ok(Ok(())) => {},
+    }
+}
+
+fn release(conn: &mut PgConnection, device_id: i32, key: &str) -> Result<(), diesel::result::Error> {
+    diesel::delete(idempotency_keys::table)
+        .filter(idempotency_keys::device_id.eq(device_id))
+        .filter(idempotency_keys::key.eq(key))
+        .execute(conn)
+        .map(|_| ())
+}
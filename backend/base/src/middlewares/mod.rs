@@ -1 +1,2 @@
 pub mod auth_user;
+pub mod idempotency;
@@ -32,6 +32,12 @@ impl Sanitize for i32 {
     }
 }
 
+impl Sanitize for bool {
+    fn sanitize(self) -> Self {
+        self
+    }
+}
+
 impl<T> Sanitize for Option<T>
 where
     T: Sanitize,
@@ -3,6 +3,16 @@
 pub mod sql_types {
     use diesel::query_builder::QueryId;
 
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "role"))]
+    pub struct Role;
+
+    impl QueryId for Role {
+        type QueryId = diesel::sql_types::Text;
+
+        const HAS_STATIC_QUERY_ID: bool = true;
+    }
+
     #[derive(diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "state"))]
     pub struct State;
@@ -14,12 +24,53 @@ pub mod sql_types {
     }
 }
 
+diesel::table! {
+    attachment_chunks (attachment_id, chunk) {
+        attachment_id -> Int4,
+        chunk -> Int4,
+        data -> Bytea,
+    }
+}
+
+diesel::table! {
+    attachments (id) {
+        id -> Int4,
+        note_id -> Int4,
+        device_id -> Int4,
+        name -> Text,
+        chunk_count -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    device_attachments (attachment_id, receiver_device_id) {
+        attachment_id -> Int4,
+        sender_device_id -> Int4,
+        receiver_device_id -> Int4,
+        key -> Text,
+    }
+}
+
 diesel::table! {
     device_folders (folder_id, receiver_device_id) {
         folder_id -> Int4,
         sender_device_id -> Int4,
         receiver_device_id -> Int4,
         name -> Text,
+        key -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    device_key_log (id) {
+        id -> Int4,
+        user_id -> Int4,
+        device_id -> Int4,
+        pubkey -> Varchar,
+        added -> Bool,
+        hash -> Varchar,
+        created_at -> Timestamp,
     }
 }
 
@@ -39,6 +90,29 @@ diesel::table! {
         pubkey -> Varchar,
         password -> Varchar,
         created_at -> Timestamp,
+        previous_pubkey -> Nullable<Varchar>,
+        rotated_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    directory_opt_outs (user_id) {
+        user_id -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::Role;
+
+    folder_members (folder_id, user_id) {
+        folder_id -> Int4,
+        user_id -> Int4,
+        role -> Role,
+        change_seq -> Int8,
+        created_at -> Timestamp,
+        removed_at -> Nullable<Timestamp>,
     }
 }
 
@@ -46,6 +120,7 @@ diesel::table! {
     folder_requests (folder_id, device_id) {
         folder_id -> Int4,
         device_id -> Int4,
+        created_at -> Timestamp,
     }
 }
 
@@ -58,6 +133,45 @@ diesel::table! {
         user_id -> Int4,
         state -> State,
         created_at -> Timestamp,
+        change_seq -> Int8,
+        parent_id -> Nullable<Int4>,
+        trashed_at -> Nullable<Timestamp>,
+        deleted_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    idempotency_keys (device_id, key) {
+        device_id -> Int4,
+        key -> Varchar,
+        request -> Varchar,
+        status -> Nullable<Int4>,
+        body -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    note_commits (note_id, commit, receiver_device_id) {
+        note_id -> Int4,
+        commit -> Int4,
+        sender_device_id -> Int4,
+        receiver_device_id -> Int4,
+        name -> Text,
+        text -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    note_contents (note_id, commit) {
+        note_id -> Int4,
+        commit -> Int4,
+        folder_id -> Int4,
+        sender_device_id -> Int4,
+        name -> Text,
+        text -> Text,
+        created_at -> Timestamp,
     }
 }
 
@@ -65,6 +179,17 @@ diesel::table! {
     note_requests (note_id, device_id) {
         note_id -> Int4,
         device_id -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    note_updates (id) {
+        id -> Int4,
+        note_id -> Int4,
+        folder_id -> Int4,
+        data -> Text,
+        created_at -> Timestamp,
     }
 }
 
@@ -79,6 +204,10 @@ diesel::table! {
         state -> State,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        change_seq -> Int8,
+        trashed_at -> Nullable<Timestamp>,
+        deleted_at -> Nullable<Timestamp>,
+        update_id -> Nullable<Int4>,
     }
 }
 
@@ -95,6 +224,10 @@ diesel::table! {
         user_id -> Int4,
         device_id -> Int4,
         updated_at -> Timestamp,
+        verifier_device_id -> Nullable<Int4>,
+        commitment -> Varchar,
+        verifier_nonce -> Nullable<Varchar>,
+        device_nonce -> Nullable<Varchar>,
     }
 }
 
@@ -106,6 +239,30 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    pending_recoveries (user_id) {
+        user_id -> Int4,
+        code -> Varchar,
+        updated_at -> Timestamp,
+        attempts -> Int4,
+    }
+}
+
+diesel::table! {
+    recovery_bundles (user_id) {
+        user_id -> Int4,
+        device_id -> Int4,
+        salt -> Varchar,
+        nonce -> Varchar,
+        ciphertext -> Text,
+        created_at -> Timestamp,
+        algorithm -> Varchar,
+        memory_cost -> Int4,
+        time_cost -> Int4,
+        parallelism -> Int4,
+    }
+}
+
 diesel::table! {
     user_devices (user_id, device_id) {
         user_id -> Int4,
@@ -119,34 +276,66 @@ diesel::table! {
         id -> Int4,
         email -> Varchar,
         created_at -> Timestamp,
+        change_seq -> Int8,
+        purged_seq -> Int8,
     }
 }
 
+diesel::joinable!(attachment_chunks -> attachments (attachment_id));
+diesel::joinable!(attachments -> devices (device_id));
+diesel::joinable!(attachments -> notes (note_id));
+diesel::joinable!(device_attachments -> attachments (attachment_id));
 diesel::joinable!(device_folders -> folders (folder_id));
+diesel::joinable!(device_key_log -> users (user_id));
 diesel::joinable!(device_notes -> notes (note_id));
+diesel::joinable!(directory_opt_outs -> users (user_id));
+diesel::joinable!(folder_members -> folders (folder_id));
+diesel::joinable!(folder_members -> users (user_id));
 diesel::joinable!(folder_requests -> devices (device_id));
 diesel::joinable!(folder_requests -> folders (folder_id));
 diesel::joinable!(folders -> users (user_id));
+diesel::joinable!(idempotency_keys -> devices (device_id));
+diesel::joinable!(note_commits -> notes (note_id));
+diesel::joinable!(note_contents -> devices (sender_device_id));
+diesel::joinable!(note_contents -> folders (folder_id));
+diesel::joinable!(note_contents -> notes (note_id));
 diesel::joinable!(note_requests -> devices (device_id));
 diesel::joinable!(note_requests -> notes (note_id));
+diesel::joinable!(note_updates -> folders (folder_id));
+diesel::joinable!(note_updates -> notes (note_id));
 diesel::joinable!(notes -> folders (folder_id));
 diesel::joinable!(pending_delete_users -> users (user_id));
 diesel::joinable!(pending_devices -> devices (device_id));
 diesel::joinable!(pending_devices -> users (user_id));
+diesel::joinable!(pending_recoveries -> users (user_id));
+diesel::joinable!(recovery_bundles -> devices (device_id));
+diesel::joinable!(recovery_bundles -> users (user_id));
 diesel::joinable!(user_devices -> devices (device_id));
 diesel::joinable!(user_devices -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    attachment_chunks,
+    attachments,
+    device_attachments,
     device_folders,
+    device_key_log,
     device_notes,
     devices,
+    directory_opt_outs,
+    folder_members,
     folder_requests,
     folders,
+    idempotency_keys,
+    note_commits,
+    note_contents,
     note_requests,
+    note_updates,
     notes,
     pending_delete_users,
     pending_devices,
+    pending_recoveries,
     pending_users,
+    recovery_bundles,
     user_devices,
     users,
 );
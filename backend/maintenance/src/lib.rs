@@ -0,0 +1,539 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration as StdDuration;
+
+use actix::{Actor, AsyncContext, Context, ContextFutureSpawner, WrapFuture};
+use actix_web::web::block;
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::{prelude::*, PgConnection};
+
+use base::{
+    schema::{
+        device_folders, device_notes, devices, folder_members, folder_requests, folders, idempotency_keys, note_requests,
+        notes, pending_delete_users, pending_devices, pending_users, user_devices, users,
+    },
+    types::Pool,
+};
+
+const PURGE_INTERVAL: u64 = 60 * 60;
+
+/// Ages after which the rows are purged
+#[derive(Clone)]
+pub struct Config {
+    /// How long the trashed folders and notes can be restored
+    pub trash_retention: Duration,
+    /// How long the deleted folders and notes are kept so that the devices can pull the deletion
+    pub deleted_retention: Duration,
+    /// How long the folder and note requests wait for a response
+    pub request_retention: Duration,
+    /// How long the pending users, devices and account deletions are kept
+    pub pending_retention: Duration,
+    /// How long the previous pubkeys of the rotated devices are kept
+    pub rotation_retention: Duration,
+    /// How long the responses of the requests with idempotency keys are kept for their replays
+    pub idempotency_retention: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            trash_retention: Duration::days(30),
+            deleted_retention: Duration::days(90),
+            request_retention: Duration::days(30),
+            pending_retention: Duration::days(1),
+            rotation_retention: Duration::days(30),
+            idempotency_retention: Duration::days(7),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Purged {
+    pub trashed_folders: usize,
+    pub trashed_notes: usize,
+    pub deleted_folders: usize,
+    pub deleted_notes: usize,
+    pub removed_members: usize,
+    pub device_folders: usize,
+    pub device_notes: usize,
+    pub folder_requests: usize,
+    pub note_requests: usize,
+    pub pending_users: usize,
+    pub pending_devices: usize,
+    pub pending_delete_users: usize,
+    pub previous_pubkeys: usize,
+    pub idempotency_keys: usize,
+}
+
+pub struct Server {
+    pool: Pool,
+    config: Config,
+}
+
+impl Server {
+    pub fn new(pool: Pool, config: Config) -> Self {
+        Server { pool, config }
+    }
+
+    fn purge(&self, ctx: &mut Context<Self>) {
+        let pool = self.pool.clone();
+        let config = self.config.clone();
+
+        async move {
+            match block(move || purge(&mut pool.get().unwrap(), &config)).await {
+                Ok(Ok(purged)) => log::debug!("maintenance is completed, {purged:?}"),
+                Ok(Err(e)) => log::error!("failed to purge rows, {e:?}"),
+                Err(e) => log::error!("failed to run maintenance, {e:?}"),
+            }
+        }
+        .into_actor(self)
+        .spawn(ctx);
+    }
+}
+
+impl Actor for Server {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.purge(ctx);
+
+        ctx.run_interval(StdDuration::from_secs(PURGE_INTERVAL), |server, ctx| server.purge(ctx));
+    }
+}
+
+/// Purges the rows that are older than the ages given in config
+pub fn purge(conn: &mut PgConnection, config: &Config) -> Result<Purged, diesel::result::Error> {
+    let now = Utc::now().naive_utc();
+    let mut purged = Purged::default();
+
+    (purged.trashed_folders, purged.trashed_notes) = note::trash::purge(conn, config.trash_retention)?;
+    log_purged("trashed folders", purged.trashed_folders);
+    log_purged("trashed notes", purged.trashed_notes);
+
+    let deleted_at = now - config.deleted_retention;
+
+    raise_purged_seqs(conn, deleted_at)?;
+
+    purged.deleted_notes = diesel::delete(notes::table)
+        .filter(notes::deleted_at.lt(deleted_at))
+        .execute(conn)?;
+    log_purged("deleted notes", purged.deleted_notes);
+
+    purged.deleted_folders = conn.transaction(|conn| {
+        let folder_ids = folders::table
+            .filter(folders::deleted_at.lt(deleted_at))
+            .select(folders::id)
+            .load::<i32>(conn)?;
+
+        // Folders that are restored from the trash after their parent is deleted may still refer to it
+        diesel::update(folders::table)
+            .filter(folders::parent_id.eq_any(&folder_ids))
+            .filter(folders::id.ne_all(&folder_ids))
+            .set(folders::parent_id.eq(None::<i32>))
+            .execute(conn)?;
+
+        diesel::delete(folders::table)
+            .filter(folders::id.eq_any(&folder_ids))
+            .execute(conn)
+    })?;
+    log_purged("deleted folders", purged.deleted_folders);
+
+    purged.removed_members = diesel::delete(folder_members::table)
+        .filter(folder_members::removed_at.lt(deleted_at))
+        .execute(conn)?;
+    log_purged("removed members", purged.removed_members);
+
+    // Devices of the members can read the shared folders even though they do not belong to the owner
+    let member_devices = folder_members::table
+        .inner_join(user_devices::table.on(user_devices::user_id.eq(folder_members::user_id)))
+        .filter(folder_members::removed_at.is_null())
+        .select((folder_members::folder_id, user_devices::device_id))
+        .load::<(i32, i32)>(conn)?
+        .into_iter()
+        .collect::<HashSet<(i32, i32)>>();
+
+    // Devices which are removed from the user can no longer read the folders and notes encrypted for them
+    let device_folders = device_folders::table
+        .inner_join(folders::table)
+        .left_join(
+            user_devices::table.on(user_devices::user_id
+                .eq(folders::user_id)
+                .and(user_devices::device_id.eq(device_folders::receiver_device_id))),
+        )
+        .filter(user_devices::device_id.is_null())
+        .select((device_folders::receiver_device_id, device_folders::folder_id))
+        .load::<(i32, i32)>(conn)?
+        .into_iter()
+        .filter(|(device_id, folder_id)| !member_devices.contains(&(*folder_id, *device_id)))
+        .collect();
+
+    for (device_id, folder_ids) in group_by_device(device_folders) {
+        purged.device_folders += diesel::delete(device_folders::table)
+            .filter(device_folders::receiver_device_id.eq(device_id))
+            .filter(device_folders::folder_id.eq_any(folder_ids))
+            .execute(conn)?;
+    }
+    log_purged("device folders", purged.device_folders);
+
+    let device_notes = device_notes::table
+        .inner_join(notes::table.inner_join(folders::table))
+        .left_join(
+            user_devices::table.on(user_devices::user_id
+                .eq(folders::user_id)
+                .and(user_devices::device_id.eq(device_notes::receiver_device_id))),
+        )
+        .filter(user_devices::device_id.is_null())
+        .select((device_notes::receiver_device_id, device_notes::note_id, notes::folder_id))
+        .load::<(i32, i32, i32)>(conn)?
+        .into_iter()
+        .filter(|(device_id, _, folder_id)| !member_devices.contains(&(*folder_id, *device_id)))
+        .map(|(device_id, note_id, _)| (device_id, note_id))
+        .collect();
+
+    for (device_id, note_ids) in group_by_device(device_notes) {
+        purged.device_notes += diesel::delete(device_notes::table)
+            .filter(device_notes::receiver_device_id.eq(device_id))
+            .filter(device_notes::note_id.eq_any(note_ids))
+            .execute(conn)?;
+    }
+    log_purged("device notes", purged.device_notes);
+
+    let requested_at = now - config.request_retention;
+
+    purged.folder_requests = diesel::delete(folder_requests::table)
+        .filter(folder_requests::created_at.lt(requested_at))
+        .execute(conn)?;
+    log_purged("folder requests", purged.folder_requests);
+
+    purged.note_requests = diesel::delete(note_requests::table)
+        .filter(note_requests::created_at.lt(requested_at))
+        .execute(conn)?;
+    log_purged("note requests", purged.note_requests);
+
+    let pending_at = now - config.pending_retention;
+
+    purged.pending_users = diesel::delete(pending_users::table)
+        .filter(pending_users::updated_at.lt(pending_at))
+        .execute(conn)?;
+    log_purged("pending users", purged.pending_users);
+
+    purged.pending_devices = diesel::delete(pending_devices::table)
+        .filter(pending_devices::updated_at.lt(pending_at))
+        .execute(conn)?;
+    log_purged("pending devices", purged.pending_devices);
+
+    purged.pending_delete_users = diesel::delete(pending_delete_users::table)
+        .filter(pending_delete_users::updated_at.lt(pending_at))
+        .execute(conn)?;
+    log_purged("pending delete users", purged.pending_delete_users);
+
+    purged.previous_pubkeys = diesel::update(devices::table)
+        .filter(devices::rotated_at.lt(now - config.rotation_retention))
+        .filter(devices::previous_pubkey.is_not_null())
+        .set(devices::previous_pubkey.eq(None::<String>))
+        .execute(conn)?;
+    log_purged("previous pubkeys", purged.previous_pubkeys);
+
+    purged.idempotency_keys = diesel::delete(idempotency_keys::table)
+        .filter(idempotency_keys::created_at.lt(now - config.idempotency_retention))
+        .execute(conn)?;
+    log_purged("idempotency keys", purged.idempotency_keys);
+
+    Ok(purged)
+}
+
+/// Raises the purge horizons of the users to the sequences of the deletions that are about to be purged. Members of the
+/// shared folders follow the folder in their own sequence, which does not keep the sequence of the deletion, so theirs is
+/// raised to the latest change of the folder.
+fn raise_purged_seqs(conn: &mut PgConnection, deleted_at: NaiveDateTime) -> Result<(), diesel::result::Error> {
+    let deleted_notes = notes::table
+        .inner_join(folders::table)
+        .filter(notes::deleted_at.lt(deleted_at))
+        .select((notes::folder_id, folders::user_id, notes::change_seq))
+        .load::<(i32, i32, i64)>(conn)?;
+
+    let deleted_folders = folders::table
+        .filter(folders::deleted_at.lt(deleted_at))
+        .select((folders::id, folders::user_id, folders::change_seq))
+        .load::<(i32, i32, i64)>(conn)?;
+
+    let folder_ids = deleted_notes.iter().chain(&deleted_folders).map(|(folder_id, _, _)| *folder_id).collect::<HashSet<_>>();
+
+    let members = folder_members::table
+        .filter(folder_members::folder_id.eq_any(folder_ids))
+        .filter(folder_members::removed_at.is_null())
+        .select((folder_members::user_id, folder_members::change_seq))
+        .load::<(i32, i64)>(conn)?;
+
+    let removed_members = folder_members::table
+        .filter(folder_members::removed_at.lt(deleted_at))
+        .select((folder_members::user_id, folder_members::change_seq))
+        .load::<(i32, i64)>(conn)?;
+
+    let mut purged_seqs: HashMap<i32, i64> = HashMap::new();
+
+    for (user_id, seq) in deleted_notes
+        .into_iter()
+        .chain(deleted_folders)
+        .map(|(_, user_id, seq)| (user_id, seq))
+        .chain(members)
+        .chain(removed_members)
+    {
+        let purged_seq = purged_seqs.entry(user_id).or_default();
+        *purged_seq = (*purged_seq).max(seq);
+    }
+
+    for (user_id, purged_seq) in purged_seqs {
+        diesel::update(users::table)
+            .filter(users::id.eq(user_id))
+            .filter(users::purged_seq.lt(purged_seq))
+            .set(users::purged_seq.eq(purged_seq))
+            .execute(conn)?;
+    }
+
+    Ok(())
+}
+
+fn group_by_device(rows: Vec<(i32, i32)>) -> HashMap<i32, Vec<i32>> {
+    let mut groups: HashMap<i32, Vec<i32>> = HashMap::new();
+
+    for (device_id, id) in rows {
+        groups.entry(device_id).or_default().push(id);
+    }
+
+    groups
+}
+
+fn log_purged(name: &str, count: usize) {
+    if count > 0 {
+        log::info!("purged {count} {name}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base::schema::{
+        device_folders, device_notes, folder_requests, folders, note_requests, notes,
+        pending_users, user_devices, users,
+    };
+    use chrono::{Duration, Utc};
+    use diesel::{
+        prelude::*,
+        sql_types::{Integer, Nullable, Timestamp},
+    };
+    use test_helpers::db::create_pool;
+    use user::test::db::UserDeviceBuilder;
+
+    use super::{purge, Config, Purged};
+
+    #[test]
+    fn it_purges_only_rows_older_than_configured_ages_when_purge_is_called() {
+        let pool = create_pool();
+        let mut conn = pool.get().unwrap();
+
+        let now = Utc::now().naive_utc();
+
+        let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+        let removed_device = UserDeviceBuilder::default()
+            .user_id(device.user_id)
+            .pubkey("removed")
+            .build(&mut conn)
+            .unwrap();
+
+        diesel::delete(user_devices::table)
+            .filter(user_devices::device_id.eq(removed_device.device_id))
+            .execute(&mut conn)
+            .unwrap();
+
+        let folder_id = diesel::insert_into(folders::table)
+            .values(folders::user_id.eq(device.user_id))
+            .returning(folders::id)
+            .get_result::<i32>(&mut conn)
+            .unwrap();
+
+        let note_ids = diesel::insert_into(notes::table)
+            .values(&vec![
+                (notes::folder_id.eq(folder_id), notes::deleted_at.eq(None)),
+                (notes::folder_id.eq(folder_id), notes::deleted_at.eq(Some(now - Duration::days(91)))),
+                (notes::folder_id.eq(folder_id), notes::deleted_at.eq(Some(now - Duration::days(1)))),
+            ])
+            .returning(notes::id)
+            .get_results::<i32>(&mut conn)
+            .unwrap();
+
+        for receiver_device_id in [device.device_id, removed_device.device_id] {
+            diesel::insert_into(device_folders::table)
+                .values((
+                    device_folders::folder_id.eq(folder_id),
+                    device_folders::sender_device_id.eq(device.device_id),
+                    device_folders::receiver_device_id.eq(receiver_device_id),
+                    device_folders::name.eq("name"),
+                ))
+                .execute(&mut conn)
+                .unwrap();
+
+            diesel::insert_into(device_notes::table)
+                .values((
+                    device_notes::note_id.eq(note_ids[0]),
+                    device_notes::sender_device_id.eq(device.device_id),
+                    device_notes::receiver_device_id.eq(receiver_device_id),
+                    device_notes::name.eq("name"),
+                    device_notes::text.eq("text"),
+                ))
+                .execute(&mut conn)
+                .unwrap();
+        }
+
+        diesel::insert_into(folder_requests::table)
+            .values((
+                folder_requests::folder_id.eq(folder_id),
+                folder_requests::device_id.eq(device.device_id),
+                folder_requests::created_at.eq(now - Duration::days(31)),
+            ))
+            .execute(&mut conn)
+            .unwrap();
+
+        diesel::insert_into(note_requests::table)
+            .values((
+                note_requests::note_id.eq(note_ids[0]),
+                note_requests::device_id.eq(device.device_id),
+                note_requests::created_at.eq(now - Duration::days(1)),
+            ))
+            .execute(&mut conn)
+            .unwrap();
+
+        diesel::insert_into(pending_users::table)
+            .values(&vec![
+                (pending_users::email.eq("old@email.com"), pending_users::code.eq("code"), pending_users::updated_at.eq(now - Duration::days(2))),
+                (pending_users::email.eq("new@email.com"), pending_users::code.eq("code"), pending_users::updated_at.eq(now)),
+            ])
+            .execute(&mut conn)
+            .unwrap();
+
+        let purged = purge(&mut conn, &Config::default()).unwrap();
+
+        assert_eq!(
+            Purged {
+                deleted_notes: 1,
+                device_folders: 1,
+                device_notes: 1,
+                folder_requests: 1,
+                pending_users: 1,
+                ..Default::default()
+            },
+            purged
+        );
+
+        let remaining_note_ids = notes::table
+            .filter(notes::folder_id.eq(folder_id))
+            .order(notes::id)
+            .select(notes::id)
+            .load::<i32>(&mut conn)
+            .unwrap();
+
+        assert_eq!(vec![note_ids[0], note_ids[2]], remaining_note_ids);
+
+        let device_note_receivers = device_notes::table
+            .filter(device_notes::note_id.eq(note_ids[0]))
+            .select(device_notes::receiver_device_id)
+            .load::<i32>(&mut conn)
+            .unwrap();
+
+        assert_eq!(vec![device.device_id], device_note_receivers);
+    }
+
+    #[test]
+    fn it_keeps_ciphertexts_of_member_devices_and_purges_old_removed_members_when_purge_is_called() {
+        let pool = create_pool();
+        let mut conn = pool.get().unwrap();
+
+        let now = Utc::now().naive_utc();
+
+        let owner = UserDeviceBuilder::default().build(&mut conn).unwrap();
+        let member = UserDeviceBuilder::default()
+            .email("member@email.com")
+            .pubkey("member")
+            .build(&mut conn)
+            .unwrap();
+        let removed_member = UserDeviceBuilder::default()
+            .email("removed@email.com")
+            .pubkey("removed")
+            .build(&mut conn)
+            .unwrap();
+
+        let folder_id = diesel::insert_into(folders::table)
+            .values(folders::user_id.eq(owner.user_id))
+            .returning(folders::id)
+            .get_result::<i32>(&mut conn)
+            .unwrap();
+
+        for (user_id, removed_at) in [(member.user_id, None), (removed_member.user_id, Some(now - Duration::days(91)))] {
+            diesel::sql_query("insert into folder_members (folder_id, user_id, role, removed_at) values ($1, $2, 'Read', $3)")
+                .bind::<Integer, _>(folder_id)
+                .bind::<Integer, _>(user_id)
+                .bind::<Nullable<Timestamp>, _>(removed_at)
+                .execute(&mut conn)
+                .unwrap();
+        }
+
+        for receiver_device_id in [member.device_id, removed_member.device_id] {
+            diesel::insert_into(device_folders::table)
+                .values((
+                    device_folders::folder_id.eq(folder_id),
+                    device_folders::sender_device_id.eq(owner.device_id),
+                    device_folders::receiver_device_id.eq(receiver_device_id),
+                    device_folders::name.eq("name"),
+                ))
+                .execute(&mut conn)
+                .unwrap();
+        }
+
+        let purged = purge(&mut conn, &Config::default()).unwrap();
+
+        assert_eq!(Purged { removed_members: 1, device_folders: 1, ..Default::default() }, purged);
+
+        let device_folder_receivers = device_folders::table
+            .filter(device_folders::folder_id.eq(folder_id))
+            .select(device_folders::receiver_device_id)
+            .load::<i32>(&mut conn)
+            .unwrap();
+
+        assert_eq!(vec![member.device_id], device_folder_receivers);
+    }
+
+    #[test]
+    fn it_raises_purged_seq_of_user_to_seq_of_purged_deletion_when_purge_is_called() {
+        let pool = create_pool();
+        let mut conn = pool.get().unwrap();
+
+        let now = Utc::now().naive_utc();
+
+        let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+
+        let folder_id = diesel::insert_into(folders::table)
+            .values(folders::user_id.eq(device.user_id))
+            .returning(folders::id)
+            .get_result::<i32>(&mut conn)
+            .unwrap();
+
+        let deleted_seq = diesel::insert_into(notes::table)
+            .values((notes::folder_id.eq(folder_id), notes::deleted_at.eq(Some(now - Duration::days(91)))))
+            .returning(notes::change_seq)
+            .get_result::<i64>(&mut conn)
+            .unwrap();
+
+        diesel::insert_into(notes::table)
+            .values((notes::folder_id.eq(folder_id), notes::deleted_at.eq(Some(now - Duration::days(1)))))
+            .execute(&mut conn)
+            .unwrap();
+
+        purge(&mut conn, &Config::default()).unwrap();
+
+        let purged_seq = users::table
+            .filter(users::id.eq(device.user_id))
+            .select(users::purged_seq)
+            .first::<i64>(&mut conn)
+            .unwrap();
+
+        assert_eq!(deleted_seq, purged_seq);
+    }
+}
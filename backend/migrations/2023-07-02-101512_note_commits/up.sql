@@ -0,0 +1,13 @@
+create table note_commits(
+    note_id             int         not null,
+    commit              int         not null,
+    sender_device_id    int         not null,
+    receiver_device_id  int         not null,
+    name                text        not null,
+    text                text        not null,
+    created_at          timestamp   not null default current_timestamp,
+    primary key (note_id, commit, receiver_device_id),
+    constraint  fk_note_commits_note_id foreign key (note_id) references notes (id) on delete cascade on update no action,
+    constraint  fk_note_commits_sender_device_id foreign key (sender_device_id) references devices (id) on delete cascade on update no action,
+    constraint  fk_note_commits_receiver_device_id foreign key (receiver_device_id) references devices (id) on delete cascade on update no action
+);
@@ -0,0 +1,14 @@
+drop trigger device_notes_change_seq on device_notes;
+drop trigger device_folders_change_seq on device_folders;
+drop trigger notes_change_seq on notes;
+drop trigger folders_change_seq on folders;
+
+drop function device_notes_change_seq;
+drop function device_folders_change_seq;
+drop function notes_change_seq;
+drop function folders_change_seq;
+drop function next_change_seq;
+
+alter table notes drop column change_seq;
+alter table folders drop column change_seq;
+alter table users drop column change_seq;
@@ -0,0 +1,64 @@
+alter table users add column change_seq bigint not null default 0;
+alter table folders add column change_seq bigint not null default 0;
+alter table notes add column change_seq bigint not null default 0;
+
+-- Updating the users row also serializes the concurrent changes of the same user,
+-- so sequences are assigned in the order that changes are committed
+create function next_change_seq(user_id int) returns bigint as $$
+    update users set change_seq = change_seq + 1 where id = user_id returning change_seq;
+$$ language sql;
+
+create function folders_change_seq() returns trigger as $$
+begin
+    new.change_seq = next_change_seq(new.user_id);
+    return new;
+end;
+$$ language plpgsql;
+
+create function notes_change_seq() returns trigger as $$
+begin
+    new.change_seq = next_change_seq((select user_id from folders where id = new.folder_id));
+    return new;
+end;
+$$ language plpgsql;
+
+create function device_folders_change_seq() returns trigger as $$
+begin
+    update folders set change_seq = next_change_seq(user_id) where id = new.folder_id;
+    return null;
+end;
+$$ language plpgsql;
+
+create function device_notes_change_seq() returns trigger as $$
+begin
+    update notes set change_seq = next_change_seq((select user_id from folders where id = notes.folder_id)) where id = new.note_id;
+    return null;
+end;
+$$ language plpgsql;
+
+create trigger folders_change_seq
+    before insert or update of state
+    on folders
+    for each row
+execute procedure folders_change_seq();
+
+create trigger notes_change_seq
+    before insert or update of folder_id, commit, state
+    on notes
+    for each row
+execute procedure notes_change_seq();
+
+create trigger device_folders_change_seq
+    after insert or update
+    on device_folders
+    for each row
+execute procedure device_folders_change_seq();
+
+create trigger device_notes_change_seq
+    after insert or update
+    on device_notes
+    for each row
+execute procedure device_notes_change_seq();
+
+update folders set state = state;
+update notes set commit = commit;
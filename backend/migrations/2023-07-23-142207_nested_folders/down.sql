@@ -0,0 +1,11 @@
+drop trigger folders_change_seq on folders;
+
+create trigger folders_change_seq
+    before insert or update of state
+    on folders
+    for each row
+execute procedure folders_change_seq();
+
+drop index folders_parent_id;
+
+alter table folders drop column parent_id;
@@ -0,0 +1,12 @@
+alter table folders add column parent_id integer references folders(id);
+
+create index folders_parent_id on folders (parent_id);
+
+-- Reparenting a folder is a change that other devices need to pull
+drop trigger folders_change_seq on folders;
+
+create trigger folders_change_seq
+    before insert or update of state, parent_id
+    on folders
+    for each row
+execute procedure folders_change_seq();
@@ -0,0 +1,3 @@
+drop table device_attachments;
+drop table attachment_chunks;
+drop table attachments;
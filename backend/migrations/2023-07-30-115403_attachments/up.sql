@@ -0,0 +1,30 @@
+create table attachments(
+    id          serial      primary key,
+    note_id     int         not null,
+    device_id   int         not null,
+    name        text        not null,
+    chunk_count int         not null,
+    created_at  timestamp   not null default current_timestamp,
+    constraint  fk_attachments_note_id foreign key (note_id) references notes (id) on delete cascade on update no action,
+    constraint  fk_attachments_device_id foreign key (device_id) references devices (id) on delete cascade on update no action,
+    check (chunk_count > 0)
+);
+
+create table attachment_chunks(
+    attachment_id   int     not null,
+    chunk           int     not null,
+    data            bytea   not null,
+    primary key (attachment_id, chunk),
+    constraint  fk_attachment_chunks_attachment_id foreign key (attachment_id) references attachments (id) on delete cascade on update no action
+);
+
+create table device_attachments(
+    attachment_id       int     not null,
+    sender_device_id    int     not null,
+    receiver_device_id  int     not null,
+    key                 text    not null,
+    primary key (attachment_id, receiver_device_id),
+    constraint  fk_device_attachments_attachment_id foreign key (attachment_id) references attachments (id) on delete cascade on update no action,
+    constraint  fk_device_attachments_sender_device_id foreign key (sender_device_id) references devices (id) on delete cascade on update no action,
+    constraint  fk_device_attachments_receiver_device_id foreign key (receiver_device_id) references devices (id) on delete cascade on update no action
+);
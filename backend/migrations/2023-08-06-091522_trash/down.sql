@@ -0,0 +1,20 @@
+alter table notes drop column trashed_at;
+alter table folders drop column trashed_at;
+
+-- Enum values cannot be removed, the type is recreated without the trashed state
+update folders set state = 'Deleted' where state = 'Trashed';
+update notes set state = 'Deleted' where state = 'Trashed';
+
+alter type State rename to State_old;
+
+create type State as enum('Clean', 'Deleted');
+
+alter table folders alter column state drop default;
+alter table folders alter column state type State using state::text::State;
+alter table folders alter column state set default 'Clean';
+
+alter table notes alter column state drop default;
+alter table notes alter column state type State using state::text::State;
+alter table notes alter column state set default 'Clean';
+
+drop type State_old;
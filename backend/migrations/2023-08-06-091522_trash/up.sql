@@ -0,0 +1,4 @@
+alter type State add value 'Trashed';
+
+alter table folders add column trashed_at timestamp default null;
+alter table notes add column trashed_at timestamp default null;
@@ -0,0 +1,5 @@
+alter table note_requests drop column created_at;
+alter table folder_requests drop column created_at;
+
+alter table notes drop column deleted_at;
+alter table folders drop column deleted_at;
@@ -0,0 +1,9 @@
+alter table folders add column deleted_at timestamp default null;
+alter table notes add column deleted_at timestamp default null;
+
+-- Already deleted rows are kept for a whole retention period from now on
+update folders set deleted_at = current_timestamp where state = 'Deleted';
+update notes set deleted_at = current_timestamp where state = 'Deleted';
+
+alter table folder_requests add column created_at timestamp not null default current_timestamp;
+alter table note_requests add column created_at timestamp not null default current_timestamp;
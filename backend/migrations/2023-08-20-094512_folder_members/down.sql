@@ -0,0 +1,35 @@
+create or replace function folders_change_seq() returns trigger as $$
+begin
+    new.change_seq = next_change_seq(new.user_id);
+    return new;
+end;
+$$ language plpgsql;
+
+create or replace function notes_change_seq() returns trigger as $$
+begin
+    new.change_seq = next_change_seq((select user_id from folders where id = new.folder_id));
+    return new;
+end;
+$$ language plpgsql;
+
+create or replace function device_folders_change_seq() returns trigger as $$
+begin
+    update folders set change_seq = next_change_seq(user_id) where id = new.folder_id;
+    return null;
+end;
+$$ language plpgsql;
+
+create or replace function device_notes_change_seq() returns trigger as $$
+begin
+    update notes set change_seq = next_change_seq((select user_id from folders where id = notes.folder_id)) where id = new.note_id;
+    return null;
+end;
+$$ language plpgsql;
+
+drop trigger folder_members_change_seq on folder_members;
+drop function folder_members_change_seq;
+drop function touch_folder_members;
+
+drop table folder_members;
+
+drop type Role;
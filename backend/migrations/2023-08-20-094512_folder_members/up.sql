@@ -0,0 +1,68 @@
+create type Role as enum ('Read', 'Write');
+
+create table folder_members
+(
+    folder_id  integer   not null references folders (id) on delete cascade,
+    user_id    integer   not null references users (id) on delete cascade,
+    role       Role      not null,
+    change_seq bigint    not null default 0,
+    created_at timestamp not null default current_timestamp,
+    removed_at timestamp default null,
+    primary key (folder_id, user_id)
+);
+
+create index folder_members_user_id on folder_members (user_id);
+
+-- Sequences of different users are not comparable, so the members follow the changes of a shared folder
+-- in their own sequence
+create function touch_folder_members(shared_folder_id int) returns void as $$
+    update folder_members set change_seq = next_change_seq(user_id)
+    where folder_id = shared_folder_id and removed_at is null;
+$$ language sql;
+
+create function folder_members_change_seq() returns trigger as $$
+begin
+    new.change_seq = next_change_seq(new.user_id);
+    -- Devices of the owner pull the folder again to learn its members
+    update folders set change_seq = next_change_seq(user_id) where id = new.folder_id;
+    return new;
+end;
+$$ language plpgsql;
+
+create trigger folder_members_change_seq
+    before insert or update of role, removed_at
+    on folder_members
+    for each row
+execute procedure folder_members_change_seq();
+
+create or replace function folders_change_seq() returns trigger as $$
+begin
+    new.change_seq = next_change_seq(new.user_id);
+    perform touch_folder_members(new.id);
+    return new;
+end;
+$$ language plpgsql;
+
+create or replace function notes_change_seq() returns trigger as $$
+begin
+    new.change_seq = next_change_seq((select user_id from folders where id = new.folder_id));
+    perform touch_folder_members(new.folder_id);
+    return new;
+end;
+$$ language plpgsql;
+
+create or replace function device_folders_change_seq() returns trigger as $$
+begin
+    update folders set change_seq = next_change_seq(user_id) where id = new.folder_id;
+    perform touch_folder_members(new.folder_id);
+    return null;
+end;
+$$ language plpgsql;
+
+create or replace function device_notes_change_seq() returns trigger as $$
+begin
+    update notes set change_seq = next_change_seq((select user_id from folders where id = notes.folder_id)) where id = new.note_id;
+    perform touch_folder_members((select folder_id from notes where id = new.note_id));
+    return null;
+end;
+$$ language plpgsql;
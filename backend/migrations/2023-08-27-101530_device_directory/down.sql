@@ -0,0 +1,7 @@
+drop trigger user_devices_key_log on user_devices;
+drop function user_devices_key_log;
+drop function append_device_key_log;
+
+drop table device_key_log;
+
+drop table directory_opt_outs;
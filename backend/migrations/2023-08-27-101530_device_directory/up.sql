@@ -0,0 +1,53 @@
+-- Users that opted out are not listed in the device directory
+create table directory_opt_outs
+(
+    user_id    integer   not null primary key references users (id) on delete cascade,
+    created_at timestamp not null default current_timestamp
+);
+
+-- Append only log of the devices added to and removed from the users. Each entry is chained to the previous
+-- one of the same user, so the head hash commits to the whole history of the keys
+create table device_key_log
+(
+    id         serial primary key,
+    user_id    integer   not null references users (id) on delete cascade,
+    device_id  integer   not null,
+    pubkey     varchar   not null,
+    added      boolean   not null,
+    hash       varchar   not null,
+    created_at timestamp not null default current_timestamp
+);
+
+create index device_key_log_user_id on device_key_log (user_id);
+
+create function append_device_key_log(log_user_id int, log_device_id int, added boolean) returns void as $$
+    insert into device_key_log (user_id, device_id, pubkey, added, hash)
+    select log_user_id, devices.id, devices.pubkey, added,
+           encode(sha256(convert_to(
+               coalesce((select hash from device_key_log where user_id = log_user_id order by id desc limit 1), '')
+                   || ':' || devices.id || ':' || devices.pubkey || ':' || added::text,
+               'UTF8'
+           )), 'hex')
+    from devices
+    where devices.id = log_device_id;
+$$ language sql;
+
+create function user_devices_key_log() returns trigger as $$
+begin
+    if tg_op = 'INSERT' then
+        perform append_device_key_log(new.user_id, new.device_id, true);
+    else
+        perform append_device_key_log(old.user_id, old.device_id, false);
+    end if;
+
+    return null;
+end;
+$$ language plpgsql;
+
+create trigger user_devices_key_log
+    after insert or delete
+    on user_devices
+    for each row
+execute procedure user_devices_key_log();
+
+select append_device_key_log(user_id, device_id, true) from user_devices order by created_at, device_id;
@@ -0,0 +1,3 @@
+drop table note_contents;
+
+alter table device_folders drop column key;
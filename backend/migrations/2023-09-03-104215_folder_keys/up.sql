@@ -0,0 +1,19 @@
+-- Content key of the folder wrapped for the receiver device. Folders created before the keys were
+-- introduced have no key until one of the devices generates it.
+alter table device_folders add column key text default null;
+
+-- Notes encrypted once with the key of the folder they are in. Notes without a content at their
+-- current commit are still encrypted per device in device_notes.
+create table note_contents(
+    note_id             int         not null,
+    commit              int         not null,
+    folder_id           int         not null,
+    sender_device_id    int         not null,
+    name                text        not null,
+    text                text        not null,
+    created_at          timestamp   not null default current_timestamp,
+    primary key (note_id, commit),
+    constraint  fk_note_contents_note_id foreign key (note_id) references notes (id) on delete cascade on update no action,
+    constraint  fk_note_contents_folder_id foreign key (folder_id) references folders (id) on delete cascade on update no action,
+    constraint  fk_note_contents_sender_device_id foreign key (sender_device_id) references devices (id) on delete cascade on update no action
+);
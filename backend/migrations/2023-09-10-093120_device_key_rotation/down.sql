@@ -0,0 +1,5 @@
+drop trigger devices_key_log on devices;
+drop function devices_key_log;
+
+alter table devices drop column rotated_at;
+alter table devices drop column previous_pubkey;
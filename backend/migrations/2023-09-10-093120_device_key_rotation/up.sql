@@ -0,0 +1,32 @@
+-- Pubkey of the device before its last rotation. Other devices decrypt what the device encrypted before the rotation
+-- with it until the maintenance clears it.
+alter table devices add column previous_pubkey varchar default null;
+alter table devices add column rotated_at timestamp default null;
+
+create function devices_key_log() returns trigger as $$
+declare
+    log_user_id integer;
+begin
+    for log_user_id in select user_id from user_devices where device_id = new.id loop
+        insert into device_key_log (user_id, device_id, pubkey, added, hash)
+        select log_user_id, new.id, old.pubkey, false,
+               encode(sha256(convert_to(
+                   coalesce((select hash from device_key_log where user_id = log_user_id order by id desc limit 1), '')
+                       || ':' || new.id || ':' || old.pubkey || ':' || false::text,
+                   'UTF8'
+               )), 'hex');
+
+        perform append_device_key_log(log_user_id, new.id, true);
+    end loop;
+
+    return null;
+end;
+$$ language plpgsql;
+
+-- Rotations are logged as the removal of the previous key followed by the addition of the new one
+create trigger devices_key_log
+    after update of pubkey
+    on devices
+    for each row
+    when (old.pubkey is distinct from new.pubkey)
+execute procedure devices_key_log();
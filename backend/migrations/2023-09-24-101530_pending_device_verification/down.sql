@@ -0,0 +1,3 @@
+alter table pending_devices
+    drop column nonce,
+    drop column verifier_device_id;
@@ -0,0 +1,3 @@
+alter table pending_devices
+    add column verifier_device_id int4 references devices(id) on delete set null,
+    add column nonce varchar;
@@ -0,0 +1,2 @@
+drop table pending_recoveries;
+drop table recovery_bundles;
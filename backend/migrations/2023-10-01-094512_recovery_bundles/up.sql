@@ -0,0 +1,23 @@
+-- Identity key of a device encrypted with a key derived from a passphrase of the user, the server cannot decrypt it
+create table recovery_bundles
+(
+    user_id    integer   not null primary key references users (id) on delete cascade,
+    device_id  integer   not null references devices (id) on delete cascade,
+    salt       varchar   not null,
+    nonce      varchar   not null,
+    ciphertext text      not null,
+    created_at timestamp not null default current_timestamp
+);
+
+create table pending_recoveries
+(
+    user_id    integer    not null primary key references users (id) on delete cascade,
+    code       varchar(8) not null,
+    updated_at timestamp  not null default current_timestamp
+);
+
+create trigger pending_recoveries_updated_at
+    before update
+    on pending_recoveries
+    for each row
+execute procedure update_timestamp();
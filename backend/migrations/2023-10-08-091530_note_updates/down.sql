@@ -0,0 +1,11 @@
+drop trigger notes_change_seq on notes;
+
+create trigger notes_change_seq
+    before insert or update of folder_id, commit, state
+    on notes
+    for each row
+execute procedure notes_change_seq();
+
+alter table notes drop column update_id;
+
+drop table note_updates;
@@ -0,0 +1,24 @@
+-- Changes of the notes whose texts are kept as CRDT documents by the devices. Changes are encrypted with the folder
+-- key, server only stores and relays them, devices merge them in any order.
+create table note_updates
+(
+    id         serial    not null primary key,
+    note_id    integer   not null references notes (id) on delete cascade,
+    -- Folder whose key the update is encrypted with, note may be moved into another folder later
+    folder_id  integer   not null references folders (id) on delete cascade,
+    data       text      not null,
+    created_at timestamp not null default current_timestamp
+);
+
+create index note_updates_note_id on note_updates (note_id, id);
+
+-- Last update of the note, it is null for the notes which are not converted
+alter table notes add column update_id integer;
+
+drop trigger notes_change_seq on notes;
+
+create trigger notes_change_seq
+    before insert or update of folder_id, commit, state, update_id
+    on notes
+    for each row
+execute procedure notes_change_seq();
@@ -0,0 +1,14 @@
+-- Responses of the requests that are sent with an idempotency key. Devices replay their pending operations with the
+-- same key until they get a response, a replayed request gets the stored response instead of being applied again.
+create table idempotency_keys
+(
+    device_id  integer      not null references devices (id) on delete cascade,
+    key        varchar(64)  not null,
+    -- Base64 SHA-256 hash of the method, uri and body of the request, a key cannot be reused for another request
+    request    varchar(44)  not null,
+    -- Status and body are null while the request is being handled
+    status     integer,
+    body       text,
+    created_at timestamp    not null default current_timestamp,
+    primary key (device_id, key)
+);
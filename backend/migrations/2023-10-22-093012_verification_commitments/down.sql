@@ -0,0 +1,5 @@
+alter table pending_devices
+    drop column device_nonce,
+    drop column verifier_nonce,
+    drop column commitment,
+    add column nonce varchar;
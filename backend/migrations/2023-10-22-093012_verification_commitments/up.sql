@@ -0,0 +1,8 @@
+-- Verifications in progress cannot be completed without a commitment
+delete from pending_devices;
+
+alter table pending_devices
+    drop column nonce,
+    add column commitment varchar not null,
+    add column verifier_nonce varchar,
+    add column device_nonce varchar;
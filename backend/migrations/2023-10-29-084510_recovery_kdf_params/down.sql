@@ -0,0 +1,8 @@
+alter table pending_recoveries
+    drop column attempts;
+
+alter table recovery_bundles
+    drop column parallelism,
+    drop column time_cost,
+    drop column memory_cost,
+    drop column algorithm;
@@ -0,0 +1,15 @@
+-- Bundles stored before the parameters were kept are derived with the ones below
+alter table recovery_bundles
+    add column algorithm varchar not null default 'argon2id',
+    add column memory_cost int4 not null default 19456,
+    add column time_cost int4 not null default 2,
+    add column parallelism int4 not null default 1;
+
+alter table recovery_bundles
+    alter column algorithm drop default,
+    alter column memory_cost drop default,
+    alter column time_cost drop default,
+    alter column parallelism drop default;
+
+alter table pending_recoveries
+    add column attempts int4 not null default 0;
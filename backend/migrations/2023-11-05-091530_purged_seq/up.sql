@@ -0,0 +1,3 @@
+-- Sequence of the latest change whose row is purged. Devices which synced before it may have missed a purged deletion,
+-- so they pull all the folders and notes again.
+alter table users add column purged_seq bigint not null default 0;
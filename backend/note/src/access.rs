@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+
+use diesel::{dsl, prelude::*, PgConnection};
+
+use base::schema::{folder_members, folders, user_devices};
+use notify::ws::{messages::{DeviceMessage, SendExclusiveDeviceMessage}, AddrServer};
+use user::models::UserDevice;
+
+use crate::models::Role;
+
+/// Folders which are shared with the user in one of the given roles
+#[dsl::auto_type]
+pub fn member_folder_ids(user_id: i32, roles: Vec<Role>) -> _ {
+    folder_members::table
+        .filter(folder_members::user_id.eq(user_id))
+        .filter(folder_members::role.eq_any(roles))
+        .filter(folder_members::removed_at.is_null())
+        .select(folder_members::folder_id)
+}
+
+/// Filters the folders that the user owns or can read as a member
+#[dsl::auto_type]
+pub fn readable(user_id: i32) -> _ {
+    let shared: member_folder_ids = member_folder_ids(user_id, vec![Role::Read, Role::Write]);
+    folders::user_id.eq(user_id).or(folders::id.eq_any(shared))
+}
+
+/// Filters the folders that the user owns or can write as a member
+#[dsl::auto_type]
+pub fn writable(user_id: i32) -> _ {
+    let shared: member_folder_ids = member_folder_ids(user_id, vec![Role::Write]);
+    folders::user_id.eq(user_id).or(folders::id.eq_any(shared))
+}
+
+/// Returns the active members of the given folders with their roles
+pub fn folder_members(conn: &mut PgConnection, folder_ids: &[i32]) -> QueryResult<HashMap<i32, Vec<(i32, Role)>>> {
+    let members = folder_members::table
+        .filter(folder_members::folder_id.eq_any(folder_ids))
+        .filter(folder_members::removed_at.is_null())
+        .select((folder_members::folder_id, folder_members::user_id, folder_members::role))
+        .load::<(i32, i32, Role)>(conn)?;
+
+    let mut folders: HashMap<i32, Vec<(i32, Role)>> = HashMap::new();
+
+    for (folder_id, user_id, role) in members {
+        folders.entry(folder_id).or_default().push((user_id, role));
+    }
+
+    Ok(folders)
+}
+
+/// Returns the owner and the active members of the folder
+pub fn folder_user_ids(conn: &mut PgConnection, folder_id: i32) -> QueryResult<Vec<i32>> {
+    let owner_id = folders::table
+        .filter(folders::id.eq(folder_id))
+        .select(folders::user_id)
+        .first::<i32>(conn)?;
+
+    let member_ids = folder_members::table
+        .filter(folder_members::folder_id.eq(folder_id))
+        .filter(folder_members::removed_at.is_null())
+        .select(folder_members::user_id)
+        .load::<i32>(conn)?;
+
+    Ok(std::iter::once(owner_id).chain(member_ids).collect())
+}
+
+/// Returns the devices that the folder and its notes are encrypted for, except the given device
+pub fn folder_device_ids(conn: &mut PgConnection, folder_id: i32, device_id: i32) -> QueryResult<Vec<i32>> {
+    let user_ids = folder_user_ids(conn, folder_id)?;
+
+    user_devices::table
+        .filter(user_devices::user_id.eq_any(user_ids))
+        .filter(user_devices::device_id.ne(device_id))
+        .select(user_devices::device_id)
+        .load::<i32>(conn)
+}
+
+/// Sends the message to the devices of all the users who can access the folder
+pub fn notify_folder(
+    conn: &mut PgConnection,
+    ws_server: &AddrServer,
+    folder_id: i32,
+    device: &UserDevice,
+    message: DeviceMessage,
+) -> QueryResult<()> {
+    for user_id in folder_user_ids(conn, folder_id)? {
+        ws_server.do_send(SendExclusiveDeviceMessage {
+            user_id,
+            excluded_device_id: device.device_id,
+            message: message.clone(),
+        });
+    }
+
+    Ok(())
+}
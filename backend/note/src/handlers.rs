@@ -1,29 +1,40 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use actix_web::{
-    delete, get, post, put,
-    web::{block, Data, Json, Path, Query},
+    delete, get, http::StatusCode, post, put,
+    web::{block, Bytes, Data, Json, Path, Query},
+    HttpResponse,
 };
-use diesel::prelude::*;
+use chrono::{NaiveDateTime, Utc};
+use diesel::{prelude::*, PgConnection};
 
 use base::{
     sanitize::Sanitized,
     schema::{
-        device_folders, device_notes, folder_requests, folders, note_requests, notes, user_devices
+        attachment_chunks, attachments, device_attachments, device_folders, device_notes, devices,
+        folder_members, folder_requests, folders, note_commits, note_contents, note_requests, note_updates, notes,
+        user_devices, users,
     },
     types::Pool,
     HttpError, HttpMessage,
 };
 use notify::ws::messages::{SendDeviceMessage, DeviceMessage, SendExclusiveDeviceMessage};
-use user::models::UserDevice;
+use user::{models::UserDevice, quota::{self, Quota}};
 
 use crate::{
+    access,
     models::{Folder, Note, State},
     requests::{
-        CreateFolderRequest, CreateNoteRequest, CreateRequests, FolderId, RespondRequests,
-        UpdateNoteRequest,
+        AddFolderMemberRequest, CreateAttachmentRequest, CreateFolderRequest, CreateNoteRequest, CreateRequests, FolderId,
+        MoveFolderRequest, MoveNoteRequest, ParentId, RespondRequests, Since, UpdateNoteRequest, After,
+        CreateNoteUpdateRequest,
+    },
+    responses::{
+        self, Attachment, Commit, CommitMismatch, CreatedAttachment, CreatedFolder, CreatedNote,
+        DeviceAttachment, DeviceFolder, DeviceNoteCommit, FolderDevice, FolderMember, FolderRequest,
+        NoteCommit, NoteRequest, NoteUpdate,
+        Requests, CreatedNoteUpdate,
     },
-    responses::{self, Commit, CreatedFolder, CreatedNote, FolderRequest, NoteRequest, Requests, DeviceFolder},
 };
 
 #[get("folders")]
@@ -35,7 +46,7 @@ pub async fn fetch_folders(
         let mut conn = pool.get().unwrap();
 
         let folders = folders::table
-            .filter(folders::user_id.eq(device.user_id))
+            .filter(access::readable(device.user_id))
             .left_join(
                 device_folders::table.on(device_folders::folder_id
                     .eq(folders::id)
@@ -43,36 +54,21 @@ pub async fn fetch_folders(
             )
             .select((
                 folders::id,
+                folders::user_id,
+                folders::parent_id,
                 folders::state,
-                (device_folders::sender_device_id, device_folders::name).nullable(),
+                folders::change_seq,
+                (device_folders::sender_device_id, device_folders::name, device_folders::key).nullable(),
             ))
-            .load::<(i32, State, Option<DeviceFolder>)>(&mut conn)?;
+            .load::<FolderRow>(&mut conn)?;
 
         let commits = notes::table
             .filter(notes::folder_id.eq_any(folders.iter().map(|f| f.0)))
             .order(notes::id.desc())
-            .select((notes::id, notes::folder_id, notes::commit, notes::state))
-            .load::<(i32, i32, i32, State)>(&mut conn)?;
-
-        let folders_with_commits = folders
-            .into_iter()
-            .map(|folder| {
-                let folder_commits = commits
-                    .iter()
-                    .filter(|c| c.1 == folder.0)
-                    .map(|c| Commit { note_id: c.0, commit: c.2, state: c.3.clone() })
-                    .collect();
-
-                responses::Folder {
-                    id: folder.0,
-                    state: folder.1,
-                    device_folder: folder.2,
-                    commits: folder_commits,
-                }
-            })
-            .collect();
+            .select((notes::id, notes::folder_id, notes::commit, notes::state, notes::update_id))
+            .load::<CommitRow>(&mut conn)?;
 
-        Result::<Vec<responses::Folder>, HttpError>::Ok(folders_with_commits)
+        folder_responses(&mut conn, device.user_id, folders, &commits).map_err(HttpError::from)
     })
     .await??;
 
@@ -88,7 +84,7 @@ pub async fn fetch_folder(
         let mut conn = pool.get().unwrap();
 
         let Some(folder) = folders::table
-            .filter(folders::user_id.eq(device.user_id))
+            .filter(access::readable(device.user_id))
             .filter(folders::id.eq(folder_id.into_inner()))
             .left_join(
                 device_folders::table.on(device_folders::folder_id
@@ -97,10 +93,13 @@ pub async fn fetch_folder(
             )
             .select((
                 folders::id,
+                folders::user_id,
+                folders::parent_id,
                 folders::state,
-                (device_folders::sender_device_id, device_folders::name).nullable(),
+                folders::change_seq,
+                (device_folders::sender_device_id, device_folders::name, device_folders::key).nullable(),
             ))
-            .first::<(i32, State, Option<DeviceFolder>)>(&mut conn)
+            .first::<FolderRow>(&mut conn)
             .optional()? else {
                 return Result::<Option<responses::Folder>, HttpError>::Ok(None);
             };
@@ -108,29 +107,119 @@ pub async fn fetch_folder(
         let commits = notes::table
             .filter(notes::folder_id.eq(folder.0))
             .order(notes::id.desc())
-            .select((notes::id, notes::commit, notes::state))
-            .load::<Commit>(&mut conn)?;
-
-        Ok(Some(responses::Folder {
-            id: folder.0,
-            state: folder.1,
-            device_folder: folder.2,
-            commits,
-        }))
+            .select((notes::id, notes::folder_id, notes::commit, notes::state, notes::update_id))
+            .load::<CommitRow>(&mut conn)?;
+
+        Ok(folder_responses(&mut conn, device.user_id, vec![folder], &commits)?.pop())
     })
     .await??;
 
     Ok(Json(folder))
 }
 
-#[post("folder")]
+pub async fn fetch_changes(
+    pool: Data<Pool>,
+    device: UserDevice,
+    query: Query<Since>,
+) -> Result<Json<responses::Changes>, HttpError> {
+    let since = query.since;
+
+    let changes = block(move || -> Result<responses::Changes, HttpError> {
+        let mut conn = pool.get().unwrap();
+
+        // Cursor is read before the changes. A change committed in between will be returned again
+        // on the next call, which is fine since applying the same change twice is harmless.
+        let (cursor, purged_seq) = users::table
+            .filter(users::id.eq(device.user_id))
+            .select((users::change_seq, users::purged_seq))
+            .first::<(i64, i64)>(&mut conn)?;
+
+        // Deletions which are purged after the cursor cannot be returned anymore, so everything is returned instead
+        let resync = since < purged_seq;
+        let since = if resync { 0 } else { since };
+
+        // Changes of the shared folders are tracked per membership in the sequence of this user
+        let (removed_folder_ids, shared_folder_ids): (Vec<_>, Vec<_>) = folder_members::table
+            .filter(folder_members::user_id.eq(device.user_id))
+            .filter(folder_members::change_seq.gt(since))
+            .select((folder_members::folder_id, folder_members::change_seq, folder_members::removed_at.is_not_null()))
+            .load::<(i32, i64, bool)>(&mut conn)?
+            .into_iter()
+            .partition(|(_, _, removed)| *removed);
+
+        let shared_folder_ids = shared_folder_ids.into_iter().map(|(id, _, _)| id).collect::<Vec<_>>();
+
+        // All the notes of a changed folder are returned since the folder may just become visible
+        // to this device
+        let commits = notes::table
+            .inner_join(folders::table)
+            .filter(
+                folders::user_id
+                    .eq(device.user_id)
+                    .and(notes::change_seq.gt(since).or(folders::change_seq.gt(since)))
+                    .or(notes::folder_id.eq_any(&shared_folder_ids)),
+            )
+            .order(notes::id.desc())
+            .select((notes::id, notes::folder_id, notes::commit, notes::state, notes::update_id))
+            .load::<CommitRow>(&mut conn)?;
+
+        let folders = folders::table
+            .filter(
+                folders::user_id
+                    .eq(device.user_id)
+                    .and(
+                        folders::change_seq
+                            .gt(since)
+                            .or(folders::id.eq_any(commits.iter().map(|c| c.1).collect::<Vec<i32>>())),
+                    )
+                    .or(folders::id.eq_any(&shared_folder_ids)),
+            )
+            .left_join(
+                device_folders::table.on(device_folders::folder_id
+                    .eq(folders::id)
+                    .and(device_folders::receiver_device_id.eq(device.device_id))),
+            )
+            .select((
+                folders::id,
+                folders::user_id,
+                folders::parent_id,
+                folders::state,
+                folders::change_seq,
+                (device_folders::sender_device_id, device_folders::name, device_folders::key).nullable(),
+            ))
+            .load::<FolderRow>(&mut conn)?;
+
+        let mut folders = folder_responses(&mut conn, device.user_id, folders, &commits)?;
+
+        // Folders that the user is removed from are deleted on the devices of the user
+        folders.extend(removed_folder_ids.into_iter().map(|(id, seq, _)| responses::Folder {
+            id,
+            seq,
+            parent_id: None,
+            state: State::Deleted,
+            shared: false,
+            role: None,
+            device_folder: None,
+            keyed: false,
+            commits: Vec::new(),
+        }));
+
+        Ok(responses::Changes { cursor, folders, resync })
+    })
+    .await??;
+
+    Ok(Json(changes))
+}
+
 pub async fn create_folder(
     pool: Data<Pool>,
+    quota: Data<Quota>,
+    query: Query<ParentId>,
     request: Sanitized<Json<Vec<CreateFolderRequest>>>,
     device: UserDevice,
     ws_server: Data<notify::ws::AddrServer>,
 ) -> Result<Json<CreatedFolder>, HttpError> {
-    let created_folder = block(move || {
+    let created_folder = block(move || -> Result<CreatedFolder, HttpError> {
         let device_folders_to_create = request.0 .0;
 
         let mut conn = pool.get().unwrap();
@@ -141,39 +230,49 @@ pub async fn create_folder(
             .select(user_devices::device_id)
             .load::<i32>(&mut conn)?;
 
-        let device_not_exist_in_request = device_folders_to_create
-            .iter()
-            .find(|device_folder| {
-                device_ids
-                    .iter()
-                    .find(|id| **id == device_folder.device_id)
-                    .is_none()
-            })
-            .is_some();
+        let omitted_ids = omitted_devices(&device_ids, device_folders_to_create.iter().map(|d| d.device_id))?;
 
-        if device_ids.len() != device_folders_to_create.iter().map(|d| d.device_id).collect::<HashSet<i32>>().len() || device_not_exist_in_request {
-            return Err(HttpError::unprocessable_entity("devices_mismatch"));
+        if let Some(parent_id) = query.parent_id {
+            folders::table
+                .filter(folders::id.eq(parent_id))
+                .filter(folders::state.eq(State::Clean))
+                .filter(folders::user_id.eq(device.user_id))
+                .select(folders::id)
+                .first::<i32>(&mut conn)?;
         }
 
-        let folder: Folder = diesel::insert_into(folders::table)
-            .values(folders::user_id.eq(device.user_id))
-            .get_result(&mut conn)?;
+        let folder = conn.transaction(|conn| -> Result<Folder, HttpError> {
+            quota::lock(conn, device.user_id)?;
+
+            let usage = quota::usage(conn, device.user_id)?;
+            quota.check_folders(usage.folders + 1)?;
+            quota.check_bytes(usage.bytes + device_folders_to_create.iter().map(|f| f.name.len() as i64).sum::<i64>())?;
+
+            let folder: Folder = diesel::insert_into(folders::table)
+                .values((folders::user_id.eq(device.user_id), folders::parent_id.eq(query.parent_id)))
+                .get_result(conn)?;
+
+            diesel::insert_into(device_folders::table)
+                .values(
+                    device_folders_to_create
+                        .into_iter()
+                        .map(|folder_to_create| {
+                            (
+                                device_folders::folder_id.eq(folder.id),
+                                device_folders::receiver_device_id.eq(folder_to_create.device_id),
+                                device_folders::sender_device_id.eq(device.device_id),
+                                device_folders::name.eq(folder_to_create.name),
+                                device_folders::key.eq(folder_to_create.key),
+                            )
+                        })
+                        .collect::<Vec<_>>(),
+                )
+                .execute(conn)?;
 
-        diesel::insert_into(device_folders::table)
-            .values(
-                device_folders_to_create
-                    .into_iter()
-                    .map(|folder_to_create| {
-                        (
-                            device_folders::folder_id.eq(folder.id),
-                            device_folders::receiver_device_id.eq(folder_to_create.device_id),
-                            device_folders::sender_device_id.eq(device.device_id),
-                            device_folders::name.eq(folder_to_create.name),
-                        )
-                    })
-                    .collect::<Vec<_>>(),
-            )
-            .execute(&mut conn)?;
+            request_device_folders(conn, folder.id, &omitted_ids)?;
+
+            Ok(folder)
+        })?;
 
         ws_server.do_send(SendExclusiveDeviceMessage {
             user_id: device.user_id,
@@ -188,35 +287,114 @@ pub async fn create_folder(
     Ok(Json(created_folder))
 }
 
-#[delete("folder/{folder_id}")]
-pub async fn delete_folder(
+#[put("folder/{folder_id}")]
+pub async fn rename_folder(
     pool: Data<Pool>,
     folder_id: Path<i32>,
+    request: Sanitized<Json<Vec<CreateFolderRequest>>>,
     device: UserDevice,
     ws_server: Data<notify::ws::AddrServer>,
 ) -> Result<Json<HttpMessage>, HttpError> {
     block(move || -> Result<(), HttpError> {
+        let device_folders_to_update = request.0 .0;
+
         let mut conn = pool.get().unwrap();
 
         let folder_id = folders::table
             .filter(folders::id.eq(folder_id.into_inner()))
             .filter(folders::state.eq(State::Clean))
-            .filter(folders::user_id.eq(device.user_id))
+            .filter(access::writable(device.user_id))
             .select(folders::id)
             .first::<i32>(&mut conn)?;
 
-        diesel::update(folders::table)
-            .filter(folders::id.eq(folder_id))
-            .set(folders::state.eq(State::Deleted))
-            .execute(&mut conn)?;
+        let device_ids = access::folder_device_ids(&mut conn, folder_id, device.device_id)?;
 
-        diesel::delete(notes::table)
-            .filter(notes::folder_id.eq(folder_id))
-            .execute(&mut conn)?;
+        let omitted_ids = omitted_devices(&device_ids, device_folders_to_update.iter().map(|d| d.device_id))?;
 
-        diesel::delete(device_folders::table)
-            .filter(device_folders::folder_id.eq(folder_id))
-            .execute(&mut conn)?;
+        conn.transaction(|conn| -> Result<(), diesel::result::Error> {
+            for device_folder in &device_folders_to_update {
+                diesel::insert_into(device_folders::table)
+                    .values((
+                        device_folders::folder_id.eq(folder_id),
+                        device_folders::receiver_device_id.eq(device_folder.device_id),
+                        device_folders::sender_device_id.eq(device.device_id),
+                        device_folders::name.eq(&device_folder.name),
+                        device_folders::key.eq(&device_folder.key),
+                    ))
+                    .on_conflict((device_folders::folder_id, device_folders::receiver_device_id))
+                    .do_update()
+                    .set((
+                        device_folders::sender_device_id.eq(device.device_id),
+                        device_folders::name.eq(&device_folder.name),
+                        device_folders::key.eq(&device_folder.key),
+                    ))
+                    .execute(conn)?;
+            }
+
+            // Remove this device folder since it holds the old name, as well as the ones of the omitted devices
+            diesel::delete(device_folders::table)
+                .filter(device_folders::folder_id.eq(folder_id))
+                .filter(device_folders::receiver_device_id.eq(device.device_id).or(device_folders::receiver_device_id.eq_any(&omitted_ids)))
+                .execute(conn)?;
+
+            request_device_folders(conn, folder_id, &omitted_ids)
+        })?;
+
+        access::notify_folder(&mut conn, &ws_server, folder_id, &device, DeviceMessage::RefreshFolder(folder_id))?;
+
+        Ok(())
+    })
+    .await??;
+
+    Ok(Json(HttpMessage::success()))
+}
+
+pub async fn move_folder(
+    pool: Data<Pool>,
+    folder_id: Path<i32>,
+    request: Sanitized<Json<MoveFolderRequest>>,
+    device: UserDevice,
+    ws_server: Data<notify::ws::AddrServer>,
+) -> Result<Json<HttpMessage>, HttpError> {
+    block(move || -> Result<(), HttpError> {
+        let mut conn = pool.get().unwrap();
+
+        let folder_id = conn.transaction(|conn| -> Result<i32, HttpError> {
+            // Locking the user serializes the moves of its folders, otherwise two concurrent moves
+            // could pass the cycle check and create a cycle together
+            users::table
+                .filter(users::id.eq(device.user_id))
+                .select(users::id)
+                .for_update()
+                .first::<i32>(conn)?;
+
+            let folder_id = folders::table
+                .filter(folders::id.eq(folder_id.into_inner()))
+                .filter(folders::state.eq(State::Clean))
+                .filter(folders::user_id.eq(device.user_id))
+                .select(folders::id)
+                .first::<i32>(conn)?;
+
+            if let Some(parent_id) = request.parent_id {
+                if folder_subtree(conn, device.user_id, folder_id)?.contains(&parent_id) {
+                    return Err(HttpError::unprocessable_entity("folder_cycle"));
+                }
+
+                folders::table
+                    .filter(folders::id.eq(parent_id))
+                    .filter(folders::state.eq(State::Clean))
+                    .filter(folders::user_id.eq(device.user_id))
+                    .select(folders::id)
+                    .first::<i32>(conn)?;
+            }
+
+            diesel::update(folders::table)
+                .filter(folders::id.eq(folder_id))
+                .set(folders::parent_id.eq(request.parent_id))
+                .execute(conn)?;
+
+            Ok(folder_id)
+        })?;
 
         ws_server.do_send(SendExclusiveDeviceMessage {
             user_id: device.user_id,
@@ -231,72 +409,172 @@ pub async fn delete_folder(
     Ok(Json(HttpMessage::success()))
 }
 
+pub async fn delete_folder(
+    pool: Data<Pool>,
+    folder_id: Path<i32>,
+    device: UserDevice,
+    ws_server: Data<notify::ws::AddrServer>,
+) -> Result<Json<HttpMessage>, HttpError> {
+    block(move || -> Result<(), HttpError> {
+        let mut conn = pool.get().unwrap();
+
+        let folder_ids = conn.transaction(|conn| -> Result<Vec<i32>, HttpError> {
+            // Moves of the folders are serialized by the user, so a subfolder cannot be moved out of the subtree
+            // while it is being trashed
+            users::table
+                .filter(users::id.eq(device.user_id))
+                .select(users::id)
+                .for_update()
+                .first::<i32>(conn)?;
+
+            let folder_id = folders::table
+                .filter(folders::id.eq(folder_id.into_inner()))
+                .filter(folders::state.eq(State::Clean))
+                .filter(folders::user_id.eq(device.user_id))
+                .select(folders::id)
+                .first::<i32>(conn)?;
+
+            let folder_ids = folder_subtree(conn, device.user_id, folder_id)?;
+
+            // Notes and device folders are kept so that the folders can be restored from the trash
+            diesel::update(folders::table)
+                .filter(folders::id.eq_any(&folder_ids))
+                .set((folders::state.eq(State::Trashed), folders::trashed_at.eq(Utc::now().naive_utc())))
+                .execute(conn)?;
+
+            Ok(folder_ids)
+        })?;
+
+        for folder_id in folder_ids {
+            access::notify_folder(&mut conn, &ws_server, folder_id, &device, DeviceMessage::RefreshFolder(folder_id))?;
+        }
+
+        Ok(())
+    })
+    .await??;
+
+    Ok(Json(HttpMessage::success()))
+}
+
+pub async fn restore_folder(
+    pool: Data<Pool>,
+    folder_id: Path<i32>,
+    device: UserDevice,
+    ws_server: Data<notify::ws::AddrServer>,
+) -> Result<Json<HttpMessage>, HttpError> {
+    block(move || -> Result<(), HttpError> {
+        let mut conn = pool.get().unwrap();
+
+        let (folder_id, parent_id, trashed_at) = folders::table
+            .filter(folders::id.eq(folder_id.into_inner()))
+            .filter(folders::state.eq(State::Trashed))
+            .filter(folders::user_id.eq(device.user_id))
+            .select((folders::id, folders::parent_id, folders::trashed_at))
+            .first::<(i32, Option<i32>, Option<NaiveDateTime>)>(&mut conn)?;
+
+        // Only the subfolders trashed alongside the folder are restored, the ones trashed before stay in the trash
+        let folders = folders::table
+            .filter(folders::user_id.eq(device.user_id))
+            .filter(folders::state.eq(State::Trashed))
+            .filter(folders::trashed_at.is_not_distinct_from(trashed_at))
+            .select((folders::id, folders::parent_id))
+            .load::<(i32, Option<i32>)>(&mut conn)?;
+
+        let folder_ids = subtree(&folders, folder_id);
+
+        // Folder is restored to the top level if its parent is not available anymore
+        let parent_exists = match parent_id {
+            Some(parent_id) => folders::table
+                .filter(folders::id.eq(parent_id))
+                .filter(folders::state.eq(State::Clean))
+                .select(folders::id)
+                .first::<i32>(&mut conn)
+                .optional()?
+                .is_some(),
+            None => true,
+        };
+
+        conn.transaction(|conn| -> Result<(), diesel::result::Error> {
+            if !parent_exists {
+                diesel::update(folders::table)
+                    .filter(folders::id.eq(folder_id))
+                    .set(folders::parent_id.eq(None::<i32>))
+                    .execute(conn)?;
+            }
+
+            diesel::update(folders::table)
+                .filter(folders::id.eq_any(&folder_ids))
+                .set((folders::state.eq(State::Clean), folders::trashed_at.eq(None::<NaiveDateTime>)))
+                .execute(conn)
+                .map(|_| ())
+        })?;
+
+        for folder_id in folder_ids {
+            access::notify_folder(&mut conn, &ws_server, folder_id, &device, DeviceMessage::RefreshFolder(folder_id))?;
+        }
+
+        Ok(())
+    })
+    .await??;
+
+    Ok(Json(HttpMessage::success()))
+}
+
 #[post("/note")]
 pub async fn create_note(
     pool: Data<Pool>,
+    quota: Data<Quota>,
     query: Query<FolderId>,
-    request: Sanitized<Json<Vec<CreateNoteRequest>>>,
+    request: Sanitized<Json<CreateNoteRequest>>,
     device: UserDevice,
     ws_server: Data<notify::ws::AddrServer>,
 ) -> Result<Json<CreatedNote>, HttpError> {
-    let note = block(move || {
-        let notes_to_create = request.0 .0;
+    let note = block(move || -> Result<CreatedNote, HttpError> {
+        let note_to_create = request.0 .0;
 
         let mut conn = pool.get().unwrap();
 
-        let folder_id = folders::table
+        let (folder_id, owner_id) = folders::table
             .filter(folders::id.eq(query.folder_id))
             .filter(folders::state.eq(State::Clean))
-            .filter(folders::user_id.eq(device.user_id))
-            .select(folders::id)
-            .first::<i32>(&mut conn)?;
+            .filter(access::writable(device.user_id))
+            .select((folders::id, folders::user_id))
+            .first::<(i32, i32)>(&mut conn)?;
 
-        let device_ids = user_devices::table
-            .filter(user_devices::user_id.eq(device.user_id))
-            .filter(user_devices::device_id.ne(device.device_id))
-            .select(user_devices::device_id)
-            .load::<i32>(&mut conn)?;
+        quota.check_note_size(&note_to_create.name, &note_to_create.text)?;
 
-        let device_not_exist_in_request = notes_to_create
-            .iter()
-            .find(|note_to_create| {
-                device_ids
-                    .iter()
-                    .find(|id| **id == note_to_create.device_id)
-                    .is_none()
-            })
-            .is_some();
+        let note = conn.transaction(|conn| -> Result<Note, HttpError> {
+            // Notes of a shared folder count against the quota of the folder owner
+            quota::lock(conn, owner_id)?;
+            let usage = quota::usage(conn, owner_id)?;
+            quota.check_notes(usage.notes + 1)?;
+            quota.check_bytes(usage.bytes + (note_to_create.name.len() + note_to_create.text.len()) as i64)?;
 
-        if device_ids.len() != notes_to_create.len() || device_not_exist_in_request {
-            return Err(HttpError::unprocessable_entity("devices_mismatch"));
-        }
+            let note: Note = diesel::insert_into(notes::table)
+                .values((notes::folder_id.eq(folder_id),))
+                .get_result(conn)?;
 
-        let note: Note = diesel::insert_into(notes::table)
-            .values((notes::folder_id.eq(folder_id),))
-            .get_result(&mut conn)?;
+            diesel::insert_into(note_contents::table)
+                .values((
+                    note_contents::note_id.eq(note.id),
+                    note_contents::commit.eq(note.commit),
+                    note_contents::folder_id.eq(folder_id),
+                    note_contents::sender_device_id.eq(device.device_id),
+                    note_contents::name.eq(&note_to_create.name),
+                    note_contents::text.eq(&note_to_create.text),
+                ))
+                .execute(conn)?;
 
-        diesel::insert_into(device_notes::table)
-            .values(
-                notes_to_create
-                    .into_iter()
-                    .map(|note_to_create| {
-                        (
-                            device_notes::note_id.eq(note.id),
-                            device_notes::receiver_device_id.eq(note_to_create.device_id),
-                            device_notes::sender_device_id.eq(device.device_id),
-                            device_notes::name.eq(note_to_create.name),
-                            device_notes::text.eq(note_to_create.text),
-                        )
-                    })
-                    .collect::<Vec<_>>(),
-            )
-            .execute(&mut conn)?;
+            Ok(note)
+        })?;
 
-        ws_server.do_send(SendExclusiveDeviceMessage {
-            user_id: device.user_id,
-            excluded_device_id: device.device_id,
-            message: DeviceMessage::RefreshNote { folder_id: note.folder_id, note_id: note.id, commit: note.commit, deleted: false }
-        });
+        access::notify_folder(
+            &mut conn,
+            &ws_server,
+            folder_id,
+            &device,
+            DeviceMessage::RefreshNote { folder_id, note_id: note.id, commit: note.commit, deleted: false },
+        )?;
 
         Ok(CreatedNote {
             id: note.id,
@@ -317,13 +595,18 @@ pub async fn fetch_note(
     let note = block(move || {
         notes::table
             .filter(notes::id.eq(note_id.into_inner()))
-            .filter(folders::user_id.eq(device.user_id))
+            .filter(access::readable(device.user_id))
             .inner_join(folders::table)
             .left_join(
                 device_notes::table.on(device_notes::note_id
                     .eq(notes::id)
                     .and(device_notes::receiver_device_id.eq(device.device_id))),
             )
+            .left_join(
+                note_contents::table.on(note_contents::note_id
+                    .eq(notes::id)
+                    .and(note_contents::commit.eq(notes::commit))),
+            )
             .select((
                 notes::id,
                 notes::commit,
@@ -334,6 +617,13 @@ pub async fn fetch_note(
                     device_notes::text,
                 )
                     .nullable(),
+                (
+                    note_contents::folder_id,
+                    note_contents::sender_device_id,
+                    note_contents::name,
+                    note_contents::text,
+                )
+                    .nullable(),
             ))
             .first(&mut pool.get().unwrap())
     })
@@ -342,545 +632,3421 @@ pub async fn fetch_note(
     Ok(Json(note))
 }
 
-#[put("note/{note_id}")]
 pub async fn update_note(
     pool: Data<Pool>,
+    quota: Data<Quota>,
     note_id: Path<i32>,
     request: Sanitized<Json<UpdateNoteRequest>>,
     device: UserDevice,
     ws_server: Data<notify::ws::AddrServer>,
-) -> Result<Json<Commit>, HttpError> {
-    let commit = block(move || -> Result<Commit, HttpError> {
+) -> Result<HttpResponse, HttpError> {
+    let updated = block(move || -> Result<Result<Commit, responses::Note>, HttpError> {
         let mut conn = pool.get().unwrap();
 
-        // TODO make incrementing commit atomic
-        let (note_id, folder_id, commit) = notes::table
+        let (note_id, folder_id, owner_id) = notes::table
             .filter(notes::id.eq(note_id.into_inner()))
             .filter(notes::state.eq(State::Clean))
-            .filter(folders::user_id.eq(device.user_id))
+            .filter(access::writable(device.user_id))
             .inner_join(folders::table)
-            .select((notes::id, notes::folder_id, notes::commit))
+            .select((notes::id, notes::folder_id, folders::user_id))
             .first::<(i32, i32, i32)>(&mut conn)?;
 
-        if commit != request.commit {
-            return Err(HttpError::conflict("commit_mismatch"));
-        }
-
-        let device_notes = request.0 .0.device_notes;
-
-        let device_ids = user_devices::table
-            .filter(user_devices::user_id.eq(device.user_id))
-            .filter(user_devices::device_id.ne(device.device_id))
-            .select(user_devices::device_id)
-            .load::<i32>(&mut conn)?;
+        let request = request.0 .0;
+
+        quota.check_note_size(&request.name, &request.text)?;
+
+        let updated = conn.transaction(|conn| -> Result<Option<(i32, Option<i32>)>, HttpError> {
+            // Device ciphertexts of the note are replaced by the new one, earlier commits are kept as history
+            quota::lock(conn, owner_id)?;
+            let usage = quota::usage(conn, owner_id)?;
+            quota.check_bytes(
+                usage.bytes - quota::note_usage(conn, note_id)? + (request.name.len() + request.text.len()) as i64,
+            )?;
+
+            // Commit is only incremented if nobody else has incremented it since the client fetched the note
+            let Some((commit, update_id)) = diesel::update(notes::table)
+                .filter(notes::id.eq(note_id))
+                .filter(notes::commit.eq(request.commit))
+                .filter(notes::state.eq(State::Clean))
+                .set(notes::commit.eq(notes::commit + 1))
+                .returning((notes::commit, notes::update_id))
+                .get_result::<(i32, Option<i32>)>(conn)
+                .optional()? else {
+                    return Ok(None);
+                };
+
+            diesel::insert_into(note_contents::table)
+                .values((
+                    note_contents::note_id.eq(note_id),
+                    note_contents::commit.eq(commit),
+                    note_contents::folder_id.eq(folder_id),
+                    note_contents::sender_device_id.eq(device.device_id),
+                    note_contents::name.eq(&request.name),
+                    note_contents::text.eq(&request.text),
+                ))
+                .execute(conn)?;
 
-        let device_not_exist_in_request = device_notes
-            .iter()
-            .find(|note_to_create| {
-                device_ids
-                    .iter()
-                    .find(|id| **id == note_to_create.device_id)
-                    .is_none()
-            })
-            .is_some();
+            // Note is no longer encrypted per device once it is written with the folder key
+            diesel::delete(device_notes::table)
+                .filter(device_notes::note_id.eq(note_id))
+                .execute(conn)?;
 
-        if device_ids.len() != device_notes.len() || device_not_exist_in_request {
-            return Err(HttpError::unprocessable_entity("devices_mismatch"));
-        }
+            diesel::delete(note_requests::table)
+                .filter(note_requests::note_id.eq(note_id))
+                .execute(conn)?;
 
-        diesel::update(notes::table)
-            .filter(notes::id.eq(note_id))
-            .set(notes::commit.eq(commit + 1))
-            .execute(&mut conn)?;
+            Ok(Some((commit, update_id)))
+        })?;
 
-        for device_note in device_notes {
-            diesel::insert_into(device_notes::table)
-                .values((
-                    device_notes::note_id.eq(note_id),
-                    device_notes::receiver_device_id.eq(device_note.device_id),
-                    device_notes::sender_device_id.eq(device.device_id),
-                    device_notes::name.eq(&device_note.name),
-                    device_notes::text.eq(&device_note.text),
-                ))
-                .on_conflict((device_notes::note_id, device_notes::receiver_device_id))
-                .do_update()
-                .set((
-                    device_notes::sender_device_id.eq(device.device_id),
-                    device_notes::name.eq(&device_note.name),
-                    device_notes::text.eq(&device_note.text),
+        let Some((commit, update_id)) = updated else {
+            // Send the current note alongside the error so that the client can merge its changes
+            // without fetching the note again
+            let note = notes::table
+                .filter(notes::id.eq(note_id))
+                .left_join(
+                    device_notes::table.on(device_notes::note_id
+                        .eq(notes::id)
+                        .and(device_notes::receiver_device_id.eq(device.device_id))),
+                )
+                .left_join(
+                    note_contents::table.on(note_contents::note_id
+                        .eq(notes::id)
+                        .and(note_contents::commit.eq(notes::commit))),
+                )
+                .select((
+                    notes::id,
+                    notes::commit,
+                    notes::state,
+                    (
+                        device_notes::sender_device_id,
+                        device_notes::name,
+                        device_notes::text,
+                    )
+                        .nullable(),
+                    (
+                        note_contents::folder_id,
+                        note_contents::sender_device_id,
+                        note_contents::name,
+                        note_contents::text,
+                    )
+                        .nullable(),
                 ))
-                .execute(&mut conn)?;
+                .first::<responses::Note>(&mut conn)?;
 
-            // Remove this device notes since it updated the note
-            diesel::delete(device_notes::table)
-                .filter(device_notes::note_id.eq(note_id))
-                .filter(device_notes::receiver_device_id.eq(device.device_id))
-                .execute(&mut conn)?;
-        }
+            return Ok(Err(note));
+        };
 
-        ws_server.do_send(SendExclusiveDeviceMessage {
-            user_id: device.user_id,
-            excluded_device_id: device.device_id,
-            message: DeviceMessage::RefreshNote { folder_id, note_id, commit: commit + 1, deleted: false }
-        });
+        access::notify_folder(
+            &mut conn,
+            &ws_server,
+            folder_id,
+            &device,
+            DeviceMessage::RefreshNote { folder_id, note_id, commit, deleted: false },
+        )?;
 
-        Ok(Commit {
+        Ok(Ok(Commit {
             note_id,
-            commit: commit + 1,
+            commit,
             state: State::Clean,
-        })
+            update_id,
+        }))
     })
     .await??;
 
-    Ok(Json(commit))
+    match updated {
+        Ok(commit) => Ok(HttpResponse::Ok().json(commit)),
+        Err(note) => Ok(HttpResponse::Conflict().json(CommitMismatch {
+            code: StatusCode::CONFLICT.as_u16(),
+            error: "commit_mismatch",
+            note,
+        })),
+    }
 }
 
-#[delete("note/{note_id}")]
-pub async fn delete_note(
+pub async fn move_note(
     pool: Data<Pool>,
     note_id: Path<i32>,
+    request: Sanitized<Json<MoveNoteRequest>>,
     device: UserDevice,
     ws_server: Data<notify::ws::AddrServer>,
 ) -> Result<Json<HttpMessage>, HttpError> {
-    block(move || -> Result<(), diesel::result::Error> {
+    block(move || -> Result<(), HttpError> {
         let mut conn = pool.get().unwrap();
 
-        let (note_id, folder_id, commit) = notes::table
+        let (note_id, source_folder_id, commit) = notes::table
             .filter(notes::id.eq(note_id.into_inner()))
             .filter(notes::state.eq(State::Clean))
             .filter(folders::user_id.eq(device.user_id))
             .inner_join(folders::table)
-            .select((notes::id, folders::id, notes::commit))
+            .select((notes::id, notes::folder_id, notes::commit))
             .first::<(i32, i32, i32)>(&mut conn)?;
 
-        diesel::update(notes::table)
-            .filter(notes::id.eq(note_id))
-            .set(notes::state.eq(State::Deleted))
-            .execute(&mut conn)?;
+        let folder_id = folders::table
+            .filter(folders::id.eq(request.folder_id))
+            .filter(folders::state.eq(State::Clean))
+            .filter(folders::user_id.eq(device.user_id))
+            .select(folders::id)
+            .first::<i32>(&mut conn)?;
 
-        diesel::delete(device_notes::table)
-            .filter(device_notes::note_id.eq(note_id))
-            .execute(&mut conn)?;
+        // Earlier commits of the note stay encrypted with the key of the source folder, so the note
+        // cannot cross the boundary of a shared folder as its members would gain or lose access to them
+        let members = access::folder_members(&mut conn, &[source_folder_id, folder_id])?;
+        if source_folder_id != folder_id && !members.is_empty() {
+            return Err(HttpError::conflict("folder_shared"));
+        }
 
-        ws_server.do_send(SendExclusiveDeviceMessage {
-            user_id: device.user_id,
-            excluded_device_id: device.device_id,
-            message: DeviceMessage::RefreshNote { folder_id, note_id, commit, deleted: true }
-        });
+        let request = request.0 .0;
 
-        Ok(())
+        // Note keeps its id and commit, its current content is replaced by the one encrypted with the
+        // key of the target folder
+        conn.transaction(|conn| -> Result<(), diesel::result::Error> {
+            diesel::update(notes::table)
+                .filter(notes::id.eq(note_id))
+                .set(notes::folder_id.eq(folder_id))
+                .execute(conn)?;
+
+            diesel::insert_into(note_contents::table)
+                .values((
+                    note_contents::note_id.eq(note_id),
+                    note_contents::commit.eq(commit),
+                    note_contents::folder_id.eq(folder_id),
+                    note_contents::sender_device_id.eq(device.device_id),
+                    note_contents::name.eq(&request.name),
+                    note_contents::text.eq(&request.text),
+                ))
+                .on_conflict((note_contents::note_id, note_contents::commit))
+                .do_update()
+                .set((
+                    note_contents::folder_id.eq(folder_id),
+                    note_contents::sender_device_id.eq(device.device_id),
+                    note_contents::name.eq(&request.name),
+                    note_contents::text.eq(&request.text),
+                ))
+                .execute(conn)?;
+
+            diesel::delete(device_notes::table)
+                .filter(device_notes::note_id.eq(note_id))
+                .execute(conn)
+                .map(|_| ())
+        })?;
+
+        ws_server.do_send(SendExclusiveDeviceMessage {
+            user_id: device.user_id,
+            excluded_device_id: device.device_id,
+            message: DeviceMessage::RefreshNote { folder_id, note_id, commit, deleted: false }
+        });
+
+        Ok(())
     })
     .await??;
 
     Ok(Json(HttpMessage::success()))
 }
 
-#[get("requests")]
-pub async fn fetch_requests(pool: Data<Pool>, device: UserDevice) -> Result<Json<Requests>, HttpError> {
-    let requests = block(move || {
+pub async fn fetch_note_commits(
+    pool: Data<Pool>,
+    note_id: Path<i32>,
+    device: UserDevice,
+) -> Result<Json<Vec<NoteCommit>>, HttpError> {
+    let commits = block(move || -> Result<Vec<NoteCommit>, HttpError> {
         let mut conn = pool.get().unwrap();
 
-        let folders: Vec<FolderRequest> = folder_requests::table
-            .inner_join(user_devices::table.on(folder_requests::device_id.eq(user_devices::device_id)))
-            .filter(user_devices::user_id.eq(device.user_id))
-            .filter(folder_requests::device_id.ne(device.device_id))
-            .select((folder_requests::folder_id, folder_requests::device_id))
-            .load(&mut conn)?;
+        let note_id = notes::table
+            .filter(notes::id.eq(note_id.into_inner()))
+            .filter(access::readable(device.user_id))
+            .inner_join(folders::table)
+            .select(notes::id)
+            .first::<i32>(&mut conn)?;
 
-        let notes: Vec<NoteRequest> = note_requests::table
-            .inner_join(user_devices::table.on(note_requests::device_id.eq(user_devices::device_id)))
-            .filter(user_devices::user_id.eq(device.user_id))
-            .filter(note_requests::device_id.ne(device.device_id))
-            .select((note_requests::note_id, note_requests::device_id))
-            .load(&mut conn)?;
+        let contents = note_contents::table
+            .filter(note_contents::note_id.eq(note_id))
+            .select((
+                note_contents::commit,
+                note_contents::sender_device_id,
+                note_contents::created_at,
+            ))
+            .load::<NoteCommit>(&mut conn)?;
+
+        // Only the commits this device can decrypt are listed, either it received or sent them
+        let device_commits = note_commits::table
+            .filter(note_commits::note_id.eq(note_id))
+            .filter(
+                note_commits::receiver_device_id
+                    .eq(device.device_id)
+                    .or(note_commits::sender_device_id.eq(device.device_id)),
+            )
+            .distinct_on(note_commits::commit)
+            .order((note_commits::commit.desc(), note_commits::receiver_device_id))
+            .select((
+                note_commits::commit,
+                note_commits::sender_device_id,
+                note_commits::created_at,
+            ))
+            .load::<NoteCommit>(&mut conn)?;
 
-        Result::<_, HttpError>::Ok(Requests {
-            folder_requests: folders,
-            note_requests: notes,
-        })
+        // Commits written before the folder got a key are only encrypted per device
+        let mut commits = device_commits
+            .into_iter()
+            .filter(|c| contents.iter().all(|content| content.commit != c.commit))
+            .collect::<Vec<_>>();
+
+        commits.extend(contents);
+
+        commits.sort_by_key(|c| std::cmp::Reverse(c.commit));
+
+        Ok(commits)
     })
     .await??;
 
-    Ok(Json(requests))
+    Ok(Json(commits))
 }
 
-pub async fn create_requests(
+/// Maximum number of updates returned at once, devices keep fetching after the last one until a page is empty
+const NOTE_UPDATES_PAGE: i64 = 100;
+
+pub async fn fetch_note_updates(
     pool: Data<Pool>,
+    note_id: Path<i32>,
+    query: Query<After>,
+    device: UserDevice,
+) -> Result<Json<Vec<NoteUpdate>>, HttpError> {
+    let updates = block(move || -> Result<Vec<NoteUpdate>, HttpError> {
+        let mut conn = pool.get().unwrap();
+
+        let note_id = notes::table
+            .filter(notes::id.eq(note_id.into_inner()))
+            .filter(access::readable(device.user_id))
+            .inner_join(folders::table)
+            .select(notes::id)
+            .first::<i32>(&mut conn)?;
+
+        note_updates::table
+            .filter(note_updates::note_id.eq(note_id))
+            .filter(note_updates::id.gt(query.after))
+            .order(note_updates::id)
+            .limit(NOTE_UPDATES_PAGE)
+            .select((note_updates::id, note_updates::folder_id, note_updates::data))
+            .load::<NoteUpdate>(&mut conn)
+            .map_err(HttpError::from)
+    })
+    .await??;
+
+    Ok(Json(updates))
+}
+
+pub async fn create_note_update(
+    pool: Data<Pool>,
+    quota: Data<Quota>,
+    note_id: Path<i32>,
+    request: Sanitized<Json<CreateNoteUpdateRequest>>,
+    device: UserDevice,
+    ws_server: Data<notify::ws::AddrServer>,
+) -> Result<Json<CreatedNoteUpdate>, HttpError> {
+    let created = block(move || -> Result<CreatedNoteUpdate, HttpError> {
+        let mut conn = pool.get().unwrap();
+
+        let (note_id, folder_id, owner_id) = notes::table
+            .filter(notes::id.eq(note_id.into_inner()))
+            .filter(notes::state.eq(State::Clean))
+            .filter(access::writable(device.user_id))
+            .inner_join(folders::table)
+            .select((notes::id, notes::folder_id, folders::user_id))
+            .first::<(i32, i32, i32)>(&mut conn)?;
+
+        let request = request.0 .0;
+
+        quota.check_note_size("", &request.data)?;
+
+        let (id, updates) = conn.transaction(|conn| -> Result<(i32, i64), HttpError> {
+            // Updates are kept alongside each other, hence they are added to the usage. A snapshot replaces the
+            // updates before it.
+            quota::lock(conn, owner_id)?;
+            let usage = quota::usage(conn, owner_id)?;
+            let replaced = if request.snapshot { quota::note_update_usage(conn, note_id)? } else { 0 };
+            quota.check_bytes(usage.bytes - replaced + request.data.len() as i64)?;
+
+            // Locking the note serializes the updates, so that only one device can convert the note
+            let update_id = notes::table
+                .filter(notes::id.eq(note_id))
+                .select(notes::update_id)
+                .for_update()
+                .first::<Option<i32>>(conn)?;
+
+            if request.previous_id.is_none() && update_id.is_some() {
+                return Err(HttpError::conflict("note_already_converted"));
+            }
+
+            if let Some(previous_id) = request.previous_id {
+                let previous_exists = diesel::dsl::select(diesel::dsl::exists(
+                    note_updates::table
+                        .filter(note_updates::id.eq(previous_id))
+                        .filter(note_updates::note_id.eq(note_id))
+                ))
+                    .get_result::<bool>(conn)?;
+
+                // Update may also be pruned by a snapshot, the device needs to fetch the snapshot then
+                if !previous_exists {
+                    return Err(HttpError::unprocessable_entity("unknown_previous_update"));
+                }
+            }
+
+            // Snapshot must contain every update, otherwise the updates it replaces would be lost
+            if request.snapshot && request.previous_id != update_id {
+                return Err(HttpError::conflict("stale_snapshot"));
+            }
+
+            let id = diesel::insert_into(note_updates::table)
+                .values((
+                    note_updates::note_id.eq(note_id),
+                    note_updates::folder_id.eq(folder_id),
+                    note_updates::data.eq(&request.data),
+                ))
+                .returning(note_updates::id)
+                .get_result::<i32>(conn)?;
+
+            if request.snapshot {
+                diesel::delete(note_updates::table)
+                    .filter(note_updates::note_id.eq(note_id))
+                    .filter(note_updates::id.lt(id))
+                    .execute(conn)?;
+            }
+
+            diesel::update(notes::table)
+                .filter(notes::id.eq(note_id))
+                .set(notes::update_id.eq(id))
+                .execute(conn)?;
+
+            let updates = note_updates::table
+                .filter(note_updates::note_id.eq(note_id))
+                .count()
+                .get_result::<i64>(conn)?;
+
+            Ok((id, updates))
+        })?;
+
+        access::notify_folder(
+            &mut conn,
+            &ws_server,
+            folder_id,
+            &device,
+            DeviceMessage::RefreshNoteUpdates { folder_id, note_id, update_id: id },
+        )?;
+
+        Ok(CreatedNoteUpdate { id, updates })
+    })
+    .await??;
+
+    Ok(Json(created))
+}
+
+#[get("note/{note_id}/commit/{commit}")]
+pub async fn fetch_note_commit(
+    pool: Data<Pool>,
+    path: Path<(i32, i32)>,
+    device: UserDevice,
+) -> Result<Json<DeviceNoteCommit>, HttpError> {
+    let (note_id, commit) = path.into_inner();
+
+    let note_commit = block(move || -> Result<DeviceNoteCommit, HttpError> {
+        let mut conn = pool.get().unwrap();
+
+        let note_id = notes::table
+            .filter(notes::id.eq(note_id))
+            .filter(access::readable(device.user_id))
+            .inner_join(folders::table)
+            .select(notes::id)
+            .first::<i32>(&mut conn)?;
+
+        let content = note_contents::table
+            .filter(note_contents::note_id.eq(note_id))
+            .filter(note_contents::commit.eq(commit))
+            .select((
+                note_contents::folder_id,
+                note_contents::sender_device_id,
+                note_contents::name,
+                note_contents::text,
+                note_contents::created_at,
+            ))
+            .first::<(i32, i32, String, String, NaiveDateTime)>(&mut conn)
+            .optional()?;
+
+        if let Some((folder_id, sender_device_id, name, text, created_at)) = content {
+            return Ok(DeviceNoteCommit {
+                commit,
+                sender_device_id,
+                receiver_device_id: None,
+                folder_id: Some(folder_id),
+                name,
+                text,
+                created_at,
+            });
+        }
+
+        // Prefer the ciphertext sent to this device. If this device is the sender of the commit,
+        // any of the ciphertexts it sent can be decrypted with the receiver's shared key.
+        note_commits::table
+            .filter(note_commits::note_id.eq(note_id))
+            .filter(note_commits::commit.eq(commit))
+            .filter(
+                note_commits::receiver_device_id
+                    .eq(device.device_id)
+                    .or(note_commits::sender_device_id.eq(device.device_id)),
+            )
+            .order(note_commits::receiver_device_id.ne(device.device_id))
+            .select((
+                note_commits::sender_device_id,
+                note_commits::receiver_device_id,
+                note_commits::name,
+                note_commits::text,
+                note_commits::created_at,
+            ))
+            .first::<(i32, i32, String, String, NaiveDateTime)>(&mut conn)
+            .map(|(sender_device_id, receiver_device_id, name, text, created_at)| DeviceNoteCommit {
+                commit,
+                sender_device_id,
+                receiver_device_id: Some(receiver_device_id),
+                folder_id: None,
+                name,
+                text,
+                created_at,
+            })
+            .map_err(HttpError::from)
+    })
+    .await??;
+
+    Ok(Json(note_commit))
+}
+
+#[delete("note/{note_id}")]
+pub async fn delete_note(
+    pool: Data<Pool>,
+    note_id: Path<i32>,
     device: UserDevice,
-    request: Sanitized<Json<CreateRequests>>,
     ws_server: Data<notify::ws::AddrServer>,
 ) -> Result<Json<HttpMessage>, HttpError> {
-    let request = request.0 .0;
+    block(move || -> Result<(), diesel::result::Error> {
+        let mut conn = pool.get().unwrap();
 
-    if request.folder_ids.len() == 0 && request.note_ids.len() == 0 {
-        return Err(HttpError::unprocessable_entity("no_request_specified"));
-    }
+        let (note_id, folder_id, commit) = notes::table
+            .filter(notes::id.eq(note_id.into_inner()))
+            .filter(notes::state.eq(State::Clean))
+            .filter(access::writable(device.user_id))
+            .inner_join(folders::table)
+            .select((notes::id, folders::id, notes::commit))
+            .first::<(i32, i32, i32)>(&mut conn)?;
+
+        // Device notes and attachments are kept so that the note can be restored from the trash
+        diesel::update(notes::table)
+            .filter(notes::id.eq(note_id))
+            .set((notes::state.eq(State::Trashed), notes::trashed_at.eq(Utc::now().naive_utc())))
+            .execute(&mut conn)?;
+
+        access::notify_folder(
+            &mut conn,
+            &ws_server,
+            folder_id,
+            &device,
+            DeviceMessage::RefreshNote { folder_id, note_id, commit, deleted: true },
+        )?;
+
+        Ok(())
+    })
+    .await??;
+
+    Ok(Json(HttpMessage::success()))
+}
 
+pub async fn restore_note(
+    pool: Data<Pool>,
+    note_id: Path<i32>,
+    device: UserDevice,
+    ws_server: Data<notify::ws::AddrServer>,
+) -> Result<Json<HttpMessage>, HttpError> {
     block(move || -> Result<(), HttpError> {
         let mut conn = pool.get().unwrap();
 
-        let folder_count = folders::table
-            .filter(folders::user_id.eq(device.user_id))
-            .filter(folders::id.eq_any(request.folder_ids.as_slice()))
-            .filter(folders::state.eq(State::Clean))
-            .select(diesel::dsl::count(folders::id))
-            .get_result::<i64>(&mut conn)?;
+        let (note_id, folder_id, folder_state, commit) = notes::table
+            .filter(notes::id.eq(note_id.into_inner()))
+            .filter(notes::state.eq(State::Trashed))
+            .filter(access::writable(device.user_id))
+            .inner_join(folders::table)
+            .select((notes::id, folders::id, folders::state, notes::commit))
+            .first::<(i32, i32, State, i32)>(&mut conn)?;
 
-        if folder_count != request.folder_ids.len() as i64 {
-            return Err(HttpError::unprocessable_entity("unknown_folder"));
+        if !matches!(folder_state, State::Clean) {
+            return Err(HttpError::conflict("folder_trashed"));
         }
 
-        let note_count = notes::table
-            .filter(folders::user_id.eq(device.user_id))
-            .filter(notes::id.eq_any(request.note_ids.as_slice()))
+        diesel::update(notes::table)
+            .filter(notes::id.eq(note_id))
+            .set((notes::state.eq(State::Clean), notes::trashed_at.eq(None::<NaiveDateTime>)))
+            .execute(&mut conn)?;
+
+        access::notify_folder(
+            &mut conn,
+            &ws_server,
+            folder_id,
+            &device,
+            DeviceMessage::RefreshNote { folder_id, note_id, commit, deleted: false },
+        )?;
+
+        Ok(())
+    })
+    .await??;
+
+    Ok(Json(HttpMessage::success()))
+}
+
+pub async fn fetch_attachments(
+    pool: Data<Pool>,
+    note_id: Path<i32>,
+    device: UserDevice,
+) -> Result<Json<Vec<Attachment>>, HttpError> {
+    let attachments = block(move || -> Result<Vec<Attachment>, HttpError> {
+        let mut conn = pool.get().unwrap();
+
+        let note_id = notes::table
+            .filter(notes::id.eq(note_id.into_inner()))
             .filter(notes::state.eq(State::Clean))
+            .filter(access::readable(device.user_id))
             .inner_join(folders::table)
-            .select(diesel::dsl::count(notes::id))
-            .get_result::<i64>(&mut conn)?;
+            .select(notes::id)
+            .first::<i32>(&mut conn)?;
 
-        if note_count != request.note_ids.len() as i64 {
-            return Err(HttpError::unprocessable_entity("unknown_note"));
+        let attachments = attachments::table
+            .filter(attachments::note_id.eq(note_id))
+            .left_join(
+                device_attachments::table.on(device_attachments::attachment_id
+                    .eq(attachments::id)
+                    .and(device_attachments::receiver_device_id.eq(device.device_id))),
+            )
+            .order(attachments::id)
+            .select((
+                attachments::id,
+                attachments::name,
+                attachments::chunk_count,
+                (device_attachments::sender_device_id, device_attachments::key).nullable(),
+            ))
+            .load::<(i32, String, i32, Option<DeviceAttachment>)>(&mut conn)?;
+
+        let uploaded_chunks = attachment_chunks::table
+            .filter(attachment_chunks::attachment_id.eq_any(attachments.iter().map(|a| a.0)))
+            .group_by(attachment_chunks::attachment_id)
+            .select((attachment_chunks::attachment_id, diesel::dsl::count_star()))
+            .load::<(i32, i64)>(&mut conn)?;
+
+        // Attachments whose chunks are still being uploaded are not listed
+        Ok(attachments
+            .into_iter()
+            .filter(|a| uploaded_chunks.iter().any(|c| c.0 == a.0 && c.1 == a.2 as i64))
+            .map(|a| Attachment { id: a.0, name: a.1, chunk_count: a.2, device_attachment: a.3 })
+            .collect())
+    })
+    .await??;
+
+    Ok(Json(attachments))
+}
+
+pub async fn create_attachment(
+    pool: Data<Pool>,
+    quota: Data<Quota>,
+    note_id: Path<i32>,
+    request: Sanitized<Json<CreateAttachmentRequest>>,
+    device: UserDevice,
+) -> Result<Json<CreatedAttachment>, HttpError> {
+    let attachment = block(move || -> Result<CreatedAttachment, HttpError> {
+        let request = request.0 .0;
+
+        let mut conn = pool.get().unwrap();
+
+        let (note_id, folder_id, owner_id) = notes::table
+            .filter(notes::id.eq(note_id.into_inner()))
+            .filter(notes::state.eq(State::Clean))
+            .filter(access::writable(device.user_id))
+            .inner_join(folders::table)
+            .select((notes::id, notes::folder_id, folders::user_id))
+            .first::<(i32, i32, i32)>(&mut conn)?;
+
+        let device_ids = access::folder_device_ids(&mut conn, folder_id, device.device_id)?;
+
+        // Omitted devices cannot decrypt the attachment, it is uploaded again to share it with them
+        omitted_devices(&device_ids, request.device_attachments.iter().map(|d| d.device_id))?;
+
+        if request.chunk_count <= 0 {
+            return Err(HttpError::unprocessable_entity("invalid_chunk_count"));
         }
 
-        conn.transaction(move |conn| {
-            let values = request
-                .folder_ids
-                .into_iter()
-                .map(|id| {
-                    (
-                        folder_requests::folder_id.eq(id),
-                        folder_requests::device_id.eq(device.device_id),
-                    )
-                })
-                .collect::<Vec<_>>();
+        conn.transaction(|conn| -> Result<CreatedAttachment, HttpError> {
+            // Chunks are counted once they are uploaded, an attachment cannot be started after the quota is exceeded
+            quota::lock(conn, owner_id)?;
+            quota.check_bytes(quota::usage(conn, owner_id)?.bytes)?;
 
-            diesel::insert_into(folder_requests::table)
-                .values(values)
-                .on_conflict_do_nothing()
+            let attachment_id = diesel::insert_into(attachments::table)
+                .values((
+                    attachments::note_id.eq(note_id),
+                    attachments::device_id.eq(device.device_id),
+                    attachments::name.eq(&request.name),
+                    attachments::chunk_count.eq(request.chunk_count),
+                ))
+                .returning(attachments::id)
+                .get_result::<i32>(conn)?;
+
+            let own_device_attachment = (device.device_id, request.key);
+
+            diesel::insert_into(device_attachments::table)
+                .values(
+                    request.device_attachments
+                        .into_iter()
+                        .map(|device_attachment| (device_attachment.device_id, device_attachment.key))
+                        .chain(std::iter::once(own_device_attachment))
+                        .map(|(receiver_device_id, key)| {
+                            (
+                                device_attachments::attachment_id.eq(attachment_id),
+                                device_attachments::sender_device_id.eq(device.device_id),
+                                device_attachments::receiver_device_id.eq(receiver_device_id),
+                                device_attachments::key.eq(key),
+                            )
+                        })
+                        .collect::<Vec<_>>(),
+                )
                 .execute(conn)?;
 
-            let values = request
-                .note_ids
-                .into_iter()
-                .map(|id| {
-                    (
-                        note_requests::note_id.eq(id),
-                        note_requests::device_id.eq(device.device_id),
-                    )
-                })
-                .collect::<Vec<_>>();
+            Ok(CreatedAttachment { id: attachment_id })
+        })
+    })
+    .await??;
 
-            diesel::insert_into(note_requests::table)
-                .values(values)
-                .on_conflict_do_nothing()
+    Ok(Json(attachment))
+}
+
+pub async fn upload_attachment_chunk(
+    pool: Data<Pool>,
+    quota: Data<Quota>,
+    path: Path<(i32, i32)>,
+    data: Bytes,
+    device: UserDevice,
+) -> Result<Json<HttpMessage>, HttpError> {
+    let (attachment_id, chunk) = path.into_inner();
+
+    block(move || -> Result<(), HttpError> {
+        let mut conn = pool.get().unwrap();
+
+        // Only the device created the attachment can upload its chunks
+        let (chunk_count, owner_id) = attachments::table
+            .filter(attachments::id.eq(attachment_id))
+            .filter(attachments::device_id.eq(device.device_id))
+            .inner_join(notes::table.inner_join(folders::table))
+            .select((attachments::chunk_count, folders::user_id))
+            .first::<(i32, i32)>(&mut conn)?;
+
+        if chunk < 0 || chunk >= chunk_count {
+            return Err(HttpError::unprocessable_entity("invalid_chunk"));
+        }
+
+        conn.transaction(|conn| -> Result<(), HttpError> {
+            // A chunk uploaded again replaces the previous one
+            quota::lock(conn, owner_id)?;
+            let previous = attachment_chunks::table
+                .filter(attachment_chunks::attachment_id.eq(attachment_id))
+                .filter(attachment_chunks::chunk.eq(chunk))
+                .select(attachment_chunks::data)
+                .first::<Vec<u8>>(conn)
+                .optional()?
+                .map(|data| data.len())
+                .unwrap_or(0);
+            quota.check_bytes(quota::usage(conn, owner_id)?.bytes - previous as i64 + data.len() as i64)?;
+
+            diesel::insert_into(attachment_chunks::table)
+                .values((
+                    attachment_chunks::attachment_id.eq(attachment_id),
+                    attachment_chunks::chunk.eq(chunk),
+                    attachment_chunks::data.eq(data.as_ref()),
+                ))
+                .on_conflict((attachment_chunks::attachment_id, attachment_chunks::chunk))
+                .do_update()
+                .set(attachment_chunks::data.eq(data.as_ref()))
+                .execute(conn)?;
+
+            Ok(())
+        })
+    })
+    .await??;
+
+    Ok(Json(HttpMessage::success()))
+}
+
+pub async fn fetch_attachment_chunk(
+    pool: Data<Pool>,
+    path: Path<(i32, i32)>,
+    device: UserDevice,
+) -> Result<HttpResponse, HttpError> {
+    let (attachment_id, chunk) = path.into_inner();
+
+    let data = block(move || -> Result<Vec<u8>, HttpError> {
+        let mut conn = pool.get().unwrap();
+
+        attachment_chunks::table
+            .filter(attachment_chunks::attachment_id.eq(attachment_id))
+            .filter(attachment_chunks::chunk.eq(chunk))
+            .filter(notes::state.eq(State::Clean))
+            .filter(access::readable(device.user_id))
+            .inner_join(attachments::table.inner_join(notes::table.inner_join(folders::table)))
+            .select(attachment_chunks::data)
+            .first::<Vec<u8>>(&mut conn)
+            .map_err(|e| e.into())
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().content_type("application/octet-stream").body(data))
+}
+
+pub async fn delete_attachment(
+    pool: Data<Pool>,
+    attachment_id: Path<i32>,
+    device: UserDevice,
+) -> Result<Json<HttpMessage>, HttpError> {
+    block(move || -> Result<(), HttpError> {
+        let mut conn = pool.get().unwrap();
+
+        let attachment_id = attachments::table
+            .filter(attachments::id.eq(attachment_id.into_inner()))
+            .filter(access::writable(device.user_id))
+            .inner_join(notes::table.inner_join(folders::table))
+            .select(attachments::id)
+            .first::<i32>(&mut conn)?;
+
+        diesel::delete(attachments::table)
+            .filter(attachments::id.eq(attachment_id))
+            .execute(&mut conn)?;
+
+        Ok(())
+    })
+    .await??;
+
+    Ok(Json(HttpMessage::success()))
+}
+
+#[get("requests")]
+pub async fn fetch_requests(pool: Data<Pool>, device: UserDevice) -> Result<Json<Requests>, HttpError> {
+    let requests = block(move || {
+        let mut conn = pool.get().unwrap();
+
+        // Requests can be responded by any device that can write into the folder, including the devices
+        // of the other members. Members who can only read the folder cannot respond them.
+        let folders: Vec<FolderRequest> = folder_requests::table
+            .inner_join(folders::table)
+            .filter(access::writable(device.user_id))
+            .filter(folder_requests::device_id.ne(device.device_id))
+            .select((folder_requests::folder_id, folder_requests::device_id))
+            .load(&mut conn)?;
+
+        let notes: Vec<NoteRequest> = note_requests::table
+            .inner_join(notes::table.inner_join(folders::table))
+            .filter(access::writable(device.user_id))
+            .filter(note_requests::device_id.ne(device.device_id))
+            .select((note_requests::note_id, note_requests::device_id))
+            .load(&mut conn)?;
+
+        Result::<_, HttpError>::Ok(Requests {
+            folder_requests: folders,
+            note_requests: notes,
+        })
+    })
+    .await??;
+
+    Ok(Json(requests))
+}
+
+pub async fn create_requests(
+    pool: Data<Pool>,
+    device: UserDevice,
+    request: Sanitized<Json<CreateRequests>>,
+    ws_server: Data<notify::ws::AddrServer>,
+) -> Result<Json<HttpMessage>, HttpError> {
+    let request = request.0 .0;
+
+    if request.folder_ids.len() == 0 && request.note_ids.len() == 0 {
+        return Err(HttpError::unprocessable_entity("no_request_specified"));
+    }
+
+    block(move || -> Result<(), HttpError> {
+        let mut conn = pool.get().unwrap();
+
+        let folder_count = folders::table
+            .filter(access::readable(device.user_id))
+            .filter(folders::id.eq_any(request.folder_ids.as_slice()))
+            .filter(folders::state.eq(State::Clean))
+            .select(diesel::dsl::count(folders::id))
+            .get_result::<i64>(&mut conn)?;
+
+        if folder_count != request.folder_ids.len() as i64 {
+            return Err(HttpError::unprocessable_entity("unknown_folder"));
+        }
+
+        let note_folder_ids = notes::table
+            .filter(access::readable(device.user_id))
+            .filter(notes::id.eq_any(request.note_ids.as_slice()))
+            .filter(notes::state.eq(State::Clean))
+            .inner_join(folders::table)
+            .select(notes::folder_id)
+            .load::<i32>(&mut conn)?;
+
+        if note_folder_ids.len() != request.note_ids.len() {
+            return Err(HttpError::unprocessable_entity("unknown_note"));
+        }
+
+        let folder_ids = request
+            .folder_ids
+            .iter()
+            .copied()
+            .chain(note_folder_ids)
+            .collect::<HashSet<i32>>();
+
+        conn.transaction(move |conn| {
+            let values = request
+                .folder_ids
+                .into_iter()
+                .map(|id| {
+                    (
+                        folder_requests::folder_id.eq(id),
+                        folder_requests::device_id.eq(device.device_id),
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            diesel::insert_into(folder_requests::table)
+                .values(values)
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+
+            let values = request
+                .note_ids
+                .into_iter()
+                .map(|id| {
+                    (
+                        note_requests::note_id.eq(id),
+                        note_requests::device_id.eq(device.device_id),
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            diesel::insert_into(note_requests::table)
+                .values(values)
+                .on_conflict_do_nothing()
                 .execute(conn)
         })?;
 
-        ws_server.do_send(SendExclusiveDeviceMessage {
-            user_id: device.user_id,
-            excluded_device_id: device.device_id,
-            message: DeviceMessage::RefreshRequests,
-        });
+        let mut user_ids = HashSet::new();
+        for folder_id in folder_ids {
+            user_ids.extend(access::folder_user_ids(&mut conn, folder_id)?);
+        }
+
+        for user_id in user_ids {
+            ws_server.do_send(SendExclusiveDeviceMessage {
+                user_id,
+                excluded_device_id: device.device_id,
+                message: DeviceMessage::RefreshRequests,
+            });
+        }
+
+        Ok(())
+    })
+    .await??;
+
+    Ok(Json(HttpMessage::success()))
+}
+
+#[post("respond-requests")]
+pub async fn respond_requests(
+    pool: Data<Pool>,
+    quota: Data<Quota>,
+    device: UserDevice,
+    request: Sanitized<Json<RespondRequests>>,
+    ws_server: Data<notify::ws::AddrServer>,
+) -> Result<Json<HttpMessage>, HttpError> {
+    let request = request.0 .0;
+
+    if request.folders.len() == 0 && request.notes.len() == 0 {
+        return Err(HttpError::unprocessable_entity("no_request_specified"));
+    }
+
+    block(move || {
+        let mut conn = pool.get().unwrap();
+
+        let requested_folder_ids: Vec<i32> =
+            request.folders.iter().map(|req| req.folder_id).collect();
+        let requested_note_ids: Vec<i32> = request.notes.iter().map(|req| req.note_id).collect();
+
+        let folder_owners = folders::table
+            .filter(access::writable(device.user_id))
+            .filter(folders::id.eq_any(requested_folder_ids.as_slice()))
+            .filter(folder_requests::device_id.eq(request.device_id))
+            .inner_join(folder_requests::table)
+            .select((folders::id, folders::user_id))
+            .load::<(i32, i32)>(&mut conn)?;
+
+        if folder_owners.len() != requested_folder_ids.len() {
+            return Err(HttpError::unprocessable_entity("unknown_folder"));
+        }
+
+        let note_owners = notes::table
+            .filter(access::writable(device.user_id))
+            .filter(notes::id.eq_any(requested_note_ids.as_slice()))
+            .inner_join(folders::table)
+            .inner_join(
+                note_requests::table.on(note_requests::note_id
+                    .eq(notes::id)
+                    .and(note_requests::device_id.eq(request.device_id))),
+            )
+            .select((notes::id, folders::user_id))
+            .load::<(i32, i32)>(&mut conn)?;
+
+        if note_owners.len() != requested_note_ids.len() {
+            return Err(HttpError::unprocessable_entity("unknown_note"));
+        }
+
+        for note in &request.notes {
+            quota.check_note_size(&note.name, &note.text)?;
+        }
+
+        // Ciphertexts count against the quota of the owner of their folders
+        let mut owner_bytes: HashMap<i32, i64> = HashMap::new();
+
+        for (folder_id, owner_id) in &folder_owners {
+            *owner_bytes.entry(*owner_id).or_default() += request
+                .folders
+                .iter()
+                .filter(|f| f.folder_id == *folder_id)
+                .map(|f| f.name.len() as i64)
+                .sum::<i64>();
+        }
+
+        for (note_id, owner_id) in &note_owners {
+            *owner_bytes.entry(*owner_id).or_default() += request
+                .notes
+                .iter()
+                .filter(|n| n.note_id == *note_id)
+                .map(|n| (n.name.len() + n.text.len()) as i64)
+                .sum::<i64>();
+        }
+
+        // Owners are locked in the same order by each request, so that concurrent ones cannot deadlock
+        let mut owner_bytes = owner_bytes.into_iter().collect::<Vec<_>>();
+        owner_bytes.sort();
+
+        let requester_id = user_devices::table
+            .filter(user_devices::device_id.eq(request.device_id))
+            .select(user_devices::user_id)
+            .first::<i32>(&mut conn)?;
+
+        let device_id = request.device_id;
+
+        conn.transaction(move |conn| -> Result<(), HttpError> {
+            for (owner_id, bytes) in owner_bytes {
+                quota::lock(conn, owner_id)?;
+                quota.check_bytes(quota::usage(conn, owner_id)?.bytes + bytes)?;
+            }
+
+            if requested_folder_ids.len() > 0 {
+                let values = request
+                    .folders
+                    .into_iter()
+                    .map(|req| {
+                        (
+                            device_folders::folder_id.eq(req.folder_id),
+                            device_folders::receiver_device_id.eq(request.device_id),
+                            device_folders::sender_device_id.eq(device.device_id),
+                            device_folders::name.eq(req.name),
+                            device_folders::key.eq(req.key),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+
+                diesel::insert_into(device_folders::table)
+                    .values(values)
+                    .on_conflict_do_nothing()
+                    .execute(conn)?;
+
+                diesel::delete(folder_requests::table)
+                    .filter(folder_requests::device_id.eq(request.device_id))
+                    .filter(folder_requests::folder_id.eq_any(requested_folder_ids.as_slice()))
+                    .execute(conn)?;
+            }
+
+            if requested_note_ids.len() > 0 {
+                let values = request
+                    .notes
+                    .iter()
+                    .map(|req| {
+                        (
+                            device_notes::note_id.eq(req.note_id),
+                            device_notes::receiver_device_id.eq(request.device_id),
+                            device_notes::sender_device_id.eq(device.device_id),
+                            device_notes::name.eq(&req.name),
+                            device_notes::text.eq(&req.text),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+
+                diesel::insert_into(device_notes::table)
+                    .values(values)
+                    .on_conflict_do_nothing()
+                    .execute(conn)?;
+
+                let commits = notes::table
+                    .filter(notes::id.eq_any(requested_note_ids.as_slice()))
+                    .select((notes::id, notes::commit))
+                    .load::<(i32, i32)>(conn)?;
+
+                // Responded notes become the first entry in the history of requesting device
+                let values = request
+                    .notes
+                    .iter()
+                    .filter_map(|req| {
+                        let (_, commit) = commits.iter().find(|(id, _)| *id == req.note_id)?;
+
+                        Some((
+                            note_commits::note_id.eq(req.note_id),
+                            note_commits::commit.eq(*commit),
+                            note_commits::receiver_device_id.eq(request.device_id),
+                            note_commits::sender_device_id.eq(device.device_id),
+                            note_commits::name.eq(&req.name),
+                            note_commits::text.eq(&req.text),
+                        ))
+                    })
+                    .collect::<Vec<_>>();
+
+                diesel::insert_into(note_commits::table)
+                    .values(values)
+                    .on_conflict_do_nothing()
+                    .execute(conn)?;
+
+                diesel::delete(note_requests::table)
+                    .filter(note_requests::device_id.eq(request.device_id))
+                    .filter(note_requests::note_id.eq_any(requested_note_ids.as_slice()))
+                    .execute(conn)?;
+            }
+
+            Ok(())
+        })?;
+
+        ws_server.do_send(SendDeviceMessage { user_id: requester_id, device_id, message: DeviceMessage::RefreshRemote });
+
+        Ok(())
+    })
+    .await??;
+
+    Ok(Json(HttpMessage::success()))
+}
+
+pub async fn fetch_folder_members(
+    pool: Data<Pool>,
+    folder_id: Path<i32>,
+    device: UserDevice,
+) -> Result<Json<Vec<FolderMember>>, HttpError> {
+    let members = block(move || -> Result<Vec<FolderMember>, HttpError> {
+        let mut conn = pool.get().unwrap();
+
+        let folder_id = folders::table
+            .filter(folders::id.eq(folder_id.into_inner()))
+            .filter(access::readable(device.user_id))
+            .select(folders::id)
+            .first::<i32>(&mut conn)?;
+
+        folder_members::table
+            .inner_join(users::table)
+            .filter(folder_members::folder_id.eq(folder_id))
+            .filter(folder_members::removed_at.is_null())
+            .order(folder_members::created_at)
+            .select((users::id, users::email, folder_members::role))
+            .load::<FolderMember>(&mut conn)
+            .map_err(|e| e.into())
+    })
+    .await??;
+
+    Ok(Json(members))
+}
+
+pub async fn add_folder_member(
+    pool: Data<Pool>,
+    folder_id: Path<i32>,
+    request: Sanitized<Json<AddFolderMemberRequest>>,
+    device: UserDevice,
+    ws_server: Data<notify::ws::AddrServer>,
+) -> Result<Json<HttpMessage>, HttpError> {
+    block(move || -> Result<(), HttpError> {
+        let request = request.0 .0;
+
+        let mut conn = pool.get().unwrap();
+
+        let folder_id = folders::table
+            .filter(folders::id.eq(folder_id.into_inner()))
+            .filter(folders::state.eq(State::Clean))
+            .filter(folders::user_id.eq(device.user_id))
+            .select(folders::id)
+            .first::<i32>(&mut conn)?;
+
+        let user_id = users::table
+            .filter(users::email.eq(&request.email))
+            .select(users::id)
+            .first::<i32>(&mut conn)
+            .optional()?
+            .ok_or(HttpError::not_found("user_not_found"))?;
+
+        if user_id == device.user_id {
+            return Err(HttpError::unprocessable_entity("cannot_share_with_owner"));
+        }
+
+        diesel::insert_into(folder_members::table)
+            .values((
+                folder_members::folder_id.eq(folder_id),
+                folder_members::user_id.eq(user_id),
+                folder_members::role.eq(request.role),
+            ))
+            .on_conflict((folder_members::folder_id, folder_members::user_id))
+            .do_update()
+            .set((
+                folder_members::role.eq(request.role),
+                folder_members::removed_at.eq(None::<NaiveDateTime>),
+            ))
+            .execute(&mut conn)?;
+
+        // Devices of the new member have no ciphertext yet, they request the folder and its notes
+        // from the devices that can already decrypt them once they receive the folder
+        access::notify_folder(&mut conn, &ws_server, folder_id, &device, DeviceMessage::RefreshFolder(folder_id))?;
+
+        Ok(())
+    })
+    .await??;
+
+    Ok(Json(HttpMessage::success()))
+}
+
+pub async fn remove_folder_member(
+    pool: Data<Pool>,
+    path: Path<(i32, i32)>,
+    device: UserDevice,
+    ws_server: Data<notify::ws::AddrServer>,
+) -> Result<Json<HttpMessage>, HttpError> {
+    let (folder_id, user_id) = path.into_inner();
+
+    block(move || -> Result<(), HttpError> {
+        let mut conn = pool.get().unwrap();
+
+        // Members can leave the folder on their own, only the owner can remove the others
+        let folder_id = folders::table
+            .filter(folders::id.eq(folder_id))
+            .filter(folders::user_id.eq(device.user_id).or(folders::id.eq_any(
+                folder_members::table
+                    .filter(folder_members::user_id.eq(device.user_id))
+                    .filter(folder_members::user_id.eq(user_id))
+                    .filter(folder_members::removed_at.is_null())
+                    .select(folder_members::folder_id),
+            )))
+            .select(folders::id)
+            .first::<i32>(&mut conn)?;
+
+        let device_ids = user_devices::table
+            .filter(user_devices::user_id.eq(user_id))
+            .select(user_devices::device_id)
+            .load::<i32>(&mut conn)?;
+
+        // Removed devices stop receiving the wrapped folder key. They still know the key they have received
+        // though, so the owner rotates it afterwards and new contents are encrypted with a key they do not know.
+        // Notes which are still encrypted per device have their ciphertexts dropped as well.
+        let removed = conn.transaction(|conn| -> Result<usize, diesel::result::Error> {
+            let removed = diesel::update(folder_members::table)
+                .filter(folder_members::folder_id.eq(folder_id))
+                .filter(folder_members::user_id.eq(user_id))
+                .filter(folder_members::removed_at.is_null())
+                .set(folder_members::removed_at.eq(Utc::now().naive_utc()))
+                .execute(conn)?;
+
+            let note_ids = notes::table
+                .filter(notes::folder_id.eq(folder_id))
+                .select(notes::id);
+
+            diesel::delete(device_folders::table)
+                .filter(device_folders::folder_id.eq(folder_id))
+                .filter(device_folders::receiver_device_id.eq_any(&device_ids))
+                .execute(conn)?;
+
+            diesel::delete(device_notes::table)
+                .filter(device_notes::note_id.eq_any(note_ids))
+                .filter(device_notes::receiver_device_id.eq_any(&device_ids))
+                .execute(conn)?;
+
+            diesel::delete(note_commits::table)
+                .filter(note_commits::note_id.eq_any(note_ids))
+                .filter(note_commits::receiver_device_id.eq_any(&device_ids))
+                .execute(conn)?;
+
+            diesel::delete(device_attachments::table)
+                .filter(device_attachments::attachment_id.eq_any(
+                    attachments::table
+                        .filter(attachments::note_id.eq_any(note_ids))
+                        .select(attachments::id),
+                ))
+                .filter(device_attachments::receiver_device_id.eq_any(&device_ids))
+                .execute(conn)?;
+
+            diesel::delete(folder_requests::table)
+                .filter(folder_requests::folder_id.eq(folder_id))
+                .filter(folder_requests::device_id.eq_any(&device_ids))
+                .execute(conn)?;
+
+            diesel::delete(note_requests::table)
+                .filter(note_requests::note_id.eq_any(note_ids))
+                .filter(note_requests::device_id.eq_any(&device_ids))
+                .execute(conn)?;
+
+            Ok(removed)
+        })?;
+
+        if removed == 0 {
+            return Err(HttpError::not_found("member_not_found"));
+        }
+
+        access::notify_folder(&mut conn, &ws_server, folder_id, &device, DeviceMessage::RefreshFolder(folder_id))?;
+
+        ws_server.do_send(SendExclusiveDeviceMessage {
+            user_id,
+            excluded_device_id: device.device_id,
+            message: DeviceMessage::RefreshFolder(folder_id),
+        });
+
+        Ok(())
+    })
+    .await??;
+
+    Ok(Json(HttpMessage::success()))
+}
+
+pub async fn fetch_folder_devices(
+    pool: Data<Pool>,
+    folder_id: Path<i32>,
+    device: UserDevice,
+) -> Result<Json<Vec<FolderDevice>>, HttpError> {
+    let devices = block(move || -> Result<Vec<FolderDevice>, HttpError> {
+        let mut conn = pool.get().unwrap();
+
+        let folder_id = folders::table
+            .filter(folders::id.eq(folder_id.into_inner()))
+            .filter(access::readable(device.user_id))
+            .select(folders::id)
+            .first::<i32>(&mut conn)?;
+
+        let device_ids = access::folder_device_ids(&mut conn, folder_id, device.device_id)?;
+
+        devices::table
+            .filter(devices::id.eq_any(device_ids))
+            .inner_join(user_devices::table.inner_join(users::table))
+            .order(devices::id)
+            .select((devices::id, devices::pubkey, devices::previous_pubkey, users::email))
+            .load::<FolderDevice>(&mut conn)
+            .map_err(|e| e.into())
+    })
+    .await??;
+
+    Ok(Json(devices))
+}
+
+/// Returns the devices which are left out of a request carrying a ciphertext for each device of a folder. Senders
+/// leave out the devices they do not trust, such as the ones whose keys have changed since they were verified. Every
+/// device in the request must be a device of the folder, and appear only once.
+fn omitted_devices(device_ids: &[i32], requested_ids: impl Iterator<Item = i32>) -> Result<Vec<i32>, HttpError> {
+    let mut requested = HashSet::new();
+
+    for id in requested_ids {
+        if !device_ids.contains(&id) || !requested.insert(id) {
+            return Err(HttpError::unprocessable_entity("devices_mismatch"));
+        }
+    }
+
+    Ok(device_ids.iter().copied().filter(|id| !requested.contains(id)).collect())
+}
+
+/// Omitted devices wait for their device folders, they are sent by a device which trusts them
+fn request_device_folders(conn: &mut PgConnection, folder_id: i32, device_ids: &[i32]) -> Result<(), diesel::result::Error> {
+    diesel::insert_into(folder_requests::table)
+        .values(
+            device_ids
+                .iter()
+                .map(|device_id| (folder_requests::folder_id.eq(folder_id), folder_requests::device_id.eq(device_id)))
+                .collect::<Vec<_>>(),
+        )
+        .on_conflict_do_nothing()
+        .execute(conn)
+        .map(|_| ())
+}
+
+/// Returns the ids of the given folder and all of its descendants which are not deleted. Folders of the user are
+/// locked until the end of the transaction, so that the subtree stays the same meanwhile.
+fn folder_subtree(conn: &mut PgConnection, user_id: i32, folder_id: i32) -> Result<Vec<i32>, diesel::result::Error> {
+    let folders = folders::table
+        .filter(folders::user_id.eq(user_id))
+        .filter(folders::state.eq(State::Clean))
+        .select((folders::id, folders::parent_id))
+        .for_update()
+        .load::<(i32, Option<i32>)>(conn)?;
+
+    Ok(subtree(&folders, folder_id))
+}
+
+/// Returns the ids of the given folder and all of its descendants within the given (id, parent_id) pairs
+fn subtree(folders: &[(i32, Option<i32>)], folder_id: i32) -> Vec<i32> {
+    let mut subtree = vec![folder_id];
+    let mut i = 0;
+
+    while i < subtree.len() {
+        let parent_id = subtree[i];
+        subtree.extend(folders.iter().filter(|f| f.1 == Some(parent_id)).map(|f| f.0));
+        i += 1;
+    }
+
+    subtree
+}
+
+type FolderRow = (i32, i32, Option<i32>, State, i64, Option<DeviceFolder>);
+type CommitRow = (i32, i32, i32, State, Option<i32>);
+
+/// Builds the responses of the folders from the perspective of the given user
+fn folder_responses(
+    conn: &mut PgConnection,
+    user_id: i32,
+    folders: Vec<FolderRow>,
+    commits: &[CommitRow],
+) -> QueryResult<Vec<responses::Folder>> {
+    let folder_ids = folders.iter().map(|f| f.0).collect::<Vec<_>>();
+    let members = access::folder_members(conn, &folder_ids)?;
+
+    // Shared folders are followed in the sequence of the member instead of the owner
+    let member_seqs = folder_members::table
+        .filter(folder_members::folder_id.eq_any(&folder_ids))
+        .filter(folder_members::user_id.eq(user_id))
+        .select((folder_members::folder_id, folder_members::change_seq))
+        .load::<(i32, i64)>(conn)?
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+
+    let keyed_ids = device_folders::table
+        .filter(device_folders::folder_id.eq_any(&folder_ids))
+        .filter(device_folders::key.is_not_null())
+        .select(device_folders::folder_id)
+        .distinct()
+        .load::<i32>(conn)?
+        .into_iter()
+        .collect::<HashSet<_>>();
+
+    Ok(folders
+        .into_iter()
+        .map(|(id, owner_id, parent_id, state, change_seq, device_folder)| {
+            let folder_members = members.get(&id).map(Vec::as_slice).unwrap_or_default();
+
+            responses::Folder {
+                id,
+                seq: if owner_id == user_id { change_seq } else { member_seqs.get(&id).copied().unwrap_or(change_seq) },
+                // Hierarchy of the folders belongs to the owner, members see the shared folders at the top level
+                parent_id: if owner_id == user_id { parent_id } else { None },
+                state,
+                shared: !folder_members.is_empty(),
+                role: folder_members.iter().find(|(id, _)| *id == user_id).map(|(_, role)| *role),
+                device_folder,
+                keyed: keyed_ids.contains(&id),
+                commits: commits
+                    .iter()
+                    .filter(|c| c.1 == id)
+                    .map(|c| Commit { note_id: c.0, commit: c.2, state: c.3.clone(), update_id: c.4 })
+                    .collect(),
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        models::{Folder, Note},
+        models::{Role, State},
+        requests::{
+            AddFolderMemberRequest, CreateFolderRequest, CreateRequests, MoveFolderRequest, MoveNoteRequest, ParentId,
+            Since, UpdateNoteRequest, After, CreateNoteUpdateRequest,
+        },
+    };
+    use base::{
+        sanitize::Sanitized,
+        schema::{
+            attachment_chunks, attachments, device_attachments, device_folders, device_notes, folder_members,
+            folder_requests, folders, notes, users, note_commits, note_contents, note_requests, note_updates,
+            idempotency_keys,
+        },
+        middlewares::idempotency::{Idempotency, IDEMPOTENCY_KEY, MAX_BODY_LEN},
+        models::Token,
+        HttpError, HttpMessage,
+    };
+    use test_helpers::db::create_pool;
+    use chrono::{Duration, NaiveDateTime, Utc};
+    use user::{quota::Quota, test::db::UserDeviceBuilder};
+    use notify::test::ws::create_server as create_notify_server;
+
+    use super::{
+        add_folder_member, create_requests, delete_folder, fetch_attachments, fetch_changes, fetch_note_commits,
+        move_folder, move_note, remove_folder_member, restore_folder, restore_note, update_note,
+        upload_attachment_chunk, create_note_update, fetch_note_updates,
+    };
+
+    use actix_web::{
+        body::MessageBody,
+        dev::{Service, ServiceFactory, ServiceRequest, ServiceResponse},
+        http::StatusCode,
+        test,
+        web::{Bytes, Data, Json, Path, Query},
+        App, HttpMessage as _,
+    };
+    use diesel::{prelude::*, PgConnection};
+
+    fn create_folder(
+        conn: &mut PgConnection,
+        user_id: Option<i32>,
+    ) -> Result<Folder, diesel::result::Error> {
+        let user_id = if let Some(user_id) = user_id {
+            user_id
+        } else {
+            diesel::insert_into(users::table)
+                .values(users::email.eq("folder@email.com"))
+                .get_result::<(i32, String, NaiveDateTime, i64)>(conn)?
+                .0
+        };
+
+        diesel::insert_into(folders::table)
+            .values(folders::user_id.eq(user_id))
+            .get_result(conn)
+    }
+
+    fn create_note(
+        conn: &mut PgConnection,
+        folder_id: Option<i32>,
+    ) -> Result<Note, diesel::result::Error> {
+        let folder_id = if let Some(folder_id) = folder_id {
+            folder_id
+        } else {
+            let user_id = diesel::insert_into(users::table)
+                .values(users::email.eq("note@email.com"))
+                .get_result::<(i32, String, NaiveDateTime, i64)>(conn)?
+                .0;
+
+            diesel::insert_into(folders::table)
+                .values(folders::user_id.eq(user_id))
+                .get_result::<Folder>(conn)?
+                .id
+        };
+
+        diesel::insert_into(notes::table)
+            .values(notes::folder_id.eq(folder_id))
+            .get_result(conn)
+    }
+
+    #[actix_web::test]
+    async fn it_returns_no_request_specified_error_if_ids_are_empty_when_create_request_is_called()
+    {
+        let pool = create_pool();
+
+        let device = UserDeviceBuilder::default().build(&mut pool.get().unwrap()).unwrap();
+        let request = CreateRequests {
+            folder_ids: Vec::new(),
+            note_ids: Vec::new(),
+        };
+
+        let res = create_requests(Data::new(pool), device, Sanitized(Json(request)), Data::new(create_notify_server())).await;
+
+        assert_eq!(
+            HttpError::unprocessable_entity("no_request_specified"),
+            res.unwrap_err()
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_returns_unknown_folder_error_if_one_of_the_folder_id_does_not_belong_to_user_when_create_request_is_called(
+    ) {
+        let pool = create_pool();
+
+        let (device, folder) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let folder = create_folder(&mut conn, None).unwrap();
+
+            (device, folder)
+        };
+
+        let request = CreateRequests {
+            folder_ids: vec![folder.id],
+            note_ids: Vec::new(),
+        };
+
+        let res = create_requests(Data::new(pool), device, Sanitized(Json(request)), Data::new(create_notify_server())).await;
+
+        assert_eq!(
+            HttpError::unprocessable_entity("unknown_folder"),
+            res.unwrap_err()
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_returns_unknown_note_error_if_one_of_the_note_id_does_not_belong_to_user_when_create_request_is_called(
+    ) {
+        let pool = create_pool();
+
+        let (device, note) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let note = create_note(&mut conn, None).unwrap();
+
+            (device, note)
+        };
+
+        let request = CreateRequests {
+            folder_ids: Vec::new(),
+            note_ids: vec![note.id],
+        };
+
+        let res = create_requests(Data::new(pool), device, Sanitized(Json(request)), Data::new(create_notify_server())).await;
+
+        assert_eq!(
+            HttpError::unprocessable_entity("unknown_note"),
+            res.unwrap_err()
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_creates_rows_for_folders_and_notes_when_create_request_is_called() {
+        let pool = create_pool();
+
+        let (device, folder, note) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
+            let note = create_note(&mut conn, Some(folder.id)).unwrap();
+
+            (device, folder, note)
+        };
+
+        let request = CreateRequests {
+            folder_ids: vec![folder.id],
+            note_ids: vec![note.id],
+        };
+
+        let res = create_requests(
+            Data::new(pool.clone()),
+            device.clone(),
+            Sanitized(Json(request)),
+            Data::new(create_notify_server()),
+        )
+        .await;
+
+        assert_eq!(HttpMessage::success(), res.unwrap().0);
+
+        let folder_request_exist = diesel::select(diesel::dsl::exists(
+            folder_requests::table
+                .filter(folder_requests::folder_id.eq(folder.id))
+                .filter(folder_requests::device_id.eq(device.device_id)),
+        ))
+        .get_result::<bool>(&mut pool.get().unwrap())
+        .unwrap();
+
+        let note_request_exist = diesel::select(diesel::dsl::exists(
+            note_requests::table
+                .filter(note_requests::note_id.eq(note.id))
+                .filter(note_requests::device_id.eq(device.device_id)),
+        ))
+        .get_result::<bool>(&mut pool.get().unwrap())
+        .unwrap();
+
+        assert!(folder_request_exist);
+        assert!(note_request_exist);
+    }
+
+    #[actix_web::test]
+    async fn it_returns_commits_sent_or_received_by_device_when_fetch_note_commits_is_called() {
+        let pool = create_pool();
+
+        let (sender, receiver, note) = {
+            let mut conn = pool.get().unwrap();
+            let sender = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let receiver = UserDeviceBuilder::default()
+                .user_id(sender.user_id)
+                .pubkey("receiver")
+                .build(&mut conn)
+                .unwrap();
+            let other = UserDeviceBuilder::default()
+                .user_id(sender.user_id)
+                .pubkey("other")
+                .build(&mut conn)
+                .unwrap();
+            let folder = create_folder(&mut conn, Some(sender.user_id)).unwrap();
+            let note = create_note(&mut conn, Some(folder.id)).unwrap();
+
+            let commits = [(1, &sender, &receiver), (2, &other, &sender), (3, &sender, &other)];
+            for (commit, from, to) in commits {
+                diesel::insert_into(note_commits::table)
+                    .values((
+                        note_commits::note_id.eq(note.id),
+                        note_commits::commit.eq(commit),
+                        note_commits::sender_device_id.eq(from.device_id),
+                        note_commits::receiver_device_id.eq(to.device_id),
+                        note_commits::name.eq("name"),
+                        note_commits::text.eq("text"),
+                    ))
+                    .execute(&mut conn)
+                    .unwrap();
+            }
+
+            (sender, receiver, note)
+        };
+
+        let commits = fetch_note_commits(Data::new(pool.clone()), Path::from(note.id), receiver)
+            .await
+            .unwrap();
+
+        assert_eq!(vec![1], commits.iter().map(|c| c.commit).collect::<Vec<_>>());
+
+        let commits = fetch_note_commits(Data::new(pool), Path::from(note.id), sender)
+            .await
+            .unwrap();
+
+        assert_eq!(vec![3, 2, 1], commits.iter().map(|c| c.commit).collect::<Vec<_>>());
+    }
+
+    #[actix_web::test]
+    async fn it_returns_commit_mismatch_error_with_current_note_if_commit_is_stale_when_update_note_is_called() {
+        let pool = create_pool();
+
+        let (device, note) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let other = UserDeviceBuilder::default()
+                .user_id(device.user_id)
+                .pubkey("other")
+                .build(&mut conn)
+                .unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
+            let note = create_note(&mut conn, Some(folder.id)).unwrap();
+
+            diesel::update(notes::table)
+                .filter(notes::id.eq(note.id))
+                .set(notes::commit.eq(note.commit + 1))
+                .execute(&mut conn)
+                .unwrap();
+
+            diesel::insert_into(device_notes::table)
+                .values((
+                    device_notes::note_id.eq(note.id),
+                    device_notes::sender_device_id.eq(other.device_id),
+                    device_notes::receiver_device_id.eq(device.device_id),
+                    device_notes::name.eq("remote name"),
+                    device_notes::text.eq("remote text"),
+                ))
+                .execute(&mut conn)
+                .unwrap();
+
+            (device, note)
+        };
+
+        let request = UpdateNoteRequest {
+            commit: note.commit,
+            name: "name".to_string(),
+            text: "text".to_string(),
+        };
+
+        let res = update_note(
+            Data::new(pool.clone()),
+            Data::new(Quota::default()),
+            Path::from(note.id),
+            Sanitized(Json(request)),
+            device,
+            Data::new(create_notify_server()),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(StatusCode::CONFLICT, res.status());
+
+        let body: serde_json::Value =
+            serde_json::from_slice(&res.into_body().try_into_bytes().unwrap()).unwrap();
+
+        assert_eq!("commit_mismatch", body["error"]);
+        assert_eq!(note.commit + 1, body["note"]["commit"]);
+        assert_eq!("remote text", body["note"]["device_note"]["text"]);
+
+        let commit = notes::table
+            .filter(notes::id.eq(note.id))
+            .select(notes::commit)
+            .first::<i32>(&mut pool.get().unwrap())
+            .unwrap();
+
+        assert_eq!(note.commit + 1, commit);
+    }
+
+    #[actix_web::test]
+    async fn it_replaces_device_notes_with_single_content_when_update_note_is_called() {
+        let pool = create_pool();
+
+        let (device, other, note) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let other = UserDeviceBuilder::default()
+                .user_id(device.user_id)
+                .pubkey("other")
+                .build(&mut conn)
+                .unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
+            let note = create_note(&mut conn, Some(folder.id)).unwrap();
+
+            diesel::insert_into(device_notes::table)
+                .values((
+                    device_notes::note_id.eq(note.id),
+                    device_notes::sender_device_id.eq(device.device_id),
+                    device_notes::receiver_device_id.eq(other.device_id),
+                    device_notes::name.eq("name"),
+                    device_notes::text.eq("text"),
+                ))
+                .execute(&mut conn)
+                .unwrap();
+
+            (device, other, note)
+        };
+
+        let request = UpdateNoteRequest {
+            commit: note.commit,
+            name: "new name".to_string(),
+            text: "new text".to_string(),
+        };
+
+        let res = update_note(
+            Data::new(pool.clone()),
+            Data::new(Quota::default()),
+            Path::from(note.id),
+            Sanitized(Json(request)),
+            device,
+            Data::new(create_notify_server()),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(StatusCode::OK, res.status());
+
+        let device_note_count = device_notes::table
+            .filter(device_notes::note_id.eq(note.id))
+            .count()
+            .get_result::<i64>(&mut pool.get().unwrap())
+            .unwrap();
+
+        assert_eq!(0, device_note_count);
+
+        let contents = note_contents::table
+            .filter(note_contents::note_id.eq(note.id))
+            .select((note_contents::commit, note_contents::folder_id, note_contents::text))
+            .load::<(i32, i32, String)>(&mut pool.get().unwrap())
+            .unwrap();
+
+        assert_eq!(vec![(note.commit + 1, note.folder_id, "new text".to_string())], contents);
+
+        // Commits encrypted with the folder key are listed for every device in the folder
+        let commits = fetch_note_commits(Data::new(pool), Path::from(note.id), other)
+            .await
+            .unwrap();
+
+        assert_eq!(vec![note.commit + 1], commits.iter().map(|c| c.commit).collect::<Vec<_>>());
+    }
+
+    #[actix_web::test]
+    async fn it_returns_only_changed_notes_since_cursor_when_fetch_changes_is_called() {
+        let pool = create_pool();
+
+        let (device, folder, note) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
+            let note = create_note(&mut conn, Some(folder.id)).unwrap();
+            create_note(&mut conn, Some(folder.id)).unwrap();
+
+            (device, folder, note)
+        };
+
+        let changes = fetch_changes(Data::new(pool.clone()), device.clone(), Query(Since { since: 0 }))
+            .await
+            .unwrap();
+
+        assert_eq!(1, changes.folders.len());
+        assert_eq!(2, changes.folders[0].commits.len());
+
+        diesel::update(notes::table)
+            .filter(notes::id.eq(note.id))
+            .set(notes::commit.eq(note.commit + 1))
+            .execute(&mut pool.get().unwrap())
+            .unwrap();
+
+        let new_changes = fetch_changes(Data::new(pool.clone()), device.clone(), Query(Since { since: changes.cursor }))
+            .await
+            .unwrap();
+
+        assert!(new_changes.cursor > changes.cursor);
+        assert_eq!(1, new_changes.folders.len());
+        assert_eq!(folder.id, new_changes.folders[0].id);
+        assert_eq!(
+            vec![(note.id, note.commit + 1)],
+            new_changes.folders[0].commits.iter().map(|c| (c.note_id, c.commit)).collect::<Vec<_>>()
+        );
+
+        let no_changes = fetch_changes(Data::new(pool), device, Query(Since { since: new_changes.cursor }))
+            .await
+            .unwrap();
+
+        assert!(no_changes.folders.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn it_returns_folder_as_keyed_if_another_device_has_its_key_when_fetch_changes_is_called() {
+        let pool = create_pool();
+
+        let (device, folder) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().pubkey("pubkey1").build(&mut conn).unwrap();
+            let other = UserDeviceBuilder::default().pubkey("pubkey2").user_id(device.user_id).build(&mut conn).unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
+
+            diesel::insert_into(device_folders::table)
+                .values((
+                    device_folders::folder_id.eq(folder.id),
+                    device_folders::sender_device_id.eq(other.device_id),
+                    device_folders::receiver_device_id.eq(other.device_id),
+                    device_folders::name.eq("name"),
+                    device_folders::key.eq(Some("key")),
+                ))
+                .execute(&mut conn)
+                .unwrap();
+
+            (device, folder)
+        };
+
+        let changes = fetch_changes(Data::new(pool), device, Query(Since { since: 0 }))
+            .await
+            .unwrap();
+
+        assert_eq!(1, changes.folders.len());
+        assert_eq!(folder.id, changes.folders[0].id);
+        assert!(changes.folders[0].device_folder.is_none());
+        assert!(changes.folders[0].keyed);
+    }
+
+    #[actix_web::test]
+    async fn it_returns_folder_with_all_notes_again_when_fetch_changes_is_called_right_before_seq_of_folder() {
+        let pool = create_pool();
+
+        let (device, folder) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
+            create_note(&mut conn, Some(folder.id)).unwrap();
+            create_note(&mut conn, Some(folder.id)).unwrap();
+
+            (device, folder)
+        };
+
+        let changes = fetch_changes(Data::new(pool.clone()), device.clone(), Query(Since { since: 0 }))
+            .await
+            .unwrap();
+
+        assert!(changes.folders[0].seq <= changes.cursor);
+
+        let held_changes = fetch_changes(Data::new(pool), device, Query(Since { since: changes.folders[0].seq - 1 }))
+            .await
+            .unwrap();
+
+        assert_eq!(1, held_changes.folders.len());
+        assert_eq!(folder.id, held_changes.folders[0].id);
+        assert_eq!(2, held_changes.folders[0].commits.len());
+    }
+
+    #[actix_web::test]
+    async fn it_returns_all_folders_to_resync_if_cursor_is_older_than_purged_seq_when_fetch_changes_is_called() {
+        let pool = create_pool();
+
+        let (device, cursor) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
+            create_note(&mut conn, Some(folder.id)).unwrap();
+
+            let cursor = users::table
+                .filter(users::id.eq(device.user_id))
+                .select(users::change_seq)
+                .first::<i64>(&mut conn)
+                .unwrap();
+
+            diesel::update(users::table)
+                .filter(users::id.eq(device.user_id))
+                .set(users::purged_seq.eq(cursor + 1))
+                .execute(&mut conn)
+                .unwrap();
+
+            (device, cursor)
+        };
+
+        let changes = fetch_changes(Data::new(pool.clone()), device.clone(), Query(Since { since: cursor }))
+            .await
+            .unwrap();
+
+        assert!(changes.resync);
+        assert_eq!(1, changes.folders.len());
+        assert_eq!(1, changes.folders[0].commits.len());
+
+        let no_changes = fetch_changes(Data::new(pool), device, Query(Since { since: cursor + 1 }))
+            .await
+            .unwrap();
+
+        assert!(!no_changes.resync);
+        assert!(no_changes.folders.is_empty());
+    }
+
+    /// App serving the given handler to the device, for the handlers that are registered with route macros
+    fn device_app(
+        pool: &base::types::Pool,
+        device: &user::models::UserDevice,
+    ) -> App<
+        impl ServiceFactory<
+            ServiceRequest,
+            Config = (),
+            Response = ServiceResponse<impl MessageBody>,
+            Error = actix_web::Error,
+            InitError = (),
+        >,
+    > {
+        let (user_id, device_id) = (device.user_id, device.device_id);
+
+        App::new()
+            .app_data(Data::new(pool.clone()))
+            .app_data(Data::new(create_notify_server()))
+            .wrap_fn(move |req, srv| {
+                req.extensions_mut().insert(Token::device(user_id, device_id));
+                srv.call(req)
+            })
+    }
+
+    #[actix_web::test]
+    async fn it_replaces_device_folders_with_new_names_when_rename_folder_is_called() {
+        let pool = create_pool();
+
+        let (device, other_device, folder) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let other_device = UserDeviceBuilder::default()
+                .user_id(device.user_id)
+                .pubkey("other")
+                .build(&mut conn)
+                .unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
+
+            for receiver_device_id in [device.device_id, other_device.device_id] {
+                diesel::insert_into(device_folders::table)
+                    .values((
+                        device_folders::folder_id.eq(folder.id),
+                        device_folders::sender_device_id.eq(device.device_id),
+                        device_folders::receiver_device_id.eq(receiver_device_id),
+                        device_folders::name.eq("old name"),
+                        device_folders::key.eq("key"),
+                    ))
+                    .execute(&mut conn)
+                    .unwrap();
+            }
+
+            (device, other_device, folder)
+        };
+
+        let app = test::init_service(device_app(&pool, &device).service(super::rename_folder)).await;
+
+        let req = test::TestRequest::put()
+            .uri(&format!("/folder/{}", folder.id))
+            .set_json(serde_json::json!([{ "name": "new name", "device_id": other_device.device_id, "key": "new key" }]))
+            .to_request();
+
+        let res = test::call_service(&app, req).await;
+
+        assert_eq!(StatusCode::OK, res.status());
+
+        let device_folders = device_folders::table
+            .filter(device_folders::folder_id.eq(folder.id))
+            .select((device_folders::receiver_device_id, device_folders::name, device_folders::key))
+            .load::<(i32, String, Option<String>)>(&mut pool.get().unwrap())
+            .unwrap();
+
+        assert_eq!(
+            vec![(other_device.device_id, "new name".to_string(), Some("new key".to_string()))],
+            device_folders
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_returns_devices_mismatch_error_if_a_device_is_unknown_when_rename_folder_is_called() {
+        let pool = create_pool();
+
+        let (device, stranger, folder) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let stranger = UserDeviceBuilder::default()
+                .email("stranger@email.com")
+                .pubkey("stranger")
+                .build(&mut conn)
+                .unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
+
+            (device, stranger, folder)
+        };
+
+        let app = test::init_service(device_app(&pool, &device).service(super::rename_folder)).await;
+
+        let req = test::TestRequest::put()
+            .uri(&format!("/folder/{}", folder.id))
+            .set_json(serde_json::json!([{ "name": "name", "device_id": stranger.device_id, "key": "key" }]))
+            .to_request();
+
+        let res: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        assert_eq!("devices_mismatch", res["error"]);
+    }
+
+    #[actix_web::test]
+    async fn it_returns_item_not_found_error_if_folder_does_not_belong_to_user_when_rename_folder_is_called() {
+        let pool = create_pool();
+
+        let (device, other_folder) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let other_folder = create_folder(&mut conn, None).unwrap();
+
+            (device, other_folder)
+        };
+
+        let app = test::init_service(device_app(&pool, &device).service(super::rename_folder)).await;
+
+        let req = test::TestRequest::put()
+            .uri(&format!("/folder/{}", other_folder.id))
+            .set_json(serde_json::json!([]))
+            .to_request();
+
+        let res = test::call_service(&app, req).await;
+
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+
+        let res: serde_json::Value = test::read_body_json(res).await;
+
+        assert_eq!("item_not_found", res["error"]);
+    }
+
+    #[actix_web::test]
+    async fn it_returns_item_not_found_error_if_target_folder_does_not_belong_to_user_when_move_note_is_called() {
+        let pool = create_pool();
+
+        let (device, note, other_folder) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
+            let note = create_note(&mut conn, Some(folder.id)).unwrap();
+            let other_folder = create_folder(&mut conn, None).unwrap();
+
+            (device, note, other_folder)
+        };
+
+        let res = move_note(
+            Data::new(pool.clone()),
+            Path::from(note.id),
+            Sanitized(Json(MoveNoteRequest {
+                folder_id: other_folder.id,
+                name: "name".to_string(),
+                text: "text".to_string(),
+            })),
+            device,
+            Data::new(create_notify_server()),
+        )
+        .await;
+
+        assert_eq!(HttpError::not_found("item_not_found"), res.unwrap_err());
+
+        let folder_id = notes::table
+            .filter(notes::id.eq(note.id))
+            .select(notes::folder_id)
+            .first::<i32>(&mut pool.get().unwrap())
+            .unwrap();
+
+        assert_eq!(note.folder_id, folder_id);
+    }
+
+    #[actix_web::test]
+    async fn it_moves_note_into_target_folder_when_move_note_is_called() {
+        let pool = create_pool();
+
+        let (device, note, target_folder) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
+            let note = create_note(&mut conn, Some(folder.id)).unwrap();
+            let target_folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
+
+            (device, note, target_folder)
+        };
+
+        let res = move_note(
+            Data::new(pool.clone()),
+            Path::from(note.id),
+            Sanitized(Json(MoveNoteRequest {
+                folder_id: target_folder.id,
+                name: "name".to_string(),
+                text: "text".to_string(),
+            })),
+            device,
+            Data::new(create_notify_server()),
+        )
+        .await;
+
+        assert_eq!(HttpMessage::success(), res.unwrap().0);
+
+        let (folder_id, commit) = notes::table
+            .filter(notes::id.eq(note.id))
+            .select((notes::folder_id, notes::commit))
+            .first::<(i32, i32)>(&mut pool.get().unwrap())
+            .unwrap();
+
+        assert_eq!(target_folder.id, folder_id);
+        assert_eq!(note.commit, commit);
+
+        let content_folder_id = note_contents::table
+            .filter(note_contents::note_id.eq(note.id))
+            .filter(note_contents::commit.eq(commit))
+            .select(note_contents::folder_id)
+            .first::<i32>(&mut pool.get().unwrap())
+            .unwrap();
+
+        assert_eq!(target_folder.id, content_folder_id);
+    }
+
+    #[actix_web::test]
+    async fn it_returns_folder_cycle_error_if_parent_is_a_descendant_when_move_folder_is_called() {
+        let pool = create_pool();
+
+        let (device, folder, child) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
+            let child = create_folder(&mut conn, Some(device.user_id)).unwrap();
+
+            diesel::update(folders::table)
+                .filter(folders::id.eq(child.id))
+                .set(folders::parent_id.eq(folder.id))
+                .execute(&mut conn)
+                .unwrap();
+
+            (device, folder, child)
+        };
+
+        let res = move_folder(
+            Data::new(pool.clone()),
+            Path::from(folder.id),
+            Sanitized(Json(MoveFolderRequest { parent_id: Some(child.id) })),
+            device,
+            Data::new(create_notify_server()),
+        )
+        .await;
+
+        assert_eq!(HttpError::unprocessable_entity("folder_cycle"), res.unwrap_err());
+
+        let parent_id = folders::table
+            .filter(folders::id.eq(folder.id))
+            .select(folders::parent_id)
+            .first::<Option<i32>>(&mut pool.get().unwrap())
+            .unwrap();
+
+        assert_eq!(None, parent_id);
+    }
+
+    #[actix_web::test]
+    async fn it_moves_descendant_folders_into_trash_when_delete_folder_is_called() {
+        let pool = create_pool();
+
+        let (device, folder, child, grandchild, sibling) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
+            let child = create_folder(&mut conn, Some(device.user_id)).unwrap();
+            let grandchild = create_folder(&mut conn, Some(device.user_id)).unwrap();
+            let sibling = create_folder(&mut conn, Some(device.user_id)).unwrap();
+
+            for (id, parent_id) in [(child.id, folder.id), (grandchild.id, child.id)] {
+                diesel::update(folders::table)
+                    .filter(folders::id.eq(id))
+                    .set(folders::parent_id.eq(parent_id))
+                    .execute(&mut conn)
+                    .unwrap();
+            }
+
+            create_note(&mut conn, Some(grandchild.id)).unwrap();
+            create_note(&mut conn, Some(sibling.id)).unwrap();
+
+            (device, folder, child, grandchild, sibling)
+        };
+
+        let res = delete_folder(
+            Data::new(pool.clone()),
+            Path::from(folder.id),
+            device,
+            Data::new(create_notify_server()),
+        )
+        .await;
+
+        assert_eq!(HttpMessage::success(), res.unwrap().0);
+
+        let mut conn = pool.get().unwrap();
+
+        let trashed_folder_ids = folders::table
+            .filter(folders::state.eq(State::Trashed))
+            .order(folders::id)
+            .select(folders::id)
+            .load::<i32>(&mut conn)
+            .unwrap();
+
+        assert_eq!(vec![folder.id, child.id, grandchild.id], trashed_folder_ids);
+
+        let note_folder_ids = notes::table
+            .order(notes::id)
+            .select(notes::folder_id)
+            .load::<i32>(&mut conn)
+            .unwrap();
+
+        assert_eq!(vec![grandchild.id, sibling.id], note_folder_ids);
+    }
+
+    #[actix_web::test]
+    async fn it_restores_only_subfolders_trashed_alongside_when_restore_folder_is_called() {
+        let pool = create_pool();
+
+        let (device, folder, child, grandchild) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
+            let child = create_folder(&mut conn, Some(device.user_id)).unwrap();
+            let grandchild = create_folder(&mut conn, Some(device.user_id)).unwrap();
+
+            for (id, parent_id) in [(child.id, folder.id), (grandchild.id, child.id)] {
+                diesel::update(folders::table)
+                    .filter(folders::id.eq(id))
+                    .set(folders::parent_id.eq(parent_id))
+                    .execute(&mut conn)
+                    .unwrap();
+            }
+
+            (device, folder, child, grandchild)
+        };
+
+        let notify_server = Data::new(create_notify_server());
+
+        for folder_id in [grandchild.id, folder.id] {
+            let res = delete_folder(
+                Data::new(pool.clone()),
+                Path::from(folder_id),
+                device.clone(),
+                notify_server.clone(),
+            )
+            .await;
+
+            assert_eq!(HttpMessage::success(), res.unwrap().0);
+        }
+
+        let res = restore_folder(
+            Data::new(pool.clone()),
+            Path::from(folder.id),
+            device,
+            notify_server,
+        )
+        .await;
+
+        assert_eq!(HttpMessage::success(), res.unwrap().0);
+
+        let trashed_folder_ids = folders::table
+            .filter(folders::id.eq_any([folder.id, child.id, grandchild.id]))
+            .filter(folders::state.eq(State::Trashed))
+            .select(folders::id)
+            .load::<i32>(&mut pool.get().unwrap())
+            .unwrap();
+
+        assert_eq!(vec![grandchild.id], trashed_folder_ids);
+    }
+
+    #[actix_web::test]
+    async fn it_returns_folder_trashed_error_if_folder_is_in_trash_when_restore_note_is_called() {
+        let pool = create_pool();
+
+        let (device, note) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
+            let note = create_note(&mut conn, Some(folder.id)).unwrap();
+
+            diesel::update(folders::table)
+                .filter(folders::id.eq(folder.id))
+                .set(folders::state.eq(State::Trashed))
+                .execute(&mut conn)
+                .unwrap();
+
+            diesel::update(notes::table)
+                .filter(notes::id.eq(note.id))
+                .set(notes::state.eq(State::Trashed))
+                .execute(&mut conn)
+                .unwrap();
+
+            (device, note)
+        };
+
+        let res = restore_note(
+            Data::new(pool.clone()),
+            Path::from(note.id),
+            device,
+            Data::new(create_notify_server()),
+        )
+        .await;
+
+        assert_eq!(HttpError::conflict("folder_trashed"), res.unwrap_err());
+    }
+
+    #[actix_web::test]
+    async fn it_returns_only_completely_uploaded_attachments_when_fetch_attachments_is_called() {
+        let pool = create_pool();
+
+        let (device, note, complete_id) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
+            let note = create_note(&mut conn, Some(folder.id)).unwrap();
+
+            let attachment_ids = diesel::insert_into(attachments::table)
+                .values(&vec![
+                    (attachments::note_id.eq(note.id), attachments::device_id.eq(device.device_id), attachments::name.eq("complete"), attachments::chunk_count.eq(2)),
+                    (attachments::note_id.eq(note.id), attachments::device_id.eq(device.device_id), attachments::name.eq("partial"), attachments::chunk_count.eq(2)),
+                ])
+                .returning(attachments::id)
+                .get_results::<i32>(&mut conn)
+                .unwrap();
+
+            diesel::insert_into(attachment_chunks::table)
+                .values(&vec![
+                    (attachment_chunks::attachment_id.eq(attachment_ids[0]), attachment_chunks::chunk.eq(0), attachment_chunks::data.eq(vec![0u8])),
+                    (attachment_chunks::attachment_id.eq(attachment_ids[0]), attachment_chunks::chunk.eq(1), attachment_chunks::data.eq(vec![1u8])),
+                    (attachment_chunks::attachment_id.eq(attachment_ids[1]), attachment_chunks::chunk.eq(0), attachment_chunks::data.eq(vec![0u8])),
+                ])
+                .execute(&mut conn)
+                .unwrap();
+
+            diesel::insert_into(device_attachments::table)
+                .values((
+                    device_attachments::attachment_id.eq(attachment_ids[0]),
+                    device_attachments::sender_device_id.eq(device.device_id),
+                    device_attachments::receiver_device_id.eq(device.device_id),
+                    device_attachments::key.eq("key"),
+                ))
+                .execute(&mut conn)
+                .unwrap();
+
+            (device, note, attachment_ids[0])
+        };
+
+        let res = fetch_attachments(Data::new(pool), Path::from(note.id), device).await;
+
+        let attachments = res.unwrap().0;
+
+        assert_eq!(1, attachments.len());
+        assert_eq!(complete_id, attachments[0].id);
+        assert_eq!("key", attachments[0].device_attachment.as_ref().unwrap().key);
+    }
+
+    #[actix_web::test]
+    async fn it_returns_item_not_found_error_if_note_is_trashed_when_fetch_attachment_chunk_is_called() {
+        let pool = create_pool();
+
+        let (device, attachment_id) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
+            let note = create_note(&mut conn, Some(folder.id)).unwrap();
+
+            let attachment_id = diesel::insert_into(attachments::table)
+                .values((
+                    attachments::note_id.eq(note.id),
+                    attachments::device_id.eq(device.device_id),
+                    attachments::name.eq("name"),
+                    attachments::chunk_count.eq(1),
+                ))
+                .returning(attachments::id)
+                .get_result::<i32>(&mut conn)
+                .unwrap();
+
+            diesel::insert_into(attachment_chunks::table)
+                .values((
+                    attachment_chunks::attachment_id.eq(attachment_id),
+                    attachment_chunks::chunk.eq(0),
+                    attachment_chunks::data.eq(vec![0u8]),
+                ))
+                .execute(&mut conn)
+                .unwrap();
+
+            diesel::update(notes::table)
+                .filter(notes::id.eq(note.id))
+                .set(notes::state.eq(State::Trashed))
+                .execute(&mut conn)
+                .unwrap();
+
+            (device, attachment_id)
+        };
+
+        let res = fetch_attachment_chunk(Data::new(pool), Path::from((attachment_id, 0)), device).await;
+
+        assert_eq!(HttpError::not_found("item_not_found"), res.unwrap_err());
+    }
+
+    #[actix_web::test]
+    async fn it_returns_invalid_chunk_error_if_chunk_is_out_of_range_when_upload_attachment_chunk_is_called() {
+        let pool = create_pool();
+
+        let (device, attachment_id) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
+            let note = create_note(&mut conn, Some(folder.id)).unwrap();
+
+            let attachment_id = diesel::insert_into(attachments::table)
+                .values((
+                    attachments::note_id.eq(note.id),
+                    attachments::device_id.eq(device.device_id),
+                    attachments::name.eq("name"),
+                    attachments::chunk_count.eq(1),
+                ))
+                .returning(attachments::id)
+                .get_result::<i32>(&mut conn)
+                .unwrap();
+
+            (device, attachment_id)
+        };
+
+        let res = upload_attachment_chunk(
+            Data::new(pool.clone()),
+            Data::new(Quota::default()),
+            Path::from((attachment_id, 1)),
+            Bytes::from_static(b"chunk"),
+            device,
+        )
+        .await;
+
+        assert_eq!(HttpError::unprocessable_entity("invalid_chunk"), res.unwrap_err());
+
+        let chunks = attachment_chunks::table
+            .filter(attachment_chunks::attachment_id.eq(attachment_id))
+            .count()
+            .get_result::<i64>(&mut pool.get().unwrap())
+            .unwrap();
+
+        assert_eq!(0, chunks);
+    }
+
+    #[actix_web::test]
+    async fn it_returns_storage_quota_exceeded_error_if_chunk_exceeds_quota_when_upload_attachment_chunk_is_called() {
+        let pool = create_pool();
+
+        let (device, attachment_id) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
+            let note = create_note(&mut conn, Some(folder.id)).unwrap();
+
+            let attachment_id = diesel::insert_into(attachments::table)
+                .values((
+                    attachments::note_id.eq(note.id),
+                    attachments::device_id.eq(device.device_id),
+                    attachments::name.eq("name"),
+                    attachments::chunk_count.eq(1),
+                ))
+                .returning(attachments::id)
+                .get_result::<i32>(&mut conn)
+                .unwrap();
+
+            (device, attachment_id)
+        };
+
+        let quota = Quota {
+            bytes: 4,
+            ..Default::default()
+        };
+
+        let res = upload_attachment_chunk(
+            Data::new(pool.clone()),
+            Data::new(quota),
+            Path::from((attachment_id, 0)),
+            Bytes::from_static(b"chunk"),
+            device,
+        )
+        .await;
+
+        assert_eq!(HttpError::quota_exceeded("storage_quota_exceeded"), res.unwrap_err());
+
+        let chunks = attachment_chunks::table
+            .filter(attachment_chunks::attachment_id.eq(attachment_id))
+            .count()
+            .get_result::<i64>(&mut pool.get().unwrap())
+            .unwrap();
+
+        assert_eq!(0, chunks);
+    }
+
+    #[actix_web::test]
+    async fn it_requests_device_folder_for_omitted_device_when_create_folder_is_called() {
+        let pool = create_pool();
+
+        let (device, trusted, changed) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let trusted = UserDeviceBuilder::default()
+                .user_id(device.user_id)
+                .pubkey("trusted")
+                .build(&mut conn)
+                .unwrap();
+            // Key of this device has changed, so the sender does not trust it until it is verified again
+            let changed = UserDeviceBuilder::default()
+                .user_id(device.user_id)
+                .pubkey("changed")
+                .build(&mut conn)
+                .unwrap();
+
+            (device, trusted, changed)
+        };
+
+        let request = vec![CreateFolderRequest {
+            name: "name".to_string(),
+            device_id: trusted.device_id,
+            key: "key".to_string(),
+        }];
+
+        let folder = super::create_folder(
+            Data::new(pool.clone()),
+            Data::new(Quota::default()),
+            Query(ParentId { parent_id: None }),
+            Sanitized(Json(request)),
+            device,
+            Data::new(create_notify_server()),
+        )
+        .await
+        .unwrap()
+        .0;
+
+        let mut conn = pool.get().unwrap();
+
+        let receiver_ids = device_folders::table
+            .filter(device_folders::folder_id.eq(folder.id))
+            .select(device_folders::receiver_device_id)
+            .load::<i32>(&mut conn)
+            .unwrap();
+
+        assert_eq!(vec![trusted.device_id], receiver_ids);
+
+        let requested_ids = folder_requests::table
+            .filter(folder_requests::folder_id.eq(folder.id))
+            .select(folder_requests::device_id)
+            .load::<i32>(&mut conn)
+            .unwrap();
+
+        assert_eq!(vec![changed.device_id], requested_ids);
+    }
+
+    #[actix_web::test]
+    async fn it_returns_folder_quota_exceeded_error_if_user_reached_folder_quota_when_create_folder_is_called() {
+        let pool = create_pool();
+
+        let device = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            create_folder(&mut conn, Some(device.user_id)).unwrap();
+
+            device
+        };
+
+        let quota = Quota {
+            folders: 1,
+            ..Default::default()
+        };
+
+        let res = super::create_folder(
+            Data::new(pool.clone()),
+            Data::new(quota),
+            Query(ParentId { parent_id: None }),
+            Sanitized(Json(Vec::new())),
+            device.clone(),
+            Data::new(create_notify_server()),
+        )
+        .await;
+
+        assert_eq!(Some(HttpError::quota_exceeded("folder_quota_exceeded")), res.err());
+
+        let folder_count = folders::table
+            .filter(folders::user_id.eq(device.user_id))
+            .count()
+            .get_result::<i64>(&mut pool.get().unwrap())
+            .unwrap();
+
+        assert_eq!(1, folder_count);
+    }
+
+    #[actix_web::test]
+    async fn it_returns_note_too_large_error_if_note_exceeds_size_limit_when_update_note_is_called() {
+        let pool = create_pool();
+
+        let (device, note) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
+            let note = create_note(&mut conn, Some(folder.id)).unwrap();
+
+            (device, note)
+        };
+
+        let quota = Quota {
+            note_bytes: 8,
+            ..Default::default()
+        };
+
+        let request = UpdateNoteRequest {
+            commit: note.commit,
+            name: "name".to_string(),
+            text: "long text".to_string(),
+        };
+
+        let res = update_note(
+            Data::new(pool.clone()),
+            Data::new(quota),
+            Path::from(note.id),
+            Sanitized(Json(request)),
+            device,
+            Data::new(create_notify_server()),
+        )
+        .await;
+
+        assert_eq!(HttpError::quota_exceeded("note_too_large"), res.unwrap_err());
+
+        let commit = notes::table
+            .filter(notes::id.eq(note.id))
+            .select(notes::commit)
+            .first::<i32>(&mut pool.get().unwrap())
+            .unwrap();
+
+        assert_eq!(note.commit, commit);
+    }
+
+    #[actix_web::test]
+    async fn it_returns_storage_quota_exceeded_error_if_history_of_note_exceeds_quota_when_update_note_is_called() {
+        let pool = create_pool();
+
+        let (device, note) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
+            let note = create_note(&mut conn, Some(folder.id)).unwrap();
+
+            (device, note)
+        };
+
+        let quota = Quota {
+            bytes: 12,
+            ..Default::default()
+        };
+
+        let mut results = vec![];
+        for commit in [note.commit, note.commit + 1] {
+            let request = UpdateNoteRequest {
+                commit,
+                name: "name".to_string(),
+                text: "text".to_string(),
+            };
+
+            let res = update_note(
+                Data::new(pool.clone()),
+                Data::new(quota),
+                Path::from(note.id),
+                Sanitized(Json(request)),
+                device.clone(),
+                Data::new(create_notify_server()),
+            )
+            .await;
+
+            results.push(res.err());
+        }
+
+        assert_eq!(vec![None, Some(HttpError::quota_exceeded("storage_quota_exceeded"))], results);
+
+        let commits = note_contents::table
+            .filter(note_contents::note_id.eq(note.id))
+            .count()
+            .get_result::<i64>(&mut pool.get().unwrap())
+            .unwrap();
+
+        assert_eq!(1, commits);
+    }
+
+    #[actix_web::test]
+    async fn it_shares_folder_with_user_of_given_email_when_add_folder_member_is_called() {
+        let pool = create_pool();
+
+        let (owner, member, folder) = {
+            let mut conn = pool.get().unwrap();
+            let owner = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let member = UserDeviceBuilder::default()
+                .email("member@email.com")
+                .pubkey("member")
+                .build(&mut conn)
+                .unwrap();
+            let parent = create_folder(&mut conn, Some(owner.user_id)).unwrap();
+            let folder: Folder = diesel::insert_into(folders::table)
+                .values((folders::user_id.eq(owner.user_id), folders::parent_id.eq(parent.id)))
+                .get_result(&mut conn)
+                .unwrap();
+            create_note(&mut conn, Some(folder.id)).unwrap();
+
+            (owner, member, folder)
+        };
+
+        let request = AddFolderMemberRequest {
+            email: "member@email.com".to_string(),
+            role: Role::Read,
+        };
+
+        let res = add_folder_member(
+            Data::new(pool.clone()),
+            Path::from(folder.id),
+            Sanitized(Json(request)),
+            owner,
+            Data::new(create_notify_server()),
+        )
+        .await;
+
+        assert_eq!(HttpMessage::success(), res.unwrap().0);
+
+        let changes = fetch_changes(Data::new(pool), member, Query(Since { since: 0 }))
+            .await
+            .unwrap();
+
+        assert_eq!(1, changes.folders.len());
+        assert_eq!(folder.id, changes.folders[0].id);
+        assert_eq!(None, changes.folders[0].parent_id);
+        assert_eq!(Some(Role::Read), changes.folders[0].role);
+        assert!(changes.folders[0].shared);
+        assert_eq!(1, changes.folders[0].commits.len());
+    }
+
+    #[actix_web::test]
+    async fn it_returns_item_not_found_error_if_member_can_only_read_folder_when_update_note_is_called() {
+        let pool = create_pool();
+
+        let (member, note) = {
+            let mut conn = pool.get().unwrap();
+            let owner = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let member = UserDeviceBuilder::default()
+                .email("member@email.com")
+                .pubkey("member")
+                .build(&mut conn)
+                .unwrap();
+            let folder = create_folder(&mut conn, Some(owner.user_id)).unwrap();
+            let note = create_note(&mut conn, Some(folder.id)).unwrap();
+
+            diesel::insert_into(folder_members::table)
+                .values((
+                    folder_members::folder_id.eq(folder.id),
+                    folder_members::user_id.eq(member.user_id),
+                    folder_members::role.eq(Role::Read),
+                ))
+                .execute(&mut conn)
+                .unwrap();
+
+            (member, note)
+        };
+
+        let request = UpdateNoteRequest {
+            commit: note.commit,
+            name: "name".to_string(),
+            text: "text".to_string(),
+        };
+
+        let res = update_note(
+            Data::new(pool.clone()),
+            Data::new(Quota::default()),
+            Path::from(note.id),
+            Sanitized(Json(request)),
+            member,
+            Data::new(create_notify_server()),
+        )
+        .await;
+
+        assert_eq!(HttpError::not_found("item_not_found"), res.unwrap_err());
+
+        let commit = notes::table
+            .filter(notes::id.eq(note.id))
+            .select(notes::commit)
+            .first::<i32>(&mut pool.get().unwrap())
+            .unwrap();
+
+        assert_eq!(note.commit, commit);
+    }
+
+    #[actix_web::test]
+    async fn it_returns_unknown_folder_error_if_member_can_only_read_folder_when_respond_requests_is_called() {
+        let pool = create_pool();
+
+        let (member, requester, folder) = {
+            let mut conn = pool.get().unwrap();
+            let owner = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let requester = UserDeviceBuilder::default()
+                .user_id(owner.user_id)
+                .pubkey("requester")
+                .build(&mut conn)
+                .unwrap();
+            let member = UserDeviceBuilder::default()
+                .email("member@email.com")
+                .pubkey("member")
+                .build(&mut conn)
+                .unwrap();
+            let folder = create_folder(&mut conn, Some(owner.user_id)).unwrap();
+
+            diesel::insert_into(folder_members::table)
+                .values((
+                    folder_members::folder_id.eq(folder.id),
+                    folder_members::user_id.eq(member.user_id),
+                    folder_members::role.eq(Role::Read),
+                ))
+                .execute(&mut conn)
+                .unwrap();
+
+            diesel::insert_into(folder_requests::table)
+                .values((
+                    folder_requests::folder_id.eq(folder.id),
+                    folder_requests::device_id.eq(requester.device_id),
+                ))
+                .execute(&mut conn)
+                .unwrap();
+
+            (member, requester, folder)
+        };
+
+        let app = test::init_service(
+            device_app(&pool, &member)
+                .app_data(Data::new(Quota::default()))
+                .service(super::respond_requests),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/respond-requests")
+            .set_json(serde_json::json!({
+                "device_id": requester.device_id,
+                "folders": [{ "folder_id": folder.id, "name": "name", "key": "key" }],
+                "notes": [],
+            }))
+            .to_request();
+
+        let res: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        assert_eq!("unknown_folder", res["error"]);
+
+        let device_folders = device_folders::table
+            .filter(device_folders::folder_id.eq(folder.id))
+            .count()
+            .get_result::<i64>(&mut pool.get().unwrap())
+            .unwrap();
+
+        assert_eq!(0, device_folders);
+    }
+
+    #[actix_web::test]
+    async fn it_deletes_ciphertexts_of_removed_member_devices_when_remove_folder_member_is_called() {
+        let pool = create_pool();
+
+        let (owner, member, folder, note) = {
+            let mut conn = pool.get().unwrap();
+            let owner = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let member = UserDeviceBuilder::default()
+                .email("member@email.com")
+                .pubkey("member")
+                .build(&mut conn)
+                .unwrap();
+            let folder = create_folder(&mut conn, Some(owner.user_id)).unwrap();
+            let note = create_note(&mut conn, Some(folder.id)).unwrap();
+
+            diesel::insert_into(folder_members::table)
+                .values((
+                    folder_members::folder_id.eq(folder.id),
+                    folder_members::user_id.eq(member.user_id),
+                    folder_members::role.eq(Role::Write),
+                ))
+                .execute(&mut conn)
+                .unwrap();
+
+            for receiver_device_id in [owner.device_id, member.device_id] {
+                diesel::insert_into(device_notes::table)
+                    .values((
+                        device_notes::note_id.eq(note.id),
+                        device_notes::sender_device_id.eq(owner.device_id),
+                        device_notes::receiver_device_id.eq(receiver_device_id),
+                        device_notes::name.eq("name"),
+                        device_notes::text.eq("text"),
+                    ))
+                    .execute(&mut conn)
+                    .unwrap();
+            }
+
+            (owner, member, folder, note)
+        };
+
+        let res = remove_folder_member(
+            Data::new(pool.clone()),
+            Path::from((folder.id, member.user_id)),
+            owner.clone(),
+            Data::new(create_notify_server()),
+        )
+        .await;
+
+        assert_eq!(HttpMessage::success(), res.unwrap().0);
+
+        let receivers = device_notes::table
+            .filter(device_notes::note_id.eq(note.id))
+            .select(device_notes::receiver_device_id)
+            .load::<i32>(&mut pool.get().unwrap())
+            .unwrap();
+
+        assert_eq!(vec![owner.device_id], receivers);
+
+        let changes = fetch_changes(Data::new(pool), member, Query(Since { since: 0 }))
+            .await
+            .unwrap();
+
+        assert_eq!(1, changes.folders.len());
+        assert!(matches!(changes.folders[0].state, State::Deleted));
+        assert!(changes.folders[0].commits.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn it_returns_note_already_converted_error_if_note_has_updates_when_create_note_update_is_called_without_previous_id() {
+        let pool = create_pool();
+
+        let (device, note) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
+            let note = create_note(&mut conn, Some(folder.id)).unwrap();
+
+            (device, note)
+        };
+
+        let create = |data: &str, previous_id: Option<i32>| create_note_update(
+            Data::new(pool.clone()),
+            Data::new(Quota::default()),
+            Path::from(note.id),
+            Sanitized(Json(CreateNoteUpdateRequest { data: data.to_string(), previous_id, snapshot: false })),
+            device.clone(),
+            Data::new(create_notify_server()),
+        );
+
+        let first = create("first", None).await.unwrap();
+
+        assert_eq!(Some(HttpError::conflict("note_already_converted")), create("second", None).await.err());
+
+        let update_ids = note_updates::table
+            .filter(note_updates::note_id.eq(note.id))
+            .select(note_updates::id)
+            .load::<i32>(&mut pool.get().unwrap())
+            .unwrap();
+
+        assert_eq!(vec![first.id], update_ids);
+    }
+
+    #[actix_web::test]
+    async fn it_returns_updates_after_the_given_id_when_fetch_note_updates_is_called() {
+        let pool = create_pool();
+
+        let (device, note) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
+            let note = create_note(&mut conn, Some(folder.id)).unwrap();
+
+            (device, note)
+        };
+
+        let create = |data: &str, previous_id: Option<i32>| create_note_update(
+            Data::new(pool.clone()),
+            Data::new(Quota::default()),
+            Path::from(note.id),
+            Sanitized(Json(CreateNoteUpdateRequest { data: data.to_string(), previous_id, snapshot: false })),
+            device.clone(),
+            Data::new(create_notify_server()),
+        );
+
+        let first = create("first", None).await.unwrap();
+        let second = create("second", Some(first.id)).await.unwrap();
+
+        let updates = fetch_note_updates(
+            Data::new(pool.clone()),
+            Path::from(note.id),
+            Query(After { after: first.id }),
+            device,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(1, updates.len());
+        assert_eq!(second.id, updates[0].id);
+        assert_eq!(note.folder_id, updates[0].folder_id);
+        assert_eq!("second", updates[0].data);
+
+        let update_id = notes::table
+            .filter(notes::id.eq(note.id))
+            .select(notes::update_id)
+            .first::<Option<i32>>(&mut pool.get().unwrap())
+            .unwrap();
+
+        assert_eq!(Some(second.id), update_id);
+    }
+
+    #[actix_web::test]
+    async fn it_returns_unknown_previous_update_error_if_previous_update_belongs_to_another_note_when_create_note_update_is_called() {
+        let pool = create_pool();
+
+        let (device, note, other_note) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
+            let note = create_note(&mut conn, Some(folder.id)).unwrap();
+            let other_note = create_note(&mut conn, Some(folder.id)).unwrap();
+
+            (device, note, other_note)
+        };
+
+        let create = |note_id: i32, previous_id: Option<i32>| create_note_update(
+            Data::new(pool.clone()),
+            Data::new(Quota::default()),
+            Path::from(note_id),
+            Sanitized(Json(CreateNoteUpdateRequest { data: "data".to_string(), previous_id, snapshot: false })),
+            device.clone(),
+            Data::new(create_notify_server()),
+        );
+
+        create(note.id, None).await.unwrap();
+        let other = create(other_note.id, None).await.unwrap();
+
+        assert_eq!(
+            Some(HttpError::unprocessable_entity("unknown_previous_update")),
+            create(note.id, Some(other.id)).await.err()
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_prunes_updates_before_the_snapshot_when_create_note_update_is_called_with_snapshot() {
+        let pool = create_pool();
+
+        let (device, note) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
+            let note = create_note(&mut conn, Some(folder.id)).unwrap();
+
+            (device, note)
+        };
+
+        let create = |data: &str, previous_id: Option<i32>, snapshot: bool| create_note_update(
+            Data::new(pool.clone()),
+            Data::new(Quota::default()),
+            Path::from(note.id),
+            Sanitized(Json(CreateNoteUpdateRequest { data: data.to_string(), previous_id, snapshot })),
+            device.clone(),
+            Data::new(create_notify_server()),
+        );
 
-        Ok(())
-    })
-    .await??;
+        let first = create("first", None, false).await.unwrap();
+        let second = create("second", Some(first.id), false).await.unwrap();
 
-    Ok(Json(HttpMessage::success()))
-}
+        assert_eq!(2, second.updates);
+        assert_eq!(Some(HttpError::conflict("stale_snapshot")), create("snapshot", Some(first.id), true).await.err());
 
-#[post("respond-requests")]
-pub async fn respond_requests(
-    pool: Data<Pool>,
-    device: UserDevice,
-    request: Sanitized<Json<RespondRequests>>,
-    ws_server: Data<notify::ws::AddrServer>,
-) -> Result<Json<HttpMessage>, HttpError> {
-    let request = request.0 .0;
+        let snapshot = create("snapshot", Some(second.id), true).await.unwrap();
 
-    if request.folders.len() == 0 && request.notes.len() == 0 {
-        return Err(HttpError::unprocessable_entity("no_request_specified"));
-    }
+        assert_eq!(1, snapshot.updates);
 
-    block(move || {
-        let mut conn = pool.get().unwrap();
+        let update_ids = note_updates::table
+            .filter(note_updates::note_id.eq(note.id))
+            .select(note_updates::id)
+            .load::<i32>(&mut pool.get().unwrap())
+            .unwrap();
 
-        let requested_folder_ids: Vec<i32> =
-            request.folders.iter().map(|req| req.folder_id).collect();
-        let requested_note_ids: Vec<i32> = request.notes.iter().map(|req| req.note_id).collect();
+        assert_eq!(vec![snapshot.id], update_ids);
+    }
 
-        let folder_count = folders::table
-            .filter(folders::user_id.eq(device.user_id))
-            .filter(folders::id.eq_any(requested_folder_ids.as_slice()))
-            .filter(folder_requests::device_id.eq(request.device_id))
-            .inner_join(folder_requests::table)
-            .select(diesel::dsl::count(folders::id))
-            .get_result::<i64>(&mut conn)?;
+    #[actix_web::test]
+    async fn it_returns_a_page_of_updates_when_fetch_note_updates_is_called() {
+        let pool = create_pool();
 
-        if folder_count != requested_folder_ids.len() as i64 {
-            return Err(HttpError::unprocessable_entity("unknown_folder"));
-        }
+        let (device, note) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
+            let note = create_note(&mut conn, Some(folder.id)).unwrap();
 
-        let note_count = notes::table
-            .filter(folders::user_id.eq(device.user_id))
-            .filter(notes::id.eq_any(requested_note_ids.as_slice()))
-            .inner_join(folders::table)
-            .inner_join(
-                note_requests::table.on(note_requests::note_id
-                    .eq(notes::id)
-                    .and(note_requests::device_id.eq(request.device_id))),
-            )
-            .select(diesel::dsl::count(notes::id))
-            .get_result::<i64>(&mut conn)?;
+            let updates = (0..super::NOTE_UPDATES_PAGE + 1)
+                .map(|_| (note_updates::note_id.eq(note.id), note_updates::folder_id.eq(folder.id), note_updates::data.eq("data")))
+                .collect::<Vec<_>>();
 
-        if note_count != requested_note_ids.len() as i64 {
-            return Err(HttpError::unprocessable_entity("unknown_note"));
-        }
+            diesel::insert_into(note_updates::table)
+                .values(&updates)
+                .execute(&mut conn)
+                .unwrap();
 
-        let device_id = request.device_id;
+            (device, note)
+        };
 
-        conn.transaction(move |conn| -> Result<(), diesel::result::Error> {
-            if requested_folder_ids.len() > 0 {
-                let values = request
-                    .folders
-                    .into_iter()
-                    .map(|req| {
-                        (
-                            device_folders::folder_id.eq(req.folder_id),
-                            device_folders::receiver_device_id.eq(request.device_id),
-                            device_folders::sender_device_id.eq(device.device_id),
-                            device_folders::name.eq(req.name),
-                        )
-                    })
-                    .collect::<Vec<_>>();
+        let fetch = |after: i32| fetch_note_updates(
+            Data::new(pool.clone()),
+            Path::from(note.id),
+            Query(After { after }),
+            device.clone(),
+        );
 
-                diesel::insert_into(device_folders::table)
-                    .values(values)
-                    .on_conflict_do_nothing()
-                    .execute(conn)?;
+        let page = fetch(0).await.unwrap();
 
-                diesel::delete(folder_requests::table)
-                    .filter(folder_requests::device_id.eq(request.device_id))
-                    .filter(folder_requests::folder_id.eq_any(requested_folder_ids.as_slice()))
-                    .execute(conn)?;
-            }
+        assert_eq!(super::NOTE_UPDATES_PAGE as usize, page.len());
 
-            if requested_note_ids.len() > 0 {
-                let values = request
-                    .notes
-                    .into_iter()
-                    .map(|req| {
-                        (
-                            device_notes::note_id.eq(req.note_id),
-                            device_notes::receiver_device_id.eq(request.device_id),
-                            device_notes::sender_device_id.eq(device.device_id),
-                            device_notes::name.eq(req.name),
-                            device_notes::text.eq(req.text),
-                        )
-                    })
-                    .collect::<Vec<_>>();
+        let next = fetch(page.last().unwrap().id).await.unwrap();
 
-                diesel::insert_into(device_notes::table)
-                    .values(values)
-                    .on_conflict_do_nothing()
-                    .execute(conn)?;
+        assert_eq!(1, next.len());
+    }
 
-                diesel::delete(note_requests::table)
-                    .filter(note_requests::device_id.eq(request.device_id))
-                    .filter(note_requests::note_id.eq_any(requested_note_ids.as_slice()))
-                    .execute(conn)?;
-            }
+    fn idempotent_app(
+        pool: &base::types::Pool,
+        device: &user::models::UserDevice,
+    ) -> App<
+        impl ServiceFactory<
+            ServiceRequest,
+            Config = (),
+            Response = ServiceResponse<impl MessageBody>,
+            Error = actix_web::Error,
+            InitError = (),
+        >,
+    > {
+        let (user_id, device_id) = (device.user_id, device.device_id);
+
+        App::new()
+            .app_data(Data::new(pool.clone()))
+            .app_data(Data::new(Quota::default()))
+            .app_data(Data::new(create_notify_server()))
+            .wrap(Idempotency)
+            .wrap_fn(move |req, srv| {
+                req.extensions_mut().insert(Token::device(user_id, device_id));
+                srv.call(req)
+            })
+            .service(super::create_note)
+    }
 
-            Ok(())
-        })?;
+    #[actix_web::test]
+    async fn it_returns_stored_response_without_creating_note_again_when_create_note_is_replayed_with_same_idempotency_key() {
+        let pool = create_pool();
 
-        ws_server.do_send(SendDeviceMessage { user_id: device.user_id, device_id, message: DeviceMessage::RefreshRemote });
+        let (device, folder) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
 
-        Ok(())
-    })
-    .await??;
+            (device, folder)
+        };
 
-    Ok(Json(HttpMessage::success()))
-}
+        let app = test::init_service(idempotent_app(&pool, &device)).await;
 
-#[cfg(test)]
-mod tests {
-    use crate::{
-        models::{Folder, Note},
-        requests::CreateRequests,
-    };
-    use base::{
-        sanitize::Sanitized,
-        schema::{folder_requests, folders, notes, users, note_requests},
-        HttpError, HttpMessage,
-    };
-    use test_helpers::db::create_pool;
-    use chrono::NaiveDateTime;
-    use user::test::db::UserDeviceBuilder;
-    use notify::test::ws::create_server as create_notify_server;
+        let mut bodies = vec![];
+        for _ in 0..2 {
+            let req = test::TestRequest::post()
+                .uri(&format!("/note?folder_id={}", folder.id))
+                .insert_header((IDEMPOTENCY_KEY, "key"))
+                .set_json(serde_json::json!({ "name": "name", "text": "text" }))
+                .to_request();
 
-    use super::create_requests;
+            let res = test::call_service(&app, req).await;
 
-    use actix_web::web::{Data, Json};
-    use diesel::{prelude::*, PgConnection};
+            assert_eq!(StatusCode::OK, res.status());
 
-    fn create_folder(
-        conn: &mut PgConnection,
-        user_id: Option<i32>,
-    ) -> Result<Folder, diesel::result::Error> {
-        let user_id = if let Some(user_id) = user_id {
-            user_id
-        } else {
-            diesel::insert_into(users::table)
-                .values(users::email.eq("folder@email.com"))
-                .get_result::<(i32, String, NaiveDateTime)>(conn)?
-                .0
-        };
+            bodies.push(test::read_body(res).await);
+        }
 
-        diesel::insert_into(folders::table)
-            .values(folders::user_id.eq(user_id))
-            .get_result(conn)
-    }
+        assert_eq!(bodies[0], bodies[1]);
 
-    fn create_note(
-        conn: &mut PgConnection,
-        folder_id: Option<i32>,
-    ) -> Result<Note, diesel::result::Error> {
-        let folder_id = if let Some(folder_id) = folder_id {
-            folder_id
-        } else {
-            let user_id = diesel::insert_into(users::table)
-                .values(users::email.eq("note@email.com"))
-                .get_result::<(i32, String, NaiveDateTime)>(conn)?
-                .0;
+        let note_ids = notes::table
+            .filter(notes::folder_id.eq(folder.id))
+            .select(notes::id)
+            .load::<i32>(&mut pool.get().unwrap())
+            .unwrap();
 
-            diesel::insert_into(folders::table)
-                .values(folders::user_id.eq(user_id))
-                .get_result::<Folder>(conn)?
-                .id
-        };
+        let created: serde_json::Value = serde_json::from_slice(&bodies[0]).unwrap();
 
-        diesel::insert_into(notes::table)
-            .values(notes::folder_id.eq(folder_id))
-            .get_result(conn)
+        assert_eq!(1, note_ids.len());
+        assert_eq!(note_ids[0], created["id"]);
     }
 
     #[actix_web::test]
-    async fn it_returns_no_request_specified_error_if_ids_are_empty_when_create_request_is_called()
-    {
+    async fn it_returns_idempotency_key_reused_error_when_same_idempotency_key_is_sent_with_another_request() {
         let pool = create_pool();
 
-        let device = UserDeviceBuilder::default().build(&mut pool.get().unwrap()).unwrap();
-        let request = CreateRequests {
-            folder_ids: Vec::new(),
-            note_ids: Vec::new(),
+        let (device, folders) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let first = create_folder(&mut conn, Some(device.user_id)).unwrap();
+            let second = create_folder(&mut conn, Some(device.user_id)).unwrap();
+
+            (device, [first, second])
         };
 
-        let res = create_requests(Data::new(pool), device, Sanitized(Json(request)), Data::new(create_notify_server())).await;
+        let app = test::init_service(idempotent_app(&pool, &device)).await;
 
-        assert_eq!(
-            HttpError::unprocessable_entity("no_request_specified"),
-            res.unwrap_err()
-        );
+        let mut statuses = vec![];
+        for folder in &folders {
+            let req = test::TestRequest::post()
+                .uri(&format!("/note?folder_id={}", folder.id))
+                .insert_header((IDEMPOTENCY_KEY, "key"))
+                .set_json(serde_json::json!({ "name": "name", "text": "text" }))
+                .to_request();
+
+            statuses.push(test::call_service(&app, req).await.status());
+        }
+
+        assert_eq!(vec![StatusCode::OK, StatusCode::UNPROCESSABLE_ENTITY], statuses);
+
+        let notes_in_second = notes::table
+            .filter(notes::folder_id.eq(folders[1].id))
+            .count()
+            .get_result::<i64>(&mut pool.get().unwrap())
+            .unwrap();
+
+        assert_eq!(0, notes_in_second);
     }
 
     #[actix_web::test]
-    async fn it_returns_unknown_folder_error_if_one_of_the_folder_id_does_not_belong_to_user_when_create_request_is_called(
-    ) {
+    async fn it_returns_idempotency_key_reused_error_when_same_idempotency_key_is_sent_with_another_body() {
         let pool = create_pool();
 
         let (device, folder) = {
             let mut conn = pool.get().unwrap();
             let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
-            let folder = create_folder(&mut conn, None).unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
 
             (device, folder)
         };
 
-        let request = CreateRequests {
-            folder_ids: vec![folder.id],
-            note_ids: Vec::new(),
-        };
+        let app = test::init_service(idempotent_app(&pool, &device)).await;
 
-        let res = create_requests(Data::new(pool), device, Sanitized(Json(request)), Data::new(create_notify_server())).await;
+        let mut statuses = vec![];
+        for name in ["first", "second"] {
+            let req = test::TestRequest::post()
+                .uri(&format!("/note?folder_id={}", folder.id))
+                .insert_header((IDEMPOTENCY_KEY, "key"))
+                .set_json(serde_json::json!({ "name": name, "text": "text" }))
+                .to_request();
 
-        assert_eq!(
-            HttpError::unprocessable_entity("unknown_folder"),
-            res.unwrap_err()
-        );
+            statuses.push(test::call_service(&app, req).await.status());
+        }
+
+        assert_eq!(vec![StatusCode::OK, StatusCode::UNPROCESSABLE_ENTITY], statuses);
+
+        let names = note_contents::table
+            .filter(note_contents::folder_id.eq(folder.id))
+            .select(note_contents::name)
+            .load::<String>(&mut pool.get().unwrap())
+            .unwrap();
+
+        assert_eq!(vec!["first".to_string()], names);
     }
 
     #[actix_web::test]
-    async fn it_returns_unknown_note_error_if_one_of_the_note_id_does_not_belong_to_user_when_create_request_is_called(
-    ) {
+    async fn it_returns_payload_too_large_error_without_reserving_idempotency_key_when_body_exceeds_the_limit() {
         let pool = create_pool();
 
-        let (device, note) = {
+        let (device, folder) = {
             let mut conn = pool.get().unwrap();
             let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
-            let note = create_note(&mut conn, None).unwrap();
+            let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
 
-            (device, note)
+            (device, folder)
         };
 
-        let request = CreateRequests {
-            folder_ids: Vec::new(),
-            note_ids: vec![note.id],
-        };
+        let app = test::init_service(idempotent_app(&pool, &device)).await;
 
-        let res = create_requests(Data::new(pool), device, Sanitized(Json(request)), Data::new(create_notify_server())).await;
+        let req = test::TestRequest::post()
+            .uri(&format!("/note?folder_id={}", folder.id))
+            .insert_header((IDEMPOTENCY_KEY, "key"))
+            .set_json(serde_json::json!({ "name": "name", "text": "a".repeat(MAX_BODY_LEN) }))
+            .to_request();
 
-        assert_eq!(
-            HttpError::unprocessable_entity("unknown_note"),
-            res.unwrap_err()
-        );
+        let res = test::call_service(&app, req).await;
+
+        assert_eq!(StatusCode::PAYLOAD_TOO_LARGE, res.status());
+
+        let keys = idempotency_keys::table
+            .filter(idempotency_keys::device_id.eq(device.device_id))
+            .count()
+            .get_result::<i64>(&mut pool.get().unwrap())
+            .unwrap();
+
+        assert_eq!(0, keys);
     }
 
     #[actix_web::test]
-    async fn it_creates_rows_for_folders_and_notes_when_create_request_is_called() {
+    async fn it_does_not_create_note_again_when_create_note_is_replayed_after_an_interrupted_request() {
         let pool = create_pool();
 
-        let (device, folder, note) = {
+        let (device, folder) = {
             let mut conn = pool.get().unwrap();
             let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
             let folder = create_folder(&mut conn, Some(device.user_id)).unwrap();
-            let note = create_note(&mut conn, Some(folder.id)).unwrap();
 
-            (device, folder, note)
+            (device, folder)
         };
 
-        let request = CreateRequests {
-            folder_ids: vec![folder.id],
-            note_ids: vec![note.id],
+        let app = test::init_service(idempotent_app(&pool, &device)).await;
+
+        let request = || {
+            test::TestRequest::post()
+                .uri(&format!("/note?folder_id={}", folder.id))
+                .insert_header((IDEMPOTENCY_KEY, "key"))
+                .set_json(serde_json::json!({ "name": "name", "text": "text" }))
+                .to_request()
         };
 
-        let res = create_requests(
-            Data::new(pool.clone()),
-            device.clone(),
-            Sanitized(Json(request)),
-            Data::new(create_notify_server()),
-        )
-        .await;
+        assert_eq!(StatusCode::OK, test::call_service(&app, request()).await.status());
 
-        assert_eq!(HttpMessage::success(), res.unwrap().0);
+        // The request is applied but its response is not stored, as if the server was stopped meanwhile
+        diesel::update(idempotency_keys::table)
+            .filter(idempotency_keys::device_id.eq(device.device_id))
+            .set((
+                idempotency_keys::status.eq(None::<i32>),
+                idempotency_keys::body.eq(None::<String>),
+                idempotency_keys::created_at.eq(Utc::now().naive_utc() - Duration::minutes(5)),
+            ))
+            .execute(&mut pool.get().unwrap())
+            .unwrap();
 
-        let folder_request_exist = diesel::select(diesel::dsl::exists(
-            folder_requests::table
-                .filter(folder_requests::folder_id.eq(folder.id))
-                .filter(folder_requests::device_id.eq(device.device_id)),
-        ))
-        .get_result::<bool>(&mut pool.get().unwrap())
-        .unwrap();
+        let res = test::call_service(&app, request()).await;
 
-        let note_request_exist = diesel::select(diesel::dsl::exists(
-            note_requests::table
-                .filter(note_requests::note_id.eq(note.id))
-                .filter(note_requests::device_id.eq(device.device_id)),
-        ))
-        .get_result::<bool>(&mut pool.get().unwrap())
-        .unwrap();
+        assert_eq!(StatusCode::CONFLICT, res.status());
 
-        assert!(folder_request_exist);
-        assert!(note_request_exist);
+        let notes_in_folder = notes::table
+            .filter(notes::folder_id.eq(folder.id))
+            .count()
+            .get_result::<i64>(&mut pool.get().unwrap())
+            .unwrap();
+
+        assert_eq!(1, notes_in_folder);
     }
 }
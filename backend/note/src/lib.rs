@@ -1,23 +1,45 @@
-use actix_web::web::{post, scope, ServiceConfig, get};
-use base::middlewares::auth_user::AuthUser;
+use actix_web::web::{delete, post, put, scope, ServiceConfig, get};
+use base::middlewares::{auth_user::AuthUser, idempotency::Idempotency};
 
 mod handlers;
 mod models;
 mod requests;
 mod responses;
+mod access;
+pub mod trash;
 
 pub fn register(config: &mut ServiceConfig) {
     config.service(
         scope("api/note")
+            .wrap(Idempotency)
             .wrap(AuthUser)
             .service(handlers::fetch_folders)
             .route("folder/{folder_id}", get().to(handlers::fetch_folder))
-            .service(handlers::create_folder)
-            .service(handlers::delete_folder)
+            .route("changes", get().to(handlers::fetch_changes))
+            .route("folder", post().to(handlers::create_folder))
+            .service(handlers::rename_folder)
+            .route("folder/{folder_id}/move", put().to(handlers::move_folder))
+            .route("folder/{folder_id}", delete().to(handlers::delete_folder))
+            .route("folder/{folder_id}/restore", put().to(handlers::restore_folder))
+            .route("folder/{folder_id}/members", get().to(handlers::fetch_folder_members))
+            .route("folder/{folder_id}/members", post().to(handlers::add_folder_member))
+            .route("folder/{folder_id}/members/{user_id}", delete().to(handlers::remove_folder_member))
+            .route("folder/{folder_id}/devices", get().to(handlers::fetch_folder_devices))
             .service(handlers::fetch_note)
             .service(handlers::create_note)
-            .service(handlers::update_note)
+            .route("note/{note_id}", put().to(handlers::update_note))
+            .route("note/{note_id}/move", put().to(handlers::move_note))
+            .route("note/{note_id}/commits", get().to(handlers::fetch_note_commits))
+            .route("note/{note_id}/updates", get().to(handlers::fetch_note_updates))
+            .route("note/{note_id}/updates", post().to(handlers::create_note_update))
+            .service(handlers::fetch_note_commit)
             .service(handlers::delete_note)
+            .route("note/{note_id}/restore", put().to(handlers::restore_note))
+            .route("note/{note_id}/attachments", get().to(handlers::fetch_attachments))
+            .route("note/{note_id}/attachments", post().to(handlers::create_attachment))
+            .route("attachment/{attachment_id}/chunk/{chunk}", put().to(handlers::upload_attachment_chunk))
+            .route("attachment/{attachment_id}/chunk/{chunk}", get().to(handlers::fetch_attachment_chunk))
+            .route("attachment/{attachment_id}", delete().to(handlers::delete_attachment))
             .service(handlers::fetch_requests)
             .route("requests", post().to(handlers::create_requests))
             .service(handlers::respond_requests),
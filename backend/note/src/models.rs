@@ -1,3 +1,4 @@
+use base::sanitize::Sanitize;
 use chrono::NaiveDateTime;
 use diesel::{
     backend::RawValue,
@@ -6,7 +7,7 @@ use diesel::{
     serialize::{self, ToSql},
     AsExpression, FromSqlRow, Queryable,
 };
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::io::Write;
 
 #[derive(Queryable, Serialize)]
@@ -15,6 +16,10 @@ pub struct Folder {
     pub user_id: i32,
     pub state: State,
     pub created_at: NaiveDateTime,
+    pub change_seq: i64,
+    pub parent_id: Option<i32>,
+    pub trashed_at: Option<NaiveDateTime>,
+    pub deleted_at: Option<NaiveDateTime>,
 }
 
 #[derive(Queryable, Serialize)]
@@ -25,12 +30,17 @@ pub struct Note {
     pub state: State,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub change_seq: i64,
+    pub trashed_at: Option<NaiveDateTime>,
+    pub deleted_at: Option<NaiveDateTime>,
+    pub update_id: Option<i32>,
 }
 
 #[derive(AsExpression, Clone, Debug, FromSqlRow, Serialize)]
 #[diesel(sql_type = base::schema::sql_types::State)]
 pub enum State {
     Clean,
+    Trashed,
     Deleted,
 }
 
@@ -40,6 +50,7 @@ impl FromSql<base::schema::sql_types::State, Pg> for State {
 
         match bytes {
             b"Clean" => Ok(State::Clean),
+            b"Trashed" => Ok(State::Trashed),
             b"Deleted" => Ok(State::Deleted),
             _ => Err("Unrecognized enum variant".into()),
         }
@@ -50,8 +61,44 @@ impl ToSql<base::schema::sql_types::State, Pg> for State {
     fn to_sql<'b>(&'b self, out: &mut serialize::Output<'b, '_, Pg>) -> serialize::Result {
         match *self {
             State::Clean => out.write_all(b"Clean")?,
+            State::Trashed => out.write_all(b"Trashed")?,
             State::Deleted => out.write_all(b"Deleted")?,
         }
         Ok(serialize::IsNull::No)
     }
 }
+
+#[derive(AsExpression, Clone, Copy, Debug, Deserialize, FromSqlRow, PartialEq, Serialize)]
+#[diesel(sql_type = base::schema::sql_types::Role)]
+pub enum Role {
+    Read,
+    Write,
+}
+
+impl Sanitize for Role {
+    fn sanitize(self) -> Self {
+        self
+    }
+}
+
+impl FromSql<base::schema::sql_types::Role, Pg> for Role {
+    fn from_sql(value: RawValue<Pg>) -> deserialize::Result<Self> {
+        let bytes = value.as_bytes();
+
+        match bytes {
+            b"Read" => Ok(Role::Read),
+            b"Write" => Ok(Role::Write),
+            _ => Err("Unrecognized enum variant".into()),
+        }
+    }
+}
+
+impl ToSql<base::schema::sql_types::Role, Pg> for Role {
+    fn to_sql<'b>(&'b self, out: &mut serialize::Output<'b, '_, Pg>) -> serialize::Result {
+        match *self {
+            Role::Read => out.write_all(b"Read")?,
+            Role::Write => out.write_all(b"Write")?,
+        }
+        Ok(serialize::IsNull::No)
+    }
+}
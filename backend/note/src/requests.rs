@@ -3,15 +3,19 @@ use serde::Deserialize;
 use base::sanitize::Sanitize;
 use derive::Sanitize;
 
+use crate::models::Role;
+
 #[derive(Deserialize, Sanitize)]
 pub struct CreateFolderRequest {
     pub name: String,
     pub device_id: i32,
+    /// Content key of the folder wrapped for the device
+    pub key: String,
 }
 
+/// Note encrypted with the content key of its folder
 #[derive(Deserialize, Sanitize)]
 pub struct CreateNoteRequest {
-    pub device_id: i32,
     pub name: String,
     pub text: String,
 }
@@ -19,7 +23,53 @@ pub struct CreateNoteRequest {
 #[derive(Deserialize, Sanitize)]
 pub struct UpdateNoteRequest {
     pub commit: i32,
-    pub device_notes: Vec<CreateNoteRequest>,
+    pub name: String,
+    pub text: String,
+}
+
+/// Change of a note document encrypted with the content key of its folder
+#[derive(Deserialize, Sanitize)]
+pub struct CreateNoteUpdateRequest {
+    pub data: String,
+    /// Last update the device has merged, it is none if the update converts the note into a document
+    pub previous_id: Option<i32>,
+    /// Whether the update is the whole document, which replaces the updates up to it
+    #[serde(default)]
+    pub snapshot: bool,
+}
+
+#[derive(Deserialize, Sanitize)]
+pub struct MoveFolderRequest {
+    pub parent_id: Option<i32>,
+}
+
+#[derive(Deserialize, Sanitize)]
+pub struct MoveNoteRequest {
+    pub folder_id: i32,
+    /// Name and text of the note encrypted with the content key of the target folder
+    pub name: String,
+    pub text: String,
+}
+
+#[derive(Deserialize, Sanitize)]
+pub struct CreateAttachmentRequest {
+    pub name: String,
+    pub chunk_count: i32,
+    /// File key of attachment wrapped for the sender device itself
+    pub key: String,
+    pub device_attachments: Vec<DeviceAttachmentRequest>,
+}
+
+#[derive(Deserialize, Sanitize)]
+pub struct DeviceAttachmentRequest {
+    pub device_id: i32,
+    pub key: String,
+}
+
+#[derive(Deserialize, Sanitize)]
+pub struct AddFolderMemberRequest {
+    pub email: String,
+    pub role: Role,
 }
 
 #[derive(Deserialize, Sanitize)]
@@ -33,6 +83,21 @@ pub struct FolderId {
     pub folder_id: i32,
 }
 
+#[derive(Deserialize)]
+pub struct ParentId {
+    pub parent_id: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct Since {
+    pub since: i64,
+}
+
+#[derive(Deserialize)]
+pub struct After {
+    pub after: i32,
+}
+
 #[derive(Deserialize, Sanitize)]
 pub struct RespondRequests {
     pub device_id: i32,
@@ -44,6 +109,8 @@ pub struct RespondRequests {
 pub struct RespondFolderRequest {
     pub folder_id: i32,
     pub name: String,
+    /// It is none if the responding device has not received the key of the folder yet
+    pub key: Option<String>,
 }
 
 #[derive(Deserialize, Sanitize)]
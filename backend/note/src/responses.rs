@@ -1,7 +1,8 @@
+use chrono::NaiveDateTime;
 use diesel::Queryable;
 use serde::Serialize;
 
-use crate::models::State;
+use crate::models::{Role, State};
 
 #[derive(Serialize)]
 pub struct CreatedFolder {
@@ -19,20 +20,44 @@ pub struct Commit {
     pub note_id: i32,
     pub commit: i32,
     pub state: State,
+    /// Last update of the note document, it is none if the note is not converted into a document
+    pub update_id: Option<i32>,
 }
 
 #[derive(Queryable, Serialize)]
 pub struct Folder {
     pub id: i32,
+    /// Position of the folder in the change sequence of the user. Changes since right before it include the folder
+    /// and all of its notes again
+    pub seq: i64,
+    pub parent_id: Option<i32>,
     pub state: State,
+    /// Whether the folder has members other than its owner
+    pub shared: bool,
+    /// Role of the user in the folder, it is none if the user owns the folder
+    pub role: Option<Role>,
     pub device_folder: Option<DeviceFolder>,
+    /// Whether a device of the folder has received its content key. Devices without the key wait for it instead of
+    /// giving the folder a new one
+    pub keyed: bool,
     pub commits: Vec<Commit>,
 }
 
+#[derive(Serialize)]
+pub struct Changes {
+    pub cursor: i64,
+    pub folders: Vec<Folder>,
+    /// Whether all the folders are returned since some deletions after the given cursor are purged. Devices drop the
+    /// folders and notes which are not returned.
+    pub resync: bool,
+}
+
 #[derive(Queryable, Serialize)]
 pub struct DeviceFolder {
     pub sender_device_id: i32,
     pub name: String,
+    /// Content key of the folder, it is none if the folder is not given a key yet
+    pub key: Option<String>,
 }
 
 #[derive(Queryable, Serialize)]
@@ -40,7 +65,17 @@ pub struct Note {
     pub id: i32,
     pub commit: i32,
     pub state: State,
+    /// Ciphertext of the note encrypted for this device, only the notes which are not written
+    /// since the folder keys are introduced have it
     pub device_note: Option<DeviceNote>,
+    pub content: Option<NoteContent>,
+}
+
+#[derive(Serialize)]
+pub struct CommitMismatch {
+    pub code: u16,
+    pub error: &'static str,
+    pub note: Note,
 }
 
 #[derive(Queryable, Serialize)]
@@ -50,6 +85,70 @@ pub struct DeviceNote {
     pub text: String,
 }
 
+/// Note encrypted with the content key of the folder
+#[derive(Queryable, Serialize)]
+pub struct NoteContent {
+    pub folder_id: i32,
+    pub sender_device_id: i32,
+    pub name: String,
+    pub text: String,
+}
+
+#[derive(Serialize)]
+pub struct CreatedNoteUpdate {
+    pub id: i32,
+    /// Number of updates the note has, devices send a snapshot once they pile up
+    pub updates: i64,
+}
+
+/// Change of a note document encrypted with the content key of the folder
+#[derive(Queryable, Serialize)]
+pub struct NoteUpdate {
+    pub id: i32,
+    /// Folder whose key the update is encrypted with
+    pub folder_id: i32,
+    pub data: String,
+}
+
+#[derive(Serialize)]
+pub struct CreatedAttachment {
+    pub id: i32,
+}
+
+#[derive(Queryable, Serialize)]
+pub struct Attachment {
+    pub id: i32,
+    pub name: String,
+    pub chunk_count: i32,
+    pub device_attachment: Option<DeviceAttachment>,
+}
+
+#[derive(Queryable, Serialize)]
+pub struct DeviceAttachment {
+    pub sender_device_id: i32,
+    pub key: String,
+}
+
+#[derive(Queryable, Serialize)]
+pub struct NoteCommit {
+    pub commit: i32,
+    pub sender_device_id: i32,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Serialize)]
+pub struct DeviceNoteCommit {
+    pub commit: i32,
+    pub sender_device_id: i32,
+    /// Device the commit is encrypted for, it is none if the commit is encrypted with a folder key
+    pub receiver_device_id: Option<i32>,
+    /// Folder whose key the commit is encrypted with
+    pub folder_id: Option<i32>,
+    pub name: String,
+    pub text: String,
+    pub created_at: NaiveDateTime,
+}
+
 #[derive(Serialize)]
 pub struct Requests {
     pub folder_requests: Vec<FolderRequest>,
@@ -67,3 +166,19 @@ pub struct NoteRequest {
     pub note_id: i32,
     pub device_id: i32,
 }
+
+#[derive(Queryable, Serialize)]
+pub struct FolderMember {
+    pub user_id: i32,
+    pub email: String,
+    pub role: Role,
+}
+
+#[derive(Queryable, Serialize)]
+pub struct FolderDevice {
+    pub id: i32,
+    pub pubkey: String,
+    pub previous_pubkey: Option<String>,
+    /// Email of the user that the device belongs to, so that its key can be checked against the directory of the user
+    pub email: String,
+}
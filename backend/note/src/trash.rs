@@ -0,0 +1,135 @@
+use chrono::{Duration, Utc};
+use diesel::{prelude::*, PgConnection};
+
+use base::schema::{attachments, device_folders, device_notes, folders, note_contents, notes};
+
+use crate::models::State;
+
+/// Deletes the folders and notes which have been in the trash longer than the retention period.
+/// Returns the number of purged folders and notes.
+pub fn purge(conn: &mut PgConnection, retention: Duration) -> Result<(usize, usize), diesel::result::Error> {
+    let now = Utc::now().naive_utc();
+    let threshold = now - retention;
+
+    conn.transaction(|conn| {
+        // Rows are kept in deleted state so that other devices can pull the deletion
+        let folder_ids = diesel::update(folders::table)
+            .filter(folders::state.eq(State::Trashed))
+            .filter(folders::trashed_at.lt(threshold))
+            .set((folders::state.eq(State::Deleted), folders::deleted_at.eq(now)))
+            .returning(folders::id)
+            .get_results::<i32>(conn)?;
+
+        diesel::delete(notes::table)
+            .filter(notes::folder_id.eq_any(&folder_ids))
+            .execute(conn)?;
+
+        diesel::delete(device_folders::table)
+            .filter(device_folders::folder_id.eq_any(&folder_ids))
+            .execute(conn)?;
+
+        let note_ids = diesel::update(notes::table)
+            .filter(notes::state.eq(State::Trashed))
+            .filter(notes::trashed_at.lt(threshold))
+            .set((notes::state.eq(State::Deleted), notes::deleted_at.eq(now)))
+            .returning(notes::id)
+            .get_results::<i32>(conn)?;
+
+        diesel::delete(device_notes::table)
+            .filter(device_notes::note_id.eq_any(&note_ids))
+            .execute(conn)?;
+
+        diesel::delete(note_contents::table)
+            .filter(note_contents::note_id.eq_any(&note_ids))
+            .execute(conn)?;
+
+        diesel::delete(attachments::table)
+            .filter(attachments::note_id.eq_any(&note_ids))
+            .execute(conn)?;
+
+        Ok((folder_ids.len(), note_ids.len()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use base::schema::{folders, notes, users};
+    use chrono::{Duration, Utc};
+    use diesel::prelude::*;
+    use test_helpers::db::create_pool;
+
+    use crate::models::State;
+
+    use super::purge;
+
+    #[test]
+    fn it_deletes_only_expired_items_when_purge_is_called() {
+        let pool = create_pool();
+        let mut conn = pool.get().unwrap();
+
+        let user_id = diesel::insert_into(users::table)
+            .values(users::email.eq("trash@email.com"))
+            .returning(users::id)
+            .get_result::<i32>(&mut conn)
+            .unwrap();
+
+        let now = Utc::now().naive_utc();
+
+        let (expired_folder_id, recent_folder_id) = {
+            let mut ids = diesel::insert_into(folders::table)
+                .values(&vec![
+                    (folders::user_id.eq(user_id), folders::state.eq(State::Trashed), folders::trashed_at.eq(now - Duration::days(31))),
+                    (folders::user_id.eq(user_id), folders::state.eq(State::Trashed), folders::trashed_at.eq(now - Duration::days(1))),
+                ])
+                .returning(folders::id)
+                .get_results::<i32>(&mut conn)
+                .unwrap()
+                .into_iter();
+
+            (ids.next().unwrap(), ids.next().unwrap())
+        };
+
+        let (expired_note_id, recent_note_id) = {
+            let mut ids = diesel::insert_into(notes::table)
+                .values(&vec![
+                    (notes::folder_id.eq(recent_folder_id), notes::state.eq(State::Trashed), notes::trashed_at.eq(now - Duration::days(31))),
+                    (notes::folder_id.eq(recent_folder_id), notes::state.eq(State::Trashed), notes::trashed_at.eq(now - Duration::days(1))),
+                ])
+                .returning(notes::id)
+                .get_results::<i32>(&mut conn)
+                .unwrap()
+                .into_iter();
+
+            (ids.next().unwrap(), ids.next().unwrap())
+        };
+
+        diesel::insert_into(notes::table)
+            .values(notes::folder_id.eq(expired_folder_id))
+            .execute(&mut conn)
+            .unwrap();
+
+        assert_eq!((1, 1), purge(&mut conn, Duration::days(30)).unwrap());
+
+        let folders = folders::table
+            .filter(folders::user_id.eq(user_id))
+            .order(folders::id)
+            .select((folders::id, folders::state))
+            .load::<(i32, State)>(&mut conn)
+            .unwrap();
+
+        assert!(matches!(folders[0], (id, State::Deleted) if id == expired_folder_id));
+        assert!(matches!(folders[1], (id, State::Trashed) if id == recent_folder_id));
+
+        let notes = notes::table
+            .inner_join(folders::table)
+            .filter(folders::user_id.eq(user_id))
+            .order(notes::id)
+            .select((notes::id, notes::state))
+            .load::<(i32, State)>(&mut conn)
+            .unwrap();
+
+        assert_eq!(2, notes.len());
+        assert!(matches!(notes[0], (id, State::Deleted) if id == expired_note_id));
+        assert!(matches!(notes[1], (id, State::Trashed) if id == recent_note_id));
+    }
+}
@@ -213,10 +213,12 @@ pub mod messages {
     #[serde(rename_all="snake_case")]
     pub enum DeviceMessage {
         AcceptPendingDevice,
+        VerifyPendingDevice { pubkey: String, nonce: String },
         RefreshRequests,
         RefreshRemote,
         RefreshFolder(i32),
         RefreshNote { folder_id: i32, note_id: i32, commit: i32, deleted: bool },
+        RefreshNoteUpdates { folder_id: i32, note_id: i32, update_id: i32 },
         Text(String),
         Timeout,
     }
@@ -7,7 +7,9 @@ use diesel::{
 };
 
 use base::{crypto::Crypto, types::Pool};
+use maintenance::{Config as MaintenanceConfig, Server as MaintenanceServer};
 use notify::{ws::Server as WsServer, mail::{Server as MailServer, MailRecipient}};
+use user::{directory::RateLimit, quota::Quota};
 
 fn setup_database() -> Pool {
     let conn_info = std::env::var("DATABASE_URL").expect("DATABASE_URL is not provided in env");
@@ -19,6 +21,52 @@ fn setup_database() -> Pool {
     pool
 }
 
+fn maintenance_config() -> MaintenanceConfig {
+    let days = |name: &str, default: chrono::Duration| {
+        std::env::var(name)
+            .map(|days| chrono::Duration::days(days.parse().unwrap_or_else(|_| panic!("{name} is not a number"))))
+            .unwrap_or(default)
+    };
+
+    let default = MaintenanceConfig::default();
+
+    MaintenanceConfig {
+        trash_retention: days("TRASH_RETENTION_DAYS", default.trash_retention),
+        deleted_retention: days("DELETED_RETENTION_DAYS", default.deleted_retention),
+        request_retention: days("REQUEST_RETENTION_DAYS", default.request_retention),
+        pending_retention: days("PENDING_RETENTION_DAYS", default.pending_retention),
+        rotation_retention: days("ROTATION_RETENTION_DAYS", default.rotation_retention),
+        idempotency_retention: days("IDEMPOTENCY_RETENTION_DAYS", default.idempotency_retention),
+    }
+}
+
+fn quota() -> Quota {
+    let limit = |name: &str, default: i64| {
+        std::env::var(name)
+            .map(|limit| limit.parse().unwrap_or_else(|_| panic!("{name} is not a number")))
+            .unwrap_or(default)
+    };
+
+    let default = Quota::default();
+
+    Quota {
+        bytes: limit("QUOTA_BYTES", default.bytes),
+        notes: limit("QUOTA_NOTES", default.notes),
+        folders: limit("QUOTA_FOLDERS", default.folders),
+        note_bytes: limit("QUOTA_NOTE_BYTES", default.note_bytes),
+    }
+}
+
+fn directory_rate_limit() -> RateLimit {
+    let default = RateLimit::default();
+
+    let lookups = std::env::var("DIRECTORY_LOOKUPS_PER_HOUR")
+        .map(|lookups| lookups.parse().expect("DIRECTORY_LOOKUPS_PER_HOUR is not a number"))
+        .unwrap_or(default.lookups);
+
+    RateLimit::new(lookups, default.window)
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv::dotenv().ok();
@@ -40,6 +88,12 @@ async fn main() -> std::io::Result<()> {
         .start()
         .recipient();
 
+    MaintenanceServer::new(pool.clone(), maintenance_config()).start();
+
+    let quota = quota();
+    // Counters of the rate limit are shared by all the workers
+    let rate_limit = Data::new(directory_rate_limit());
+
     HttpServer::new(move || {
         let cors = Cors::default()
             .allowed_origin(
@@ -62,6 +116,8 @@ async fn main() -> std::io::Result<()> {
             .app_data(Data::new(crypto.clone()))
             .app_data(Data::new(notify_server.clone()))
             .app_data(Data::new(mail_server.clone()))
+            .app_data(Data::new(quota))
+            .app_data(rate_limit.clone())
             .wrap(Logger::default())
             .configure(auth::register)
             .configure(note::register)
@@ -0,0 +1,111 @@
+use std::{collections::HashMap, sync::Mutex, time::{Duration, Instant}};
+
+use diesel::{prelude::*, PgConnection};
+use serde::Serialize;
+
+use base::{
+    crypto::Crypto,
+    schema::{device_key_log, devices, user_devices},
+    HttpError,
+};
+
+/// Limits the directory lookups of each user so that the directory cannot be used to enumerate the emails
+pub struct RateLimit {
+    pub lookups: u32,
+    pub window: Duration,
+    counters: Mutex<HashMap<i32, (Instant, u32)>>,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        RateLimit::new(30, Duration::from_secs(60 * 60))
+    }
+}
+
+impl RateLimit {
+    pub fn new(lookups: u32, window: Duration) -> Self {
+        RateLimit { lookups, window, counters: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn check(&self, user_id: i32) -> Result<(), HttpError> {
+        let now = Instant::now();
+        let mut counters = self.counters.lock().unwrap();
+
+        counters.retain(|_, (started_at, _)| now.duration_since(*started_at) < self.window);
+
+        let (_, lookups) = counters.entry(user_id).or_insert((now, 0));
+
+        if *lookups >= self.lookups {
+            return Err(HttpError::too_many_requests("too_many_lookups"));
+        }
+
+        *lookups += 1;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Queryable, Serialize)]
+pub struct DirectoryDevice {
+    pub id: i32,
+    pub pubkey: String,
+}
+
+#[derive(Debug, Queryable, Serialize)]
+pub struct LogEntry {
+    pub device_id: i32,
+    pub pubkey: String,
+    pub added: bool,
+    pub hash: String,
+}
+
+/// Head of the key log of a user, signed by the server. Clients keep the last record they have seen, so a
+/// server that swaps the keys either has to rewrite the log, which changes the hashes of the seen entries,
+/// or sign two conflicting records, which can be presented as a proof.
+#[derive(Debug, Serialize)]
+pub struct Record {
+    pub seq: i64,
+    pub head: String,
+    pub signer: String,
+    pub signature: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Directory {
+    pub email: String,
+    pub devices: Vec<DirectoryDevice>,
+    pub log: Vec<LogEntry>,
+    pub record: Record,
+}
+
+impl Record {
+    pub fn message(email: &str, seq: i64, head: &str) -> String {
+        format!("{email}:{seq}:{head}")
+    }
+}
+
+pub fn directory(conn: &mut PgConnection, crypto: &Crypto, user_id: i32, email: String) -> Result<Directory, HttpError> {
+    let devices = user_devices::table
+        .inner_join(devices::table)
+        .filter(user_devices::user_id.eq(user_id))
+        .order(devices::id)
+        .select((devices::id, devices::pubkey))
+        .load::<DirectoryDevice>(conn)?;
+
+    let log = device_key_log::table
+        .filter(device_key_log::user_id.eq(user_id))
+        .order(device_key_log::id)
+        .select((device_key_log::device_id, device_key_log::pubkey, device_key_log::added, device_key_log::hash))
+        .load::<LogEntry>(conn)?;
+
+    let seq = log.len() as i64;
+    let head = log.last().map(|entry| entry.hash.clone()).unwrap_or_default();
+    let signature = crypto.sign_record(&Record::message(&email, seq, &head));
+
+    Ok(Directory {
+        email,
+        devices,
+        log,
+        record: Record { seq, head, signer: crypto.record_pubkey(), signature },
+    })
+}
@@ -1,21 +1,24 @@
 use askama::Template;
-use actix_web::{web::{self, block, Data, Json, Payload}, HttpRequest, HttpResponse, http::StatusCode};
+use actix_web::{web::{self, block, Data, Json, Path, Payload}, HttpRequest, HttpResponse, http::StatusCode};
 use chrono::{NaiveDateTime, Utc};
 use diesel::prelude::*;
 use notify::ws::messages::{DeviceMessage, SendDeviceMessage};
 use rand::seq::SliceRandom;
 
 use base::{
+    crypto::{self, Crypto},
     sanitize::Sanitized,
-    schema::{devices, pending_devices, users, pending_delete_users, user_devices, device_notes, device_folders, note_requests, folder_requests},
+    schema::{devices, pending_devices, users, pending_delete_users, user_devices, device_notes, device_folders, note_requests, folder_requests, directory_opt_outs, recovery_bundles},
     types::Pool,
     HttpError, HttpMessage
 };
 use notify::mail::{MailRecipient, messages::SendMail};
 
 use crate::{
-    models::{Device, DEVICE_COLUMNS, UserDevice},
-    requests::{AddDevice, CloseAccount, DeleteDevice},
+    directory::{self, Directory, RateLimit},
+    models::{Device, DeviceVerification, DEVICE_COLUMNS, RotationKey, UserDevice, VerificationNonce},
+    quota::{self, Quota, UsageReport},
+    requests::{AddDevice, CloseAccount, DeleteDevice, RotatePubkey, SendVerificationNonce, StoreRecoveryBundle, UpdateDirectory},
     templates::CloseAccount as CloseAccountTemplate,
 };
 
@@ -36,6 +39,164 @@ pub async fn fetch_devices(
     Ok(Json(devices))
 }
 
+pub async fn fetch_directory(
+    pool: Data<Pool>,
+    crypto: Data<Crypto>,
+    rate_limit: Data<RateLimit>,
+    device: UserDevice,
+    email: Path<String>,
+) -> Result<Json<Directory>, HttpError> {
+    rate_limit.check(device.user_id)?;
+
+    let directory = block(move || -> Result<Directory, HttpError> {
+        let mut conn = pool.get().unwrap();
+
+        // Unlisted users are reported the same way as unknown ones, otherwise their existence would leak
+        let user_id = users::table
+            .filter(users::email.eq(email.as_str()))
+            .filter(diesel::dsl::not(diesel::dsl::exists(
+                directory_opt_outs::table.filter(directory_opt_outs::user_id.eq(users::id))
+            )))
+            .select(users::id)
+            .first::<i32>(&mut conn)
+            .optional()?
+            .ok_or(HttpError::not_found("user_not_found"))?;
+
+        directory::directory(&mut conn, &crypto, user_id, email.into_inner())
+    })
+    .await??;
+
+    Ok(Json(directory))
+}
+
+pub async fn update_directory(
+    pool: Data<Pool>,
+    device: UserDevice,
+    request: Json<UpdateDirectory>,
+) -> Result<Json<HttpMessage>, HttpError> {
+    block(move || {
+        let mut conn = pool.get().unwrap();
+
+        if request.listed {
+            diesel::delete(directory_opt_outs::table)
+                .filter(directory_opt_outs::user_id.eq(device.user_id))
+                .execute(&mut conn)
+        } else {
+            diesel::insert_into(directory_opt_outs::table)
+                .values(directory_opt_outs::user_id.eq(device.user_id))
+                .on_conflict_do_nothing()
+                .execute(&mut conn)
+        }
+    })
+    .await??;
+
+    Ok(Json(HttpMessage::success()))
+}
+
+/// Starts the comparison of the short authentication strings and returns the commitment of the pending device. The
+/// commitment is fetched before [`send_verification_nonce`] so that the pending device cannot be impersonated with a
+/// nonce chosen after seeing the one of the verifier. Only the device starting it can add the pending device afterwards.
+pub async fn start_device_verification(
+    pool: Data<Pool>,
+    device: UserDevice,
+    request: Sanitized<Json<AddDevice>>,
+) -> Result<Json<DeviceVerification>, HttpError> {
+    let commitment = block(move || -> Result<String, HttpError> {
+        let mut conn = pool.get().unwrap();
+
+        let pending_device_id = fetch_pending_device_id(&mut conn, device.user_id, &request.pubkey)?;
+
+        diesel::update(pending_devices::table)
+            .filter(pending_devices::user_id.eq(device.user_id))
+            .filter(pending_devices::device_id.eq(pending_device_id))
+            .set((
+                pending_devices::verifier_device_id.eq(device.device_id),
+                pending_devices::verifier_nonce.eq(None::<String>),
+                pending_devices::device_nonce.eq(None::<String>),
+            ))
+            .returning(pending_devices::commitment)
+            .get_result::<String>(&mut conn)
+            .map_err(|e| e.into())
+    })
+    .await??;
+
+    Ok(Json(DeviceVerification { commitment }))
+}
+
+/// Relays the nonce of the verifier to the pending device, which reveals its own nonce in return
+pub async fn send_verification_nonce(
+    pool: Data<Pool>,
+    ws_server: Data<notify::ws::AddrServer>,
+    device: UserDevice,
+    request: Sanitized<Json<SendVerificationNonce>>,
+) -> Result<Json<HttpMessage>, HttpError> {
+    if !crypto::is_valid_verification_value(&request.nonce) {
+        return Err(HttpError::unprocessable_entity("invalid_nonce"));
+    }
+
+    let nonce = request.nonce.clone();
+    let (pending_device_id, pubkey) = block(move || -> Result<(i32, String), HttpError> {
+        let mut conn = pool.get().unwrap();
+
+        let pending_device_id = fetch_pending_device_id(&mut conn, device.user_id, &request.pubkey)?;
+
+        let updated = diesel::update(pending_devices::table)
+            .filter(pending_devices::user_id.eq(device.user_id))
+            .filter(pending_devices::device_id.eq(pending_device_id))
+            .filter(pending_devices::verifier_device_id.eq(device.device_id))
+            .filter(pending_devices::verifier_nonce.is_null())
+            .set(pending_devices::verifier_nonce.eq(&request.nonce))
+            .execute(&mut conn)?;
+
+        if updated == 0 {
+            return Err(HttpError::unprocessable_entity("verification_not_started"));
+        }
+
+        let pubkey = devices::table
+            .find(device.device_id)
+            .select(devices::pubkey)
+            .first::<String>(&mut conn)?;
+
+        Ok((pending_device_id, pubkey))
+    })
+    .await??;
+
+    ws_server.do_send(SendDeviceMessage {
+        user_id: device.user_id,
+        device_id: pending_device_id,
+        message: DeviceMessage::VerifyPendingDevice { pubkey, nonce },
+    });
+
+    Ok(Json(HttpMessage::success()))
+}
+
+/// Returns the nonce revealed by the pending device, which is none until the pending device receives the nonce of the
+/// verifier
+pub async fn fetch_verification_nonce(
+    pool: Data<Pool>,
+    device: UserDevice,
+    request: web::Query<AddDevice>,
+) -> Result<Json<VerificationNonce>, HttpError> {
+    let nonce = block(move || -> Result<Option<String>, HttpError> {
+        let mut conn = pool.get().unwrap();
+
+        let pending_device_id = fetch_pending_device_id(&mut conn, device.user_id, &request.pubkey)?;
+
+        pending_devices::table
+            .filter(pending_devices::user_id.eq(device.user_id))
+            .filter(pending_devices::device_id.eq(pending_device_id))
+            .filter(pending_devices::verifier_device_id.eq(device.device_id))
+            .filter(pending_devices::verifier_nonce.is_not_null())
+            .select(pending_devices::device_nonce)
+            .first::<Option<String>>(&mut conn)
+            .optional()?
+            .ok_or(HttpError::unprocessable_entity("verification_not_started"))
+    })
+    .await??;
+
+    Ok(Json(VerificationNonce { nonce }))
+}
+
 pub async fn add_device(
     pool: Data<Pool>,
     ws_server: Data<notify::ws::AddrServer>,
@@ -45,32 +206,19 @@ pub async fn add_device(
     let (created_device, pending_device_id) = block(move || {
         let mut conn = pool.get().unwrap();
 
-        let user_device_exists = diesel::dsl::select(diesel::dsl::exists(
-            user_devices::table
-                .inner_join(devices::table)
-                .filter(devices::pubkey.eq(&request.pubkey))
-                .filter(user_devices::user_id.eq(&device.user_id))
+        let pending_device_id = fetch_pending_device_id(&mut conn, device.user_id, &request.pubkey)?;
+
+        let verified = diesel::dsl::select(diesel::dsl::exists(
+            pending_devices::table
+                .filter(pending_devices::user_id.eq(device.user_id))
+                .filter(pending_devices::device_id.eq(pending_device_id))
+                .filter(pending_devices::verifier_device_id.eq(device.device_id))
+                .filter(pending_devices::device_nonce.is_not_null())
         ))
             .get_result::<bool>(&mut conn)?;
 
-        if user_device_exists {
-            return Err(HttpError::conflict("device_already_exists"));
-        }
-
-        let (pending_device_id, updated_at) = pending_devices::table
-            .inner_join(devices::table)
-            .filter(devices::pubkey.eq(&request.pubkey))
-            .filter(pending_devices::user_id.eq(device.user_id))
-            .select((pending_devices::device_id, pending_devices::updated_at))
-            .first::<(i32, NaiveDateTime)>(&mut conn)?;
-
-        let minutes_since_pubkey_received = Utc::now()
-            .naive_utc()
-            .signed_duration_since(updated_at)
-            .num_minutes();
-
-        if minutes_since_pubkey_received > 5 {
-            return Err(HttpError::unprocessable_entity("expired_pubkey"));
+        if !verified {
+            return Err(HttpError::unprocessable_entity("verification_not_started"));
         }
 
         diesel::delete(pending_devices::table)
@@ -100,6 +248,38 @@ pub async fn add_device(
     Ok(Json(created_device))
 }
 
+fn fetch_pending_device_id(conn: &mut PgConnection, user_id: i32, pubkey: &str) -> Result<i32, HttpError> {
+    let user_device_exists = diesel::dsl::select(diesel::dsl::exists(
+        user_devices::table
+            .inner_join(devices::table)
+            .filter(devices::pubkey.eq(pubkey))
+            .filter(user_devices::user_id.eq(user_id))
+    ))
+        .get_result::<bool>(conn)?;
+
+    if user_device_exists {
+        return Err(HttpError::conflict("device_already_exists"));
+    }
+
+    let (pending_device_id, updated_at) = pending_devices::table
+        .inner_join(devices::table)
+        .filter(devices::pubkey.eq(pubkey))
+        .filter(pending_devices::user_id.eq(user_id))
+        .select((pending_devices::device_id, pending_devices::updated_at))
+        .first::<(i32, NaiveDateTime)>(conn)?;
+
+    let minutes_since_pubkey_received = Utc::now()
+        .naive_utc()
+        .signed_duration_since(updated_at)
+        .num_minutes();
+
+    if minutes_since_pubkey_received > 5 {
+        return Err(HttpError::unprocessable_entity("expired_pubkey"));
+    }
+
+    Ok(pending_device_id)
+}
+
 pub async fn delete_device(
     pool: Data<Pool>,
     device: UserDevice,
@@ -160,6 +340,113 @@ pub async fn delete_device(
     Ok(Json(HttpMessage::success()))
 }
 
+pub async fn fetch_rotation_key(crypto: Data<Crypto>) -> Json<RotationKey> {
+    Json(RotationKey { pubkey: crypto.rotation_pubkey() })
+}
+
+pub async fn rotate_pubkey(
+    pool: Data<Pool>,
+    crypto: Data<Crypto>,
+    device: UserDevice,
+    request: Sanitized<Json<RotatePubkey>>,
+) -> Result<Json<HttpMessage>, HttpError> {
+    if !crypto::is_valid_pubkey(&request.pubkey) {
+        return Err(HttpError::unprocessable_entity("invalid_pubkey"));
+    }
+
+    block(move || {
+        pool.get().unwrap().transaction(|conn| {
+            let pubkey = devices::table
+                .find(device.device_id)
+                .select(devices::pubkey)
+                .for_update()
+                .first::<String>(conn)?;
+
+            // Device is shared by the accounts on the same server, the rotation may already be made by another one
+            if pubkey == request.pubkey {
+                return Ok(());
+            }
+
+            if !crypto.verify_rotation(&pubkey, &request.pubkey, &request.proof) {
+                return Err(HttpError::unprocessable_entity("invalid_proof"));
+            }
+
+            diesel::update(devices::table)
+                .filter(devices::id.eq(device.device_id))
+                .set((
+                    devices::pubkey.eq(&request.pubkey),
+                    devices::previous_pubkey.eq(&pubkey),
+                    devices::rotated_at.eq(Utc::now().naive_utc()),
+                ))
+                .execute(conn)
+                .map_err(|e| match e {
+                    diesel::result::Error::DatabaseError(
+                        diesel::result::DatabaseErrorKind::UniqueViolation,
+                        _,
+                    ) => HttpError::conflict("pubkey_already_used"),
+                    _ => e.into(),
+                })?;
+
+            // Recovery bundle holds the previous key, the device could not login with it anymore
+            diesel::delete(recovery_bundles::table)
+                .filter(recovery_bundles::device_id.eq(device.device_id))
+                .execute(conn)?;
+
+            Ok(())
+        })
+    })
+    .await??;
+
+    Ok(Json(HttpMessage::success()))
+}
+
+/// Stores the identity key of the device which is encrypted on the device with a key derived from a passphrase. Parameters
+/// of the derivation are stored along with the salt. Only one bundle is kept for a user, the one stored last replaces the
+/// others.
+pub async fn store_recovery_bundle(
+    pool: Data<Pool>,
+    device: UserDevice,
+    request: Sanitized<Json<StoreRecoveryBundle>>,
+) -> Result<Json<HttpMessage>, HttpError> {
+    block(move || {
+        let values = (
+            recovery_bundles::device_id.eq(device.device_id),
+            recovery_bundles::salt.eq(&request.salt),
+            recovery_bundles::algorithm.eq(&request.algorithm),
+            recovery_bundles::memory_cost.eq(request.memory_cost),
+            recovery_bundles::time_cost.eq(request.time_cost),
+            recovery_bundles::parallelism.eq(request.parallelism),
+            recovery_bundles::nonce.eq(&request.nonce),
+            recovery_bundles::ciphertext.eq(&request.ciphertext),
+            recovery_bundles::created_at.eq(Utc::now().naive_utc()),
+        );
+
+        diesel::insert_into(recovery_bundles::table)
+            .values((recovery_bundles::user_id.eq(device.user_id), values))
+            .on_conflict(recovery_bundles::user_id)
+            .do_update()
+            .set(values)
+            .execute(&mut pool.get().unwrap())
+    })
+    .await??;
+
+    Ok(Json(HttpMessage::success()))
+}
+
+pub async fn delete_recovery_bundle(
+    pool: Data<Pool>,
+    device: UserDevice,
+) -> Result<Json<HttpMessage>, HttpError> {
+    block(move || {
+        diesel::delete(recovery_bundles::table)
+            .filter(recovery_bundles::user_id.eq(device.user_id))
+            .execute(&mut pool.get().unwrap())
+    })
+    .await??;
+
+    Ok(Json(HttpMessage::success()))
+}
+
 pub async fn send_close_account_code(
     pool: Data<Pool>,
     device: UserDevice,
@@ -254,16 +541,56 @@ pub async fn listen_notifications(
     })
 }
 
+pub async fn fetch_usage(
+    pool: Data<Pool>,
+    quota: Data<Quota>,
+    device: UserDevice,
+) -> Result<Json<UsageReport>, HttpError> {
+    let usage = block(move || quota::usage(&mut pool.get().unwrap(), device.user_id)).await??;
+
+    Ok(Json(UsageReport { usage, quota: **quota }))
+}
+
 #[cfg(test)]
 mod tests {
-    use actix_web::web::Data;
+    use actix_web::web::{Data, Json, Path, Query};
+    use base64::prelude::{Engine, BASE64_STANDARD};
+    use chrono::Utc;
     use diesel::prelude::*;
-    use base::{HttpError, schema::user_devices};
+    use base::{
+        crypto::Crypto,
+        sanitize::Sanitized,
+        HttpError,
+        schema::{device_folders, device_key_log, device_notes, devices, folders, notes, pending_devices, user_devices},
+    };
+    use notify::test::ws::create_server as create_notify_server;
+    use ring::hmac;
     use test_helpers::db::create_pool;
+    use x25519_dalek::{PublicKey, StaticSecret};
+
+    use crate::{
+        directory::{RateLimit, Record},
+        quota::{Quota, Usage},
+        requests::{AddDevice, RotatePubkey, SendVerificationNonce, UpdateDirectory},
+        test::db::UserDeviceBuilder,
+    };
+
+    use super::{
+        add_device, delete_device, fetch_directory, fetch_usage, fetch_verification_nonce, rotate_pubkey, send_verification_nonce,
+        start_device_verification, update_directory,
+    };
+
+    fn pubkey(secret: &StaticSecret) -> String {
+        BASE64_STANDARD.encode(PublicKey::from(secret).as_bytes())
+    }
 
-    use crate::test::db::UserDeviceBuilder;
+    fn rotation_proof(crypto: &Crypto, secret: &StaticSecret, pubkey: &str) -> String {
+        let rotation_pubkey = <[u8; 32]>::try_from(BASE64_STANDARD.decode(crypto.rotation_pubkey()).unwrap()).unwrap();
+        let shared_secret = secret.diffie_hellman(&PublicKey::from(rotation_pubkey));
+        let key = hmac::Key::new(hmac::HMAC_SHA256, shared_secret.as_bytes());
 
-    use super::delete_device;
+        BASE64_STANDARD.encode(hmac::sign(&key, pubkey.as_bytes()))
+    }
 
     #[actix_web::test]
     async fn it_returns_unknown_device_error_if_user_does_not_have_a_device_with_given_id_when_delete_device_is_called(
@@ -339,4 +666,320 @@ mod tests {
 
         assert!(!device_exists);
     }
+
+    #[actix_web::test]
+    async fn it_returns_consumption_excluding_deleted_notes_when_fetch_usage_is_called() {
+        let pool = create_pool();
+
+        let device = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+
+            let folder_id = diesel::insert_into(folders::table)
+                .values(folders::user_id.eq(device.user_id))
+                .returning(folders::id)
+                .get_result::<i32>(&mut conn)
+                .unwrap();
+
+            let note_ids = diesel::insert_into(notes::table)
+                .values(&vec![
+                    (notes::folder_id.eq(folder_id), notes::deleted_at.eq(None)),
+                    (notes::folder_id.eq(folder_id), notes::deleted_at.eq(Some(Utc::now().naive_utc()))),
+                ])
+                .returning(notes::id)
+                .get_results::<i32>(&mut conn)
+                .unwrap();
+
+            diesel::insert_into(device_folders::table)
+                .values((
+                    device_folders::folder_id.eq(folder_id),
+                    device_folders::sender_device_id.eq(device.device_id),
+                    device_folders::receiver_device_id.eq(device.device_id),
+                    device_folders::name.eq("folder"),
+                ))
+                .execute(&mut conn)
+                .unwrap();
+
+            diesel::insert_into(device_notes::table)
+                .values((
+                    device_notes::note_id.eq(note_ids[0]),
+                    device_notes::sender_device_id.eq(device.device_id),
+                    device_notes::receiver_device_id.eq(device.device_id),
+                    device_notes::name.eq("name"),
+                    device_notes::text.eq("text"),
+                ))
+                .execute(&mut conn)
+                .unwrap();
+
+            device
+        };
+
+        let res = fetch_usage(Data::new(pool.clone()), Data::new(Quota::default()), device).await.unwrap();
+
+        assert_eq!(Usage { bytes: 14, notes: 1, folders: 1 }, res.usage);
+    }
+
+    #[actix_web::test]
+    async fn it_returns_devices_with_signed_record_of_key_log_when_fetch_directory_is_called() {
+        let pool = create_pool();
+        let crypto = Crypto::new("secret");
+
+        let (device, other_device) = {
+            let mut conn = pool.get().unwrap();
+            let device = UserDeviceBuilder::default().email("email@email.com").pubkey("pubkey1").build(&mut conn).unwrap();
+            let other_device = UserDeviceBuilder::default().email("email@email2.com").pubkey("pubkey2").build(&mut conn).unwrap();
+            let removed_device = UserDeviceBuilder::default().user_id(other_device.user_id).pubkey("pubkey3").build(&mut conn).unwrap();
+
+            diesel::delete(user_devices::table)
+                .filter(user_devices::device_id.eq(removed_device.device_id))
+                .execute(&mut conn)
+                .unwrap();
+
+            (device, other_device)
+        };
+
+        let directory = fetch_directory(
+            Data::new(pool),
+            Data::new(crypto.clone()),
+            Data::new(RateLimit::default()),
+            device,
+            Path::from("email@email2.com".to_string()),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(1, directory.devices.len());
+        assert_eq!(other_device.device_id, directory.devices[0].id);
+        assert_eq!(vec![true, true, false], directory.log.iter().map(|entry| entry.added).collect::<Vec<bool>>());
+        assert_eq!(3, directory.record.seq);
+        assert_eq!(directory.log[2].hash, directory.record.head);
+        assert_eq!(crypto.record_pubkey(), directory.record.signer);
+        assert_eq!(
+            crypto.sign_record(&Record::message("email@email2.com", 3, &directory.record.head)),
+            directory.record.signature
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_returns_user_not_found_error_if_user_is_not_listed_when_fetch_directory_is_called() {
+        let pool = create_pool();
+        let device = UserDeviceBuilder::default().email("email@email.com").pubkey("pubkey1").build(&mut pool.get().unwrap()).unwrap();
+        let unlisted_device = UserDeviceBuilder::default().email("email@email2.com").pubkey("pubkey2").build(&mut pool.get().unwrap()).unwrap();
+
+        update_directory(Data::new(pool.clone()), unlisted_device, Json(UpdateDirectory { listed: false }))
+            .await
+            .unwrap();
+
+        let res = fetch_directory(
+            Data::new(pool),
+            Data::new(Crypto::new("secret")),
+            Data::new(RateLimit::default()),
+            device,
+            Path::from("email@email2.com".to_string()),
+        )
+        .await;
+
+        assert_eq!(HttpError::not_found("user_not_found"), res.unwrap_err());
+    }
+
+    #[actix_web::test]
+    async fn it_returns_too_many_lookups_error_if_user_exceeds_rate_limit_when_fetch_directory_is_called() {
+        let pool = create_pool();
+        let device = UserDeviceBuilder::default().build(&mut pool.get().unwrap()).unwrap();
+        let rate_limit = Data::new(RateLimit::new(1, std::time::Duration::from_secs(60)));
+
+        let lookup = || fetch_directory(
+            Data::new(pool.clone()),
+            Data::new(Crypto::new("secret")),
+            rate_limit.clone(),
+            device.clone(),
+            Path::from("unknown@email.com".to_string()),
+        );
+
+        assert_eq!(HttpError::not_found("user_not_found"), lookup().await.unwrap_err());
+        assert_eq!(HttpError::too_many_requests("too_many_lookups"), lookup().await.unwrap_err());
+    }
+
+    #[actix_web::test]
+    async fn it_replaces_pubkey_and_logs_the_rotation_when_rotate_pubkey_is_called() {
+        let pool = create_pool();
+        let crypto = Crypto::new("secret");
+        let old_secret = StaticSecret::from([1; 32]);
+        let new_pubkey = pubkey(&StaticSecret::from([2; 32]));
+
+        let device = UserDeviceBuilder::default().pubkey(&pubkey(&old_secret)).build(&mut pool.get().unwrap()).unwrap();
+        let request = RotatePubkey { pubkey: new_pubkey.clone(), proof: rotation_proof(&crypto, &old_secret, &new_pubkey) };
+
+        let res = rotate_pubkey(Data::new(pool.clone()), Data::new(crypto), device.clone(), Sanitized(Json(request))).await;
+
+        assert!(res.is_ok());
+
+        let mut conn = pool.get().unwrap();
+
+        let (current, previous) = devices::table
+            .find(device.device_id)
+            .select((devices::pubkey, devices::previous_pubkey))
+            .first::<(String, Option<String>)>(&mut conn)
+            .unwrap();
+
+        assert_eq!(new_pubkey, current);
+        assert_eq!(Some(pubkey(&old_secret)), previous);
+
+        let log = device_key_log::table
+            .filter(device_key_log::user_id.eq(device.user_id))
+            .order(device_key_log::id)
+            .select((device_key_log::pubkey, device_key_log::added))
+            .load::<(String, bool)>(&mut conn)
+            .unwrap();
+
+        assert_eq!(
+            vec![(pubkey(&old_secret), true), (pubkey(&old_secret), false), (new_pubkey, true)],
+            log
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_returns_invalid_proof_error_if_proof_is_not_made_with_previous_key_when_rotate_pubkey_is_called() {
+        let pool = create_pool();
+        let crypto = Crypto::new("secret");
+        let new_secret = StaticSecret::from([2; 32]);
+        let new_pubkey = pubkey(&new_secret);
+
+        let device = UserDeviceBuilder::default()
+            .pubkey(&pubkey(&StaticSecret::from([1; 32])))
+            .build(&mut pool.get().unwrap())
+            .unwrap();
+        let request = RotatePubkey { pubkey: new_pubkey.clone(), proof: rotation_proof(&crypto, &new_secret, &new_pubkey) };
+
+        let res = rotate_pubkey(Data::new(pool), Data::new(crypto), device, Sanitized(Json(request))).await;
+
+        assert_eq!(HttpError::unprocessable_entity("invalid_proof"), res.unwrap_err());
+    }
+
+    #[actix_web::test]
+    async fn it_returns_verification_not_started_error_if_verification_is_started_by_another_device_when_add_device_is_called() {
+        let pool = create_pool();
+
+        let (device, other_device) = {
+            let mut conn = pool.get().unwrap();
+
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let other_device = UserDeviceBuilder::default().user_id(device.user_id).pubkey("other").build(&mut conn).unwrap();
+            let pending_device_id = diesel::insert_into(devices::table)
+                .values((devices::pubkey.eq("pending"), devices::password.eq("password")))
+                .returning(devices::id)
+                .get_result::<i32>(&mut conn)
+                .unwrap();
+
+            diesel::insert_into(pending_devices::table)
+                .values((
+                    pending_devices::user_id.eq(device.user_id),
+                    pending_devices::device_id.eq(pending_device_id),
+                    pending_devices::commitment.eq("commitment"),
+                ))
+                .execute(&mut conn)
+                .unwrap();
+
+            (device, other_device)
+        };
+
+        let pool = Data::new(pool);
+        let ws_server = Data::new(create_notify_server());
+
+        let res = add_device(pool.clone(), ws_server.clone(), device.clone(), Sanitized(Json(AddDevice { pubkey: "pending".to_string() }))).await;
+        assert_eq!(HttpError::unprocessable_entity("verification_not_started"), res.map(|_| ()).unwrap_err());
+
+        start_device_verification(pool.clone(), other_device, Sanitized(Json(AddDevice { pubkey: "pending".to_string() })))
+            .await
+            .unwrap();
+
+        let request = SendVerificationNonce { pubkey: "pending".to_string(), nonce: BASE64_STANDARD.encode([1; 32]) };
+        let res = send_verification_nonce(pool.clone(), ws_server.clone(), device.clone(), Sanitized(Json(request))).await;
+        assert_eq!(HttpError::unprocessable_entity("verification_not_started"), res.map(|_| ()).unwrap_err());
+
+        let res = add_device(pool, ws_server, device, Sanitized(Json(AddDevice { pubkey: "pending".to_string() }))).await;
+        assert_eq!(HttpError::unprocessable_entity("verification_not_started"), res.map(|_| ()).unwrap_err());
+    }
+
+    #[actix_web::test]
+    async fn it_adds_pending_device_once_it_reveals_its_nonce_when_add_device_is_called() {
+        let pool = create_pool();
+
+        let device = {
+            let mut conn = pool.get().unwrap();
+
+            let device = UserDeviceBuilder::default().build(&mut conn).unwrap();
+            let pending_device_id = diesel::insert_into(devices::table)
+                .values((devices::pubkey.eq("pending"), devices::password.eq("password")))
+                .returning(devices::id)
+                .get_result::<i32>(&mut conn)
+                .unwrap();
+
+            diesel::insert_into(pending_devices::table)
+                .values((
+                    pending_devices::user_id.eq(device.user_id),
+                    pending_devices::device_id.eq(pending_device_id),
+                    pending_devices::commitment.eq("commitment"),
+                ))
+                .execute(&mut conn)
+                .unwrap();
+
+            device
+        };
+
+        let pool = Data::new(pool);
+        let ws_server = Data::new(create_notify_server());
+
+        let verification = start_device_verification(pool.clone(), device.clone(), Sanitized(Json(AddDevice { pubkey: "pending".to_string() })))
+            .await
+            .unwrap();
+
+        assert_eq!("commitment", verification.commitment);
+
+        let res = fetch_verification_nonce(pool.clone(), device.clone(), Query(AddDevice { pubkey: "pending".to_string() })).await;
+        assert_eq!(HttpError::unprocessable_entity("verification_not_started"), res.map(|_| ()).unwrap_err());
+
+        let request = SendVerificationNonce { pubkey: "pending".to_string(), nonce: BASE64_STANDARD.encode([1; 32]) };
+        send_verification_nonce(pool.clone(), ws_server.clone(), device.clone(), Sanitized(Json(request)))
+            .await
+            .unwrap();
+
+        let nonce = fetch_verification_nonce(pool.clone(), device.clone(), Query(AddDevice { pubkey: "pending".to_string() }))
+            .await
+            .unwrap();
+
+        assert_eq!(None, nonce.nonce);
+
+        let res = add_device(pool.clone(), ws_server.clone(), device.clone(), Sanitized(Json(AddDevice { pubkey: "pending".to_string() }))).await;
+        assert_eq!(HttpError::unprocessable_entity("verification_not_started"), res.map(|_| ()).unwrap_err());
+
+        let revealed = BASE64_STANDARD.encode([2; 32]);
+        diesel::update(pending_devices::table)
+            .filter(pending_devices::user_id.eq(device.user_id))
+            .set(pending_devices::device_nonce.eq(&revealed))
+            .execute(&mut pool.get().unwrap())
+            .unwrap();
+
+        let nonce = fetch_verification_nonce(pool.clone(), device.clone(), Query(AddDevice { pubkey: "pending".to_string() }))
+            .await
+            .unwrap();
+
+        assert_eq!(Some(revealed), nonce.nonce);
+
+        let added = add_device(pool.clone(), ws_server, device.clone(), Sanitized(Json(AddDevice { pubkey: "pending".to_string() })))
+            .await
+            .unwrap();
+
+        assert_eq!("pending", added.pubkey);
+
+        let user_device_exists = diesel::dsl::select(diesel::dsl::exists(
+            user_devices::table
+                .filter(user_devices::user_id.eq(device.user_id))
+                .filter(user_devices::device_id.eq(added.id))
+        ))
+            .get_result::<bool>(&mut pool.get().unwrap())
+            .unwrap();
+
+        assert!(user_device_exists);
+    }
 }
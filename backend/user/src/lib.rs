@@ -2,8 +2,10 @@ use base::middlewares::auth_user::AuthUser;
 
 use actix_web::web::{delete, get, post, put, scope, ServiceConfig};
 
+pub mod directory;
 mod handlers;
 pub mod models;
+pub mod quota;
 mod requests;
 mod templates;
 pub mod test;
@@ -35,13 +37,23 @@ pub fn register(config: &mut ServiceConfig) {
             .wrap(AuthUser)
             .route("devices", get().to(handlers::fetch_devices))
             .route("device", post().to(handlers::add_device))
+            .route("device/verification", post().to(handlers::start_device_verification))
+            .route("device/verification", put().to(handlers::send_verification_nonce))
+            .route("device/verification", get().to(handlers::fetch_verification_nonce))
             .route("device", delete().to(handlers::delete_device))
+            .route("device/pubkey", put().to(handlers::rotate_pubkey))
+            .route("rotation-key", get().to(handlers::fetch_rotation_key))
+            .route("recovery-bundle", put().to(handlers::store_recovery_bundle))
+            .route("recovery-bundle", delete().to(handlers::delete_recovery_bundle))
             .route(
                 "send-close-code",
                 post().to(handlers::send_close_account_code),
             )
             .route("close", put().to(handlers::close_account))
             .route("notifications", get().to(handlers::listen_notifications))
+            .route("usage", get().to(handlers::fetch_usage))
+            .route("directory", put().to(handlers::update_directory))
+            .route("{email}/devices", get().to(handlers::fetch_directory))
     );
 
     config.service(
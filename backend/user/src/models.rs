@@ -21,10 +21,26 @@ pub struct Device {
     pub id: i32,
     pub pubkey: String,
     pub created_at: NaiveDateTime,
+    pub previous_pubkey: Option<String>,
 }
 
-pub const DEVICE_COLUMNS: (devices::id, devices::pubkey, devices::created_at) =
-    (devices::id, devices::pubkey, devices::created_at);
+pub const DEVICE_COLUMNS: (devices::id, devices::pubkey, devices::created_at, devices::previous_pubkey) =
+    (devices::id, devices::pubkey, devices::created_at, devices::previous_pubkey);
+
+#[derive(Serialize)]
+pub struct RotationKey {
+    pub pubkey: String,
+}
+
+#[derive(Serialize)]
+pub struct DeviceVerification {
+    pub commitment: String,
+}
+
+#[derive(Serialize)]
+pub struct VerificationNonce {
+    pub nonce: Option<String>,
+}
 
 #[derive(Queryable, Serialize)]
 pub struct User {
@@ -0,0 +1,177 @@
+use diesel::{dsl::sum, prelude::*, PgConnection};
+use serde::Serialize;
+
+use base::{
+    schema::{attachment_chunks, attachments, device_folders, device_notes, folders, note_contents, note_updates, notes, users},
+    HttpError,
+};
+
+diesel::define_sql_function! {
+    #[sql_name = "octet_length"]
+    fn text_length(x: diesel::sql_types::Text) -> diesel::sql_types::Integer;
+}
+
+diesel::define_sql_function! {
+    #[sql_name = "octet_length"]
+    fn bytea_length(x: diesel::sql_types::Binary) -> diesel::sql_types::Integer;
+}
+
+/// Limits which are applied to each user separately
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Quota {
+    /// Total size of the ciphertexts stored for the user, in bytes
+    pub bytes: i64,
+    /// Number of notes, including the trashed ones
+    pub notes: i64,
+    /// Number of folders, including the trashed ones
+    pub folders: i64,
+    /// Size of a note's ciphertext, in bytes
+    pub note_bytes: i64,
+}
+
+impl Default for Quota {
+    fn default() -> Self {
+        Quota {
+            bytes: 100 * 1024 * 1024,
+            notes: 10_000,
+            folders: 1_000,
+            note_bytes: 1024 * 1024,
+        }
+    }
+}
+
+impl Quota {
+    pub fn check_note_size(&self, name: &str, text: &str) -> Result<(), HttpError> {
+        if (name.len() + text.len()) as i64 > self.note_bytes {
+            return Err(HttpError::quota_exceeded("note_too_large"));
+        }
+
+        Ok(())
+    }
+
+    pub fn check_bytes(&self, bytes: i64) -> Result<(), HttpError> {
+        if bytes > self.bytes {
+            return Err(HttpError::quota_exceeded("storage_quota_exceeded"));
+        }
+
+        Ok(())
+    }
+
+    pub fn check_notes(&self, notes: i64) -> Result<(), HttpError> {
+        if notes > self.notes {
+            return Err(HttpError::quota_exceeded("note_quota_exceeded"));
+        }
+
+        Ok(())
+    }
+
+    pub fn check_folders(&self, folders: i64) -> Result<(), HttpError> {
+        if folders > self.folders {
+            return Err(HttpError::quota_exceeded("folder_quota_exceeded"));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Usage {
+    pub bytes: i64,
+    pub notes: i64,
+    pub folders: i64,
+}
+
+#[derive(Serialize)]
+pub struct UsageReport {
+    pub usage: Usage,
+    pub quota: Quota,
+}
+
+/// Locks the user until the end of the transaction. Writes checking the quota of the same user are serialized this way,
+/// otherwise concurrent ones could pass the checks together and exceed the quota.
+pub fn lock(conn: &mut PgConnection, user_id: i32) -> Result<(), diesel::result::Error> {
+    users::table
+        .filter(users::id.eq(user_id))
+        .select(users::id)
+        .for_update()
+        .first::<i32>(conn)
+        .map(|_| ())
+}
+
+/// Calculates how much of the quota is consumed by the user. Deleted folders and notes are not counted
+/// since their ciphertexts are already removed, while trashed ones still occupy space until they are purged.
+pub fn usage(conn: &mut PgConnection, user_id: i32) -> Result<Usage, diesel::result::Error> {
+    let folders = folders::table
+        .filter(folders::user_id.eq(user_id))
+        .filter(folders::deleted_at.is_null())
+        .count()
+        .get_result::<i64>(conn)?;
+
+    let notes = notes::table
+        .inner_join(folders::table)
+        .filter(folders::user_id.eq(user_id))
+        .filter(notes::deleted_at.is_null())
+        .count()
+        .get_result::<i64>(conn)?;
+
+    let folder_bytes = device_folders::table
+        .inner_join(folders::table)
+        .filter(folders::user_id.eq(user_id))
+        .select(sum(text_length(device_folders::name)))
+        .get_result::<Option<i64>>(conn)?;
+
+    let note_bytes = device_notes::table
+        .inner_join(notes::table.inner_join(folders::table))
+        .filter(folders::user_id.eq(user_id))
+        .select(sum(text_length(device_notes::name) + text_length(device_notes::text)))
+        .get_result::<Option<i64>>(conn)?;
+
+    // Earlier commits of the notes are kept as their history, so they occupy space as well
+    let content_bytes = note_contents::table
+        .inner_join(notes::table.on(notes::id.eq(note_contents::note_id)))
+        .inner_join(folders::table.on(folders::id.eq(notes::folder_id)))
+        .filter(folders::user_id.eq(user_id))
+        .select(sum(text_length(note_contents::name) + text_length(note_contents::text)))
+        .get_result::<Option<i64>>(conn)?;
+
+    let update_bytes = note_updates::table
+        .inner_join(notes::table.inner_join(folders::table.on(folders::id.eq(notes::folder_id))))
+        .filter(folders::user_id.eq(user_id))
+        .select(sum(text_length(note_updates::data)))
+        .get_result::<Option<i64>>(conn)?;
+
+    let attachment_bytes = attachment_chunks::table
+        .inner_join(attachments::table.inner_join(notes::table.inner_join(folders::table)))
+        .filter(folders::user_id.eq(user_id))
+        .select(sum(bytea_length(attachment_chunks::data)))
+        .get_result::<Option<i64>>(conn)?;
+
+    Ok(Usage {
+        bytes: folder_bytes.unwrap_or(0)
+            + note_bytes.unwrap_or(0)
+            + content_bytes.unwrap_or(0)
+            + update_bytes.unwrap_or(0)
+            + attachment_bytes.unwrap_or(0),
+        notes,
+        folders,
+    })
+}
+
+/// Calculates the size of the ciphertexts encrypted for each device of the note, they are removed once the note is
+/// written with the key of its folder. Contents of the earlier commits are kept, so they are not replaced by a write.
+pub fn note_usage(conn: &mut PgConnection, note_id: i32) -> Result<i64, diesel::result::Error> {
+    device_notes::table
+        .filter(device_notes::note_id.eq(note_id))
+        .select(sum(text_length(device_notes::name) + text_length(device_notes::text)))
+        .get_result::<Option<i64>>(conn)
+        .map(|bytes| bytes.unwrap_or(0))
+}
+
+/// Calculates the size of the document updates currently stored for the note
+pub fn note_update_usage(conn: &mut PgConnection, note_id: i32) -> Result<i64, diesel::result::Error> {
+    note_updates::table
+        .filter(note_updates::note_id.eq(note_id))
+        .select(sum(text_length(note_updates::data)))
+        .get_result::<Option<i64>>(conn)
+        .map(|bytes| bytes.unwrap_or(0))
+}
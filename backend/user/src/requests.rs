@@ -8,6 +8,29 @@ pub struct AddDevice {
     pub pubkey: String,
 }
 
+#[derive(Sanitize, Deserialize)]
+pub struct SendVerificationNonce {
+    pub pubkey: String,
+    pub nonce: String,
+}
+
+#[derive(Sanitize, Deserialize)]
+pub struct RotatePubkey {
+    pub pubkey: String,
+    pub proof: String,
+}
+
+#[derive(Sanitize, Deserialize)]
+pub struct StoreRecoveryBundle {
+    pub salt: String,
+    pub algorithm: String,
+    pub memory_cost: i32,
+    pub time_cost: i32,
+    pub parallelism: i32,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
 #[derive(Deserialize)]
 pub struct DeleteDevice {
     pub id: Option<i32>,
@@ -17,3 +40,8 @@ pub struct DeleteDevice {
 pub struct CloseAccount {
     pub code: String,
 }
+
+#[derive(Deserialize)]
+pub struct UpdateDirectory {
+    pub listed: bool,
+}
@@ -29,7 +29,7 @@ pub mod db {
                 IdOrBuild::Build(email) => {
                     diesel::insert_into(users::table)
                         .values(users::email.eq(email))
-                        .get_result::<(i32, String, NaiveDateTime)>(conn)?
+                        .get_result::<(i32, String, NaiveDateTime, i64)>(conn)?
                         .0
                 },
             };
@@ -39,8 +39,8 @@ pub mod db {
                 IdOrBuild::Build(pubkey) => {
                     diesel::insert_into(devices::table)
                         .values((devices::pubkey.eq(pubkey), devices::password.eq("password")))
-                        .get_result::<(i32, String, String, NaiveDateTime)>(conn)?
-                        .0
+                        .returning(devices::id)
+                        .get_result::<i32>(conn)?
                 }
             };
 
@@ -27,6 +27,10 @@ class AppState : ObservableObject {
     func handleError(_ e: NoteError) {
         switch e {
         case .Mavinote(.NoConnection): emit(BusEvent.ShowMessage("No Internet Connection"))
+        case .Mavinote(.QuotaExceeded(.Storage)): emit(BusEvent.ShowMessage("Storage quota of the account is exceeded"))
+        case .Mavinote(.QuotaExceeded(.Notes)): emit(BusEvent.ShowMessage("Note limit of the account is reached"))
+        case .Mavinote(.QuotaExceeded(.Folders)): emit(BusEvent.ShowMessage("Folder limit of the account is reached"))
+        case .Mavinote(.QuotaExceeded(.NoteSize)): emit(BusEvent.ShowMessage("Note is too large"))
         case .Mavinote(.DeviceDeleted(let accountId)): Task {
             switch await AccountViewModel.removeAccount(accountId) {
             case .failure(.Mavinote(.DeviceDeleted(_))):
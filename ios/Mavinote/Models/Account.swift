@@ -54,3 +54,50 @@ struct Mavinote: Deserialize {
 
     }
 }
+
+enum Verification: Deserialize {
+    case Code(String)
+    case Accepted
+
+    static func deserialize(_ deserializer: Deserializer) throws -> Verification {
+        let index = try deserializer.deserialize_variant_index()
+
+        switch index {
+        case 0: return .Code(try String.deserialize(deserializer))
+        case 1: return .Accepted
+        default: throw DeserializationError.invalidInput(issue: "Unknown variant index \(index) for Verification")
+        }
+    }
+}
+
+enum Encryption: Deserialize {
+    case Disabled
+    case Locked
+    case Unlocked
+
+    static func deserialize(_ deserializer: Deserializer) throws -> Encryption {
+        let index = try deserializer.deserialize_variant_index()
+
+        switch index {
+        case 0: return .Disabled
+        case 1: return .Locked
+        case 2: return .Unlocked
+        default: throw DeserializationError.invalidInput(issue: "Unknown variant index \(index) for Encryption")
+        }
+    }
+}
+
+enum ConflictPolicy: Deserialize {
+    case Merge
+    case Copy
+
+    static func deserialize(_ deserializer: Deserializer) throws -> ConflictPolicy {
+        let index = try deserializer.deserialize_variant_index()
+
+        switch index {
+        case 0: return .Merge
+        case 1: return .Copy
+        default: throw DeserializationError.invalidInput(issue: "Unknown variant index \(index) for ConflictPolicy")
+        }
+    }
+}
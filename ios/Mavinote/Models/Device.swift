@@ -5,6 +5,8 @@ struct Device: Deserialize, Identifiable {
     let accountId: Int32
     let pubkey: String
     let createdAt: String
+    let trust: Trust
+    let fingerprint: String
 
     static func deserialize(_ deserializer: Deserializer) throws -> Device {
         try deserializer.increase_container_depth()
@@ -13,7 +15,9 @@ struct Device: Deserialize, Identifiable {
             id: try deserializer.deserialize_i32(),
             accountId: try deserializer.deserialize_i32(),
             pubkey: try deserializer.deserialize_str(),
-            createdAt: try deserializer.deserialize_str()
+            createdAt: try deserializer.deserialize_str(),
+            trust: try Trust.deserialize(deserializer),
+            fingerprint: try deserializer.deserialize_str()
         )
 
         try deserializer.decrease_container_depth()
@@ -21,3 +25,64 @@ struct Device: Deserialize, Identifiable {
         return device
     }
 }
+
+struct DirectoryDevice: Deserialize, Identifiable {
+    let id: Int32
+    let accountId: Int32
+    let email: String
+    let pubkey: String
+
+    static func deserialize(_ deserializer: Deserializer) throws -> DirectoryDevice {
+        try deserializer.increase_container_depth()
+
+        let device = DirectoryDevice(
+            id: try deserializer.deserialize_i32(),
+            accountId: try deserializer.deserialize_i32(),
+            email: try deserializer.deserialize_str(),
+            pubkey: try deserializer.deserialize_str()
+        )
+
+        try deserializer.decrease_container_depth()
+
+        return device
+    }
+}
+
+enum Trust: Deserialize {
+    case Unverified
+    case Verified
+    case Changed
+
+    static func deserialize(_ deserializer: Deserializer) throws -> Trust {
+        let index = try deserializer.deserialize_variant_index()
+
+        switch index {
+        case 0: return .Unverified
+        case 1: return .Verified
+        case 2: return .Changed
+        default: throw DeserializationError.invalidInput(issue: "Unknown variant index for Trust")
+        }
+    }
+}
+
+struct TrustEvent: Deserialize {
+    let accountId: Int32
+    let deviceId: Int32
+    let trust: Trust
+    let fingerprint: String
+
+    static func deserialize(_ deserializer: Deserializer) throws -> TrustEvent {
+        try deserializer.increase_container_depth()
+
+        let event = TrustEvent(
+            accountId: try deserializer.deserialize_i32(),
+            deviceId: try deserializer.deserialize_i32(),
+            trust: try Trust.deserialize(deserializer),
+            fingerprint: try deserializer.deserialize_str()
+        )
+
+        try deserializer.decrease_container_depth()
+
+        return event
+    }
+}
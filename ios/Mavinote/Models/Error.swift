@@ -7,6 +7,7 @@ enum NoteError : Error, Deserialize {
     case Database(String)
     case Crypto(CryptoError)
     case Unreachable(String)
+    case Locked
     // This is used by Swift and not returned from Rust
     case TaskCancellation
 
@@ -19,6 +20,7 @@ enum NoteError : Error, Deserialize {
         case 2: return .Database(try deserializer.deserialize_str())
         case 3: return .Crypto(try CryptoError.deserialize(deserializer))
         case 4: return .Unreachable(try deserializer.deserialize_str())
+        case 5: return .Locked
         default: throw DeserializationError.invalidInput(issue: "Unknown variant index \(index) for NoteError")
         }
     }
@@ -31,6 +33,7 @@ enum MavinoteError {
     case UnexpectedResponse
     case DeviceDeleted(Int32)
     case Unknown(String)
+    case QuotaExceeded(Quota)
 
     static func deserialize(_ deserializer: Deserializer) throws -> MavinoteError {
         let index = try deserializer.deserialize_variant_index()
@@ -42,19 +45,43 @@ enum MavinoteError {
         case 3: return .UnexpectedResponse
         case 4: return .DeviceDeleted(try Int32.deserialize(deserializer))
         case 5: return .Unknown(try String.deserialize(deserializer))
+        case 6: return .QuotaExceeded(try Quota.deserialize(deserializer))
         default: throw DeserializationError.invalidInput(issue: "Unknown variant index \(index) for MavinoteError")
         }
     }
 }
 
+enum Quota {
+    case Storage
+    case Notes
+    case Folders
+    case NoteSize
+
+    static func deserialize(_ deserializer: Deserializer) throws -> Quota {
+        let index = try deserializer.deserialize_variant_index()
+
+        switch index {
+        case 0: return .Storage
+        case 1: return .Notes
+        case 2: return .Folders
+        case 3: return .NoteSize
+        default: throw DeserializationError.invalidInput(issue: "Unknown variant index \(index) for Quota")
+        }
+    }
+}
+
 enum StorageError {
     case EmailAlreadyExists
+    case File(String)
+    case SecretStore(String)
 
     static func deserialize(_ deserializer: Deserializer) throws -> StorageError {
         let index = try deserializer.deserialize_variant_index()
 
         switch index {
         case 0: return .EmailAlreadyExists
+        case 1: return .File(try String.deserialize(deserializer))
+        case 2: return .SecretStore(try String.deserialize(deserializer))
         default: throw DeserializationError.invalidInput(issue: "Unknown variant index \(index) for StorageError")
         }
     }
@@ -65,6 +92,9 @@ enum CryptoError {
     case InvalidLength
     case Decrypt
     case Encrypt
+    case DirectoryMismatch
+    case FingerprintMismatch
+    case CommitmentMismatch
 
     static func deserialize(_ deserializer: Deserializer) throws -> CryptoError {
         let index = try deserializer.deserialize_variant_index()
@@ -74,6 +104,9 @@ enum CryptoError {
         case 1: return .InvalidLength
         case 2: return .Decrypt
         case 3: return .Encrypt
+        case 4: return .DirectoryMismatch
+        case 5: return .FingerprintMismatch
+        case 6: return .CommitmentMismatch
         default: throw DeserializationError.invalidInput(issue: "Unknown variant index \(index) for CryptoError")
         }
     }
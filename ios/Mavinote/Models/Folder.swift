@@ -4,8 +4,11 @@ struct Folder : Identifiable, Deserialize {
     let id: Int32
     let accountId: Int32
     let remoteId: Int32?
+    let parentId: Int32?
     let name: String
     let state: ModelState
+    let shared: Bool
+    let role: Role?
 
     static func deserialize(_ deserializer: Deserializer) throws -> Folder {
         try deserializer.increase_container_depth()
@@ -14,8 +17,11 @@ struct Folder : Identifiable, Deserialize {
             id: try deserializer.deserialize_i32(),
             accountId: try deserializer.deserialize_i32(),
             remoteId: try Optional<Int32>.deserialize(deserializer),
+            parentId: try Optional<Int32>.deserialize(deserializer),
             name: try deserializer.deserialize_str(),
-            state: try ModelState.deserialize(deserializer)
+            state: try ModelState.deserialize(deserializer),
+            shared: try deserializer.deserialize_bool(),
+            role: try Optional<Role>.deserialize(deserializer)
         )
 
         try deserializer.decrease_container_depth()
@@ -23,3 +29,48 @@ struct Folder : Identifiable, Deserialize {
         return folder
     }
 }
+
+struct FolderMember: Identifiable, Deserialize {
+    let userId: Int32
+    let email: String
+    let role: Role
+
+    var id: Int32 { userId }
+
+    static func deserialize(_ deserializer: Deserializer) throws -> FolderMember {
+        try deserializer.increase_container_depth()
+
+        let member = FolderMember(
+            userId: try deserializer.deserialize_i32(),
+            email: try deserializer.deserialize_str(),
+            role: try Role.deserialize(deserializer)
+        )
+
+        try deserializer.decrease_container_depth()
+
+        return member
+    }
+}
+
+enum Role: Deserialize {
+    case Read
+    case Write
+
+    static func deserialize(_ deserializer: Deserializer) throws -> Role {
+        try deserializer.increase_container_depth()
+
+        let index = try deserializer.deserialize_variant_index()
+
+        let role: Role
+
+        switch index {
+        case 0: role = Read
+        case 1: role = Write
+        default: throw DeserializationError.invalidInput(issue: "Invalid variant for Role \(index)")
+        }
+
+        try deserializer.decrease_container_depth()
+
+        return role
+    }
+}
@@ -28,10 +28,59 @@ struct Note : Identifiable, Deserialize {
     }
 }
 
+struct Commit : Identifiable, Deserialize {
+    let noteId: Int32
+    let commit: Int32
+    let deviceId: Int32
+    let createdAt: String
+
+    var id: Int32 { commit }
+
+    static func deserialize(_ deserializer: Deserializer) throws -> Commit {
+        try deserializer.increase_container_depth()
+
+        let commit = Commit(
+            noteId: try deserializer.deserialize_i32(),
+            commit: try deserializer.deserialize_i32(),
+            deviceId: try deserializer.deserialize_i32(),
+            createdAt: try deserializer.deserialize_str()
+        )
+
+        try deserializer.decrease_container_depth()
+
+        return commit
+    }
+}
+
+struct CommitNote : Deserialize {
+    let noteId: Int32
+    let commit: Int32
+    let name: String
+    let text: String
+    let createdAt: String
+
+    static func deserialize(_ deserializer: Deserializer) throws -> CommitNote {
+        try deserializer.increase_container_depth()
+
+        let commitNote = CommitNote(
+            noteId: try deserializer.deserialize_i32(),
+            commit: try deserializer.deserialize_i32(),
+            name: try deserializer.deserialize_str(),
+            text: try deserializer.deserialize_str(),
+            createdAt: try deserializer.deserialize_str()
+        )
+
+        try deserializer.decrease_container_depth()
+
+        return commitNote
+    }
+}
+
 enum ModelState: Deserialize {
     case Clean
     case Modified
     case Deleted
+    case Trashed
 
     static func deserialize(_ deserializer: Deserializer) throws -> ModelState {
         try deserializer.increase_container_depth()
@@ -44,6 +93,7 @@ enum ModelState: Deserialize {
         case 0: state = Clean
         case 1: state = Modified
         case 2: state = Deleted
+        case 3: state = Trashed
         default: throw DeserializationError.invalidInput(issue: "Invalid variant for State \(index)")
         }
 
@@ -53,3 +103,179 @@ enum ModelState: Deserialize {
     }
 
 }
+
+struct Attachment : Identifiable, Deserialize {
+    let id: Int32
+    let noteId: Int32
+    let name: String
+
+    static func deserialize(_ deserializer: Deserializer) throws -> Attachment {
+        try deserializer.increase_container_depth()
+
+        let attachment = Attachment(
+            id: try deserializer.deserialize_i32(),
+            noteId: try deserializer.deserialize_i32(),
+            name: try deserializer.deserialize_str()
+        )
+
+        try deserializer.decrease_container_depth()
+
+        return attachment
+    }
+}
+
+struct SearchResult : Identifiable, Deserialize {
+    let noteId: Int32
+    let folderId: Int32
+    let name: String
+    let snippet: [SnippetPart]
+
+    var id: Int32 { noteId }
+
+    static func deserialize(_ deserializer: Deserializer) throws -> SearchResult {
+        try deserializer.increase_container_depth()
+
+        let result = SearchResult(
+            noteId: try deserializer.deserialize_i32(),
+            folderId: try deserializer.deserialize_i32(),
+            name: try deserializer.deserialize_str(),
+            snippet: try [SnippetPart].deserialize(deserializer)
+        )
+
+        try deserializer.decrease_container_depth()
+
+        return result
+    }
+}
+
+struct SnippetPart : Deserialize {
+    let text: String
+    let highlighted: Bool
+
+    static func deserialize(_ deserializer: Deserializer) throws -> SnippetPart {
+        try deserializer.increase_container_depth()
+
+        let part = SnippetPart(
+            text: try deserializer.deserialize_str(),
+            highlighted: try deserializer.deserialize_bool()
+        )
+
+        try deserializer.decrease_container_depth()
+
+        return part
+    }
+}
+
+struct Conflict : Identifiable, Deserialize {
+    let id: Int32
+    let note: Note
+    let copy: Note
+    let remoteCommit: Int32
+    let createdAt: String
+
+    static func deserialize(_ deserializer: Deserializer) throws -> Conflict {
+        try deserializer.increase_container_depth()
+
+        let conflict = Conflict(
+            id: try deserializer.deserialize_i32(),
+            note: try Note.deserialize(deserializer),
+            copy: try Note.deserialize(deserializer),
+            remoteCommit: try deserializer.deserialize_i32(),
+            createdAt: try deserializer.deserialize_str()
+        )
+
+        try deserializer.decrease_container_depth()
+
+        return conflict
+    }
+}
+
+enum OperationKind: Deserialize {
+    case CreateFolder
+    case RenameFolder
+    case MoveFolder
+    case DeleteFolder
+    case CreateNote
+    case UpdateNote
+    case MoveNote
+    case DeleteNote
+
+    static func deserialize(_ deserializer: Deserializer) throws -> OperationKind {
+        let index = try deserializer.deserialize_variant_index()
+
+        switch index {
+        case 0: return .CreateFolder
+        case 1: return .RenameFolder
+        case 2: return .MoveFolder
+        case 3: return .DeleteFolder
+        case 4: return .CreateNote
+        case 5: return .UpdateNote
+        case 6: return .MoveNote
+        case 7: return .DeleteNote
+        default: throw DeserializationError.invalidInput(issue: "Unknown variant index \(index) for OperationKind")
+        }
+    }
+}
+
+struct FailedOperation : Identifiable, Deserialize {
+    let id: Int32
+    let accountId: Int32
+    let kind: OperationKind
+    let name: String
+    let error: String
+
+    static func deserialize(_ deserializer: Deserializer) throws -> FailedOperation {
+        try deserializer.increase_container_depth()
+
+        let operation = FailedOperation(
+            id: try deserializer.deserialize_i32(),
+            accountId: try deserializer.deserialize_i32(),
+            kind: try OperationKind.deserialize(deserializer),
+            name: try deserializer.deserialize_str(),
+            error: try deserializer.deserialize_str()
+        )
+
+        try deserializer.decrease_container_depth()
+
+        return operation
+    }
+}
+
+struct NoteRevision : Identifiable, Deserialize {
+    let id: Int32
+    let noteId: Int32
+    let name: String
+    let text: String
+    let createdAt: String
+
+    static func deserialize(_ deserializer: Deserializer) throws -> NoteRevision {
+        try deserializer.increase_container_depth()
+
+        let revision = NoteRevision(
+            id: try deserializer.deserialize_i32(),
+            noteId: try deserializer.deserialize_i32(),
+            name: try deserializer.deserialize_str(),
+            text: try deserializer.deserialize_str(),
+            createdAt: try deserializer.deserialize_str()
+        )
+
+        try deserializer.decrease_container_depth()
+
+        return revision
+    }
+}
+
+enum RevisionRetention: Deserialize {
+    case Count(Int32)
+    case Days(Int32)
+
+    static func deserialize(_ deserializer: Deserializer) throws -> RevisionRetention {
+        let index = try deserializer.deserialize_variant_index()
+
+        switch index {
+        case 0: return .Count(try deserializer.deserialize_i32())
+        case 1: return .Days(try deserializer.deserialize_i32())
+        default: throw DeserializationError.invalidInput(issue: "Unknown variant index \(index) for RevisionRetention")
+        }
+    }
+}
@@ -25,8 +25,8 @@ class AccountViewModel {
         return await Runtime.runOnce { reax_account_request_verification($0, email) }
     }
 
-    static func waitVerification(_ token: String) async -> AccountResult<()> {
-        return await Runtime.runOnceUnit { reax_account_wait_verification($0, token) }
+    static func waitVerification(_ token: String) -> AsyncStream<AccountResult<Verification>> {
+        return Runtime.runStream { reax_account_wait_verification($0, token) }
     }
 
     static func sendVerificationCode(_ email: String) async -> AccountResult<()> {
@@ -72,4 +72,72 @@ class AccountViewModel {
     static func updateWelcomeShown(_ shown: Bool) async -> AccountResult<DeUnit> {
         return await Runtime.runOnce { reax_account_update_welcome_shown($0, shown) }
     }
+
+    static func directoryDevices(_ accountId: Int32, _ email: String) async -> AccountResult<[DirectoryDevice]> {
+        return await Runtime.runOnce { reax_account_directory_devices($0, accountId, email) }
+    }
+
+    static func updateDirectory(_ accountId: Int32, _ listed: Bool) async -> AccountResult<()> {
+        return await Runtime.runOnceUnit { reax_account_update_directory($0, accountId, listed) }
+    }
+
+    static func rotateIdentityKey() async -> AccountResult<()> {
+        return await Runtime.runOnceUnit { reax_account_rotate_identity_key($0) }
+    }
+
+    static func verifyDevice(_ accountId: Int32, _ deviceId: Int32, _ fingerprint: String) async -> AccountResult<()> {
+        return await Runtime.runOnceUnit { reax_account_verify_device($0, accountId, deviceId, fingerprint) }
+    }
+
+    static func trustEvents() -> AsyncStream<AccountResult<TrustEvent>> {
+        return Runtime.runStream { reax_account_trust_events($0) }
+    }
+
+    static func startDeviceVerification(_ accountId: Int32, _ pubkey: String) async -> AccountResult<String> {
+        return await Runtime.runOnce { reax_account_start_device_verification($0, accountId, pubkey) }
+    }
+
+    static func createRecoveryBundle(_ accountId: Int32, _ passphrase: String) async -> AccountResult<()> {
+        return await Runtime.runOnceUnit { reax_account_create_recovery_bundle($0, accountId, passphrase) }
+    }
+
+    static func deleteRecoveryBundle(_ accountId: Int32) async -> AccountResult<()> {
+        return await Runtime.runOnceUnit { reax_account_delete_recovery_bundle($0, accountId) }
+    }
+
+    static func sendRecoveryCode(_ email: String) async -> AccountResult<()> {
+        return await Runtime.runOnceUnit { reax_account_send_recovery_code($0, email) }
+    }
+
+    static func recoverAccount(_ email: String, _ code: String, _ passphrase: String) async -> AccountResult<()> {
+        return await Runtime.runOnceUnit { reax_account_recover_account($0, email, code, passphrase) }
+    }
+
+    static func encryption() async -> AccountResult<Encryption> {
+        return await Runtime.runOnce { reax_account_encryption($0) }
+    }
+
+    static func enableEncryption(_ pin: String) async -> AccountResult<()> {
+        return await Runtime.runOnceUnit { reax_account_enable_encryption($0, pin) }
+    }
+
+    static func disableEncryption(_ pin: String) async -> AccountResult<()> {
+        return await Runtime.runOnceUnit { reax_account_disable_encryption($0, pin) }
+    }
+
+    static func unlock(_ pin: String) async -> AccountResult<()> {
+        return await Runtime.runOnceUnit { reax_account_unlock($0, pin) }
+    }
+
+    static func lock() async -> AccountResult<()> {
+        return await Runtime.runOnceUnit { reax_account_lock($0) }
+    }
+
+    static func conflictPolicy(_ accountId: Int32) async -> AccountResult<ConflictPolicy> {
+        return await Runtime.runOnce { reax_account_conflict_policy($0, accountId) }
+    }
+
+    static func updateConflictPolicy(_ accountId: Int32, _ keepCopies: Bool) async -> AccountResult<()> {
+        return await Runtime.runOnceUnit { reax_account_update_conflict_policy($0, accountId, keepCopies) }
+    }
 }
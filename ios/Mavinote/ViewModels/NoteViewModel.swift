@@ -17,8 +17,16 @@ class NoteViewModel {
         return await Runtime.runOnce { reax_note_folder($0, folderId) }
     }
 
-    static func createFolder(_ accountId: Int32, _ name: String) async -> NoteResult<()> {
-        return await Runtime.runOnceUnit { reax_note_create_folder($0, accountId, name) }
+    static func createFolder(_ accountId: Int32, _ name: String, parentId: Int32? = nil) async -> NoteResult<()> {
+        return await Runtime.runOnceUnit { reax_note_create_folder($0, accountId, parentId ?? 0, name) }
+    }
+
+    static func moveFolder(_ folderId: Int32, _ parentId: Int32?) async -> NoteResult<()> {
+        return await Runtime.runOnceUnit { reax_note_move_folder($0, folderId, parentId ?? 0) }
+    }
+
+    static func renameFolder(_ folderId: Int32, _ name: String) async -> NoteResult<()> {
+        return await Runtime.runOnceUnit { reax_note_rename_folder($0, folderId, name) }
     }
 
     static func deleteFolder(_ folderId: Int32) async -> NoteResult<()> {
@@ -44,4 +52,124 @@ class NoteViewModel {
     static func deleteNote(_ noteId: Int32) async -> NoteResult<()> {
         return await Runtime.runOnceUnit { reax_note_delete_note($0, noteId) }
     }
+
+    static func moveNote(_ noteId: Int32, _ folderId: Int32) async -> NoteResult<()> {
+        return await Runtime.runOnceUnit { reax_note_move_note($0, noteId, folderId) }
+    }
+
+    static func noteCommits(_ noteId: Int32) async -> NoteResult<[Commit]> {
+        return await Runtime.runOnce { reax_note_note_commits($0, noteId) }
+    }
+
+    static func noteCommit(_ noteId: Int32, _ commit: Int32) async -> NoteResult<CommitNote?> {
+        return await Runtime.runOnce { reax_note_note_commit($0, noteId, commit) }
+    }
+
+    static func restoreCommit(_ noteId: Int32, _ commit: Int32) async -> NoteResult<()> {
+        return await Runtime.runOnceUnit { reax_note_restore_commit($0, noteId, commit) }
+    }
+
+    static func attachments(_ noteId: Int32) async -> NoteResult<[Attachment]> {
+        return await Runtime.runOnce { reax_note_attachments($0, noteId) }
+    }
+
+    static func addAttachment(_ noteId: Int32, _ path: String) async -> NoteResult<Attachment> {
+        return await Runtime.runOnce { reax_note_add_attachment($0, noteId, path) }
+    }
+
+    static func attachmentPath(_ noteId: Int32, _ attachmentId: Int32) async -> NoteResult<String> {
+        return await Runtime.runOnce { reax_note_attachment_path($0, noteId, attachmentId) }
+    }
+
+    static func deleteAttachment(_ noteId: Int32, _ attachmentId: Int32) async -> NoteResult<()> {
+        return await Runtime.runOnceUnit { reax_note_delete_attachment($0, noteId, attachmentId) }
+    }
+
+    static func trash() async -> NoteResult<[Note]> {
+        return await Runtime.runOnce { reax_note_trash($0) }
+    }
+
+    static func restoreNote(_ noteId: Int32) async -> NoteResult<()> {
+        return await Runtime.runOnceUnit { reax_note_restore_note($0, noteId) }
+    }
+
+    static func folderMembers(_ folderId: Int32) async -> NoteResult<[FolderMember]> {
+        return await Runtime.runOnce { reax_note_folder_members($0, folderId) }
+    }
+
+    static func shareFolder(_ folderId: Int32, _ email: String, _ write: Bool) async -> NoteResult<()> {
+        return await Runtime.runOnceUnit { reax_note_share_folder($0, folderId, email, write) }
+    }
+
+    static func removeFolderMember(_ folderId: Int32, _ userId: Int32) async -> NoteResult<()> {
+        return await Runtime.runOnceUnit { reax_note_remove_folder_member($0, folderId, userId) }
+    }
+
+    static func search(_ query: String, _ accountId: Int32) -> AsyncStream<NoteResult<SearchResult>> {
+        return Runtime.runStream { reax_note_search($0, query, accountId) }
+    }
+
+    static func conflicts() -> AsyncStream<NoteResult<[Conflict]>> {
+        return Runtime.runStream { reax_note_conflicts($0) }
+    }
+
+    static func resolveConflict(_ conflictId: Int32) async -> NoteResult<()> {
+        return await Runtime.runOnceUnit { reax_note_resolve_conflict($0, conflictId) }
+    }
+
+    static func discardConflict(_ conflictId: Int32) async -> NoteResult<()> {
+        return await Runtime.runOnceUnit { reax_note_discard_conflict($0, conflictId) }
+    }
+
+    static func failedOperations() -> AsyncStream<NoteResult<[FailedOperation]>> {
+        return Runtime.runStream { reax_note_failed_operations($0) }
+    }
+
+    static func retryFailedOperation(_ operationId: Int32) async -> NoteResult<()> {
+        return await Runtime.runOnceUnit { reax_note_retry_failed_operation($0, operationId) }
+    }
+
+    static func discardFailedOperation(_ operationId: Int32) async -> NoteResult<()> {
+        return await Runtime.runOnceUnit { reax_note_discard_failed_operation($0, operationId) }
+    }
+
+    static func noteRevisions(_ noteId: Int32) async -> NoteResult<[NoteRevision]> {
+        return await Runtime.runOnce { reax_note_note_revisions($0, noteId) }
+    }
+
+    static func restoreRevision(_ revisionId: Int32) async -> NoteResult<()> {
+        return await Runtime.runOnceUnit { reax_note_restore_revision($0, revisionId) }
+    }
+
+    static func revisionRetention() async -> NoteResult<RevisionRetention> {
+        return await Runtime.runOnce { reax_note_revision_retention($0) }
+    }
+
+    static func updateRevisionRetention(_ value: Int32, _ days: Bool) async -> NoteResult<()> {
+        return await Runtime.runOnceUnit { reax_note_update_revision_retention($0, value, days) }
+    }
+
+    static func convertNote(_ noteId: Int32) async -> NoteResult<()> {
+        return await Runtime.runOnceUnit { reax_note_convert_note($0, noteId) }
+    }
+
+    static func noteConverted(_ noteId: Int32) async -> NoteResult<Bool> {
+        return await Runtime.runOnce { reax_note_note_converted($0, noteId) }
+    }
+
+    static func trashedFolders() async -> NoteResult<[Folder]> {
+        return await Runtime.runOnce { reax_note_trashed_folders($0) }
+    }
+
+    static func restoreFolder(_ folderId: Int32) async -> NoteResult<()> {
+        return await Runtime.runOnceUnit { reax_note_restore_folder($0, folderId) }
+    }
+
+    static func trashRetention() async -> NoteResult<Int32> {
+        return await Runtime.runOnce { reax_note_trash_retention($0) }
+    }
+
+    static func updateTrashRetention(_ days: Int32) async -> NoteResult<()> {
+        return await Runtime.runOnceUnit { reax_note_update_trash_retention($0, days) }
+    }
 }
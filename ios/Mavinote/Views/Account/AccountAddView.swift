@@ -170,6 +170,7 @@ private struct ShowPublicKeyView: View {
 
     @EnvironmentObject var appState: AppState
     @State var publicKey: String?
+    @State var verificationCode: String?
 
     var body: some View {
         ScrollView {
@@ -191,6 +192,15 @@ private struct ShowPublicKeyView: View {
                             .bold()
                     }
                 }
+
+                if let verificationCode = verificationCode {
+                    VStack(alignment: .leading, spacing: 12.0) {
+                        Text("The other device displays a verification code. Add this device there only if it matches the code below.")
+
+                        Text(verificationCode)
+                            .bold()
+                    }
+                }
             }
             .padding(.all, 12.0)
         }
@@ -204,15 +214,19 @@ private struct ShowPublicKeyView: View {
             }
 
             verificationTask = Task {
-                switch await AccountViewModel.waitVerification(token) {
-                case .success(_):
-                    switch await AccountViewModel.addAccount(email) {
-                    case .success(_):
-                        onAccountAdd(appState: appState)
-                    case .failure(let e): appState.handleError(e)
+                for await result in AccountViewModel.waitVerification(token) {
+                    switch result {
+                    case .success(.Code(let code)):
+                        verificationCode = code
+                    case .success(.Accepted):
+                        switch await AccountViewModel.addAccount(email) {
+                        case .success(_):
+                            onAccountAdd(appState: appState)
+                        case .failure(let e): appState.handleError(e)
+                        }
+                    case .failure(let e):
+                        appState.handleError(e)
                     }
-                case .failure(let e):
-                    appState.handleError(e)
                 }
             }
         }
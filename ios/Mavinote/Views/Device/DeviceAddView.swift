@@ -13,6 +13,7 @@ struct DeviceAddView: View {
     @State var validationErrors = Set<ValidationErrors>()
     @State var error: String?
     @State var inProgress = false
+    @State var verificationCode: String?
 
     var body: some View {
         VStack {
@@ -22,7 +23,7 @@ struct DeviceAddView: View {
 
                     Text("In order to add a new device into this account, you first need to choose Add an Existing Account in Add Account page on new device.")
 
-                    Text("Then you need to type the Public Key of the new device below and tap Add Device.")
+                    Text("Then you need to type the Public Key of the new device below and tap Verify Device. Both devices display a verification code, add the device only if the codes match.")
 
                     VStack(alignment: .leading) {
                         Text("Device Public Key")
@@ -38,6 +39,16 @@ struct DeviceAddView: View {
                                 .foregroundColor(.red)
                         }
                     }
+
+                    if let verificationCode = verificationCode {
+                        VStack(alignment: .leading) {
+                            Text("Verification Code")
+                                .font(.callout)
+
+                            Text(verificationCode)
+                                .bold()
+                        }
+                    }
                 }
                 .padding(.all, 12)
             }
@@ -60,26 +71,24 @@ struct DeviceAddView: View {
                 inProgress = true
 
                 Task {
-                    switch await AccountViewModel.addDevice(accountId, publicKey) {
-                    case .success(_):
-                        appState.emit(.ShowMessage("Device is added successfully"))
-                        appState.navigate(route: .Accounts)
-                    case .failure(let e):
-                        switch e {
-                        case .Mavinote(.Message("item_not_found")):
-                            error = "Public Key is not found"
-                        case .Mavinote(.Message("device_already_exists")):
-                            error = "Device with this public key is already added"
-                        case .Mavinote(.Message("expired_pubkey")):
-                            error = "5 minutes waiting is timed out. Please try the steps on new device again."
-                        default: appState.handleError(e)
+                    if verificationCode == nil {
+                        switch await AccountViewModel.startDeviceVerification(accountId, publicKey) {
+                        case .success(let code): verificationCode = code
+                        case .failure(let e): handleError(e)
+                        }
+                    } else {
+                        switch await AccountViewModel.addDevice(accountId, publicKey) {
+                        case .success(_):
+                            appState.emit(.ShowMessage("Device is added successfully"))
+                            appState.navigate(route: .Accounts)
+                        case .failure(let e): handleError(e)
                         }
                     }
 
                     inProgress = false
                 }
             }) {
-                Text("Add Device")
+                Text(verificationCode == nil ? "Verify Device" : "Codes Match, Add Device")
                     .frame(maxWidth: .infinity)
                     .foregroundColor(.white)
             }
@@ -90,6 +99,9 @@ struct DeviceAddView: View {
             .cornerRadius(8)
             .padding(.all, 12)
         }
+        .onChange(of: publicKey) { _ in
+            verificationCode = nil
+        }
         .navigationTitle("Add Device")
         .alert(item: $error) { error in
             Alert(
@@ -101,6 +113,23 @@ struct DeviceAddView: View {
     }
 }
 
+extension DeviceAddView {
+    func handleError(_ e: NoteError) {
+        switch e {
+        case .Mavinote(.Message("item_not_found")):
+            error = "Public Key is not found"
+        case .Mavinote(.Message("device_already_exists")):
+            error = "Device with this public key is already added"
+        case .Mavinote(.Message("expired_pubkey")):
+            error = "5 minutes waiting is timed out. Please try the steps on new device again."
+        case .Mavinote(.Message("verification_not_started")):
+            verificationCode = nil
+            error = "Verification is restarted on the new device. Please verify the device again."
+        default: appState.handleError(e)
+        }
+    }
+}
+
 struct DeviceAdd_Preview: PreviewProvider {
     static var previews: some View {
         NavigationView {
@@ -108,17 +108,17 @@ struct FoldersView_Preview: PreviewProvider {
             AccountWithFolders(
                 account: Account(id: 1, name: "Local", kind: .Local),
                 folders: [
-                    Folder(id: 1, accountId: 1, remoteId: nil, name: "Favorites", state: .Clean),
-                    Folder(id: 2, accountId: 1, remoteId: nil, name: "Todos", state: .Clean),
-                    Folder(id: 4, accountId: 1, remoteId: nil, name: "Projects", state: .Clean),
-                    Folder(id: 5, accountId: 1, remoteId: nil, name: "Kernel", state: .Clean),
+                    Folder(id: 1, accountId: 1, remoteId: nil, parentId: nil, name: "Favorites", state: .Clean, shared: false, role: nil),
+                    Folder(id: 2, accountId: 1, remoteId: nil, parentId: nil, name: "Todos", state: .Clean, shared: false, role: nil),
+                    Folder(id: 4, accountId: 1, remoteId: nil, parentId: nil, name: "Projects", state: .Clean, shared: false, role: nil),
+                    Folder(id: 5, accountId: 1, remoteId: nil, parentId: nil, name: "Kernel", state: .Clean, shared: false, role: nil),
                ]
             ),
             AccountWithFolders(account: Account(id: 3, name: "Remote", kind: .Mavinote), folders: []),
             AccountWithFolders(
                 account: Account(id: 2, name: "Mavinote", kind: .Mavinote),
                 folders: [
-                    Folder(id: 3, accountId: 2, remoteId: nil, name: "Race Cars", state: .Clean),
+                    Folder(id: 3, accountId: 2, remoteId: nil, parentId: nil, name: "Race Cars", state: .Clean, shared: false, role: nil),
                ]
             ),
         ]
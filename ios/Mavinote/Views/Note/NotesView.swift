@@ -116,7 +116,7 @@ private struct _NotesView : View {
 
 struct NotesView_Preview : PreviewProvider {
     static var previews : some View {
-        let folder = Folder(id: 1, accountId: 1, remoteId: nil, name: "My Folder", state: .Clean)
+        let folder = Folder(id: 1, accountId: 1, remoteId: nil, parentId: nil, name: "My Folder", state: .Clean, shared: false, role: nil)
         let notes = [
             Note(id: 1, folderId: 1, remoteId: nil, commit: 1, name: "Little Note", text: "Empty text", state: .Clean),
             Note(id: 2, folderId: 1, remoteId: nil, commit: 1, name: "Hacky Solutions", text: "Empty text", state: .Clean),
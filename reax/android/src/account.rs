@@ -83,12 +83,12 @@ pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_AccountViewModelKt__1request
 pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_AccountViewModelKt__1waitVerification(
     mut env: JNIEnv,
     _: JClass,
-    once_id: jint,
+    stream_id: jint,
     token: JString,
 ) -> jlong {
     let token = env.get_string(&token).unwrap().to_str().unwrap().to_owned();
 
-    universal::account::wait_verification(once_id, token) as jlong
+    universal::account::wait_verification(stream_id, token) as jlong
 }
 
 #[no_mangle]
@@ -199,3 +199,198 @@ pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_AccountViewModelKt__1updateW
 ) -> jlong {
     universal::account::update_welcome_shown(once_id, shown > 0) as jlong
 }
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_AccountViewModelKt__1directoryDevices(
+    mut env: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    account_id: jint,
+    email: JString,
+) -> jlong {
+    let email = env.get_string(&email).unwrap().to_str().unwrap().to_owned();
+
+    universal::account::directory_devices(once_id, account_id, email) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_AccountViewModelKt__1updateDirectory(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    account_id: jint,
+    listed: jboolean,
+) -> jlong {
+    universal::account::update_directory(once_id, account_id, listed != 0) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_AccountViewModelKt__1rotateIdentityKey(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+) -> jlong {
+    universal::account::rotate_identity_key(once_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_AccountViewModelKt__1verifyDevice(
+    mut env: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    account_id: jint,
+    device_id: jint,
+    fingerprint: JString,
+) -> jlong {
+    let fingerprint = env.get_string(&fingerprint).unwrap().to_str().unwrap().to_owned();
+
+    universal::account::verify_device(once_id, account_id, device_id, fingerprint) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_AccountViewModelKt__1trustEvents(
+    _: JNIEnv,
+    _: JClass,
+    stream_id: jint,
+) -> jlong {
+    universal::account::trust_events(stream_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_AccountViewModelKt__1startDeviceVerification(
+    mut env: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    account_id: jint,
+    pubkey: JString,
+) -> jlong {
+    let pubkey = env.get_string(&pubkey).unwrap().to_str().unwrap().to_owned();
+
+    universal::account::start_device_verification(once_id, account_id, pubkey) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_AccountViewModelKt__1createRecoveryBundle(
+    mut env: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    account_id: jint,
+    passphrase: JString,
+) -> jlong {
+    let passphrase = env.get_string(&passphrase).unwrap().to_str().unwrap().to_owned();
+
+    universal::account::create_recovery_bundle(once_id, account_id, passphrase) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_AccountViewModelKt__1deleteRecoveryBundle(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    account_id: jint,
+) -> jlong {
+    universal::account::delete_recovery_bundle(once_id, account_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_AccountViewModelKt__1sendRecoveryCode(
+    mut env: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    email: JString,
+) -> jlong {
+    let email = env.get_string(&email).unwrap().to_str().unwrap().to_owned();
+
+    universal::account::send_recovery_code(once_id, email) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_AccountViewModelKt__1recoverAccount(
+    mut env: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    email: JString,
+    code: JString,
+    passphrase: JString,
+) -> jlong {
+    let email = env.get_string(&email).unwrap().to_str().unwrap().to_owned();
+    let code = env.get_string(&code).unwrap().to_str().unwrap().to_owned();
+    let passphrase = env.get_string(&passphrase).unwrap().to_str().unwrap().to_owned();
+
+    universal::account::recover_account(once_id, email, code, passphrase) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_AccountViewModelKt__1encryption(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+) -> jlong {
+    universal::account::encryption(once_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_AccountViewModelKt__1enableEncryption(
+    mut env: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    pin: JString,
+) -> jlong {
+    let pin = env.get_string(&pin).unwrap().to_str().unwrap().to_owned();
+
+    universal::account::enable_encryption(once_id, pin) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_AccountViewModelKt__1disableEncryption(
+    mut env: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    pin: JString,
+) -> jlong {
+    let pin = env.get_string(&pin).unwrap().to_str().unwrap().to_owned();
+
+    universal::account::disable_encryption(once_id, pin) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_AccountViewModelKt__1unlock(
+    mut env: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    pin: JString,
+) -> jlong {
+    let pin = env.get_string(&pin).unwrap().to_str().unwrap().to_owned();
+
+    universal::account::unlock(once_id, pin) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_AccountViewModelKt__1lock(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+) -> jlong {
+    universal::account::lock(once_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_AccountViewModelKt__1conflictPolicy(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    account_id: jint,
+) -> jlong {
+    universal::account::conflict_policy(once_id, account_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_AccountViewModelKt__1updateConflictPolicy(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    account_id: jint,
+    keep_copies: jboolean,
+) -> jlong {
+    universal::account::update_conflict_policy(once_id, account_id, keep_copies != 0) as jlong
+}
@@ -1,6 +1,6 @@
 use jni::{
     objects::{JString, JClass},
-    sys::{jint, jlong},
+    sys::{jint, jlong, jboolean},
     JNIEnv
 };
 
@@ -46,11 +46,37 @@ pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1createFold
     _: JClass,
     once_id: jint,
     account_id: jint,
+    parent_id: jint,
     name: JString,
 ) -> jlong {
     let name = env.get_string(&name).unwrap().to_str().unwrap().to_owned();
 
-    universal::note::create_folder(once_id, account_id, name) as jlong
+    // Folder ids start from 1, 0 is used for no parent
+    universal::note::create_folder(once_id, account_id, (parent_id != 0).then_some(parent_id), name) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1renameFolder(
+    mut env: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    folder_id: jint,
+    name: JString,
+) -> jlong {
+    let name = env.get_string(&name).unwrap().to_str().unwrap().to_owned();
+
+    universal::note::rename_folder(once_id, folder_id, name) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1moveFolder(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    folder_id: jint,
+    parent_id: jint,
+) -> jlong {
+    universal::note::move_folder(once_id, folder_id, (parent_id != 0).then_some(parent_id)) as jlong
 }
 
 #[no_mangle]
@@ -118,3 +144,314 @@ pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1deleteNote
 ) -> jlong {
     universal::note::delete_note(once_id, note_id) as jlong
 }
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1moveNote(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    note_id: jint,
+    folder_id: jint,
+) -> jlong {
+    universal::note::move_note(once_id, note_id, folder_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1noteCommits(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    note_id: jint,
+) -> jlong {
+    universal::note::note_commits(once_id, note_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1noteCommit(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    note_id: jint,
+    commit: jint,
+) -> jlong {
+    universal::note::note_commit(once_id, note_id, commit) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1restoreCommit(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    note_id: jint,
+    commit: jint,
+) -> jlong {
+    universal::note::restore_commit(once_id, note_id, commit) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1attachments(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    note_id: jint,
+) -> jlong {
+    universal::note::attachments(once_id, note_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1addAttachment(
+    mut env: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    note_id: jint,
+    path: JString,
+) -> jlong {
+    let path = env.get_string(&path).unwrap().to_str().unwrap().to_owned();
+
+    universal::note::add_attachment(once_id, note_id, path) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1attachmentPath(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    note_id: jint,
+    attachment_id: jint,
+) -> jlong {
+    universal::note::attachment_path(once_id, note_id, attachment_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1deleteAttachment(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    note_id: jint,
+    attachment_id: jint,
+) -> jlong {
+    universal::note::delete_attachment(once_id, note_id, attachment_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1trash(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+) -> jlong {
+    universal::note::trash(once_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1restoreNote(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    note_id: jint,
+) -> jlong {
+    universal::note::restore_note(once_id, note_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1folderMembers(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    folder_id: jint,
+) -> jlong {
+    universal::note::folder_members(once_id, folder_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1shareFolder(
+    mut env: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    folder_id: jint,
+    email: JString,
+    write: jboolean,
+) -> jlong {
+    let email = env.get_string(&email).unwrap().to_str().unwrap().to_owned();
+
+    universal::note::share_folder(once_id, folder_id, email, write != 0) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1removeFolderMember(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    folder_id: jint,
+    user_id: jint,
+) -> jlong {
+    universal::note::remove_folder_member(once_id, folder_id, user_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1search(
+    mut env: JNIEnv,
+    _: JClass,
+    stream_id: jint,
+    query: JString,
+    account_id: jint,
+) -> jlong {
+    let query = env.get_string(&query).unwrap().to_str().unwrap().to_owned();
+
+    universal::note::search(stream_id, query, account_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1conflicts(
+    _: JNIEnv,
+    _: JClass,
+    stream_id: jint,
+) -> jlong {
+    universal::note::conflicts(stream_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1resolveConflict(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    conflict_id: jint,
+) -> jlong {
+    universal::note::resolve_conflict(once_id, conflict_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1discardConflict(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    conflict_id: jint,
+) -> jlong {
+    universal::note::discard_conflict(once_id, conflict_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1failedOperations(
+    _: JNIEnv,
+    _: JClass,
+    stream_id: jint,
+) -> jlong {
+    universal::note::failed_operations(stream_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1retryFailedOperation(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    operation_id: jint,
+) -> jlong {
+    universal::note::retry_failed_operation(once_id, operation_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1discardFailedOperation(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    operation_id: jint,
+) -> jlong {
+    universal::note::discard_failed_operation(once_id, operation_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1noteRevisions(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    note_id: jint,
+) -> jlong {
+    universal::note::note_revisions(once_id, note_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1restoreRevision(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    revision_id: jint,
+) -> jlong {
+    universal::note::restore_revision(once_id, revision_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1revisionRetention(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+) -> jlong {
+    universal::note::revision_retention(once_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1updateRevisionRetention(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    value: jint,
+    days: jboolean,
+) -> jlong {
+    universal::note::update_revision_retention(once_id, value, days != 0) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1convertNote(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    note_id: jint,
+) -> jlong {
+    universal::note::convert_note(once_id, note_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1noteConverted(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    note_id: jint,
+) -> jlong {
+    universal::note::note_converted(once_id, note_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1trashedFolders(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+) -> jlong {
+    universal::note::trashed_folders(once_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1restoreFolder(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    folder_id: jint,
+) -> jlong {
+    universal::note::restore_folder(once_id, folder_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1trashRetention(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+) -> jlong {
+    universal::note::trash_retention(once_id) as jlong
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_bwqr_mavinote_viewmodels_NoteViewModelKt__1updateTrashRetention(
+    _: JNIEnv,
+    _: JClass,
+    once_id: jint,
+    days: jint,
+) -> jlong {
+    universal::note::update_trash_retention(once_id, days) as jlong
+}
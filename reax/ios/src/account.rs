@@ -115,12 +115,12 @@ pub extern "C" fn reax_account_sign_up(
 
 #[no_mangle]
 pub extern "C" fn reax_account_wait_verification(
-    once_id: i32,
+    stream_id: i32,
     token: *const c_char,
 ) -> * mut c_void {
     let token = unsafe { CStr::from_ptr(token).to_str().unwrap().to_string() };
 
-    universal::account::wait_verification(once_id, token) as * mut c_void
+    universal::account::wait_verification(stream_id, token) as * mut c_void
 }
 
 #[no_mangle]
@@ -140,3 +140,108 @@ pub extern "C" fn reax_account_welcome_shown(once_id: i32) -> * mut c_void {
 pub extern "C" fn reax_account_update_welcome_shown(once_id: i32, shown: bool) -> * mut c_void {
     universal::account::update_welcome_shown(once_id, shown) as * mut c_void
 }
+
+#[no_mangle]
+pub extern "C" fn reax_account_directory_devices(once_id: i32, account_id: i32, email: * const c_char) -> * mut c_void {
+    let email = unsafe { CStr::from_ptr(email).to_str().unwrap().to_string() };
+
+    universal::account::directory_devices(once_id, account_id, email) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_account_update_directory(once_id: i32, account_id: i32, listed: bool) -> * mut c_void {
+    universal::account::update_directory(once_id, account_id, listed) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_account_rotate_identity_key(once_id: i32) -> * mut c_void {
+    universal::account::rotate_identity_key(once_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_account_verify_device(once_id: i32, account_id: i32, device_id: i32, fingerprint: * const c_char) -> * mut c_void {
+    let fingerprint = unsafe { CStr::from_ptr(fingerprint).to_str().unwrap().to_string() };
+
+    universal::account::verify_device(once_id, account_id, device_id, fingerprint) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_account_trust_events(stream_id: i32) -> * mut c_void {
+    universal::account::trust_events(stream_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_account_start_device_verification(once_id: i32, account_id: i32, pubkey: * const c_char) -> * mut c_void {
+    let pubkey = unsafe { CStr::from_ptr(pubkey).to_str().unwrap().to_string() };
+
+    universal::account::start_device_verification(once_id, account_id, pubkey) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_account_create_recovery_bundle(once_id: i32, account_id: i32, passphrase: * const c_char) -> * mut c_void {
+    let passphrase = unsafe { CStr::from_ptr(passphrase).to_str().unwrap().to_string() };
+
+    universal::account::create_recovery_bundle(once_id, account_id, passphrase) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_account_delete_recovery_bundle(once_id: i32, account_id: i32) -> * mut c_void {
+    universal::account::delete_recovery_bundle(once_id, account_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_account_send_recovery_code(once_id: i32, email: * const c_char) -> * mut c_void {
+    let email = unsafe { CStr::from_ptr(email).to_str().unwrap().to_string() };
+
+    universal::account::send_recovery_code(once_id, email) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_account_recover_account(once_id: i32, email: * const c_char, code: * const c_char, passphrase: * const c_char) -> * mut c_void {
+    let email = unsafe { CStr::from_ptr(email).to_str().unwrap().to_string() };
+    let code = unsafe { CStr::from_ptr(code).to_str().unwrap().to_string() };
+    let passphrase = unsafe { CStr::from_ptr(passphrase).to_str().unwrap().to_string() };
+
+    universal::account::recover_account(once_id, email, code, passphrase) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_account_encryption(once_id: i32) -> * mut c_void {
+    universal::account::encryption(once_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_account_enable_encryption(once_id: i32, pin: * const c_char) -> * mut c_void {
+    let pin = unsafe { CStr::from_ptr(pin).to_str().unwrap().to_string() };
+
+    universal::account::enable_encryption(once_id, pin) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_account_disable_encryption(once_id: i32, pin: * const c_char) -> * mut c_void {
+    let pin = unsafe { CStr::from_ptr(pin).to_str().unwrap().to_string() };
+
+    universal::account::disable_encryption(once_id, pin) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_account_unlock(once_id: i32, pin: * const c_char) -> * mut c_void {
+    let pin = unsafe { CStr::from_ptr(pin).to_str().unwrap().to_string() };
+
+    universal::account::unlock(once_id, pin) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_account_lock(once_id: i32) -> * mut c_void {
+    universal::account::lock(once_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_account_conflict_policy(once_id: i32, account_id: i32) -> * mut c_void {
+    universal::account::conflict_policy(once_id, account_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_account_update_conflict_policy(once_id: i32, account_id: i32, keep_copies: bool) -> * mut c_void {
+    universal::account::update_conflict_policy(once_id, account_id, keep_copies) as * mut c_void
+}
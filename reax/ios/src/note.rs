@@ -6,10 +6,16 @@ pub extern "C" fn reax_note_init() {
 }
 
 #[no_mangle]
-pub extern "C" fn reax_note_create_folder(once_id: i32, account_id: i32, name: * const c_char) -> * mut c_void {
+pub extern "C" fn reax_note_create_folder(once_id: i32, account_id: i32, parent_id: i32, name: * const c_char) -> * mut c_void {
     let name = unsafe { CStr::from_ptr(name).to_str().unwrap().to_string() };
 
-    universal::note::create_folder(once_id, account_id, name) as * mut c_void
+    // Folder ids start from 1, 0 is used for no parent
+    universal::note::create_folder(once_id, account_id, (parent_id != 0).then_some(parent_id), name) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_move_folder(once_id: i32, folder_id: i32, parent_id: i32) -> * mut c_void {
+    universal::note::move_folder(once_id, folder_id, (parent_id != 0).then_some(parent_id)) as * mut c_void
 }
 
 #[no_mangle]
@@ -29,6 +35,11 @@ pub extern "C" fn reax_note_delete_note(once_id: i32, note_id: i32) -> * mut c_v
     universal::note::delete_note(once_id, note_id) as * mut c_void
 }
 
+#[no_mangle]
+pub extern "C" fn reax_note_move_note(once_id: i32, note_id: i32, folder_id: i32) -> * mut c_void {
+    universal::note::move_note(once_id, note_id, folder_id) as * mut c_void
+}
+
 #[no_mangle]
 pub extern "C" fn reax_note_folder(once_id: i32, folder_id: i32) -> * mut c_void {
     universal::note::folder(once_id, folder_id) as * mut c_void
@@ -44,6 +55,16 @@ pub extern "C" fn reax_note_note(once_id: i32, note_id: i32) -> * mut c_void {
     universal::note::note(once_id, note_id) as * mut c_void
 }
 
+#[no_mangle]
+pub extern "C" fn reax_note_note_commits(once_id: i32, note_id: i32) -> * mut c_void {
+    universal::note::note_commits(once_id, note_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_note_commit(once_id: i32, note_id: i32, commit: i32) -> * mut c_void {
+    universal::note::note_commit(once_id, note_id, commit) as * mut c_void
+}
+
 #[no_mangle]
 pub extern "C" fn reax_note_note_summaries(stream_id: i32, folder_id: i32) -> * mut c_void {
     universal::note::note_summaries(stream_id, folder_id) as * mut c_void
@@ -56,7 +77,155 @@ pub extern "C" fn reax_note_update_note(once_id: i32, note_id: i32, text: * cons
     universal::note::update_note(once_id, note_id, text) as * mut c_void
 }
 
+#[no_mangle]
+pub extern "C" fn reax_note_restore_commit(once_id: i32, note_id: i32, commit: i32) -> * mut c_void {
+    universal::note::restore_commit(once_id, note_id, commit) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_rename_folder(once_id: i32, folder_id: i32, name: * const c_char) -> * mut c_void {
+    let name = unsafe { CStr::from_ptr(name).to_str().unwrap().to_string() };
+
+    universal::note::rename_folder(once_id, folder_id, name) as * mut c_void
+}
+
 #[no_mangle]
 pub extern "C" fn reax_note_sync(once_id: i32) -> * mut c_void {
     universal::note::sync(once_id) as * mut c_void
 }
+
+#[no_mangle]
+pub extern "C" fn reax_note_attachments(once_id: i32, note_id: i32) -> * mut c_void {
+    universal::note::attachments(once_id, note_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_add_attachment(once_id: i32, note_id: i32, path: * const c_char) -> * mut c_void {
+    let path = unsafe { CStr::from_ptr(path).to_str().unwrap().to_string() };
+
+    universal::note::add_attachment(once_id, note_id, path) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_attachment_path(once_id: i32, note_id: i32, attachment_id: i32) -> * mut c_void {
+    universal::note::attachment_path(once_id, note_id, attachment_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_delete_attachment(once_id: i32, note_id: i32, attachment_id: i32) -> * mut c_void {
+    universal::note::delete_attachment(once_id, note_id, attachment_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_trash(once_id: i32) -> * mut c_void {
+    universal::note::trash(once_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_restore_note(once_id: i32, note_id: i32) -> * mut c_void {
+    universal::note::restore_note(once_id, note_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_folder_members(once_id: i32, folder_id: i32) -> * mut c_void {
+    universal::note::folder_members(once_id, folder_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_share_folder(once_id: i32, folder_id: i32, email: * const c_char, write: bool) -> * mut c_void {
+    let email = unsafe { CStr::from_ptr(email).to_str().unwrap().to_string() };
+
+    universal::note::share_folder(once_id, folder_id, email, write) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_remove_folder_member(once_id: i32, folder_id: i32, user_id: i32) -> * mut c_void {
+    universal::note::remove_folder_member(once_id, folder_id, user_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_search(stream_id: i32, query: * const c_char, account_id: i32) -> * mut c_void {
+    let query = unsafe { CStr::from_ptr(query).to_str().unwrap().to_string() };
+
+    universal::note::search(stream_id, query, account_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_conflicts(stream_id: i32) -> * mut c_void {
+    universal::note::conflicts(stream_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_resolve_conflict(once_id: i32, conflict_id: i32) -> * mut c_void {
+    universal::note::resolve_conflict(once_id, conflict_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_discard_conflict(once_id: i32, conflict_id: i32) -> * mut c_void {
+    universal::note::discard_conflict(once_id, conflict_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_failed_operations(stream_id: i32) -> * mut c_void {
+    universal::note::failed_operations(stream_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_retry_failed_operation(once_id: i32, operation_id: i32) -> * mut c_void {
+    universal::note::retry_failed_operation(once_id, operation_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_discard_failed_operation(once_id: i32, operation_id: i32) -> * mut c_void {
+    universal::note::discard_failed_operation(once_id, operation_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_note_revisions(once_id: i32, note_id: i32) -> * mut c_void {
+    universal::note::note_revisions(once_id, note_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_restore_revision(once_id: i32, revision_id: i32) -> * mut c_void {
+    universal::note::restore_revision(once_id, revision_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_revision_retention(once_id: i32) -> * mut c_void {
+    universal::note::revision_retention(once_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_update_revision_retention(once_id: i32, value: i32, days: bool) -> * mut c_void {
+    universal::note::update_revision_retention(once_id, value, days) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_convert_note(once_id: i32, note_id: i32) -> * mut c_void {
+    universal::note::convert_note(once_id, note_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_note_converted(once_id: i32, note_id: i32) -> * mut c_void {
+    universal::note::note_converted(once_id, note_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_trashed_folders(once_id: i32) -> * mut c_void {
+    universal::note::trashed_folders(once_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_restore_folder(once_id: i32, folder_id: i32) -> * mut c_void {
+    universal::note::restore_folder(once_id, folder_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_trash_retention(once_id: i32) -> * mut c_void {
+    universal::note::trash_retention(once_id) as * mut c_void
+}
+
+#[no_mangle]
+pub extern "C" fn reax_note_update_trash_retention(once_id: i32, days: i32) -> * mut c_void {
+    universal::note::update_trash_retention(once_id, days) as * mut c_void
+}
@@ -14,7 +14,7 @@ void * reax_account_devices(int32_t once_id, int32_t account_id);
 void * reax_account_add_device(int32_t once_id, int32_t account_id, const char * fingerprint);
 void * reax_account_delete_device(int32_t once_id, int32_t account_id, int32_t device_id);
 void * reax_account_request_verification(int32_t once_id, const char * email);
-void * reax_account_wait_verification(int32_t once_id, const char * token);
+void * reax_account_wait_verification(int32_t stream_id, const char * token);
 void * reax_account_add_account(int32_t once_id, const char * email);
 void * reax_account_public_key(int32_t once_id);
 void * reax_account_send_verification_code(int32_t once_id, const char * email);
@@ -25,15 +25,64 @@ void * reax_account_close_account(int32_t once_id, int32_t account_id, const cha
 void * reax_account_listen_notifications(int32_t stream_id, int32_t account_id);
 void * reax_account_welcome_shown(int32_t once_id);
 void * reax_account_update_welcome_shown(int32_t once_id, bool shown);
+void * reax_account_directory_devices(int32_t once_id, int32_t account_id, const char * email);
+void * reax_account_update_directory(int32_t once_id, int32_t account_id, bool listed);
+void * reax_account_rotate_identity_key(int32_t once_id);
+void * reax_account_verify_device(int32_t once_id, int32_t account_id, int32_t device_id, const char * fingerprint);
+void * reax_account_trust_events(int32_t stream_id);
+void * reax_account_start_device_verification(int32_t once_id, int32_t account_id, const char * pubkey);
+void * reax_account_create_recovery_bundle(int32_t once_id, int32_t account_id, const char * passphrase);
+void * reax_account_delete_recovery_bundle(int32_t once_id, int32_t account_id);
+void * reax_account_send_recovery_code(int32_t once_id, const char * email);
+void * reax_account_recover_account(int32_t once_id, const char * email, const char * code, const char * passphrase);
+void * reax_account_encryption(int32_t once_id);
+void * reax_account_enable_encryption(int32_t once_id, const char * pin);
+void * reax_account_disable_encryption(int32_t once_id, const char * pin);
+void * reax_account_unlock(int32_t once_id, const char * pin);
+void * reax_account_lock(int32_t once_id);
+void * reax_account_conflict_policy(int32_t once_id, int32_t account_id);
+void * reax_account_update_conflict_policy(int32_t once_id, int32_t account_id, bool keep_copies);
 
 void reax_note_init();
 void * reax_note_sync(int32_t once_id);
 void * reax_note_folders(int32_t stream_id);
 void * reax_note_folder(int32_t once_id, int32_t folder_id);
-void * reax_note_create_folder(int32_t once_id, int32_t account_id, const char * name);
+void * reax_note_create_folder(int32_t once_id, int32_t account_id, int32_t parent_id, const char * name);
+void * reax_note_move_folder(int32_t once_id, int32_t folder_id, int32_t parent_id);
+void * reax_note_rename_folder(int32_t once_id, int32_t folder_id, const char * name);
 void * reax_note_delete_folder(int32_t once_id, int32_t folder_id);
 void * reax_note_note_summaries(int32_t stream_id, int32_t folder_id);
 void * reax_note_note(int32_t once_id, int32_t note_id);
 void * reax_note_create_note(int32_t once_id, int32_t folder_id, const char * text);
 void * reax_note_update_note(int32_t once_id, int32_t note_id, const char * text);
 void * reax_note_delete_note(int32_t once_id, int32_t note_id);
+void * reax_note_move_note(int32_t once_id, int32_t note_id, int32_t folder_id);
+void * reax_note_note_commits(int32_t once_id, int32_t note_id);
+void * reax_note_note_commit(int32_t once_id, int32_t note_id, int32_t commit);
+void * reax_note_restore_commit(int32_t once_id, int32_t note_id, int32_t commit);
+void * reax_note_attachments(int32_t once_id, int32_t note_id);
+void * reax_note_add_attachment(int32_t once_id, int32_t note_id, const char * path);
+void * reax_note_attachment_path(int32_t once_id, int32_t note_id, int32_t attachment_id);
+void * reax_note_delete_attachment(int32_t once_id, int32_t note_id, int32_t attachment_id);
+void * reax_note_trash(int32_t once_id);
+void * reax_note_restore_note(int32_t once_id, int32_t note_id);
+void * reax_note_folder_members(int32_t once_id, int32_t folder_id);
+void * reax_note_share_folder(int32_t once_id, int32_t folder_id, const char * email, bool write);
+void * reax_note_remove_folder_member(int32_t once_id, int32_t folder_id, int32_t user_id);
+void * reax_note_search(int32_t stream_id, const char * query, int32_t account_id);
+void * reax_note_conflicts(int32_t stream_id);
+void * reax_note_resolve_conflict(int32_t once_id, int32_t conflict_id);
+void * reax_note_discard_conflict(int32_t once_id, int32_t conflict_id);
+void * reax_note_failed_operations(int32_t stream_id);
+void * reax_note_retry_failed_operation(int32_t once_id, int32_t operation_id);
+void * reax_note_discard_failed_operation(int32_t once_id, int32_t operation_id);
+void * reax_note_note_revisions(int32_t once_id, int32_t note_id);
+void * reax_note_restore_revision(int32_t once_id, int32_t revision_id);
+void * reax_note_revision_retention(int32_t once_id);
+void * reax_note_update_revision_retention(int32_t once_id, int32_t value, bool days);
+void * reax_note_convert_note(int32_t once_id, int32_t note_id);
+void * reax_note_note_converted(int32_t once_id, int32_t note_id);
+void * reax_note_trashed_folders(int32_t once_id);
+void * reax_note_restore_folder(int32_t once_id, int32_t folder_id);
+void * reax_note_trash_retention(int32_t once_id);
+void * reax_note_update_trash_retention(int32_t once_id, int32_t days);
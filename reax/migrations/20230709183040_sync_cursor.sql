@@ -0,0 +1 @@
+alter table accounts add column sync_cursor integer not null default 0;
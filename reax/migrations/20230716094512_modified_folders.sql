@@ -0,0 +1,33 @@
+-- Check constraints cannot be altered in sqlite, so folders and notes tables are recreated to allow modified folders
+create table folders_new(
+    id          integer primary key autoincrement,
+    account_id  integer         not null,
+    remote_id   integer default null,
+    name        varchar(255)    not null,
+    state       varchar(8)      not null    default 'Clean',
+    foreign key(account_id) references accounts(id) on delete cascade on update no action,
+    unique(account_id, remote_id),
+    check(state in ('Clean', 'Modified', 'Deleted'))
+);
+
+create table notes_new(
+    id          integer primary key autoincrement,
+    folder_id   integer         not null,
+    remote_id   integer         default null,
+    'commit'    integer         not null,
+    name        varchar(255)    not null,
+    text        text            not null,
+    state       varchar(8)      not null,
+    foreign key(folder_id) references folders_new(id) on delete cascade on update no action,
+    unique(folder_id, remote_id),
+    check(state in ('Clean', 'Modified', 'Deleted'))
+);
+
+insert into folders_new (id, account_id, remote_id, name, state) select id, account_id, remote_id, name, state from folders;
+insert into notes_new (id, folder_id, remote_id, 'commit', name, text, state) select id, folder_id, remote_id, "commit", name, text, state from notes;
+
+drop table notes;
+drop table folders;
+
+alter table folders_new rename to folders;
+alter table notes_new rename to notes;
@@ -0,0 +1,2 @@
+-- Removing a folder also removes its subfolders
+alter table folders add column parent_id integer default null references folders(id) on delete cascade on update no action;
@@ -0,0 +1,19 @@
+-- Check constraints cannot be altered in sqlite, so notes table is recreated to allow trashed notes
+create table notes_new(
+    id          integer primary key autoincrement,
+    folder_id   integer         not null,
+    remote_id   integer         default null,
+    'commit'    integer         not null,
+    name        varchar(255)    not null,
+    text        text            not null,
+    state       varchar(8)      not null,
+    foreign key(folder_id) references folders(id) on delete cascade on update no action,
+    unique(folder_id, remote_id),
+    check(state in ('Clean', 'Modified', 'Deleted', 'Trashed'))
+);
+
+insert into notes_new (id, folder_id, remote_id, 'commit', name, text, state) select id, folder_id, remote_id, "commit", name, text, state from notes;
+
+drop table notes;
+
+alter table notes_new rename to notes;
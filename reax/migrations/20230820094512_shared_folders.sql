@@ -0,0 +1,3 @@
+-- Folders shared with this account are owned by another user, their role restricts the modifications
+alter table folders add column shared boolean not null default false;
+alter table folders add column role varchar(5) default null check(role in ('Read', 'Write'));
@@ -0,0 +1,21 @@
+-- Devices of other users that are looked up from the directory of the server, with the last record of their key
+-- log. A record that does not extend the stored one means that the server swapped the keys.
+create table directory_devices(
+    id          integer     not null,
+    account_id  integer     not null,
+    email       text        not null,
+    pubkey      varchar(64) not null,
+    foreign key(account_id) references accounts(id) on delete cascade on update no action,
+    unique(account_id, email, id)
+);
+
+create table directory_records(
+    account_id  integer     not null,
+    email       text        not null,
+    seq         integer     not null,
+    head        text        not null,
+    signer      text        not null,
+    signature   text        not null,
+    foreign key(account_id) references accounts(id) on delete cascade on update no action,
+    unique(account_id, email)
+);
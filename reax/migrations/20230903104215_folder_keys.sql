@@ -0,0 +1,2 @@
+-- Content key of the folder, notes in the folder are encrypted once with it
+alter table folders add column key text default null;
@@ -0,0 +1,9 @@
+-- Pubkey of the device before its last rotation, ciphertexts made before the rotation are decrypted with it
+alter table devices add column previous_pubkey varchar(64) default null;
+
+-- Accounts whose servers are not told about the rotation of the identity key yet. Servers know this device by the
+-- previous key until then.
+create table pending_rotations(
+    account_id  integer     not null    unique,
+    foreign key(account_id) references accounts(id) on delete cascade on update no action
+);
@@ -0,0 +1,3 @@
+-- Devices are trusted on first use. A device whose pubkey changes afterwards is not encrypted for until the user
+-- verifies its fingerprint again.
+alter table devices add column trust text not null default 'Unverified';
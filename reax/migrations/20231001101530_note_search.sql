@@ -0,0 +1,10 @@
+-- Notes hold ciphertexts when the local encryption is enabled, so the index is written by the application with the
+-- plain names and texts, and it is kept empty while the encryption is enabled
+create virtual table note_search using fts5(name, text, tokenize = 'unicode61 remove_diacritics 2');
+
+insert into note_search (rowid, name, text)
+select id, name, text from notes where not exists (select 1 from store where key = 'DataKey');
+
+create trigger note_search_delete after delete on notes begin
+    delete from note_search where rowid = old.id;
+end;
@@ -0,0 +1,14 @@
+-- Text of the note at its commit, which is the common ancestor when the note is modified on both this device and remote
+alter table notes add column base text default null;
+
+update notes set base = text where remote_id is not null and state = 'Clean';
+
+create table note_merges(
+    id              integer primary key autoincrement,
+    note_id         integer not null,
+    base_commit     integer not null,
+    remote_commit   integer not null,
+    conflicts       integer not null,
+    created_at      text    not null    default current_timestamp,
+    foreign key(note_id) references notes(id) on delete cascade on update no action
+);
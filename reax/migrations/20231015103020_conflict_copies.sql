@@ -0,0 +1,12 @@
+-- Accounts either merge the conflicting texts of a note, or keep the remote text and move the local one to a copy
+alter table accounts add column conflict_policy varchar(5) not null default 'Merge' check(conflict_policy in ('Merge', 'Copy'));
+
+create table note_conflicts(
+    id              integer primary key autoincrement,
+    note_id         integer not null,
+    copy_id         integer not null    unique,
+    remote_commit   integer not null,
+    created_at      text    not null    default current_timestamp,
+    foreign key(note_id) references notes(id) on delete cascade on update no action,
+    foreign key(copy_id) references notes(id) on delete cascade on update no action
+);
@@ -0,0 +1,11 @@
+-- Texts which are replaced by an update or a sync, they are encrypted like the notes when the local encryption is enabled
+create table note_revisions(
+    id          integer primary key autoincrement,
+    note_id     integer not null,
+    name        varchar(255)    not null,
+    text        text            not null,
+    created_at  text            not null    default current_timestamp,
+    foreign key(note_id) references notes(id) on delete cascade on update no action
+);
+
+create index note_revisions_note_id on note_revisions(note_id);
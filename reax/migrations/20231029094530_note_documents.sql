@@ -0,0 +1,13 @@
+-- Notes whose texts are kept as CRDT documents, the text of the note is the text of its document. Documents are
+-- encrypted like the notes when the local encryption is enabled.
+create table note_documents(
+    note_id     integer primary key,
+    -- Changes made on this device are recorded with this actor
+    actor       varchar(64)     not null,
+    document    text            not null,
+    -- Sequence number of the last change of this device which is sent to remote
+    pushed_seq  integer         not null    default 0,
+    -- Last remote update which is merged into the document
+    update_id   integer,
+    foreign key(note_id) references notes(id) on delete cascade on update no action
+);
@@ -0,0 +1,22 @@
+-- Outbox of the changes which are not sent to remote yet. Operations of an account are replayed in the order they are
+-- made, each with its own idempotency key so that remote applies an operation once even if its response is lost.
+-- Payload of an operation is read from its folder or note while replaying, so the outbox never keeps a name or a text.
+create table operations(
+    id              integer primary key autoincrement,
+    account_id      integer         not null,
+    kind            varchar(16)     not null    check(kind in ('CreateFolder', 'RenameFolder', 'MoveFolder', 'DeleteFolder', 'CreateNote', 'UpdateNote', 'DeleteNote')),
+    folder_id       integer,
+    note_id         integer,
+    idempotency_key varchar(64)     not null    unique,
+    attempts        integer         not null    default 0,
+    last_error      text,
+    -- Unix time in seconds, a failed operation and the ones after it are not replayed before it
+    retry_at        integer         not null    default 0,
+    created_at      text            not null    default current_timestamp,
+    check((folder_id is null) != (note_id is null)),
+    foreign key(account_id) references accounts(id) on delete cascade on update no action,
+    foreign key(folder_id) references folders(id) on delete cascade on update no action,
+    foreign key(note_id) references notes(id) on delete cascade on update no action
+);
+
+create index operations_account_id on operations(account_id, id);
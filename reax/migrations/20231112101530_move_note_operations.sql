@@ -0,0 +1,26 @@
+-- Moves of the notes are also sent through the outbox. Check constraint of a table cannot be altered, so the table is
+-- created again with the new kind.
+create table operations_new(
+    id              integer primary key autoincrement,
+    account_id      integer         not null,
+    kind            varchar(16)     not null    check(kind in ('CreateFolder', 'RenameFolder', 'MoveFolder', 'DeleteFolder', 'CreateNote', 'UpdateNote', 'MoveNote', 'DeleteNote')),
+    folder_id       integer,
+    note_id         integer,
+    idempotency_key varchar(64)     not null    unique,
+    attempts        integer         not null    default 0,
+    last_error      text,
+    retry_at        integer         not null    default 0,
+    created_at      text            not null    default current_timestamp,
+    check((folder_id is null) != (note_id is null)),
+    foreign key(account_id) references accounts(id) on delete cascade on update no action,
+    foreign key(folder_id) references folders(id) on delete cascade on update no action,
+    foreign key(note_id) references notes(id) on delete cascade on update no action
+);
+
+insert into operations_new select * from operations;
+
+drop table operations;
+
+alter table operations_new rename to operations;
+
+create index operations_account_id on operations(account_id, id);
@@ -0,0 +1,6 @@
+-- Trashed notes and folders are purged from this device once they stay in the trash longer than the retention
+alter table notes add column trashed_at text default null;
+
+alter table folders add column trashed_at text default null;
+
+update notes set trashed_at = current_timestamp where state = 'Trashed';
@@ -0,0 +1,3 @@
+-- Operations which remote refuses for good are kept aside as failed instead of holding back the later ones of their
+-- account, until they are retried or discarded
+alter table operations add column failed boolean not null default false;
@@ -1,24 +1,26 @@
 use std::{future::Future, time::Duration};
 
-use reqwest::{Client, ClientBuilder, header::{HeaderMap, HeaderValue}, StatusCode};
+use reqwest::{Client, ClientBuilder, RequestBuilder, header::{HeaderMap, HeaderValue}, StatusCode};
 use serde::{Deserialize, Serialize};
 use futures_util::{StreamExt, SinkExt};
 use tokio::time::Instant;
 use tokio_tungstenite::connect_async;
 
-use crate::models::RemoteId;
+use crate::models::{RemoteId, Role};
 
-pub use requests::{CreateFolderRequest, CreateNoteRequest, RespondRequests, RespondFolderRequest, RespondNoteRequest, CreateRequests};
+pub use requests::{CreateFolderRequest, CreateNoteRequest, RespondRequests, RespondFolderRequest, RespondNoteRequest, CreateRequests, CreateAttachmentRequest, DeviceAttachmentRequest, StoreRecoveryBundle};
 pub use responses::Device;
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all="snake_case")]
 pub enum DeviceMessage {
     AcceptPendingDevice,
+    VerifyPendingDevice { pubkey: String, nonce: String },
     RefreshRequests,
     RefreshRemote,
     RefreshFolder(i32),
     RefreshNote { folder_id: i32, note_id: i32, commit: i32, deleted: bool },
+    RefreshNoteUpdates { folder_id: i32, note_id: i32, update_id: i32 },
     Text(String),
     Timeout,
 }
@@ -41,6 +43,28 @@ pub enum Error {
     UnexpectedResponse,
     DeviceDeleted(i32),
     Unknown(String),
+    QuotaExceeded(Quota),
+}
+
+/// Limit of the account that is exceeded by the request
+#[derive(Clone, Debug, Serialize)]
+pub enum Quota {
+    Storage,
+    Notes,
+    Folders,
+    NoteSize,
+}
+
+impl Quota {
+    fn from_error(error: &str) -> Option<Quota> {
+        match error {
+            "storage_quota_exceeded" => Some(Quota::Storage),
+            "note_quota_exceeded" => Some(Quota::Notes),
+            "folder_quota_exceeded" => Some(Quota::Folders),
+            "note_too_large" => Some(Quota::NoteSize),
+            _ => None,
+        }
+    }
 }
 
 impl From<reqwest::Error> for Error {
@@ -115,7 +139,9 @@ impl AuthClient {
             .map_err(|e| e.into())
     }
 
-    pub async fn wait_verification(ws_url: &str, token: &str) -> Result<(), Error> {
+    /// Waits until the pending device is accepted, `on_verification` is called with the pubkey and the nonce of the
+    /// verifying device each time a device sends its nonce
+    pub async fn wait_verification<F: Future<Output = ()>>(ws_url: &str, token: &str, on_verification: impl Fn(String, String) -> F) -> Result<(), Error> {
         let ws_failed = || Error::Message("ws_failed".to_string());
 
         let (mut sock, _) = connect_async(format!("{}/auth/wait-verification?token={}", ws_url, token)).await
@@ -146,6 +172,7 @@ impl AuthClient {
                         Ok(msg) => {
                             match serde_json::from_str::<DeviceMessage>(&msg) {
                                 Ok(DeviceMessage::AcceptPendingDevice) => return Ok(()),
+                                Ok(DeviceMessage::VerifyPendingDevice { pubkey, nonce }) => on_verification(pubkey, nonce).await,
                                 Ok(DeviceMessage::Timeout) => return Err(Error::Message("ws_timeout".to_string())),
                                 Ok(msg) => log::debug!("unexpected device message is received {msg:?}"),
                                 Err(e) => log::debug!("failed to deserialize device message {e:?}"),
@@ -162,8 +189,8 @@ impl AuthClient {
         }
     }
 
-    pub async fn request_verification(&self, email: &str, pubkey: &str, password: &str) -> Result<Token, Error> {
-        let request = requests::RequestVerification { email, pubkey, password };
+    pub async fn request_verification(&self, email: &str, pubkey: &str, password: &str, commitment: &str) -> Result<Token, Error> {
+        let request = requests::RequestVerification { email, pubkey, password, commitment };
 
         self.client
             .post(format!("{}/auth/request-verification", self.api_url))
@@ -177,6 +204,47 @@ impl AuthClient {
             .map_err(|e| e.into())
     }
 
+    pub async fn reveal_verification(&self, token: &str, nonce: &str) -> Result<(), Error> {
+        let request = requests::RevealVerification { token, nonce };
+
+        self.client
+            .post(format!("{}/auth/reveal-verification", self.api_url))
+            .body(serde_json::to_string(&request).unwrap())
+            .send()
+            .await
+            .map(Self::error_for_status)?
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn send_recovery_code(&self, email: &str) -> Result<(), Error> {
+        let request = requests::SendCode { email };
+
+        self.client
+            .post(format!("{}/auth/send-recovery-code", self.api_url))
+            .body(serde_json::to_string(&request).unwrap())
+            .send()
+            .await
+            .map(Self::error_for_status)?
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn recover(&self, email: &str, code: &str) -> Result<responses::RecoveryBundle, Error> {
+        let request = requests::Recover { email, code };
+
+        self.client
+            .post(format!("{}/auth/recover", self.api_url))
+            .body(serde_json::to_string(&request).unwrap())
+            .send()
+            .await
+            .map(Self::error_for_status)?
+            .await?
+            .json()
+            .await
+            .map_err(|e| e.into())
+    }
+
     pub async fn send_verification_code(&self, email: &str) -> Result<(), Error> {
         let request = requests::SendCode { email };
 
@@ -211,6 +279,7 @@ pub struct MavinoteClient {
     account_id: i32,
     api_url: String,
     client: Client,
+    idempotency_key: Option<String>,
 }
 
 impl MavinoteClient {
@@ -228,6 +297,21 @@ impl MavinoteClient {
             account_id,
             api_url,
             client,
+            idempotency_key: None,
+        }
+    }
+
+    /// Client whose changes are applied once by remote however many times they are sent with the same key
+    pub fn with_idempotency_key(mut self, key: String) -> Self {
+        self.idempotency_key = Some(key);
+
+        self
+    }
+
+    fn idempotent(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.idempotency_key {
+            Some(key) => request.header("Idempotency-Key", key),
+            None => request,
         }
     }
 
@@ -248,6 +332,12 @@ impl MavinoteClient {
             return Err(Error::Unauthorized(Some(self.account_id)));
         }
 
+        if status == StatusCode::PAYLOAD_TOO_LARGE {
+            if let Some(quota) = Quota::from_error(&error) {
+                return Err(Error::QuotaExceeded(quota));
+            }
+        }
+
         Err(Error::Message(error))
     }
 
@@ -314,6 +404,47 @@ impl MavinoteClient {
             .map_err(|e| e.into())
     }
 
+    pub async fn start_device_verification(&self, pubkey: &str) -> Result<responses::DeviceVerification, Error> {
+        let request = requests::AddDevice { pubkey };
+
+        self.client
+            .post(format!("{}/user/device/verification", self.api_url))
+            .body(serde_json::to_string(&request).unwrap())
+            .send()
+            .await
+            .map(|r| async { self.error_for_status(r).await })?
+            .await?
+            .json()
+            .await
+            .map_err(|e| e.into())
+    }
+
+    pub async fn send_verification_nonce(&self, pubkey: &str, nonce: &str) -> Result<(), Error> {
+        let request = requests::SendVerificationNonce { pubkey, nonce };
+
+        self.client
+            .put(format!("{}/user/device/verification", self.api_url))
+            .body(serde_json::to_string(&request).unwrap())
+            .send()
+            .await
+            .map(|r| async { self.error_for_status(r).await })?
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn fetch_verification_nonce(&self, pubkey: &str) -> Result<responses::VerificationNonce, Error> {
+        self.client
+            .get(format!("{}/user/device/verification", self.api_url))
+            .query(&requests::AddDevice { pubkey })
+            .send()
+            .await
+            .map(|r| async { self.error_for_status(r).await })?
+            .await?
+            .json()
+            .await
+            .map_err(|e| e.into())
+    }
+
     pub async fn add_device(&self, pubkey: &str) -> Result<responses::Device, Error> {
         let request = requests::AddDevice { pubkey };
 
@@ -345,6 +476,78 @@ impl MavinoteClient {
             .map(|_| ())
     }
 
+    pub async fn fetch_rotation_key(&self) -> Result<responses::RotationKey, Error> {
+        self.client
+            .get(format!("{}/user/rotation-key", self.api_url))
+            .send()
+            .await
+            .map(|r| async { self.error_for_status(r).await })?
+            .await?
+            .json()
+            .await
+            .map_err(|e| e.into())
+    }
+
+    pub async fn rotate_pubkey(&self, pubkey: &str, proof: &str) -> Result<(), Error> {
+        let request = requests::RotatePubkey { pubkey, proof };
+
+        self.client
+            .put(format!("{}/user/device/pubkey", self.api_url))
+            .body(serde_json::to_string(&request).unwrap())
+            .send()
+            .await
+            .map(|r| async { self.error_for_status(r).await })?
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn store_recovery_bundle(&self, request: &requests::StoreRecoveryBundle<'_>) -> Result<(), Error> {
+
+        self.client
+            .put(format!("{}/user/recovery-bundle", self.api_url))
+            .body(serde_json::to_string(request).unwrap())
+            .send()
+            .await
+            .map(|r| async { self.error_for_status(r).await })?
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn delete_recovery_bundle(&self) -> Result<(), Error> {
+        self.client
+            .delete(format!("{}/user/recovery-bundle", self.api_url))
+            .send()
+            .await
+            .map(|r| async { self.error_for_status(r).await })?
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn fetch_directory(&self, email: &str) -> Result<responses::Directory, Error> {
+        self.client
+            .get(format!("{}/user/{}/devices", self.api_url, email))
+            .send()
+            .await
+            .map(|r| async { self.error_for_status(r).await })?
+            .await?
+            .json()
+            .await
+            .map_err(|e| e.into())
+    }
+
+    pub async fn update_directory(&self, listed: bool) -> Result<(), Error> {
+        let request = requests::UpdateDirectory { listed };
+
+        self.client
+            .put(format!("{}/user/directory", self.api_url))
+            .body(serde_json::to_string(&request).unwrap())
+            .send()
+            .await
+            .map(|r| async { self.error_for_status(r).await })?
+            .await
+            .map(|_| ())
+    }
+
     pub async fn fetch_folders(&self) -> Result<Vec<responses::Folder>, Error> {
         self.client
             .get(format!("{}/note/folders", self.api_url))
@@ -357,6 +560,18 @@ impl MavinoteClient {
             .map_err(|e| e.into())
     }
 
+    pub async fn fetch_changes(&self, since: i64) -> Result<responses::Changes, Error> {
+        self.client
+            .get(format!("{}/note/changes?since={}", self.api_url, since))
+            .send()
+            .await
+            .map(|r| async { self.error_for_status(r).await })?
+            .await?
+            .json()
+            .await
+            .map_err(|e| e.into())
+    }
+
     pub async fn fetch_folder(&self, folder_id: RemoteId) -> Result<Option<responses::Folder>, Error> {
         let response = self.client
             .get(format!("{}/note/folder/{}", self.api_url, folder_id.0))
@@ -375,9 +590,13 @@ impl MavinoteClient {
 
     }
 
-    pub async fn create_folder(&self, request: &[requests::CreateFolderRequest]) -> Result<responses::CreatedFolder, Error> {
-        self.client
-            .post(format!("{}/note/folder", self.api_url))
+    pub async fn create_folder(&self, parent_id: Option<RemoteId>, request: &[requests::CreateFolderRequest]) -> Result<responses::CreatedFolder, Error> {
+        let url = match parent_id {
+            Some(parent_id) => format!("{}/note/folder?parent_id={}", self.api_url, parent_id.0),
+            None => format!("{}/note/folder", self.api_url),
+        };
+
+        self.idempotent(self.client.post(url))
             .body(serde_json::to_string(request).unwrap())
             .send()
             .await
@@ -388,9 +607,38 @@ impl MavinoteClient {
             .map_err(|e| e.into())
     }
 
+    pub async fn rename_folder(&self, folder_id: RemoteId, request: &[requests::CreateFolderRequest]) -> Result<(), Error> {
+        self.idempotent(self.client.put(format!("{}/note/folder/{}", self.api_url, folder_id.0)))
+            .body(serde_json::to_string(request).unwrap())
+            .send()
+            .await
+            .map(|r| async { self.error_for_status(r).await })?
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn move_folder(&self, folder_id: RemoteId, parent_id: Option<RemoteId>) -> Result<(), Error> {
+        self.idempotent(self.client.put(format!("{}/note/folder/{}/move", self.api_url, folder_id.0)))
+            .body(serde_json::to_string(&requests::MoveFolderRequest { parent_id: parent_id.map(|id| id.0) }).unwrap())
+            .send()
+            .await
+            .map(|r| async { self.error_for_status(r).await })?
+            .await
+            .map(|_| ())
+    }
+
     pub async fn delete_folder(&self, folder_id: RemoteId) -> Result<(), Error> {
+        self.idempotent(self.client.delete(format!("{}/note/folder/{}", self.api_url, folder_id.0)))
+            .send()
+            .await
+            .map(|r| async { self.error_for_status(r).await })?
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn restore_folder(&self, folder_id: RemoteId) -> Result<(), Error> {
         self.client
-            .delete(format!("{}/note/folder/{}", self.api_url, folder_id.0))
+            .put(format!("{}/note/folder/{}/restore", self.api_url, folder_id.0))
             .send()
             .await
             .map(|r| async { self.error_for_status(r).await })?
@@ -398,6 +646,51 @@ impl MavinoteClient {
             .map(|_| ())
     }
 
+    pub async fn fetch_folder_members(&self, folder_id: RemoteId) -> Result<Vec<responses::FolderMember>, Error> {
+        self.client
+            .get(format!("{}/note/folder/{}/members", self.api_url, folder_id.0))
+            .send()
+            .await
+            .map(|r| async { self.error_for_status(r).await })?
+            .await?
+            .json()
+            .await
+            .map_err(|e| e.into())
+    }
+
+    pub async fn add_folder_member(&self, folder_id: RemoteId, email: &str, role: Role) -> Result<(), Error> {
+        self.client
+            .post(format!("{}/note/folder/{}/members", self.api_url, folder_id.0))
+            .body(serde_json::to_string(&requests::AddFolderMemberRequest { email, role }).unwrap())
+            .send()
+            .await
+            .map(|r| async { self.error_for_status(r).await })?
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn remove_folder_member(&self, folder_id: RemoteId, user_id: i32) -> Result<(), Error> {
+        self.client
+            .delete(format!("{}/note/folder/{}/members/{}", self.api_url, folder_id.0, user_id))
+            .send()
+            .await
+            .map(|r| async { self.error_for_status(r).await })?
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn fetch_folder_devices(&self, folder_id: RemoteId) -> Result<Vec<responses::FolderDevice>, Error> {
+        self.client
+            .get(format!("{}/note/folder/{}/devices", self.api_url, folder_id.0))
+            .send()
+            .await
+            .map(|r| async { self.error_for_status(r).await })?
+            .await?
+            .json()
+            .await
+            .map_err(|e| e.into())
+    }
+
     pub async fn fetch_note(&self, note_id: RemoteId) -> Result<Option<responses::Note>, Error> {
         let response = self.client
             .get(format!("{}/note/note/{}", self.api_url, note_id.0))
@@ -415,10 +708,9 @@ impl MavinoteClient {
             .map_err(|e| e.into())
     }
 
-    pub async fn create_note(&self, folder_id: RemoteId, device_notes: &[requests::CreateNoteRequest]) -> Result<responses::CreatedNote, Error> {
-        self.client
-            .post(format!("{}/note/note?folder_id={}", self.api_url, folder_id.0))
-            .body(serde_json::to_string(&device_notes).unwrap())
+    pub async fn create_note(&self, folder_id: RemoteId, request: &requests::CreateNoteRequest) -> Result<responses::CreatedNote, Error> {
+        self.idempotent(self.client.post(format!("{}/note/note?folder_id={}", self.api_url, folder_id.0)))
+            .body(serde_json::to_string(request).unwrap())
             .send()
             .await
             .map(|r| async { self.error_for_status(r).await })?
@@ -428,11 +720,45 @@ impl MavinoteClient {
             .map_err(|e| e.into())
     }
 
-    pub async fn update_note(&self, note_id: RemoteId, commit: i32, device_notes: &[requests::CreateNoteRequest]) -> Result<responses::Commit, Error> {
-        let request = requests::UpdateNoteRequest { commit,  device_notes };
+    pub async fn update_note(&self, note_id: RemoteId, commit: i32, note: &requests::CreateNoteRequest) -> Result<responses::NoteUpdate, Error> {
+        let request = requests::UpdateNoteRequest { commit, name: &note.name, text: &note.text };
+
+        let response = self.idempotent(self.client.put(format!("{}/note/note/{}", self.api_url, note_id.0)))
+            .body(serde_json::to_string(&request).unwrap())
+            .send()
+            .await?;
+
+        if response.status() == StatusCode::CONFLICT {
+            return response.json::<responses::CommitMismatch>()
+                .await
+                .map(|mismatch| responses::NoteUpdate::Mismatch(mismatch.note))
+                .map_err(|e| e.into());
+        }
+
+        self.error_for_status(response)
+            .await?
+            .json()
+            .await
+            .map(responses::NoteUpdate::Committed)
+            .map_err(|e| e.into())
+    }
 
+    pub async fn fetch_note_updates(&self, note_id: RemoteId, after: i32) -> Result<Vec<responses::DocumentUpdate>, Error> {
         self.client
-            .put(format!("{}/note/note/{}", self.api_url, note_id.0))
+            .get(format!("{}/note/note/{}/updates?after={}", self.api_url, note_id.0, after))
+            .send()
+            .await
+            .map(|r| async { self.error_for_status(r).await })?
+            .await?
+            .json()
+            .await
+            .map_err(|e| e.into())
+    }
+
+    pub async fn create_note_update(&self, note_id: RemoteId, data: &str, previous_id: Option<i32>, snapshot: bool) -> Result<responses::CreatedDocumentUpdate, Error> {
+        let request = requests::CreateNoteUpdateRequest { data, previous_id, snapshot };
+
+        self.idempotent(self.client.post(format!("{}/note/note/{}/updates", self.api_url, note_id.0)))
             .body(serde_json::to_string(&request).unwrap())
             .send()
             .await
@@ -443,9 +769,119 @@ impl MavinoteClient {
             .map_err(|e| e.into())
     }
 
+    pub async fn move_note(&self, note_id: RemoteId, folder_id: RemoteId, note: &requests::CreateNoteRequest) -> Result<(), Error> {
+        let request = requests::MoveNoteRequest { folder_id: folder_id.0, name: &note.name, text: &note.text };
+
+        self.idempotent(self.client.put(format!("{}/note/note/{}/move", self.api_url, note_id.0)))
+            .body(serde_json::to_string(&request).unwrap())
+            .send()
+            .await
+            .map(|r| async { self.error_for_status(r).await })?
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn fetch_attachments(&self, note_id: RemoteId) -> Result<Vec<responses::Attachment>, Error> {
+        self.client
+            .get(format!("{}/note/note/{}/attachments", self.api_url, note_id.0))
+            .send()
+            .await
+            .map(|r| async { self.error_for_status(r).await })?
+            .await?
+            .json()
+            .await
+            .map_err(|e| e.into())
+    }
+
+    pub async fn create_attachment(&self, note_id: RemoteId, request: &requests::CreateAttachmentRequest) -> Result<responses::CreatedAttachment, Error> {
+        self.client
+            .post(format!("{}/note/note/{}/attachments", self.api_url, note_id.0))
+            .body(serde_json::to_string(request).unwrap())
+            .send()
+            .await
+            .map(|r| async { self.error_for_status(r).await })?
+            .await?
+            .json()
+            .await
+            .map_err(|e| e.into())
+    }
+
+    pub async fn upload_attachment_chunk(&self, attachment_id: i32, chunk: i32, data: Vec<u8>) -> Result<(), Error> {
+        self.client
+            .put(format!("{}/note/attachment/{}/chunk/{}", self.api_url, attachment_id, chunk))
+            .header("Content-Type", "application/octet-stream")
+            .body(data)
+            .send()
+            .await
+            .map(|r| async { self.error_for_status(r).await })?
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn fetch_attachment_chunk(&self, attachment_id: i32, chunk: i32) -> Result<Vec<u8>, Error> {
+        self.client
+            .get(format!("{}/note/attachment/{}/chunk/{}", self.api_url, attachment_id, chunk))
+            .send()
+            .await
+            .map(|r| async { self.error_for_status(r).await })?
+            .await?
+            .bytes()
+            .await
+            .map(|bytes| bytes.to_vec())
+            .map_err(|e| e.into())
+    }
+
+    pub async fn delete_attachment(&self, attachment_id: i32) -> Result<(), Error> {
+        self.client
+            .delete(format!("{}/note/attachment/{}", self.api_url, attachment_id))
+            .send()
+            .await
+            .map(|r| async { self.error_for_status(r).await })?
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn fetch_note_commits(&self, note_id: RemoteId) -> Result<Vec<responses::NoteCommit>, Error> {
+        self.client
+            .get(format!("{}/note/note/{}/commits", self.api_url, note_id.0))
+            .send()
+            .await
+            .map(|r| async { self.error_for_status(r).await })?
+            .await?
+            .json()
+            .await
+            .map_err(|e| e.into())
+    }
+
+    pub async fn fetch_note_commit(&self, note_id: RemoteId, commit: i32) -> Result<Option<responses::DeviceNoteCommit>, Error> {
+        let response = self.client
+            .get(format!("{}/note/note/{}/commit/{}", self.api_url, note_id.0, commit))
+            .send()
+            .await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        self.error_for_status(response)
+            .await?
+            .json()
+            .await
+            .map_err(|e| e.into())
+    }
+
     pub async fn delete_note(&self, note_id: RemoteId) -> Result<(), Error> {
+        self.idempotent(self.client.delete(format!("{}/note/note/{}", self.api_url, note_id.0)))
+            .send()
+            .await
+            .map(|r| async { self.error_for_status(r).await })?
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn restore_note(&self, note_id: RemoteId) -> Result<(), Error> {
         self.client
-            .delete(format!("{}/note/note/{}", self.api_url, note_id.0))
+            .put(format!("{}/note/note/{}/restore", self.api_url, note_id.0))
             .send()
             .await
             .map(|r| async { self.error_for_status(r).await })?
@@ -491,6 +927,8 @@ impl MavinoteClient {
 mod requests {
     use serde::Serialize;
 
+    use crate::models::Role;
+
     #[derive(Serialize)]
     pub struct Login<'a> {
         pub email: &'a str,
@@ -507,19 +945,50 @@ mod requests {
     pub struct CreateFolderRequest {
         pub name: String,
         pub device_id: i32,
+        pub key: String,
+    }
+
+    #[derive(Serialize)]
+    pub struct UpdateDirectory {
+        pub listed: bool,
+    }
+
+    #[derive(Serialize)]
+    pub struct AddFolderMemberRequest<'a> {
+        pub email: &'a str,
+        pub role: Role,
     }
 
     #[derive(Serialize)]
     pub struct CreateNoteRequest {
         pub name: String,
         pub text: String,
-        pub device_id: i32,
     }
 
     #[derive(Serialize)]
     pub struct UpdateNoteRequest<'a> {
         pub commit: i32,
-        pub device_notes: &'a [CreateNoteRequest],
+        pub name: &'a str,
+        pub text: &'a str,
+    }
+
+    #[derive(Serialize)]
+    pub struct CreateNoteUpdateRequest<'a> {
+        pub data: &'a str,
+        pub previous_id: Option<i32>,
+        pub snapshot: bool,
+    }
+
+    #[derive(Serialize)]
+    pub struct MoveFolderRequest {
+        pub parent_id: Option<i32>,
+    }
+
+    #[derive(Serialize)]
+    pub struct MoveNoteRequest<'a> {
+        pub folder_id: i32,
+        pub name: &'a str,
+        pub text: &'a str,
     }
 
     #[derive(Serialize)]
@@ -535,6 +1004,13 @@ mod requests {
         pub email: &'a str,
         pub pubkey: &'a str,
         pub password: &'a str,
+        pub commitment: &'a str,
+    }
+
+    #[derive(Serialize)]
+    pub struct RevealVerification<'a> {
+        pub token: &'a str,
+        pub nonce: &'a str,
     }
 
     #[derive(Serialize)]
@@ -547,6 +1023,35 @@ mod requests {
         pub pubkey: &'a str,
     }
 
+    #[derive(Serialize)]
+    pub struct SendVerificationNonce<'a> {
+        pub pubkey: &'a str,
+        pub nonce: &'a str,
+    }
+
+    #[derive(Serialize)]
+    pub struct RotatePubkey<'a> {
+        pub pubkey: &'a str,
+        pub proof: &'a str,
+    }
+
+    #[derive(Serialize)]
+    pub struct Recover<'a> {
+        pub email: &'a str,
+        pub code: &'a str,
+    }
+
+    #[derive(Serialize)]
+    pub struct StoreRecoveryBundle<'a> {
+        pub salt: &'a str,
+        pub algorithm: &'a str,
+        pub memory_cost: u32,
+        pub time_cost: u32,
+        pub parallelism: u32,
+        pub nonce: &'a str,
+        pub ciphertext: &'a str,
+    }
+
     #[derive(Serialize)]
     pub struct RespondRequests {
         pub device_id: i32,
@@ -558,6 +1063,7 @@ mod requests {
     pub struct RespondFolderRequest {
         pub folder_id: i32,
         pub name: String,
+        pub key: Option<String>,
     }
 
     #[derive(Serialize)]
@@ -567,6 +1073,20 @@ mod requests {
         pub text: String,
     }
 
+    #[derive(Serialize)]
+    pub struct CreateAttachmentRequest {
+        pub name: String,
+        pub chunk_count: i32,
+        pub key: String,
+        pub device_attachments: Vec<DeviceAttachmentRequest>,
+    }
+
+    #[derive(Serialize)]
+    pub struct DeviceAttachmentRequest {
+        pub device_id: i32,
+        pub key: String,
+    }
+
     #[derive(Default, Serialize)]
     pub struct CreateRequests {
         pub folder_ids: Vec<i32>,
@@ -576,9 +1096,9 @@ mod requests {
 
 pub mod responses {
     use chrono::NaiveDateTime;
-    use serde::Deserialize;
+    use serde::{Deserialize, Serialize};
 
-    use crate::models::{RemoteId, State};
+    use crate::models::{RemoteId, Role, State};
 
     #[derive(Deserialize)]
     pub struct CreatedFolder {
@@ -596,13 +1116,75 @@ pub mod responses {
         pub id: i32,
         pub pubkey: String,
         pub created_at: NaiveDateTime,
+        pub previous_pubkey: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct RotationKey {
+        pub pubkey: String,
+    }
+
+    #[derive(Deserialize)]
+    pub struct DeviceVerification {
+        pub commitment: String,
+    }
+
+    #[derive(Deserialize)]
+    pub struct VerificationNonce {
+        pub nonce: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct RecoveryBundle {
+        pub salt: String,
+        pub algorithm: String,
+        pub memory_cost: u32,
+        pub time_cost: u32,
+        pub parallelism: u32,
+        pub nonce: String,
+        pub ciphertext: String,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Directory {
+        pub devices: Vec<DirectoryDevice>,
+        pub log: Vec<KeyLogEntry>,
+        pub record: DirectoryRecord,
+    }
+
+    #[derive(Deserialize)]
+    pub struct DirectoryDevice {
+        pub id: i32,
+        pub pubkey: String,
+    }
+
+    #[derive(Deserialize)]
+    pub struct KeyLogEntry {
+        pub device_id: i32,
+        pub pubkey: String,
+        pub added: bool,
+        pub hash: String,
+    }
+
+    #[derive(Deserialize)]
+    pub struct DirectoryRecord {
+        pub seq: i32,
+        pub head: String,
+        pub signer: String,
+        pub signature: String,
     }
 
     #[derive(Debug, Deserialize)]
     pub struct Folder {
         pub id: i32,
+        pub seq: i64,
+        pub parent_id: Option<i32>,
         pub state: State,
+        pub shared: bool,
+        pub role: Option<Role>,
         pub device_folder: Option<DeviceFolder>,
+        /// Whether a device of the folder has received its content key
+        pub keyed: bool,
         pub commits: Vec<Commit>,
     }
     impl Folder {
@@ -611,10 +1193,34 @@ pub mod responses {
         }
     }
 
+    #[derive(Debug, Deserialize, Serialize)]
+    pub struct FolderMember {
+        pub user_id: i32,
+        pub email: String,
+        pub role: Role,
+    }
+
+    #[derive(Deserialize)]
+    pub struct FolderDevice {
+        pub id: i32,
+        pub pubkey: String,
+        pub previous_pubkey: Option<String>,
+        pub email: String,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Changes {
+        pub cursor: i64,
+        pub folders: Vec<Folder>,
+        /// Whether all the folders are returned since some deletions after the cursor are purged in remote
+        pub resync: bool,
+    }
+
     #[derive(Debug, Deserialize)]
     pub struct DeviceFolder {
         pub sender_device_id: i32,
         pub name: String,
+        pub key: Option<String>,
     }
 
     #[derive(Deserialize)]
@@ -635,6 +1241,7 @@ pub mod responses {
         pub commit: i32,
         pub state: State,
         pub device_note: Option<DeviceNote>,
+        pub content: Option<NoteContent>,
     }
 
     impl Note {
@@ -650,11 +1257,66 @@ pub mod responses {
         pub text: String,
     }
 
+    #[derive(Debug, Deserialize)]
+    pub struct NoteContent {
+        pub folder_id: i32,
+        pub sender_device_id: i32,
+        pub name: String,
+        pub text: String,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct CommitMismatch {
+        pub note: Note,
+    }
+
+    /// Result of updating a note. If the note is updated by some other device in the meantime,
+    /// the current note is returned instead of a new commit.
+    #[derive(Debug)]
+    pub enum NoteUpdate {
+        Committed(Commit),
+        Mismatch(Note),
+    }
+
+    #[derive(Deserialize)]
+    pub struct CreatedDocumentUpdate {
+        pub id: i32,
+        pub updates: i64,
+    }
+
+    /// Change of a note document encrypted with the key of the folder
+    #[derive(Deserialize)]
+    pub struct DocumentUpdate {
+        pub id: i32,
+        pub folder_id: i32,
+        pub data: String,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct NoteCommit {
+        pub commit: i32,
+        pub sender_device_id: i32,
+        pub created_at: NaiveDateTime,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct DeviceNoteCommit {
+        pub commit: i32,
+        pub sender_device_id: i32,
+        pub receiver_device_id: Option<i32>,
+        pub folder_id: Option<i32>,
+        pub name: String,
+        pub text: String,
+        pub created_at: NaiveDateTime,
+    }
+
     #[derive(Clone, Debug, Deserialize)]
     pub struct Commit {
         pub note_id: i32,
         pub commit: i32,
         pub state: State,
+        /// Last update of the note document, it is none if the note is not converted into a document
+        pub update_id: Option<i32>,
     }
 
     impl Commit {
@@ -669,6 +1331,25 @@ pub mod responses {
         pub note_requests: Vec<NoteRequest>,
     }
 
+    #[derive(Deserialize)]
+    pub struct CreatedAttachment {
+        pub id: i32,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Attachment {
+        pub id: i32,
+        pub name: String,
+        pub chunk_count: i32,
+        pub device_attachment: Option<DeviceAttachment>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct DeviceAttachment {
+        pub sender_device_id: i32,
+        pub key: String,
+    }
+
     #[derive(Deserialize)]
     pub struct FolderRequest {
         pub folder_id: i32,
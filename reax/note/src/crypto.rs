@@ -1,10 +1,13 @@
-use aes_gcm_siv::{Aes256GcmSiv, KeyInit, aead::Aead};
+use aes_gcm_siv::{Aes256GcmSiv, KeyInit, aead::{Aead, Payload}};
+use argon2::{Algorithm, Argon2, Params, Version};
 use base64ct::{Base64, Encoding};
-use serde::Serialize;
+use rand::{RngCore, rngs::OsRng};
+use ring::{digest::{digest, SHA256}, hmac, signature::{UnparsedPublicKey, ED25519}};
+use serde::{Deserialize, Serialize};
 use sqlx::{Sqlite, pool::PoolConnection};
 use x25519_dalek::{StaticSecret, PublicKey};
 
-use crate::{models::StoreKey, storage::db, Error as NoteError};
+use crate::{models::{StoreKey, Trust}, storage::secrets, Error as NoteError};
 
 #[derive(Clone, Debug, Serialize)]
 pub enum Error {
@@ -12,11 +15,52 @@ pub enum Error {
     InvalidLength,
     Decrypt,
     Encrypt,
+    DirectoryMismatch,
+    FingerprintMismatch,
+    CommitmentMismatch,
 }
 
 pub struct DeviceCipher {
     pub device_id: i32,
+    /// Whether anything can be encrypted for the device. Ciphertexts of untrusted devices are still decrypted.
+    pub trusted: bool,
     cipher: Aes256GcmSiv,
+    previous: Vec<Aes256GcmSiv>,
+}
+
+/// Secrets encrypted with a key derived from a passphrase
+#[derive(Deserialize, Serialize)]
+pub struct Sealed {
+    pub salt: String,
+    /// Secrets sealed before the parameters were stored are derived with the default ones
+    #[serde(default)]
+    pub kdf: KdfParams,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+/// Parameters of the Argon2 key derivation, stored along with the salt so that they can be raised without making the
+/// sealed secrets unreadable
+#[derive(Clone, Deserialize, Serialize)]
+pub struct KdfParams {
+    pub algorithm: String,
+    pub memory_cost: u32,
+    pub time_cost: u32,
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    /// Parameters recommended by OWASP for argon2id, which are bearable on phones
+    fn default() -> Self {
+        KdfParams { algorithm: "argon2id".to_string(), memory_cost: 19 * 1024, time_cost: 2, parallelism: 1 }
+    }
+}
+
+/// Identity key of this device. After a rotation, the previous key is kept until the servers of all the accounts
+/// learn the new one and the grace period passes.
+pub struct Identity {
+    pub privkey: StaticSecret,
+    pub previous: Option<StaticSecret>,
 }
 
 impl PartialEq for DeviceCipher {
@@ -34,12 +78,29 @@ impl std::hash::Hash for DeviceCipher {
 impl Eq for DeviceCipher { }
 
 impl DeviceCipher {
-    pub fn try_from_key(device_id: i32, privkey: &StaticSecret, pubkey: &str) -> Result<Self, Error> {
+    pub fn try_from_key(device_id: i32, identity: &Identity, pubkey: &str, previous_pubkey: Option<&str>) -> Result<Self, Error> {
         let pubkey = PublicKey::from(parse_key(pubkey)?);
-        let shared_secret = privkey.diffie_hellman(&pubkey);
-        let cipher = Aes256GcmSiv::new_from_slice(&shared_secret.to_bytes()).unwrap();
+        let previous_pubkey = previous_pubkey.map(parse_key).transpose()?.map(PublicKey::from);
 
-        Ok(DeviceCipher { device_id, cipher })
+        // Ciphertexts made before either of the devices rotated its key can only be decrypted with the previous keys
+        let previous = [
+            (identity.previous.as_ref(), Some(&pubkey)),
+            (Some(&identity.privkey), previous_pubkey.as_ref()),
+            (identity.previous.as_ref(), previous_pubkey.as_ref()),
+        ]
+            .into_iter()
+            .filter_map(|keys| match keys {
+                (Some(privkey), Some(pubkey)) => Some(shared_cipher(privkey, pubkey)),
+                _ => None,
+            })
+            .collect();
+
+        Ok(DeviceCipher { device_id, trusted: true, cipher: shared_cipher(&identity.privkey, &pubkey), previous })
+    }
+
+    pub fn with_trust(mut self, trust: Trust) -> Self {
+        self.trusted = trust != Trust::Changed;
+        self
     }
 
     pub fn encrypt(&self, message: &str, nonce: [u8; 12]) -> Result<String, Error> {
@@ -63,20 +124,278 @@ impl DeviceCipher {
         }
 
         let (nonce, bytes) = bytes.split_at(12);
-        let bytes = self.cipher.decrypt(nonce.into(), bytes)
-            .map_err(|_| Error::Decrypt)?;
+        let bytes = std::iter::once(&self.cipher)
+            .chain(&self.previous)
+            .find_map(|cipher| cipher.decrypt(nonce.into(), bytes).ok())
+            .ok_or(Error::Decrypt)?;
 
         String::from_utf8(bytes)
             .map_err(|_| Error::Decrypt)
     }
 }
 
-pub async fn load_privkey(conn: &mut PoolConnection<Sqlite>) -> Result<StaticSecret, NoteError> {
-    let store = db::fetch_value(conn, StoreKey::IdentityPrivKey).await?.unwrap();
+/// Separates the keys of a rotated cipher, it cannot appear in a base64 encoded key
+const KEY_RING_SEPARATOR: &str = ".";
+
+/// Cipher with a random key, used for attachments and for the contents of folders. Data is encrypted
+/// once with the key, and only the key is encrypted for each device.
+#[derive(Clone)]
+pub struct FileCipher {
+    key: [u8; 32],
+    cipher: Aes256GcmSiv,
+    /// Keys replaced by rotations, latest first. They only decrypt the contents encrypted before the rotations.
+    previous: Vec<([u8; 32], Aes256GcmSiv)>,
+}
+
+impl FileCipher {
+    pub fn generate() -> Self {
+        let mut key = [0; 32];
+        OsRng.fill_bytes(&mut key);
+
+        FileCipher { key, cipher: Aes256GcmSiv::new_from_slice(&key).unwrap(), previous: Vec::new() }
+    }
+
+    /// Key may also be a key ring returned by [`FileCipher::key`] after a rotation
+    pub fn try_from_key(key: &str) -> Result<Self, Error> {
+        let mut keys = key.split(KEY_RING_SEPARATOR)
+            .map(|key| parse_key(key).map(|key| (key, Aes256GcmSiv::new_from_slice(&key).unwrap())));
+
+        let (key, cipher) = keys.next().unwrap()?;
+
+        Ok(FileCipher { key, cipher, previous: keys.collect::<Result<_, _>>()? })
+    }
+
+    /// Returns a cipher with a new key, contents encrypted with the current keys can still be decrypted by it
+    pub fn rotate(self) -> Self {
+        let mut rotated = FileCipher::generate();
+
+        rotated.previous = std::iter::once((self.key, self.cipher))
+            .chain(self.previous)
+            .collect();
+
+        rotated
+    }
+
+    /// Whether this cipher is rotated from the current key of the given one
+    pub fn rotated_from(&self, other: &FileCipher) -> bool {
+        self.previous.iter().any(|(key, _)| *key == other.key)
+    }
+
+    pub fn key(&self) -> String {
+        std::iter::once(&self.key)
+            .chain(self.previous.iter().map(|(key, _)| key))
+            .map(|key| Base64::encode_string(key))
+            .collect::<Vec<_>>()
+            .join(KEY_RING_SEPARATOR)
+    }
+
+    pub fn encrypt(&self, bytes: &[u8]) -> Result<Vec<u8>, Error> {
+        self.encrypt_with_aad(bytes, &[])
+    }
+
+    /// Encrypts the bytes bound to the associated data, they can only be decrypted with the same associated data
+    pub fn encrypt_with_aad(&self, bytes: &[u8], aad: &[u8]) -> Result<Vec<u8>, Error> {
+        // Key is used by many devices, so nonces cannot be tracked like the ones of device ciphers. Random
+        // nonces may collide after many messages, which only reveals whether the messages are equal with AES-GCM-SIV.
+        let mut nonce = [0; 12];
+        OsRng.fill_bytes(&mut nonce);
+
+        let ciphertext = self.cipher.encrypt(&nonce.into(), Payload { msg: bytes, aad })
+            .map_err(|_| Error::Encrypt)?;
+
+        let mut encrypted = nonce.to_vec();
+        encrypted.extend_from_slice(ciphertext.as_slice());
+
+        Ok(encrypted)
+    }
+
+    pub fn decrypt(&self, bytes: &[u8]) -> Result<Vec<u8>, Error> {
+        self.decrypt_with_aad(bytes, &[])
+    }
+
+    pub fn decrypt_with_aad(&self, bytes: &[u8], aad: &[u8]) -> Result<Vec<u8>, Error> {
+        if bytes.len() < 12 {
+            return Err(Error::Decrypt);
+        }
+
+        let (nonce, bytes) = bytes.split_at(12);
+
+        std::iter::once(&self.cipher)
+            .chain(self.previous.iter().map(|(_, cipher)| cipher))
+            .find_map(|cipher| cipher.decrypt(nonce.into(), Payload { msg: bytes, aad }).ok())
+            .ok_or(Error::Decrypt)
+    }
+
+    pub fn encrypt_str(&self, message: &str) -> Result<String, Error> {
+        self.encrypt(message.as_bytes())
+            .map(|bytes| Base64::encode_string(&bytes))
+    }
+
+    pub fn decrypt_str(&self, encoded: &str) -> Result<String, Error> {
+        let bytes = Base64::decode_vec(encoded)
+            .map_err(|_| Error::Base64Decode)?;
+
+        String::from_utf8(self.decrypt(&bytes)?)
+            .map_err(|_| Error::Decrypt)
+    }
+}
+
+/// Verifies the signature of a record that is published by the server
+pub fn verify_record(signer: &str, message: &str, signature: &str) -> Result<(), Error> {
+    let signer = Base64::decode_vec(signer)
+        .map_err(|_| Error::Base64Decode)?;
+    let signature = Base64::decode_vec(signature)
+        .map_err(|_| Error::Base64Decode)?;
+
+    UnparsedPublicKey::new(&ED25519, signer)
+        .verify(message.as_bytes(), &signature)
+        .map_err(|_| Error::DirectoryMismatch)
+}
+
+/// Hash of an entry in the device key log of a user, chained to the hash of the previous entry
+pub fn key_log_hash(prev_hash: &str, device_id: i32, pubkey: &str, added: bool) -> String {
+    digest(&SHA256, format!("{prev_hash}:{device_id}:{pubkey}:{added}").as_bytes())
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Proves the possession of the previous identity key to the server by keying the HMAC of the new pubkey with the
+/// secret shared with the rotation key of the server
+pub fn rotation_proof(previous: &StaticSecret, rotation_pubkey: &str, pubkey: &str) -> Result<String, Error> {
+    let shared_secret = previous.diffie_hellman(&PublicKey::from(parse_key(rotation_pubkey)?));
+    let key = hmac::Key::new(hmac::HMAC_SHA256, shared_secret.as_bytes());
+
+    Ok(Base64::encode_string(hmac::sign(&key, pubkey.as_bytes()).as_ref()))
+}
+
+/// Safety number of two devices, which is the same on both of them since the pubkeys are ordered before hashing
+pub fn fingerprint(pubkey: &str, other_pubkey: &str) -> Result<String, Error> {
+    let mut keys = [parse_key(pubkey)?, parse_key(other_pubkey)?];
+    keys.sort();
+
+    let hash = digest(&SHA256, &keys.concat());
+
+    Ok(hash.as_ref()[..30]
+        .chunks(5)
+        .map(|chunk| chunk.iter().fold(0u64, |acc, byte| (acc << 8) | *byte as u64) % 100000)
+        .map(|number| format!("{number:05}"))
+        .collect::<Vec<_>>()
+        .join(" "))
+}
+
+/// Random nonce which each device contributes to the verification code
+pub fn verification_nonce() -> String {
+    let mut nonce = [0; 32];
+    OsRng.fill_bytes(&mut nonce);
+
+    Base64::encode_string(&nonce)
+}
+
+/// Commitment of the pending device to its nonce, sent before the nonce of the verifier is known and checked by the
+/// verifier once the nonce is revealed
+pub fn verification_commitment(pubkey: &str, nonce: &str) -> Result<String, Error> {
+    let input = [&parse_key(pubkey)?[..], &parse_key(nonce)?[..]].concat();
+
+    Ok(Base64::encode_string(digest(&SHA256, &input).as_ref()))
+}
+
+/// Short authentication string which the user compares on both devices while adding a device. The verifier pubkey and
+/// nonce come first so that the devices hash the values in the same order. Since the pending device commits to its
+/// nonce before the verifier picks one, neither the server nor a device can search for nonces giving matching codes.
+pub fn verification_code(verifier_pubkey: &str, pubkey: &str, verifier_nonce: &str, nonce: &str) -> Result<String, Error> {
+    let input = [
+        &parse_key(verifier_pubkey)?[..],
+        &parse_key(pubkey)?[..],
+        &parse_key(verifier_nonce)?[..],
+        &parse_key(nonce)?[..],
+    ].concat();
+    let hash = digest(&SHA256, &input);
+
+    let number = hash.as_ref()[..4].iter().fold(0u32, |acc, byte| (acc << 8) | *byte as u32) % 1000000;
+
+    Ok(format!("{:03} {:03}", number / 1000, number % 1000))
+}
+
+/// Encrypts the secrets with a key derived from the passphrase. Argon2id makes each guess of the passphrase costly for
+/// the server, which stores the bundle.
+pub fn seal_with_passphrase(passphrase: &str, secrets: &[u8]) -> Result<Sealed, Error> {
+    let mut salt = [0; 16];
+    OsRng.fill_bytes(&mut salt);
+
+    let mut nonce = [0; 12];
+    OsRng.fill_bytes(&mut nonce);
+
+    let kdf = KdfParams::default();
+    let ciphertext = passphrase_cipher(passphrase, &salt, &kdf)?
+        .encrypt(&nonce.into(), secrets)
+        .map_err(|_| Error::Encrypt)?;
+
+    Ok(Sealed {
+        salt: Base64::encode_string(&salt),
+        kdf,
+        nonce: Base64::encode_string(&nonce),
+        ciphertext: Base64::encode_string(&ciphertext),
+    })
+}
+
+/// Decrypts the secrets sealed with the passphrase, a wrong passphrase fails with [`Error::Decrypt`]
+pub fn open_with_passphrase(passphrase: &str, sealed: &Sealed) -> Result<Vec<u8>, Error> {
+    let salt = Base64::decode_vec(&sealed.salt).map_err(|_| Error::Base64Decode)?;
+    let nonce = Base64::decode_vec(&sealed.nonce).map_err(|_| Error::Base64Decode)?;
+    let ciphertext = Base64::decode_vec(&sealed.ciphertext).map_err(|_| Error::Base64Decode)?;
+
+    if nonce.len() != 12 {
+        return Err(Error::InvalidLength);
+    }
+
+    passphrase_cipher(passphrase, &salt, &sealed.kdf)?
+        .decrypt(nonce.as_slice().into(), ciphertext.as_slice())
+        .map_err(|_| Error::Decrypt)
+}
+
+pub fn decode_privkey(privkey: &str) -> Result<StaticSecret, Error> {
+    parse_key(privkey).map(StaticSecret::from)
+}
+
+pub fn encode_pubkey(privkey: &StaticSecret) -> String {
+    Base64::encode_string(PublicKey::from(privkey).as_bytes())
+}
+
+pub async fn load_identity(conn: &mut PoolConnection<Sqlite>) -> Result<Identity, NoteError> {
+    let privkey = secrets::get(conn, StoreKey::IdentityPrivKey).await?.unwrap();
+    let privkey = parse_key(&privkey).map(StaticSecret::from)?;
+
+    let previous = match secrets::get(conn, StoreKey::PreviousIdentityPrivKey).await? {
+        Some(previous) => Some(parse_key(&previous).map(StaticSecret::from)?),
+        None => None,
+    };
+
+    Ok(Identity { privkey, previous })
+}
+
+fn passphrase_cipher(passphrase: &str, salt: &[u8], kdf: &KdfParams) -> Result<Aes256GcmSiv, Error> {
+    // Parameters come from the server for recovery bundles, a phone cannot spare more memory than this
+    const MAX_MEMORY_COST: u32 = 256 * 1024;
+
+    if kdf.memory_cost > MAX_MEMORY_COST {
+        return Err(Error::InvalidLength);
+    }
+
+    let algorithm = Algorithm::new(&kdf.algorithm).map_err(|_| Error::InvalidLength)?;
+    let params = Params::new(kdf.memory_cost, kdf.time_cost, kdf.parallelism, Some(32)).map_err(|_| Error::InvalidLength)?;
+
+    let mut key = [0; 32];
+    Argon2::new(algorithm, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|_| Error::InvalidLength)?;
+
+    Ok(Aes256GcmSiv::new_from_slice(&key).unwrap())
+}
 
-    parse_key(&store.value)
-        .map(|bytes| StaticSecret::from(bytes))
-        .map_err(|e| e.into())
+fn shared_cipher(privkey: &StaticSecret, pubkey: &PublicKey) -> Aes256GcmSiv {
+    Aes256GcmSiv::new_from_slice(&privkey.diffie_hellman(pubkey).to_bytes()).unwrap()
 }
 
 fn parse_key(key: &str) -> Result<[u8; 32], Error> {
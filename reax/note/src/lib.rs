@@ -7,6 +7,8 @@ pub mod models;
 pub mod storage;
 
 mod crypto;
+#[cfg(feature = "storage")]
+mod merge;
 
 #[derive(Clone, Debug, Serialize)]
 pub enum Error {
@@ -15,16 +17,30 @@ pub enum Error {
     Database(String),
     Crypto(crypto::Error),
     Unreachable(&'static str),
+    /// Local encryption is enabled and the pin is not entered yet
+    Locked,
 }
 
 #[derive(Clone, Debug, Serialize)]
 pub enum StorageError {
     EmailAlreadyExists,
+    File(String),
+    /// Failure reported by the secret store of the host
+    SecretStore(String),
 }
 
 #[cfg(feature = "storage")]
 impl From<sqlx::Error> for Error {
     fn from(e: sqlx::Error) -> Self {
+        // Values which cannot be sealed or opened by the vault are carried as decode errors by sqlx
+        if let sqlx::Error::Decode(source) = &e {
+            match source.downcast_ref::<storage::vault::Error>() {
+                Some(storage::vault::Error::Locked) => return Error::Locked,
+                Some(storage::vault::Error::Crypto(e)) => return Error::Crypto(e.clone()),
+                None => {},
+            }
+        }
+
         Error::Database(e.to_string())
     }
 }
@@ -35,6 +51,12 @@ impl From<accounts::mavinote::Error> for Error {
     }
 }
 
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Storage(StorageError::File(e.to_string()))
+    }
+}
+
 impl From<StorageError> for Error {
     fn from(e: StorageError) -> Self {
         Error::Storage(e)
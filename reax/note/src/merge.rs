@@ -0,0 +1,227 @@
+use std::ops::Range;
+
+use similar::{Algorithm, DiffOp, capture_diff_slices};
+
+/// Result of a three-way merge
+pub struct Merged {
+    pub text: String,
+    /// Number of hunks which both sides changed differently, they are kept between conflict markers
+    pub conflicts: usize,
+}
+
+/// Labels written next to the conflict markers
+pub struct Labels<'a> {
+    pub local: &'a str,
+    pub remote: &'a str,
+}
+
+/// Merges the changes made on both sides since the base. Texts are compared line by line first, lines changed on both
+/// sides are compared word by word then, and only the hunks which still overlap are marked as conflicts.
+pub fn merge(base: &str, local: &str, remote: &str, labels: &Labels) -> Merged {
+    let mut conflicts = 0;
+
+    let text = merge_tokens(&lines(base), &lines(local), &lines(remote), &mut |base, local, remote| {
+        if let Some(merged) = merge_tokens(&words(base), &words(local), &words(remote), &mut |_, _, _| None) {
+            return Some(merged);
+        }
+
+        conflicts += 1;
+
+        Some(conflict(local, remote, labels))
+    });
+
+    // Line merge resolves every conflict itself
+    Merged { text: text.unwrap(), conflicts }
+}
+
+/// Change of one side, given as the replaced range of the base and the range of the side which replaces it
+struct Hunk {
+    base: Range<usize>,
+    side: Range<usize>,
+}
+
+fn hunks(base: &[&str], side: &[&str]) -> Vec<Hunk> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+
+    for op in capture_diff_slices(Algorithm::Myers, base, side) {
+        if let DiffOp::Equal { .. } = op {
+            continue;
+        }
+
+        let (base_range, side_range) = (op.old_range(), op.new_range());
+
+        // Consecutive deletes and inserts replace a single range
+        match hunks.last_mut() {
+            Some(last) if last.base.end == base_range.start && last.side.end == side_range.start => {
+                last.base.end = base_range.end;
+                last.side.end = side_range.end;
+            }
+            _ => hunks.push(Hunk { base: base_range, side: side_range }),
+        }
+    }
+
+    hunks
+}
+
+/// Applies the changes of both sides to the base. Overlapping changes are passed to `resolve`, and the merge fails if it
+/// cannot resolve them.
+fn merge_tokens(base: &[&str], local: &[&str], remote: &[&str], resolve: &mut dyn FnMut(&str, &str, &str) -> Option<String>) -> Option<String> {
+    let sides = [(local, hunks(base, local)), (remote, hunks(base, remote))];
+
+    let mut merged = String::new();
+    let mut next = [0, 0];
+    // Difference between the positions of the base and the sides after the processed hunks
+    let mut offsets = [0isize, 0isize];
+    let mut cursor = 0;
+
+    loop {
+        let start = sides
+            .iter()
+            .zip(next)
+            .filter_map(|((_, hunks), next)| hunks.get(next).map(|hunk| hunk.base.start))
+            .min();
+
+        let Some(start) = start else {
+            break;
+        };
+
+        // Hunks of both sides which touch each other are handled together
+        let mut end = start;
+        let mut taken = [next[0], next[1]];
+        loop {
+            let mut extended = false;
+
+            for (i, (_, hunks)) in sides.iter().enumerate() {
+                while let Some(hunk) = hunks.get(taken[i]).filter(|hunk| hunk.base.start <= end) {
+                    end = end.max(hunk.base.end);
+                    taken[i] += 1;
+                    extended = true;
+                }
+            }
+
+            if !extended {
+                break;
+            }
+        }
+
+        merged.extend(base[cursor..start].iter().copied());
+
+        let mut ranges = [0..0, 0..0];
+        for i in 0..2 {
+            let side_start = (start as isize + offsets[i]) as usize;
+
+            for hunk in &sides[i].1[next[i]..taken[i]] {
+                offsets[i] += hunk.side.len() as isize - hunk.base.len() as isize;
+            }
+
+            ranges[i] = side_start..(end as isize + offsets[i]) as usize;
+        }
+
+        let local_changed = taken[0] > next[0];
+        let remote_changed = taken[1] > next[1];
+        let local_part = local[ranges[0].clone()].concat();
+        let remote_part = remote[ranges[1].clone()].concat();
+
+        if !remote_changed || local_part == remote_part {
+            merged += &local_part;
+        } else if !local_changed {
+            merged += &remote_part;
+        } else {
+            merged += &resolve(&base[start..end].concat(), &local_part, &remote_part)?;
+        }
+
+        next = taken;
+        cursor = end;
+    }
+
+    merged.extend(base[cursor..].iter().copied());
+
+    Some(merged)
+}
+
+fn conflict(local: &str, remote: &str, labels: &Labels) -> String {
+    let mut block = format!("<<<<<<< {}\n{local}", labels.local);
+    if !local.is_empty() && !local.ends_with('\n') {
+        block.push('\n');
+    }
+
+    block += "=======\n";
+    block += remote;
+    if !remote.is_empty() && !remote.ends_with('\n') {
+        block.push('\n');
+    }
+
+    block + ">>>>>>> " + labels.remote + "\n"
+}
+
+fn lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// Splits the text into words and the whitespaces between them, so that joining them gives the text back
+fn words(text: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = 0;
+
+    for (i, c) in text.char_indices().skip(1) {
+        let prev = text[..i].chars().next_back().unwrap();
+
+        if prev.is_whitespace() != c.is_whitespace() {
+            words.push(&text[start..i]);
+            start = i;
+        }
+    }
+
+    if start < text.len() {
+        words.push(&text[start..]);
+    }
+
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge, Labels};
+
+    const LABELS: Labels = Labels { local: "this device", remote: "commit 2" };
+
+    #[test]
+    fn it_applies_changes_of_both_sides_if_hunks_do_not_overlap() {
+        let merged = merge("first\nsecond\nthird\n", "FIRST\nsecond\nthird\n", "first\nsecond\nTHIRD\n", &LABELS);
+
+        assert_eq!("FIRST\nsecond\nTHIRD\n", merged.text);
+        assert_eq!(0, merged.conflicts);
+    }
+
+    #[test]
+    fn it_merges_words_if_both_sides_change_the_same_line() {
+        let merged = merge("the quick fox\n", "the slow fox\n", "the quick dog\n", &LABELS);
+
+        assert_eq!("the slow dog\n", merged.text);
+        assert_eq!(0, merged.conflicts);
+    }
+
+    #[test]
+    fn it_keeps_the_change_once_if_both_sides_make_the_same_change() {
+        let merged = merge("first\nsecond\n", "first\nchanged\n", "first\nchanged\n", &LABELS);
+
+        assert_eq!("first\nchanged\n", merged.text);
+        assert_eq!(0, merged.conflicts);
+    }
+
+    #[test]
+    fn it_marks_overlapping_hunks_as_conflicts() {
+        let merged = merge("first\nsecond\nthird\n", "first\nlocal\nthird\n", "first\nremote\nthird\n", &LABELS);
+
+        assert_eq!("first\n<<<<<<< this device\nlocal\n=======\nremote\n>>>>>>> commit 2\nthird\n", merged.text);
+        assert_eq!(1, merged.conflicts);
+    }
+
+    #[test]
+    fn it_terminates_conflict_markers_if_texts_do_not_end_with_newline() {
+        let merged = merge("base", "local", "remote", &LABELS);
+
+        assert_eq!("<<<<<<< this device\nlocal\n=======\nremote\n>>>>>>> commit 2\n", merged.text);
+        assert_eq!(1, merged.conflicts);
+    }
+}
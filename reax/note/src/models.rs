@@ -2,21 +2,37 @@ use serde::{Deserialize, Serialize};
 use chrono::NaiveDateTime;
 
 #[cfg(feature = "storage")]
-use sqlx::{FromRow, Type};
+use sqlx::{FromRow, Row, Type, sqlite::SqliteRow};
 
 #[derive(Copy, Clone)]
 pub struct LocalId(pub i32);
 #[derive(Copy, Clone)]
 pub struct RemoteId(pub i32);
 
+#[derive(Clone, Debug)]
 #[cfg_attr(feature = "storage", derive(Type))]
 pub enum StoreKey {
     Version,
     IdentityPrivKey,
     IdentityPubKey,
+    PreviousIdentityPrivKey,
+    IdentityRotatedAt,
     Password,
     WelcomeShown,
     NonceId,
+    /// Key of the local encryption, sealed with the pin of the user
+    DataKey,
+    RevisionRetention,
+    TrashRetention,
+    /// Nonce which the pending device committed to while requesting a verification
+    VerificationNonce,
+}
+
+impl StoreKey {
+    /// Whether the value is encrypted when the local encryption is enabled
+    pub fn is_secret(&self) -> bool {
+        matches!(self, StoreKey::IdentityPrivKey | StoreKey::PreviousIdentityPrivKey | StoreKey::Password)
+    }
 }
 
 #[cfg_attr(feature = "storage", derive(FromRow))]
@@ -40,6 +56,15 @@ pub enum AccountKind {
     Local
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[cfg_attr(feature = "storage", derive(Type))]
+pub enum ConflictPolicy {
+    /// Changes of both sides are merged into the note
+    Merge,
+    /// Note takes the remote text, and the local text is kept in a conflicted copy
+    Copy,
+}
+
 #[derive(Debug, Serialize)]
 #[cfg_attr(feature = "storage", derive(FromRow))]
 pub struct Device {
@@ -47,6 +72,52 @@ pub struct Device {
     pub account_id: i32,
     pub pubkey: String,
     pub created_at: NaiveDateTime,
+    pub trust: Trust,
+    /// Safety number of this device and the device, which is compared on both of them to verify the keys
+    #[cfg_attr(feature = "storage", sqlx(default))]
+    pub fingerprint: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[cfg_attr(feature = "storage", derive(Type))]
+pub enum Trust {
+    /// Trusted on first use, fingerprint is not compared yet
+    Unverified,
+    Verified,
+    /// Pubkey is changed after the device is trusted, nothing is encrypted for the device until it is verified again
+    Changed,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TrustEvent {
+    pub account_id: i32,
+    pub device_id: i32,
+    pub trust: Trust,
+    pub fingerprint: String,
+}
+
+#[derive(Debug, Serialize)]
+pub enum Verification {
+    /// Code to compare with the one displayed on the verifying device
+    Code(String),
+    Accepted,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "storage", derive(FromRow))]
+pub struct DirectoryDevice {
+    pub id: i32,
+    pub account_id: i32,
+    pub email: String,
+    pub pubkey: String,
+}
+
+#[cfg_attr(feature = "storage", derive(FromRow))]
+pub struct DirectoryRecord {
+    pub seq: i32,
+    pub head: String,
+    pub signer: String,
+    pub signature: String,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -61,8 +132,11 @@ pub struct Folder {
     pub id: i32,
     pub account_id: i32,
     pub remote_id: Option<i32>,
+    pub parent_id: Option<i32>,
     pub name: String,
     pub state: State,
+    pub shared: bool,
+    pub role: Option<Role>,
 }
 
 impl Folder {
@@ -73,10 +147,13 @@ impl Folder {
     pub fn remote_id(&self) -> Option<RemoteId> {
         self.remote_id.map(|id| RemoteId(id))
     }
+
+    pub fn parent_id(&self) -> Option<LocalId> {
+        self.parent_id.map(LocalId)
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
-#[cfg_attr(feature = "storage", derive(FromRow))]
 pub struct Note {
     pub id: i32,
     pub folder_id: i32,
@@ -87,6 +164,58 @@ pub struct Note {
     pub state: State,
 }
 
+#[cfg(feature = "storage")]
+impl<'r> FromRow<'r, SqliteRow> for Note {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        // Name and text are stored encrypted if the local encryption is enabled
+        Ok(Note {
+            id: row.try_get("id")?,
+            folder_id: row.try_get("folder_id")?,
+            remote_id: row.try_get("remote_id")?,
+            commit: row.try_get("commit")?,
+            name: crate::storage::vault::open(row.try_get("name")?)?,
+            text: crate::storage::vault::open(row.try_get("text")?)?,
+            state: row.try_get("state")?,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct NoteRevision {
+    pub id: i32,
+    pub note_id: i32,
+    pub name: String,
+    pub text: String,
+    pub created_at: String,
+}
+
+#[cfg(feature = "storage")]
+impl<'r> FromRow<'r, SqliteRow> for NoteRevision {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(NoteRevision {
+            id: row.try_get("id")?,
+            note_id: row.try_get("note_id")?,
+            name: crate::storage::vault::open(row.try_get("name")?)?,
+            text: crate::storage::vault::open(row.try_get("text")?)?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+/// How long the replaced texts of a note are kept
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+pub enum RevisionRetention {
+    /// Only the latest revisions are kept
+    Count(i32),
+    Days(i32),
+}
+
+impl Default for RevisionRetention {
+    fn default() -> Self {
+        RevisionRetention::Count(50)
+    }
+}
+
 impl Note {
     pub fn local_id(&self) -> LocalId {
         LocalId(self.id)
@@ -97,10 +226,113 @@ impl Note {
     }
 }
 
+#[derive(Debug, Serialize)]
+pub struct Commit {
+    pub note_id: i32,
+    pub commit: i32,
+    pub device_id: i32,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CommitNote {
+    pub note_id: i32,
+    pub commit: i32,
+    pub name: String,
+    pub text: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Attachment {
+    pub id: i32,
+    pub note_id: i32,
+    pub name: String,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 #[cfg_attr(feature = "storage", derive(Type))]
 pub enum State {
     Clean,
     Modified,
     Deleted,
+    Trashed,
+}
+
+#[derive(Debug, Serialize)]
+pub enum Encryption {
+    Disabled,
+    /// Notes and secrets cannot be read until the pin is entered
+    Locked,
+    Unlocked,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Conflict {
+    pub id: i32,
+    pub note: Note,
+    /// Note holding the text of this device, which lost the conflict
+    pub copy: Note,
+    pub remote_commit: i32,
+    pub created_at: String,
+}
+
+/// Change that an operation in the outbox sends to remote
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[cfg_attr(feature = "storage", derive(Type))]
+pub enum OperationKind {
+    CreateFolder,
+    RenameFolder,
+    MoveFolder,
+    DeleteFolder,
+    CreateNote,
+    UpdateNote,
+    MoveNote,
+    DeleteNote,
+}
+
+impl OperationKind {
+    pub fn targets_note(&self) -> bool {
+        matches!(self, OperationKind::CreateNote | OperationKind::UpdateNote | OperationKind::MoveNote | OperationKind::DeleteNote)
+    }
+}
+
+/// Operation which remote refuses for good, it waits until it is retried or discarded
+#[derive(Debug, Serialize)]
+pub struct FailedOperation {
+    pub id: i32,
+    pub account_id: i32,
+    pub kind: OperationKind,
+    /// Name of the folder or the note that the operation targets
+    pub name: String,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    pub note_id: i32,
+    pub folder_id: i32,
+    pub name: String,
+    /// Part of the text around the matches
+    pub snippet: Vec<SnippetPart>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnippetPart {
+    pub text: String,
+    pub highlighted: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FolderMember {
+    pub user_id: i32,
+    pub email: String,
+    pub role: Role,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[cfg_attr(feature = "storage", derive(Type))]
+pub enum Role {
+    Read,
+    Write,
 }
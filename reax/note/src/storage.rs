@@ -1,29 +1,66 @@
-use std::sync::Arc;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+use std::time::Duration;
 
 use base64ct::{Base64, Encoding};
 use rand::{Rng, thread_rng, distributions::Alphanumeric, rngs::OsRng};
 use once_cell::sync::OnceCell;
 use sqlx::{Pool, Sqlite, types::Json, pool::PoolConnection};
-use tokio::sync::watch::{channel, Sender};
+use tokio::sync::{broadcast, watch::{channel, Sender}};
 use x25519_dalek::{StaticSecret, PublicKey};
 
 use base::{State, observable_map::{ObservableMap, Receiver}, Config};
 
-use crate::{Error, StorageError, models::{StoreKey, Device}, crypto, accounts::mavinote::{Error as MavinoteError, AuthClient, Token}};
-use crate::accounts::mavinote::{MavinoteClient, CreateFolderRequest, CreateNoteRequest};
-use crate::models::{Folder, Note, State as ModelState, LocalId, Account, AccountKind, Mavinote};
+use crate::{Error, StorageError, models::{StoreKey, Device, Trust, TrustEvent}, crypto, accounts::mavinote::{Error as MavinoteError, AuthClient, Token}};
+use crate::accounts::mavinote::{MavinoteClient, CreateFolderRequest, CreateNoteRequest, CreateAttachmentRequest, DeviceAttachmentRequest, responses::{self, NoteUpdate}};
+use crate::models::{Folder, FolderMember, Note, Role, State as ModelState, LocalId, RemoteId, Account, AccountKind, Mavinote, Commit, CommitNote, Attachment, NoteRevision, RevisionRetention, OperationKind};
 
 
+pub mod conflict;
 pub mod db;
+pub mod directory;
+pub mod document;
+pub mod outbox;
+pub mod recovery;
+pub mod rotation;
+pub mod search;
+pub mod secrets;
 pub mod sync;
+pub mod vault;
+
+pub use conflict::{conflicts, resolve_conflict, discard_conflict};
+pub use document::{convert_note, note_converted};
+pub use outbox::{failed_operations, retry_failed_operation, discard_failed_operation};
+pub use recovery::{create_recovery_bundle, recover_account};
+pub use search::search;
+pub use vault::unlock;
 
 pub(crate) const NOT_MAVINOTE_ACCOUNT: Error = Error::Unreachable("NotMavinoteAccount");
 const FOLDER_NOT_FOUND: Error = Error::Unreachable("FolderNotFound");
-const NOTE_NOT_FOUND: Error = Error::Unreachable("NoteNotFound");
+pub(crate) const NOTE_NOT_FOUND: Error = Error::Unreachable("NoteNotFound");
+const COMMIT_NOT_FOUND: Error = Error::Unreachable("CommitNotFound");
+const REVISION_NOT_FOUND: Error = Error::Unreachable("RevisionNotFound");
+const FOLDER_NOT_SYNCED: Error = Error::Unreachable("FolderNotSynced");
+const FOLDER_CYCLE: Error = Error::Unreachable("FolderCycle");
+pub(crate) const NOTE_NOT_SYNCED: Error = Error::Unreachable("NoteNotSynced");
+const NOTE_NOT_TRASHED: Error = Error::Unreachable("NoteNotTrashed");
+const FOLDER_NOT_TRASHED: Error = Error::Unreachable("FolderNotTrashed");
+const ATTACHMENT_NOT_FOUND: Error = Error::Unreachable("AttachmentNotFound");
+const ATTACHMENT_NAME_INVALID: Error = Error::Unreachable("AttachmentNameInvalid");
+pub(crate) const FOLDER_READ_ONLY: Error = Error::Unreachable("FolderReadOnly");
+const FOLDER_NOT_OWNED: Error = Error::Unreachable("FolderNotOwned");
+const FOLDER_SHARED: Error = Error::Unreachable("FolderShared");
+const MEMBER_NOT_FOUND: Error = Error::Unreachable("MemberNotFound");
+pub(crate) const FOLDER_KEY_PENDING: Error = Error::Unreachable("FolderKeyPending");
+
+/// Size of plain attachment chunks, encrypted chunks are slightly larger
+const ATTACHMENT_CHUNK_SIZE: usize = 128 * 1024;
 
 static ACCOUNTS: OnceCell<Sender<State<Vec<Account>, Error>>> = OnceCell::new();
 pub(crate) static FOLDERS: OnceCell<Sender<State<Vec<Folder>, Error>>> = OnceCell::new();
 static NOTES_MAP: OnceCell<Arc<ObservableMap<State<Vec<Note>, Error>>>> = OnceCell::new();
+pub(crate) static TRUST_EVENTS: OnceCell<broadcast::Sender<TrustEvent>> = OnceCell::new();
 
 pub(crate) async fn login(account_id: i32) -> Result<Token, Error> {
     let config = runtime::get::<Arc<Config>>().unwrap();
@@ -31,8 +68,8 @@ pub(crate) async fn login(account_id: i32) -> Result<Token, Error> {
 
     let mavinote = db::fetch_account_data::<Mavinote>(&mut conn, account_id).await?
         .ok_or(NOT_MAVINOTE_ACCOUNT)?;
-    let identity_public_key = db::fetch_value(&mut conn, StoreKey::IdentityPubKey).await?.unwrap().value;
-    let password = db::fetch_value(&mut conn, StoreKey::Password).await?.unwrap().value;
+    let identity_public_key = rotation::account_pubkey(&mut conn, account_id).await?;
+    let password = secrets::get(&mut conn, StoreKey::Password).await?.unwrap();
 
     let token = AuthClient::new(config.api_url.clone())
         .login(&mavinote.email, &identity_public_key, &password)
@@ -46,7 +83,10 @@ pub(crate) async fn login(account_id: i32) -> Result<Token, Error> {
 pub async fn init() -> Result<(), Error> {
     ACCOUNTS.set(channel(State::default()).0).unwrap();
     FOLDERS.set(channel(State::default()).0).unwrap();
+    conflict::CONFLICTS.set(channel(State::default()).0).unwrap();
+    outbox::FAILED_OPERATIONS.set(channel(State::default()).0).unwrap();
     NOTES_MAP.set(Arc::new(ObservableMap::new())).unwrap();
+    TRUST_EVENTS.set(broadcast::channel(16).0).unwrap();
 
     let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await.unwrap();
 
@@ -59,13 +99,19 @@ pub async fn init() -> Result<(), Error> {
         let identity_secret_key = StaticSecret::new(OsRng);
         let identity_public_key = PublicKey::from(&identity_secret_key);
 
-        db::store_value(&mut conn, StoreKey::Version, "1").await?;
-        db::store_value(&mut conn, StoreKey::IdentityPrivKey, Base64::encode_string(&identity_secret_key.to_bytes()).as_str()).await?;
+        // Version is stored last, so that the secrets are generated again if the app is killed in between
+        secrets::put(&mut conn, StoreKey::IdentityPrivKey, Base64::encode_string(&identity_secret_key.to_bytes())).await?;
+        secrets::put(&mut conn, StoreKey::Password, password).await?;
         db::store_value(&mut conn, StoreKey::IdentityPubKey, Base64::encode_string(&identity_public_key.to_bytes()).as_str()).await?;
-        db::store_value(&mut conn, StoreKey::Password, &password).await?;
+        db::store_value(&mut conn, StoreKey::Version, "1").await?;
     }
 
-    Ok(())
+    vault::init(&mut conn).await?;
+
+    let retention = db::fetch_trash_retention(&mut conn).await?;
+    db::purge_trash(&mut conn, retention).await?;
+
+    secrets::migrate(&mut conn).await
 }
 
 pub(crate) async fn mavinote_client(conn: &mut PoolConnection<Sqlite>, account_id: i32) -> Result<Option<MavinoteClient>, Error> {
@@ -78,6 +124,157 @@ pub(crate) async fn mavinote_client(conn: &mut PoolConnection<Sqlite>, account_i
     }
 }
 
+fn note_name(text: &str) -> String {
+    let ending_index = text.char_indices().nth(30).unwrap_or((text.len(), ' ')).0;
+
+    text[..ending_index].replace('\n', "")
+}
+
+/// Encrypts the name and the content key of the folder for each trusted device. Remote requests the folder for the
+/// devices left out, it is sent to them once they are verified again.
+pub(crate) async fn encrypt_device_folders(conn: &mut PoolConnection<Sqlite>, ciphers: &[crypto::DeviceCipher], name: &str, folder_cipher: &crypto::FileCipher) -> Result<Vec<CreateFolderRequest>, Error> {
+    let ciphers = ciphers.iter().filter(|c| c.trusted).collect::<Vec<_>>();
+    let name_nonces = db::unique_nonces(conn, &ciphers.iter().map(|c| c.device_id).collect::<Vec<_>>()).await?;
+    let key_nonces = db::unique_nonces(conn, &ciphers.iter().map(|c| c.device_id).collect::<Vec<_>>()).await?;
+    let key = folder_cipher.key();
+
+    let mut device_folders = vec![];
+    for (cipher, (name_nonce, key_nonce)) in ciphers.iter().zip(name_nonces.into_iter().zip(key_nonces)) {
+        device_folders.push(CreateFolderRequest {
+            device_id: cipher.device_id,
+            name: cipher.encrypt(name, name_nonce)?,
+            key: cipher.encrypt(&key, key_nonce)?,
+        });
+    }
+
+    Ok(device_folders)
+}
+
+pub(crate) fn encrypt_note(folder_cipher: &crypto::FileCipher, name: &str, text: &str) -> Result<CreateNoteRequest, Error> {
+    Ok(CreateNoteRequest {
+        name: folder_cipher.encrypt_str(name)?,
+        text: folder_cipher.encrypt_str(text)?,
+    })
+}
+
+/// Decrypts the name and text of a remote note. Notes are encrypted with the key of their folder, except the ones
+/// which are not written since the folder keys are introduced. Returns none if the note cannot be decrypted yet.
+pub(crate) fn decrypt_remote_note(folder_cipher: Option<&crypto::FileCipher>, ciphers: &[crypto::DeviceCipher], note: &responses::Note) -> Result<Option<(String, String)>, Error> {
+    if let Some(content) = &note.content {
+        let Some(folder_cipher) = folder_cipher else {
+            log::debug!("A note is received before the key of its folder");
+            return Ok(None);
+        };
+
+        return Ok(Some((folder_cipher.decrypt_str(&content.name)?, folder_cipher.decrypt_str(&content.text)?)));
+    }
+
+    let Some(device_note) = &note.device_note else {
+        return Ok(None);
+    };
+
+    let Some(cipher) = ciphers.iter().find(|c| c.device_id == device_note.sender_device_id) else {
+        log::warn!("A note with unknown sender device is received");
+        return Ok(None);
+    };
+
+    Ok(Some((cipher.decrypt(&device_note.name)?, cipher.decrypt(&device_note.text)?)))
+}
+
+/// Loads the cipher of the folder contents, it is none while this device waits for the key of the folder. Folders
+/// which are not created in remote yet are given a new key here, the other ones are given a key by remote sync only if
+/// no device of the folder has one.
+pub(crate) async fn load_folder_cipher(conn: &mut PoolConnection<Sqlite>, folder: &Folder) -> Result<Option<crypto::FileCipher>, Error> {
+    if let Some(key) = db::fetch_folder_key(conn, folder.local_id()).await? {
+        return Ok(Some(crypto::FileCipher::try_from_key(&key)?));
+    }
+
+    if folder.remote_id.is_some() {
+        return Ok(None);
+    }
+
+    let cipher = crypto::FileCipher::generate();
+    db::update_folder_key(conn, folder.local_id(), &cipher.key()).await?;
+
+    Ok(Some(cipher))
+}
+
+/// Replaces the content key of the folder with a new one, previous keys are kept in the key ring of the folder to
+/// decrypt the existing contents. New key is stored right away, the folder stays modified until remote has it so that
+/// local sync sends it if [`send_folder_key`] fails.
+async fn rotate_folder_key(conn: &mut PoolConnection<Sqlite>, folder: &Folder) -> Result<crypto::FileCipher, Error> {
+    let cipher = load_folder_cipher(conn, folder).await?
+        .ok_or(FOLDER_KEY_PENDING)?
+        .rotate();
+
+    db::update_folder_key(conn, folder.local_id(), &cipher.key()).await?;
+    db::update_folder_state(conn, folder.local_id(), ModelState::Modified).await?;
+
+    Ok(cipher)
+}
+
+/// Wraps the rotated key for the current devices of the folder only, so that removed devices cannot decrypt the new
+/// contents
+async fn send_folder_key(conn: &mut PoolConnection<Sqlite>, client: &MavinoteClient, folder: &Folder, remote_id: RemoteId, cipher: &crypto::FileCipher) -> Result<(), Error> {
+    let identity = crypto::load_identity(conn).await?;
+    let ciphers = folder_ciphers(conn, client, folder, &identity).await?;
+
+    let device_folders = encrypt_device_folders(conn, &ciphers, &folder.name, cipher).await?;
+
+    let dev_ref = device_folders.as_slice();
+    client.clone().login_on_unauthorized(&|client| async move { client.rename_folder(remote_id, dev_ref).await }, &login).await?;
+
+    // A folder which is modified before keeps waiting for its queued changes
+    db::update_folder_state(conn, folder.local_id(), folder.state.clone()).await.map_err(|e| e.into())
+}
+
+/// Returns the ciphers of the devices that the folder is encrypted for. Shared folders are also encrypted
+/// for the devices of their members which are only known by remote.
+async fn folder_ciphers(conn: &mut PoolConnection<Sqlite>, client: &MavinoteClient, folder: &Folder, identity: &crypto::Identity) -> Result<Vec<crypto::DeviceCipher>, Error> {
+    let keys = db::fetch_device_keys(conn, folder.account_id).await?;
+
+    let devices = match folder.remote_id() {
+        Some(remote_id) if folder.shared => {
+            let devices = client
+                .clone()
+                .login_on_unauthorized(&|client| async move { client.fetch_folder_devices(remote_id).await }, &login)
+                .await?;
+
+            let members = devices.iter().filter(|device| !keys.iter().any(|key| key.id == device.id)).collect::<Vec<_>>();
+            let listed = directory::listed_devices(conn, client, folder.account_id, &members).await?;
+
+            devices
+                .into_iter()
+                .map(|device| db::DeviceKey {
+                    trust: remote_device_trust(&keys, &listed, device.id, &device.pubkey),
+                    id: device.id,
+                    pubkey: device.pubkey,
+                    previous_pubkey: device.previous_pubkey,
+                })
+                .collect::<Vec<_>>()
+        },
+        _ => keys,
+    };
+
+    devices
+        .iter()
+        .map(|device| crypto::DeviceCipher::try_from_key(device.id, identity, &device.pubkey, device.previous_pubkey.as_deref())
+            .map(|cipher| cipher.with_trust(device.trust)))
+        .collect::<Result<Vec<_>, crypto::Error>>()
+        .map_err(|e| e.into())
+}
+
+/// Trust of a device that is listed by remote. Only the devices of the account are tracked, the ones of the other members
+/// are trusted only if the verified directory lists them with the same key, otherwise they are treated as changed.
+fn remote_device_trust(keys: &[db::DeviceKey], listed: &HashSet<i32>, device_id: i32, pubkey: &str) -> Trust {
+    match keys.iter().find(|key| key.id == device_id) {
+        Some(key) if key.pubkey == pubkey => key.trust,
+        Some(_) => Trust::Changed,
+        None if listed.contains(&device_id) => Trust::Verified,
+        None => Trust::Changed,
+    }
+}
+
 pub(crate) async fn update_send_accounts(conn: &mut PoolConnection<Sqlite>) {
     let sender = ACCOUNTS.get().unwrap();
     // If nobody loaded the accounts, then do not load the accounts
@@ -148,20 +345,57 @@ pub async fn request_verification(email: String) -> Result<String, Error> {
     }
 
     let identity_public_key = db::fetch_value(&mut conn, StoreKey::IdentityPubKey).await?.unwrap().value;
-    let password = db::fetch_value(&mut conn, StoreKey::Password).await?.unwrap().value;
+    let password = secrets::get(&mut conn, StoreKey::Password).await?.unwrap();
+
+    let nonce = crypto::verification_nonce();
+    let commitment = crypto::verification_commitment(&identity_public_key, &nonce)?;
+    db::store_value(&mut conn, StoreKey::VerificationNonce, &nonce).await?;
 
     AuthClient::new(config.api_url.clone())
-        .request_verification(&email, &identity_public_key, &password).await
+        .request_verification(&email, &identity_public_key, &password, &commitment).await
         .map(|token| token.token)
         .map_err(|e| e.into())
 }
 
-pub async fn wait_verification(token: String) -> Result<(), Error> {
+/// Waits until a device of the account adds this device. The verification code is passed to `on_code` when the other
+/// device sends its nonce, the user adds this device there only if the codes on both screens match. The committed nonce
+/// is revealed once, a later verification requires requesting it again.
+pub async fn wait_verification(token: String, on_code: impl Fn(String)) -> Result<(), Error> {
     let config = runtime::get::<Arc<Config>>().unwrap();
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
 
-    AuthClient::wait_verification(&config.ws_url, &token)
-        .await
-        .map_err(|e| e.into())
+    let pubkey = db::fetch_value(&mut conn, StoreKey::IdentityPubKey).await?.unwrap().value;
+    let nonce = db::fetch_value(&mut conn, StoreKey::VerificationNonce).await?
+        .ok_or(Error::Unreachable("verification is not requested"))?
+        .value;
+
+    let client = &AuthClient::new(config.api_url.clone());
+    let revealed = &AtomicBool::new(false);
+    let (pubkey, nonce, token_ref, on_code) = (&pubkey, &nonce, &token, &on_code);
+
+    AuthClient::wait_verification(&config.ws_url, &token, |verifier_pubkey, verifier_nonce| async move {
+        if revealed.load(Ordering::Relaxed) {
+            log::warn!("verification is started again after the nonce is revealed");
+            return;
+        }
+
+        match crypto::verification_code(&verifier_pubkey, pubkey, &verifier_nonce, nonce) {
+            Ok(code) => on_code(code),
+            Err(e) => {
+                log::error!("failed to derive verification code, {e:?}");
+                return;
+            }
+        }
+
+        revealed.store(true, Ordering::Relaxed);
+
+        if let Err(e) = client.reveal_verification(token_ref, nonce).await {
+            log::error!("failed to reveal verification nonce, {e:?}");
+        }
+    })
+        .await?;
+
+    db::delete_value(&mut conn, StoreKey::VerificationNonce).await.map_err(|e| e.into())
 }
 
 pub async fn add_account(email: String) -> Result<(), Error> {
@@ -169,7 +403,7 @@ pub async fn add_account(email: String) -> Result<(), Error> {
     let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await.unwrap();
 
     let identity_public_key = db::fetch_value(&mut conn, StoreKey::IdentityPubKey).await?.unwrap().value;
-    let password = db::fetch_value(&mut conn, StoreKey::Password).await?.unwrap().value;
+    let password = secrets::get(&mut conn, StoreKey::Password).await?.unwrap();
 
     let token = AuthClient::new(config.api_url.clone())
         .login(&email, &identity_public_key, &password)
@@ -233,7 +467,7 @@ pub async fn sign_up(email: String, code: String) -> Result<(), Error> {
     let config = runtime::get::<Arc<Config>>().unwrap();
 
     let identity_public_key = db::fetch_value(&mut conn, StoreKey::IdentityPubKey).await?.unwrap().value;
-    let password = db::fetch_value(&mut conn, StoreKey::Password).await?.unwrap().value;
+    let password = secrets::get(&mut conn, StoreKey::Password).await?.unwrap();
 
     let token = AuthClient::new(config.api_url.clone())
         .sign_up(&email, &code, &identity_public_key, &password)
@@ -316,19 +550,127 @@ pub async fn close_account(account_id: i32, code: String) -> Result<(), Error> {
 pub async fn devices(account_id: i32) -> Result<Vec<Device>, Error> {
     let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
 
-    db::fetch_devices(&mut conn, account_id).await
-        .map_err(|e| e.into())
+    let pubkey = db::fetch_value(&mut conn, StoreKey::IdentityPubKey).await?.unwrap().value;
+    let mut devices = db::fetch_devices(&mut conn, account_id).await?;
+
+    for device in &mut devices {
+        device.fingerprint = crypto::fingerprint(&pubkey, &device.pubkey)?;
+    }
+
+    Ok(devices)
+}
+
+/// Marks the device as verified after the user compares its fingerprint on both devices. Fingerprint is checked again
+/// in case the pubkey changes while it is being compared.
+pub async fn verify_device(account_id: i32, device_id: i32, fingerprint: String) -> Result<(), Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    let pubkey = db::fetch_value(&mut conn, StoreKey::IdentityPubKey).await?.unwrap().value;
+    let device = db::fetch_device(&mut conn, account_id, device_id).await?
+        .ok_or(Error::Unreachable("DeviceNotFound"))?;
+
+    if crypto::fingerprint(&pubkey, &device.pubkey)? != fingerprint {
+        return Err(crypto::Error::FingerprintMismatch.into());
+    }
+
+    db::update_device_trust(&mut conn, account_id, device_id, Trust::Verified).await?;
+
+    // Folders are encrypted for the device again if it is left out while its key was not trusted
+    if device.trust == Trust::Changed {
+        db::update_account_folders_modified(&mut conn, account_id).await?;
+    }
+
+    send_trust_event(TrustEvent { account_id, device_id, trust: Trust::Verified, fingerprint });
+
+    Ok(())
+}
+
+pub fn trust_events() -> broadcast::Receiver<TrustEvent> {
+    TRUST_EVENTS.get().unwrap().subscribe()
+}
+
+pub(crate) fn send_trust_event(event: TrustEvent) {
+    // Sending fails only if nobody listens the events
+    let _ = TRUST_EVENTS.get().unwrap().send(event);
 }
 
 pub async fn delete_device(account_id: i32, device_id: i32) -> Result<(), Error> {
     let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
 
-    mavinote_client(&mut conn, account_id).await?
-        .ok_or(NOT_MAVINOTE_ACCOUNT)?
+    let client = mavinote_client(&mut conn, account_id).await?
+        .ok_or(NOT_MAVINOTE_ACCOUNT)?;
+
+    client
+        .clone()
         .login_on_unauthorized(&|client| async move { client.delete_device(Some(device_id)).await }, &login)
         .await?;
 
-    db::delete_devices(&mut conn, account_id, &[device_id]).await.map_err(|e| e.into())
+    db::delete_devices(&mut conn, account_id, &[device_id]).await?;
+
+    // Deleted device keeps the keys it has received, so the folders it could decrypt get new ones
+    for folder in db::fetch_account_folders(&mut conn, account_id).await? {
+        let Some(remote_id) = folder.remote_id().filter(|_| folder.role != Some(Role::Read)) else {
+            continue;
+        };
+
+        let rotated = match rotate_folder_key(&mut conn, &folder).await {
+            Ok(cipher) => send_folder_key(&mut conn, &client, &folder, remote_id, &cipher).await,
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = rotated {
+            log::warn!("failed to rotate the key of folder {}, {e:?}", folder.id);
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the verification code which should match the one displayed on the new device before calling [`add_device`]. The
+/// commitment of the new device is fetched before sending the nonce of this device, the code is derived once the new
+/// device reveals the committed nonce.
+pub async fn start_device_verification(account_id: i32, pubkey: String) -> Result<String, Error> {
+    const POLL_INTERVAL: Duration = Duration::from_secs(2);
+    const POLL_ATTEMPTS: usize = 60;
+
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    let mavinote = mavinote_client(&mut conn, account_id).await?.ok_or(NOT_MAVINOTE_ACCOUNT)?;
+
+    let pub_ref = &pubkey;
+    let verification = mavinote.clone()
+        .login_on_unauthorized(&|client| { async move { client.start_device_verification(pub_ref).await } }, &login).await?;
+
+    let own_nonce = crypto::verification_nonce();
+    let nonce_ref = &own_nonce;
+    mavinote.clone()
+        .login_on_unauthorized(&|client| { async move { client.send_verification_nonce(pub_ref, nonce_ref).await } }, &login).await?;
+
+    let mut revealed = None;
+    for _ in 0..POLL_ATTEMPTS {
+        tokio::time::sleep(POLL_INTERVAL).await;
+
+        revealed = mavinote.clone()
+            .login_on_unauthorized(&|client| { async move { client.fetch_verification_nonce(pub_ref).await } }, &login).await?
+            .nonce;
+
+        if revealed.is_some() {
+            break;
+        }
+    }
+
+    let Some(nonce) = revealed else {
+        return Err(MavinoteError::Message("verification_timeout".to_string()).into());
+    };
+
+    if crypto::verification_commitment(&pubkey, &nonce)? != verification.commitment {
+        return Err(crypto::Error::CommitmentMismatch.into());
+    }
+
+    let own_pubkey = rotation::account_pubkey(&mut conn, account_id).await?;
+
+    crypto::verification_code(&own_pubkey, &pubkey, &own_nonce, &nonce)
+        .map_err(|e| e.into())
 }
 
 pub async fn add_device(account_id: i32, pubkey: String) -> Result<(), Error> {
@@ -339,7 +681,8 @@ pub async fn add_device(account_id: i32, pubkey: String) -> Result<(), Error> {
         .ok_or(NOT_MAVINOTE_ACCOUNT)?
         .login_on_unauthorized(&|client| { async move { client.add_device(pub_ref).await } }, &login).await?;
 
-    db::create_devices(&mut conn, account_id, &[device]).await.map_err(|e| e.into())
+    // Pubkey is typed by the user and the verification codes are compared on both devices
+    db::create_devices(&mut conn, account_id, &[device], Trust::Verified).await.map_err(|e| e.into())
 }
 
 pub async fn folders() -> tokio::sync::watch::Receiver<State<Vec<Folder>, Error>> {
@@ -365,22 +708,43 @@ pub async fn folder(folder_id: i32) -> Result<Option<Folder>, Error> {
     db::fetch_folder(&mut conn, LocalId(folder_id)).await.map_err(|e| e.into())
 }
 
-pub async fn create_folder(account_id: i32, name: String) -> Result<(), Error> {
+pub async fn create_folder(account_id: i32, parent_id: Option<i32>, name: String) -> Result<(), Error> {
     let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
 
-    let remote_id = if let Some(client) = mavinote_client(&mut conn, account_id).await? {
-        let devices = db::fetch_devices(&mut conn, account_id).await?;
-        let nonces = db::unique_nonces(&mut conn, &devices.iter().map(|d| d.id).collect::<Vec<_>>()).await?;
-        let privkey = crypto::load_privkey(&mut conn).await?;
+    let parent = match parent_id {
+        Some(parent_id) => Some(
+            db::fetch_folder(&mut conn, LocalId(parent_id)).await?
+                .filter(|parent| parent.account_id == account_id && parent.state != ModelState::Deleted)
+                .ok_or(FOLDER_NOT_FOUND)?
+        ),
+        None => None,
+    };
+
+    if parent.as_ref().is_some_and(|parent| parent.role.is_some()) {
+        return Err(FOLDER_NOT_OWNED);
+    }
 
-        let mut device_folders = vec![];
-        for (device, nonce) in devices.into_iter().zip(nonces) {
-            let cipher = crypto::DeviceCipher::try_from_key(device.id, &privkey, &device.pubkey)?;
-            device_folders.push(CreateFolderRequest { device_id: device.id, name: cipher.encrypt(&name, nonce)? });
-        }
+    let client = match (outbox::client(&mut conn, account_id).await?, &parent) {
+        // Parent is not created in remote yet, folder is created after its parent by the outbox
+        (Some(_), Some(parent)) if parent.remote_id.is_none() => None,
+        (client, _) => client,
+    };
+
+    let folder_cipher = crypto::FileCipher::generate();
+
+    let remote_id = if let Some(client) = client {
+        let identity = crypto::load_identity(&mut conn).await?;
+        let ciphers = db::fetch_device_keys(&mut conn, account_id).await?
+            .into_iter()
+            .map(|device| crypto::DeviceCipher::try_from_key(device.id, &identity, &device.pubkey, device.previous_pubkey.as_deref())
+                .map(|cipher| cipher.with_trust(device.trust)))
+            .collect::<Result<Vec<_>, crypto::Error>>()?;
+
+        let device_folders = encrypt_device_folders(&mut conn, &ciphers, &name, &folder_cipher).await?;
 
         let dev_ref = device_folders.as_slice();
-        match client.login_on_unauthorized(&|client| async move { client.create_folder(dev_ref).await }, &login).await {
+        let parent_remote_id = parent.as_ref().and_then(|parent| parent.remote_id());
+        match client.login_on_unauthorized(&|client| async move { client.create_folder(parent_remote_id, dev_ref).await }, &login).await {
             Ok(folder) => Some(folder.id()),
             Err(MavinoteError::Message(msg)) if msg == "devices_mismatch" => {
                 sync::sync_devices(&mut conn, account_id).await?;
@@ -395,7 +759,12 @@ pub async fn create_folder(account_id: i32, name: String) -> Result<(), Error> {
         None
     };
 
-    let folder = db::create_folder(&mut conn, remote_id, account_id, name).await?;
+    let folder = db::create_folder(&mut conn, remote_id, account_id, parent.map(|parent| parent.local_id()), name).await?;
+    db::update_folder_key(&mut conn, folder.local_id(), &folder_cipher.key()).await?;
+
+    if remote_id.is_none() {
+        outbox::enqueue(&mut conn, account_id, OperationKind::CreateFolder, folder.local_id()).await?;
+    }
 
     FOLDERS.get().unwrap().send_modify(move |state| {
         if let State::Ok(folders) = state {
@@ -406,183 +775,493 @@ pub async fn create_folder(account_id: i32, name: String) -> Result<(), Error> {
     Ok(())
 }
 
-pub async fn delete_folder(folder_id: i32) -> Result<(), Error> {
+pub async fn rename_folder(folder_id: i32, name: String) -> Result<(), Error> {
     let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
 
     let folder = db::fetch_folder(&mut conn, LocalId(folder_id)).await?
         .ok_or(FOLDER_NOT_FOUND)?;
 
-    let mut delete = true;
+    if folder.role == Some(Role::Read) {
+        return Err(FOLDER_READ_ONLY);
+    }
 
-    if let Some(remote_id) = folder.remote_id() {
-        if let Some(mavinote) = mavinote_client(&mut conn, folder.account_id).await? {
-            if let Err(e) = mavinote.login_on_unauthorized(&|client| async move { client.delete_folder(remote_id).await }, &login).await {
-                log::debug!("failed to delete folder in remote, {e:?}");
+    let folder_cipher = load_folder_cipher(&mut conn, &folder).await?;
 
-                delete = false;
-            }
-        }
-    }
+    let sent = match (folder.remote_id(), outbox::client(&mut conn, folder.account_id).await?, folder_cipher) {
+        (Some(remote_id), Some(client), Some(folder_cipher)) => {
+            let identity = crypto::load_identity(&mut conn).await?;
+            let ciphers = folder_ciphers(&mut conn, &client, &folder, &identity).await?;
 
-    if delete {
-        db::delete_folder(&mut conn, folder.local_id()).await?;
-    } else {
-        db::delete_folder_local(&mut conn, folder.local_id()).await?;
-    }
+            let device_folders = encrypt_device_folders(&mut conn, &ciphers, &name, &folder_cipher).await?;
 
-    FOLDERS.get().unwrap().send_if_modified(|state| {
-        if let State::Ok(vec) = state {
-            let prev_len = vec.len();
+            let dev_ref = device_folders.as_slice();
+            match client.login_on_unauthorized(&|client| async move { client.rename_folder(remote_id, dev_ref).await }, &login).await {
+                Ok(_) => true,
+                Err(MavinoteError::Message(msg)) if msg == "devices_mismatch" => {
+                    sync::sync_devices(&mut conn, folder.account_id).await?;
+                    false
+                },
+                Err(e) => {
+                    log::debug!("failed to rename folder in remote, {e:?}");
+                    false
+                }
+            }
+        },
+        // Folder is renamed by the outbox once its key is received
+        (Some(_), _, _) => false,
+        // Folder is not created in remote yet, it will be created with its new name
+        (None, _, _) => true,
+    };
 
-            vec.retain(|f| f.local_id().0 != folder_id);
+    // A modified folder may still have a pending move, it stays modified until the outbox sends it
+    let state = match sent {
+        false if outbox::enqueue(&mut conn, folder.account_id, OperationKind::RenameFolder, folder.local_id()).await? => ModelState::Modified,
+        _ => folder.state.clone(),
+    };
 
-            return prev_len != vec.len();
-        }
+    db::update_folder(&mut conn, folder.local_id(), &name, state.clone()).await?;
 
-        false
+    FOLDERS.get().unwrap().send_modify(move |folders| {
+        if let State::Ok(folders) = folders {
+            if let Some(folder) = folders.iter_mut().find(|f| f.id == folder_id) {
+                folder.name = name;
+                folder.state = state;
+            }
+        }
     });
 
     Ok(())
 }
 
-pub async fn notes(folder_id: i32) -> Receiver<State<Vec<Note>, Error>> {
-    let notes_map = NOTES_MAP.get().unwrap();
-
-    if !notes_map.contains_key(folder_id) {
-        notes_map.insert(folder_id, State::Loading);
-
-        let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await.unwrap();
-        notes_map.update(folder_id, db::fetch_notes(&mut conn, LocalId(folder_id)).await.map_err(|e| e.into()).into());
-    }
-
-    notes_map.subscribe(folder_id).unwrap()
-}
-
-pub async fn note(note_id: i32) -> Result<Option<Note>, Error> {
-    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await.unwrap();
-    db::fetch_note(&mut conn, LocalId(note_id)).await.map_err(|e| e.into())
-}
-
-pub async fn create_note(folder_id: i32, text: String) -> Result<i32, Error> {
-    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await.unwrap();
+pub async fn move_folder(folder_id: i32, parent_id: Option<i32>) -> Result<(), Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
 
-    let folder = db::fetch_folder(&mut conn, LocalId(folder_id))
-        .await?
+    let folder = db::fetch_folder(&mut conn, LocalId(folder_id)).await?
         .ok_or(FOLDER_NOT_FOUND)?;
 
-    let text = text.as_str().trim();
-    let ending_index = text.char_indices().nth(30).unwrap_or((text.len(), ' ')).0;
-    let name = text[..ending_index].replace('\n', "");
+    // Hierarchy of the shared folders belongs to their owner
+    if folder.role.is_some() {
+        return Err(FOLDER_NOT_OWNED);
+    }
 
-    let remote_note = if let Some(mavinote) = mavinote_client(&mut conn, folder.account_id).await? {
-        if let Some(remote_id) = folder.remote_id() {
-            let devices = db::fetch_devices(&mut conn, folder.account_id).await?;
-            let name_nonces = db::unique_nonces(&mut conn, &devices.iter().map(|d| d.id).collect::<Vec<_>>()).await?;
-            let text_nonces = db::unique_nonces(&mut conn, &devices.iter().map(|d| d.id).collect::<Vec<_>>()).await?;
+    let parent = match parent_id {
+        Some(parent_id) => {
+            let parent = db::fetch_folder(&mut conn, LocalId(parent_id)).await?
+                .filter(|parent| parent.account_id == folder.account_id && parent.state != ModelState::Deleted)
+                .ok_or(FOLDER_NOT_FOUND)?;
 
-            let privkey = crypto::load_privkey(&mut conn).await?;
+            if parent.role.is_some() {
+                return Err(FOLDER_NOT_OWNED);
+            }
 
-            let mut device_notes = vec![];
-            for (device, (name_nonce, text_nonce)) in devices.into_iter().zip(name_nonces.into_iter().zip(text_nonces)) {
-                let cipher = crypto::DeviceCipher::try_from_key(device.id, &privkey, &device.pubkey)?;
-                device_notes.push(CreateNoteRequest{ device_id: device.id, name: cipher.encrypt(&name, name_nonce)?, text: cipher.encrypt(&text, text_nonce)? });
+            // A folder cannot be moved under itself or any of its subfolders
+            if db::fetch_folder_subtree(&mut conn, folder.local_id()).await?.iter().any(|f| f.id == parent.id) {
+                return Err(FOLDER_CYCLE);
             }
 
-            let dev_ref = device_notes.as_ref();
-            match mavinote.login_on_unauthorized(&|client| async move { client.create_note(remote_id, dev_ref).await }, &login).await {
-                Ok(note) => Some(note),
-                Err(MavinoteError::Message(msg)) if msg == "devices_mismatch" => {
-                    sync::sync_devices(&mut conn, folder.account_id).await?;
-                    None
-                },
+            Some(parent)
+        },
+        None => None,
+    };
+
+    let sent = match (folder.remote_id(), outbox::client(&mut conn, folder.account_id).await?) {
+        // New parent is not created in remote yet, folder is moved by the outbox after its parent is created
+        (Some(_), Some(_)) if parent.as_ref().is_some_and(|parent| parent.remote_id.is_none()) => false,
+        (Some(remote_id), Some(client)) => {
+            let parent_remote_id = parent.as_ref().and_then(|parent| parent.remote_id());
+            match client.login_on_unauthorized(&|client| async move { client.move_folder(remote_id, parent_remote_id).await }, &login).await {
+                Ok(_) => true,
                 Err(e) => {
-                    log::debug!("failed to create note in remote, {e:?}");
-                    None
-                },
+                    log::debug!("failed to move folder in remote, {e:?}");
+                    false
+                }
             }
-        } else {
-            None
-        }
-    } else {
-        None
+        },
+        (Some(_), None) => false,
+        // Folder is not created in remote yet, it will be created under its new parent
+        (None, _) => true,
     };
 
-    let local_note = db::create_note(
-        &mut conn,
-        folder.local_id(),
-        remote_note.as_ref().map(|n| n.id()),
-        name,
-        text.to_string(),
-        remote_note.map(|n| n.commit).unwrap_or(0)
-    ).await?;
+    let state = match sent {
+        false if outbox::enqueue(&mut conn, folder.account_id, OperationKind::MoveFolder, folder.local_id()).await? => ModelState::Modified,
+        _ => folder.state.clone(),
+    };
 
-    let note_id = local_note.id;
+    db::update_folder_parent(&mut conn, folder.local_id(), parent.as_ref().map(|parent| parent.local_id()), state.clone()).await?;
 
-    NOTES_MAP.get().unwrap().update_modify(folder_id, move |state| {
-        if let State::Ok(notes) = state {
-            notes.push(local_note);
+    FOLDERS.get().unwrap().send_modify(move |folders| {
+        if let State::Ok(folders) = folders {
+            if let Some(folder) = folders.iter_mut().find(|f| f.id == folder_id) {
+                folder.parent_id = parent_id;
+                folder.state = state;
+            }
         }
     });
 
-    Ok(note_id)
+    Ok(())
 }
 
-pub async fn update_note(note_id: i32, text: String) -> Result<(), Error> {
+pub async fn delete_folder(folder_id: i32) -> Result<(), Error> {
     let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
 
-    let note = db::fetch_note(&mut conn, LocalId(note_id)).await?
-        .ok_or(NOTE_NOT_FOUND)?;
-
-    let text = text.as_str().trim();
-    let ending_index = text.char_indices().nth(30).unwrap_or((text.len(), ' ')).0;
-    let name = text[..ending_index].replace('\n', "");
+    let folder = db::fetch_folder(&mut conn, LocalId(folder_id)).await?
+        .ok_or(FOLDER_NOT_FOUND)?;
 
-    let (commit, state) = if let Some(remote_id) = note.remote_id() {
-        let folder = db::fetch_folder(&mut conn, LocalId(note.folder_id)).await?.unwrap();
-        let Some(mavinote) = mavinote_client(&mut conn, folder.account_id).await? else {
-            return Err(Error::Unreachable("Mavinote account must have a client"));
-        };
+    // Members cannot delete a shared folder, they leave it instead
+    if folder.role.is_some() {
+        drop(conn);
 
-        let devices = db::fetch_devices(&mut conn, folder.account_id).await?;
-        let name_nonces = db::unique_nonces(&mut conn, &devices.iter().map(|d| d.id).collect::<Vec<_>>()).await?;
-        let text_nonces = db::unique_nonces(&mut conn, &devices.iter().map(|d| d.id).collect::<Vec<_>>()).await?;
+        return leave_folder(folder).await;
+    }
 
-        let privkey = crypto::load_privkey(&mut conn).await?;
+    let subtree_ids = db::fetch_folder_subtree(&mut conn, folder.local_id()).await?
+        .into_iter()
+        .map(|folder| folder.id)
+        .collect::<Vec<_>>();
 
-        let mut device_notes = vec![];
-        for (device, (name_nonce, text_nonce)) in devices.into_iter().zip(name_nonces.into_iter().zip(text_nonces)) {
-            let cipher = crypto::DeviceCipher::try_from_key(device.id, &privkey, &device.pubkey)?;
-            device_notes.push(CreateNoteRequest{ device_id: device.id, name: cipher.encrypt(&name, name_nonce)?, text: cipher.encrypt(&text, text_nonce)? });
-        }
+    // Queued operations of the subtree are dropped alongside, so that they do not hold back the deletion. Folders which
+    // are not created in remote yet are created by local sync once they are restored.
+    db::trash_folder(&mut conn, folder.local_id()).await?;
 
-        let dev_ref = device_notes.as_slice();
-        let commit = note.commit;
-        match mavinote.login_on_unauthorized(&|client| async move { client.update_note(remote_id, commit, dev_ref).await }, &login).await {
-            Ok(commit) => (commit.commit, ModelState::Clean),
-            Err(MavinoteError::Message(msg)) if msg == "devices_mismatch" => {
-                sync::sync_devices(&mut conn, folder.account_id).await?;
-                (note.commit, ModelState::Modified)
+    if let Some(remote_id) = folder.remote_id() {
+        let sent = match outbox::client(&mut conn, folder.account_id).await? {
+            Some(mavinote) => match mavinote.login_on_unauthorized(&|client| async move { client.delete_folder(remote_id).await }, &login).await {
+                Ok(_) => true,
+                Err(e) => {
+                    log::debug!("failed to delete folder in remote, {e:?}");
+                    false
+                }
             },
-            Err(e) => {
-                log::debug!("failed to update note with id {note_id}, {e:?}");
-                (note.commit, ModelState::Modified)
-            }
+            None => false,
+        };
+
+        // Deleted state marks the folders which are not moved into the trash of remote yet
+        if !sent && outbox::enqueue(&mut conn, folder.account_id, OperationKind::DeleteFolder, folder.local_id()).await? {
+            db::update_folder_state(&mut conn, folder.local_id(), ModelState::Deleted).await?;
         }
-    } else {
-        (note.commit, ModelState::Clean)
-    };
+    }
 
-    db::update_note(&mut conn, note.local_id(), &name, text, commit, state).await?;
+    FOLDERS.get().unwrap().send_if_modified(|state| {
+        if let State::Ok(vec) = state {
+            let prev_len = vec.len();
 
-    if let Some(updated_note) = db::fetch_note(&mut conn, note.local_id()).await? {
-        NOTES_MAP.get().unwrap().update_modify(note.folder_id, move |state| {
-            if let State::Ok(notes) = state {
-                if let Some(note) = notes.iter_mut().find(|n| n.id == note_id) {
-                    *note = updated_note;
-                }
-            }
-        });
-    }
+            vec.retain(|f| !subtree_ids.contains(&f.id));
+
+            return prev_len != vec.len();
+        }
+
+        false
+    });
+
+    Ok(())
+}
+
+async fn folder_client(conn: &mut PoolConnection<Sqlite>, folder_id: i32) -> Result<(Folder, RemoteId, MavinoteClient), Error> {
+    let folder = db::fetch_folder(conn, LocalId(folder_id)).await?
+        .ok_or(FOLDER_NOT_FOUND)?;
+
+    let client = mavinote_client(conn, folder.account_id).await?
+        .ok_or(NOT_MAVINOTE_ACCOUNT)?;
+
+    // Members are managed in remote
+    let remote_id = folder.remote_id().ok_or(FOLDER_NOT_SYNCED)?;
+
+    Ok((folder, remote_id, client))
+}
+
+/// Pulls the sharing state of the folder from remote after its members are changed
+async fn refresh_folder_sharing(conn: &mut PoolConnection<Sqlite>, client: MavinoteClient, folder: &Folder, remote_id: RemoteId) -> Result<(), Error> {
+    let Some(remote_folder) = client.login_on_unauthorized(&|client| async move { client.fetch_folder(remote_id).await }, &login).await? else {
+        return Ok(());
+    };
+
+    db::update_folder_sharing(conn, folder.local_id(), remote_folder.shared, remote_folder.role).await?;
+
+    update_send_folders(conn).await;
+
+    Ok(())
+}
+
+pub async fn folder_members(folder_id: i32) -> Result<Vec<FolderMember>, Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    let (_, remote_id, client) = folder_client(&mut conn, folder_id).await?;
+
+    let members = client
+        .login_on_unauthorized(&|client| async move { client.fetch_folder_members(remote_id).await }, &login)
+        .await?;
+
+    Ok(members
+        .into_iter()
+        .map(|member| FolderMember { user_id: member.user_id, email: member.email, role: member.role })
+        .collect())
+}
+
+/// Shares the folder with the account of given email. Devices of the new member request the folder
+/// and its notes from the devices that can decrypt them, so nothing is encrypted here.
+pub async fn share_folder(folder_id: i32, email: String, role: Role) -> Result<(), Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    let (folder, remote_id, client) = folder_client(&mut conn, folder_id).await?;
+
+    if folder.role.is_some() {
+        return Err(FOLDER_NOT_OWNED);
+    }
+
+    let email_ref = email.as_str();
+    client
+        .clone()
+        .login_on_unauthorized(&|client| async move { client.add_folder_member(remote_id, email_ref, role).await }, &login)
+        .await?;
+
+    refresh_folder_sharing(&mut conn, client, &folder, remote_id).await
+}
+
+pub async fn remove_folder_member(folder_id: i32, user_id: i32) -> Result<(), Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    let (folder, remote_id, client) = folder_client(&mut conn, folder_id).await?;
+
+    if folder.role.is_some() {
+        return Err(FOLDER_NOT_OWNED);
+    }
+
+    // Removed member keeps the key it has received, new contents are encrypted with a key it does not know. Key is
+    // rotated before the member is removed, so that it is sent by local sync if it cannot be sent here.
+    let cipher = rotate_folder_key(&mut conn, &folder).await?;
+
+    client
+        .clone()
+        .login_on_unauthorized(&|client| async move { client.remove_folder_member(remote_id, user_id).await }, &login)
+        .await?;
+
+    if let Err(e) = send_folder_key(&mut conn, &client, &folder, remote_id, &cipher).await {
+        log::warn!("failed to send the rotated key of folder {}, {e:?}", folder.id);
+    }
+
+    refresh_folder_sharing(&mut conn, client, &folder, remote_id).await
+}
+
+async fn leave_folder(folder: Folder) -> Result<(), Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    let (_, remote_id, client) = folder_client(&mut conn, folder.id).await?;
+
+    let email = db::fetch_account_data::<Mavinote>(&mut conn, folder.account_id).await?
+        .ok_or(NOT_MAVINOTE_ACCOUNT)?
+        .email;
+
+    let member = client
+        .clone()
+        .login_on_unauthorized(&|client| async move { client.fetch_folder_members(remote_id).await }, &login)
+        .await?
+        .into_iter()
+        .find(|member| member.email == email)
+        .ok_or(MEMBER_NOT_FOUND)?;
+
+    client
+        .login_on_unauthorized(&|client| async move { client.remove_folder_member(remote_id, member.user_id).await }, &login)
+        .await?;
+
+    db::delete_folder(&mut conn, folder.local_id()).await?;
+
+    FOLDERS.get().unwrap().send_if_modified(|state| {
+        if let State::Ok(vec) = state {
+            let prev_len = vec.len();
+
+            vec.retain(|f| f.id != folder.id);
+
+            return prev_len != vec.len();
+        }
+
+        false
+    });
+
+    Ok(())
+}
+
+pub async fn notes(folder_id: i32) -> Receiver<State<Vec<Note>, Error>> {
+    let notes_map = NOTES_MAP.get().unwrap();
+
+    if !notes_map.contains_key(folder_id) {
+        notes_map.insert(folder_id, State::Loading);
+
+        let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await.unwrap();
+        notes_map.update(folder_id, db::fetch_notes(&mut conn, LocalId(folder_id)).await.map_err(|e| e.into()).into());
+    }
+
+    notes_map.subscribe(folder_id).unwrap()
+}
+
+pub async fn note(note_id: i32) -> Result<Option<Note>, Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await.unwrap();
+    db::fetch_note(&mut conn, LocalId(note_id)).await.map_err(|e| e.into())
+}
+
+pub async fn create_note(folder_id: i32, text: String) -> Result<i32, Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await.unwrap();
+
+    let folder = db::fetch_folder(&mut conn, LocalId(folder_id))
+        .await?
+        .ok_or(FOLDER_NOT_FOUND)?;
+
+    if folder.role == Some(Role::Read) {
+        return Err(FOLDER_READ_ONLY);
+    }
+
+    let text = text.as_str().trim();
+    let name = note_name(text);
+
+    let remote_note = if let Some(mavinote) = outbox::client(&mut conn, folder.account_id).await? {
+        // Note is created by the outbox once the key of its folder is received
+        if let (Some(remote_id), Some(folder_cipher)) = (folder.remote_id(), load_folder_cipher(&mut conn, &folder).await?) {
+            let request = encrypt_note(&folder_cipher, &name, text)?;
+
+            let req_ref = &request;
+            match mavinote.login_on_unauthorized(&|client| async move { client.create_note(remote_id, req_ref).await }, &login).await {
+                Ok(note) => Some(note),
+                Err(e) => {
+                    log::debug!("failed to create note in remote, {e:?}");
+                    None
+                },
+            }
+        } else {
+            None
+        }
+    } else {
+        None
+    };
+
+    let local_note = db::create_note(
+        &mut conn,
+        folder.local_id(),
+        remote_note.as_ref().map(|n| n.id()),
+        name,
+        text.to_string(),
+        remote_note.map(|n| n.commit).unwrap_or(0)
+    ).await?;
+
+    let note_id = local_note.id;
+
+    // Note is created after its folder if the folder is not created in remote yet
+    if local_note.remote_id.is_none() {
+        outbox::enqueue(&mut conn, folder.account_id, OperationKind::CreateNote, local_note.local_id()).await?;
+    }
+
+    NOTES_MAP.get().unwrap().update_modify(folder_id, move |state| {
+        if let State::Ok(notes) = state {
+            notes.push(local_note);
+        }
+    });
+
+    Ok(note_id)
+}
+
+pub async fn update_note(note_id: i32, text: String) -> Result<(), Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    let note = db::fetch_note(&mut conn, LocalId(note_id)).await?
+        .ok_or(NOTE_NOT_FOUND)?;
+
+    let text = text.as_str().trim().to_string();
+    let name = note_name(&text);
+
+    let folder = db::fetch_folder(&mut conn, LocalId(note.folder_id)).await?.unwrap();
+
+    if folder.role == Some(Role::Read) {
+        return Err(FOLDER_READ_ONLY);
+    }
+
+    let (name, text, base, commit, state) = if let Some(remote_id) = note.remote_id() {
+        let client = match outbox::client(&mut conn, folder.account_id).await? {
+            Some(mavinote) => load_folder_cipher(&mut conn, &folder).await?.map(|folder_cipher| (mavinote, folder_cipher)),
+            None => None,
+        };
+
+        let Some((mavinote, folder_cipher)) = client else {
+            // Earlier changes of the account wait in the outbox, or the key of the folder is not received yet. This one
+            // is sent by the outbox after them
+            if let Some(document) = db::fetch_note_document(&mut conn, note.local_id()).await? {
+                document::edit(&mut conn, document, &text).await?;
+            }
+
+            return finish_update_note(&mut conn, &note, &name, &text, None, note.commit, ModelState::Modified).await;
+        };
+
+        let identity = crypto::load_identity(&mut conn).await?;
+        let ciphers = folder_ciphers(&mut conn, &mavinote, &folder, &identity).await?;
+
+        // Texts of the converted notes are sent as the changes of their documents
+        if let Some(document) = db::fetch_note_document(&mut conn, note.local_id()).await? {
+            let state = document::update(&mut conn, &mavinote, folder.account_id, &folder_cipher, &note, document, &text).await?;
+
+            return finish_update_note(&mut conn, &note, &name, &text, None, note.commit, state).await;
+        }
+
+        let request = encrypt_note(&folder_cipher, &name, &text)?;
+
+        let req_ref = &request;
+        let commit = note.commit;
+        match mavinote.clone().login_on_unauthorized(&|client| async move { client.update_note(remote_id, commit, req_ref).await }, &login).await {
+            Ok(NoteUpdate::Committed(commit)) => (name, text.clone(), Some(text), commit.commit, ModelState::Clean),
+            Ok(NoteUpdate::Mismatch(remote_note)) => {
+                if let Some((remote_name, remote_text)) = decrypt_remote_note(Some(&folder_cipher), &ciphers, &remote_note)? {
+                    // Remote note is sent alongside the mismatch, merge it with ours and try once more
+                    let text = match sync::resolve_conflict(&mut conn, folder.account_id, &note, &text, &remote_text, remote_note.commit).await? {
+                        sync::Resolution::Merged(text) => text,
+                        sync::Resolution::Copied => return finish_update_note(&mut conn, &note, &remote_name, &remote_text, Some(&remote_text), remote_note.commit, ModelState::Clean).await,
+                    };
+                    let name = note_name(&text);
+
+                    let request = encrypt_note(&folder_cipher, &name, &text)?;
+
+                    let req_ref = &request;
+                    let commit = remote_note.commit;
+                    match mavinote.login_on_unauthorized(&|client| async move { client.update_note(remote_id, commit, req_ref).await }, &login).await {
+                        Ok(NoteUpdate::Committed(commit)) => (name, text.clone(), Some(text), commit.commit, ModelState::Clean),
+                        Ok(NoteUpdate::Mismatch(_)) => (name, text, Some(remote_text), remote_note.commit, ModelState::Modified),
+                        Err(e) => {
+                            log::debug!("failed to update merged note with id {note_id}, {e:?}");
+                            (name, text, Some(remote_text), remote_note.commit, ModelState::Modified)
+                        }
+                    }
+                } else {
+                    log::debug!("note with id {note_id} cannot be merged with remote note, it will be merged on next sync");
+                    (name, text, None, note.commit, ModelState::Modified)
+                }
+            },
+            Err(e) => {
+                log::debug!("failed to update note with id {note_id}, {e:?}");
+                (name, text, None, note.commit, ModelState::Modified)
+            }
+        }
+    } else {
+        (name, text, None, note.commit, ModelState::Clean)
+    };
+
+    finish_update_note(&mut conn, &note, &name, &text, base.as_deref(), commit, state).await
+}
+
+/// Stores the updated note, a note which could not be sent is queued in the outbox
+async fn finish_update_note(conn: &mut PoolConnection<Sqlite>, note: &Note, name: &str, text: &str, base: Option<&str>, commit: i32, state: ModelState) -> Result<(), Error> {
+    if state == ModelState::Modified {
+        let folder = db::fetch_folder(conn, LocalId(note.folder_id)).await?.unwrap();
+
+        outbox::enqueue(conn, folder.account_id, OperationKind::UpdateNote, note.local_id()).await?;
+    }
+
+    db::update_note(conn, note.local_id(), name, text, base, commit, state).await?;
+
+    if let Some(updated_note) = db::fetch_note(conn, note.local_id()).await? {
+        let note_id = note.id;
+        NOTES_MAP.get().unwrap().update_modify(note.folder_id, move |state| {
+            if let State::Ok(notes) = state {
+                if let Some(note) = notes.iter_mut().find(|n| n.id == note_id) {
+                    *note = updated_note;
+                }
+            }
+        });
+    }
 
     Ok(())
 }
@@ -593,23 +1272,571 @@ pub async fn delete_note(note_id: i32) -> Result<(), Error> {
     let note = db::fetch_note(&mut conn, LocalId(note_id)).await?
         .ok_or(NOTE_NOT_FOUND)?;
 
-    let mut delete = true;
+    let folder = db::fetch_folder(&mut conn, LocalId(note.folder_id)).await?.unwrap();
+
+    if folder.role == Some(Role::Read) {
+        return Err(FOLDER_READ_ONLY);
+    }
+
+    // Deleted state marks the notes which are not moved into the trash of remote yet
+    let mut state = ModelState::Trashed;
 
     if let Some(remote_id) = note.remote_id() {
-        let folder = db::fetch_folder(&mut conn, LocalId(note.folder_id)).await?.unwrap();
-        if let Some(mavinote) = mavinote_client(&mut conn, folder.account_id).await? {
-            if let Err(e) = mavinote.login_on_unauthorized(&|client| async move { client.delete_note(remote_id).await }, &login).await {
-                log::debug!("failed to delete note in remote, {e:?}");
+        let sent = match outbox::client(&mut conn, folder.account_id).await? {
+            Some(mavinote) => match mavinote.login_on_unauthorized(&|client| async move { client.delete_note(remote_id).await }, &login).await {
+                Ok(_) => true,
+                Err(e) => {
+                    log::debug!("failed to delete note in remote, {e:?}");
+                    false
+                }
+            },
+            None => false,
+        };
+
+        if !sent && outbox::enqueue(&mut conn, folder.account_id, OperationKind::DeleteNote, note.local_id()).await? {
+            state = ModelState::Deleted;
+        }
+    }
+
+    db::update_note_state(&mut conn, note.local_id(), state).await?;
+
+    update_send_notes(&mut conn, LocalId(note.folder_id)).await;
+
+    Ok(())
+}
+
+pub async fn trash() -> Result<Vec<Note>, Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    db::fetch_trashed_notes(&mut conn).await.map_err(|e| e.into())
+}
 
-                delete = false;
+/// Trashed folders are listed without their subfolders, which are restored alongside them
+pub async fn trashed_folders() -> Result<Vec<Folder>, Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    db::fetch_trashed_folders(&mut conn).await.map_err(|e| e.into())
+}
+
+pub async fn restore_folder(folder_id: i32) -> Result<(), Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    let folder = db::fetch_trashed_folders(&mut conn).await?
+        .into_iter()
+        .find(|folder| folder.id == folder_id)
+        .ok_or(FOLDER_NOT_TRASHED)?;
+
+    match (&folder.state, folder.remote_id()) {
+        // Folder has not reached the trash of remote, its queued deletion is skipped by the outbox. It is marked as
+        // modified since the queued changes of the folder are dropped when it is trashed.
+        (ModelState::Deleted, _) => db::update_folder_state(&mut conn, folder.local_id(), ModelState::Modified).await?,
+        // Trash of remote is the source of truth, the folder stays in the trash if it cannot be restored there
+        (_, Some(remote_id)) => {
+            if let Some(mavinote) = mavinote_client(&mut conn, folder.account_id).await? {
+                mavinote.login_on_unauthorized(&|client| async move { client.restore_folder(remote_id).await }, &login).await?;
+            }
+        },
+        (_, None) => {},
+    }
+
+    db::restore_folder(&mut conn, folder.local_id()).await?;
+
+    update_send_folders(&mut conn).await;
+
+    Ok(())
+}
+
+/// Days the trashed notes and folders are kept on this device
+pub async fn trash_retention() -> Result<i32, Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    db::fetch_trash_retention(&mut conn).await
+        .map_err(|e| e.into())
+}
+
+pub async fn update_trash_retention(days: i32) -> Result<(), Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    db::update_trash_retention(&mut conn, days).await
+        .map_err(|e| e.into())
+}
+
+pub async fn restore_note(note_id: i32) -> Result<(), Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    let note = db::fetch_note(&mut conn, LocalId(note_id)).await?
+        .ok_or(NOTE_NOT_FOUND)?;
+
+    let folder = db::fetch_folder(&mut conn, LocalId(note.folder_id)).await?.unwrap();
+
+    if folder.role == Some(Role::Read) {
+        return Err(FOLDER_READ_ONLY);
+    }
+
+    match (&note.state, note.remote_id()) {
+        // Note has not reached the trash of remote, so there is nothing to restore in remote
+        (ModelState::Deleted, _) => {},
+        // Note is trashed before it is created in remote, it is created now
+        (ModelState::Trashed, None) => {
+            outbox::enqueue(&mut conn, folder.account_id, OperationKind::CreateNote, note.local_id()).await?;
+        },
+        (ModelState::Trashed, Some(remote_id)) => {
+            if let Some(mavinote) = mavinote_client(&mut conn, folder.account_id).await? {
+                mavinote.login_on_unauthorized(&|client| async move { client.restore_note(remote_id).await }, &login).await?;
+            }
+        },
+        _ => return Err(NOTE_NOT_TRASHED),
+    }
+
+    db::update_note_state(&mut conn, note.local_id(), ModelState::Clean).await?;
+
+    update_send_notes(&mut conn, LocalId(note.folder_id)).await;
+
+    Ok(())
+}
+
+pub async fn move_note(note_id: i32, folder_id: i32) -> Result<(), Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    let note = db::fetch_note(&mut conn, LocalId(note_id)).await?
+        .ok_or(NOTE_NOT_FOUND)?;
+
+    if note.folder_id == folder_id {
+        return Ok(());
+    }
+
+    let source = db::fetch_folder(&mut conn, LocalId(note.folder_id)).await?.unwrap();
+    let target = db::fetch_folder(&mut conn, LocalId(folder_id)).await?
+        .ok_or(FOLDER_NOT_FOUND)?;
+
+    // Earlier commits of the note stay encrypted with the key of the source folder, so it cannot cross the
+    // boundary of a shared folder
+    if source.shared || target.shared {
+        return Err(FOLDER_SHARED);
+    }
+
+    if source.role == Some(Role::Read) || target.role == Some(Role::Read) {
+        return Err(FOLDER_READ_ONLY);
+    }
+
+    if source.account_id != target.account_id {
+        // Devices of the target account are different, the note is created there from scratch and the original is
+        // trashed. Both are sent through the outboxes of the accounts, so a failure cannot leave the note in both.
+        let delete = match note.remote_id() {
+            Some(_) => outbox::key(&mut conn, source.account_id).await?.map(|key| (source.account_id, key)),
+            None => None,
+        };
+        let create = outbox::key(&mut conn, target.account_id).await?.map(|key| (target.account_id, key));
+
+        // Deleted state marks the notes which are not moved into the trash of remote yet
+        let state = if delete.is_some() { ModelState::Deleted } else { ModelState::Trashed };
+
+        db::transfer_note(&mut conn, &note, target.local_id(), state, create, delete).await?;
+
+        update_send_notes(&mut conn, source.local_id()).await;
+        update_send_notes(&mut conn, target.local_id()).await;
+
+        return Ok(());
+    }
+
+    // Target folder is not created in remote yet, the note would be orphaned in remote after its creation
+    if note.remote_id().is_some() && target.remote_id().is_none() {
+        return Err(FOLDER_NOT_SYNCED);
+    }
+
+    let sent = match (note.remote_id(), target.remote_id(), outbox::client(&mut conn, source.account_id).await?) {
+        (Some(remote_id), Some(target_remote_id), Some(mavinote)) => {
+            match send_move_note(&mut conn, &mavinote, &note, remote_id, &target, target_remote_id).await {
+                Ok(()) => true,
+                Err(e) => {
+                    log::debug!("failed to move note in remote, {e:?}");
+                    false
+                }
+            }
+        },
+        (Some(_), _, None) => false,
+        // Note is not created in remote yet, it will be created in its new folder
+        _ => true,
+    };
+
+    // Queued move keeps the note pending until the outbox sends it
+    if !sent {
+        outbox::enqueue(&mut conn, source.account_id, OperationKind::MoveNote, note.local_id()).await?;
+    }
+
+    db::update_note_folder(&mut conn, note.local_id(), target.local_id()).await?;
+
+    update_send_notes(&mut conn, source.local_id()).await;
+    update_send_notes(&mut conn, target.local_id()).await;
+
+    Ok(())
+}
+
+/// Moves the note in remote, it is encrypted again with the key of the target folder
+async fn send_move_note(conn: &mut PoolConnection<Sqlite>, mavinote: &MavinoteClient, note: &Note, remote_id: RemoteId, target: &Folder, target_remote_id: RemoteId) -> Result<(), Error> {
+    let folder_cipher = load_folder_cipher(conn, target).await?.ok_or(FOLDER_KEY_PENDING)?;
+
+    let request = encrypt_note(&folder_cipher, &note.name, &note.text)?;
+
+    let req_ref = &request;
+    mavinote.clone().login_on_unauthorized(&|client| async move { client.move_note(remote_id, target_remote_id, req_ref).await }, &login).await
+        .map_err(|e| e.into())
+}
+
+pub async fn note_commits(note_id: i32) -> Result<Vec<Commit>, Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    let note = db::fetch_note(&mut conn, LocalId(note_id)).await?
+        .ok_or(NOTE_NOT_FOUND)?;
+
+    let Some(remote_id) = note.remote_id() else {
+        return Ok(Vec::new());
+    };
+
+    let folder = db::fetch_folder(&mut conn, LocalId(note.folder_id)).await?.unwrap();
+    let Some(mavinote) = mavinote_client(&mut conn, folder.account_id).await? else {
+        return Err(Error::Unreachable("Mavinote account must have a client"));
+    };
+
+    let commits = mavinote
+        .login_on_unauthorized(&|client| async move { client.fetch_note_commits(remote_id).await }, &login)
+        .await?;
+
+    Ok(commits
+        .into_iter()
+        .map(|commit| Commit { note_id, commit: commit.commit, device_id: commit.sender_device_id, created_at: commit.created_at })
+        .collect())
+}
+
+pub async fn note_commit(note_id: i32, commit: i32) -> Result<Option<CommitNote>, Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    let note = db::fetch_note(&mut conn, LocalId(note_id)).await?
+        .ok_or(NOTE_NOT_FOUND)?;
+
+    let Some(remote_id) = note.remote_id() else {
+        return Ok(None);
+    };
+
+    let folder = db::fetch_folder(&mut conn, LocalId(note.folder_id)).await?.unwrap();
+    let Some(mavinote) = mavinote_client(&mut conn, folder.account_id).await? else {
+        return Err(Error::Unreachable("Mavinote account must have a client"));
+    };
+
+    let Some(device_note) = mavinote
+        .clone()
+        .login_on_unauthorized(&|client| async move { client.fetch_note_commit(remote_id, commit).await }, &login)
+        .await? else {
+        return Ok(None);
+    };
+
+    // Commits encrypted with a folder key can be decrypted as long as this device has the key of that folder
+    if let Some(folder_id) = device_note.folder_id {
+        let Some(commit_folder) = db::fetch_folder_by_remote_id(&mut conn, RemoteId(folder_id), folder.account_id).await? else {
+            log::debug!("A note commit of an unknown folder is received");
+            return Ok(None);
+        };
+
+        let Some(key) = db::fetch_folder_key(&mut conn, commit_folder.local_id()).await? else {
+            return Ok(None);
+        };
+
+        let cipher = crypto::FileCipher::try_from_key(&key)?;
+
+        return Ok(Some(CommitNote {
+            note_id,
+            commit: device_note.commit,
+            name: cipher.decrypt_str(&device_note.name)?,
+            text: cipher.decrypt_str(&device_note.text)?,
+            created_at: device_note.created_at,
+        }));
+    }
+
+    let identity = crypto::load_identity(&mut conn).await?;
+    let ciphers = folder_ciphers(&mut conn, &mavinote, &folder, &identity).await?;
+
+    // Commits sent by this device are decrypted with the key shared with its receiver
+    let Some(cipher) = ciphers.iter().find(|c| c.device_id == device_note.sender_device_id)
+        .or_else(|| ciphers.iter().find(|c| Some(c.device_id) == device_note.receiver_device_id)) else {
+        log::warn!("A note commit with unknown devices is received");
+        return Ok(None);
+    };
+
+    Ok(Some(CommitNote {
+        note_id,
+        commit: device_note.commit,
+        name: cipher.decrypt(&device_note.name)?,
+        text: cipher.decrypt(&device_note.text)?,
+        created_at: device_note.created_at,
+    }))
+}
+
+pub async fn restore_commit(note_id: i32, commit: i32) -> Result<(), Error> {
+    let commit_note = note_commit(note_id, commit).await?
+        .ok_or(COMMIT_NOT_FOUND)?;
+
+    update_note(note_id, commit_note.text).await
+}
+
+/// Texts of the note before its updates, latest first. They are kept on this device only.
+pub async fn note_revisions(note_id: i32) -> Result<Vec<NoteRevision>, Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    db::fetch_note_revisions(&mut conn, LocalId(note_id)).await
+        .map_err(|e| e.into())
+}
+
+/// Updates the note with the text of the revision, the current text is kept as another revision
+pub async fn restore_revision(revision_id: i32) -> Result<(), Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    let revision = db::fetch_note_revision(&mut conn, revision_id).await?
+        .ok_or(REVISION_NOT_FOUND)?;
+
+    update_note(revision.note_id, revision.text).await
+}
+
+pub async fn revision_retention() -> Result<RevisionRetention, Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    db::fetch_revision_retention(&mut conn).await
+        .map_err(|e| e.into())
+}
+
+pub async fn update_revision_retention(retention: RevisionRetention) -> Result<(), Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    db::update_revision_retention(&mut conn, retention).await
+        .map_err(|e| e.into())
+}
+
+async fn note_client(conn: &mut PoolConnection<Sqlite>, note_id: i32) -> Result<(Folder, RemoteId, MavinoteClient), Error> {
+    let note = db::fetch_note(conn, LocalId(note_id)).await?
+        .ok_or(NOTE_NOT_FOUND)?;
+
+    let folder = db::fetch_folder(conn, LocalId(note.folder_id)).await?.unwrap();
+    let client = mavinote_client(conn, folder.account_id).await?
+        .ok_or(NOT_MAVINOTE_ACCOUNT)?;
+
+    // Attachments are linked to the notes in remote
+    let remote_id = note.remote_id().ok_or(NOTE_NOT_SYNCED)?;
+
+    Ok((folder, remote_id, client))
+}
+
+fn attachment_dir(attachment_id: i32) -> PathBuf {
+    let config = runtime::get::<Arc<Config>>().unwrap();
+
+    PathBuf::from(&config.storage_dir)
+        .join("attachments")
+        .join(attachment_id.to_string())
+}
+
+/// Cached file of the attachment. It is named after the attachment id since the name is chosen by whoever uploads
+/// the attachment, only the extension of the name is kept so that the file can still be opened by its type.
+fn attachment_file(attachment_id: i32, name: &str) -> PathBuf {
+    let file_name = match Path::new(name).extension() {
+        Some(extension) => format!("{attachment_id}.{}", extension.to_string_lossy()),
+        None => attachment_id.to_string(),
+    };
+
+    attachment_dir(attachment_id).join(file_name)
+}
+
+/// Associated data of an attachment chunk, so that remote cannot move the chunk to another attachment or position,
+/// nor drop the chunks at the end
+fn chunk_aad(attachment_id: i32, index: i32, chunk_count: i32) -> Vec<u8> {
+    format!("{attachment_id}:{index}:{chunk_count}").into_bytes()
+}
+
+/// Keeps only the last component of the attachment name, names that do not point to a file are rejected
+fn attachment_name(name: &str) -> Result<String, Error> {
+    match Path::new(name).file_name().and_then(|name| name.to_str()) {
+        Some(name) if !name.is_empty() && name != "." && name != ".." => Ok(name.to_string()),
+        _ => Err(ATTACHMENT_NAME_INVALID),
+    }
+}
+
+async fn own_cipher(conn: &mut PoolConnection<Sqlite>, identity: &crypto::Identity) -> Result<crypto::DeviceCipher, Error> {
+    let pubkey = db::fetch_value(conn, StoreKey::IdentityPubKey).await?.unwrap().value;
+
+    let previous_pubkey = identity.previous.as_ref().map(crypto::encode_pubkey);
+
+    // Id of this device is not stored locally, it is not needed for the cipher anyway
+    crypto::DeviceCipher::try_from_key(0, identity, &pubkey, previous_pubkey.as_deref())
+        .map_err(|e| e.into())
+}
+
+async fn attachment_file_cipher(conn: &mut PoolConnection<Sqlite>, client: &MavinoteClient, folder: &Folder, attachment: &responses::Attachment) -> Result<Option<crypto::FileCipher>, Error> {
+    let Some(device_attachment) = &attachment.device_attachment else {
+        log::debug!("An attachment with no device attachment is received");
+        return Ok(None);
+    };
+
+    let identity = crypto::load_identity(conn).await?;
+    let ciphers = folder_ciphers(conn, client, folder, &identity).await?;
+
+    let cipher = match ciphers.into_iter().find(|cipher| cipher.device_id == device_attachment.sender_device_id) {
+        Some(cipher) => cipher,
+        // Attachments sent by this device carry a file key wrapped for this device itself
+        None => own_cipher(conn, &identity).await?,
+    };
+
+    let Ok(key) = cipher.decrypt(&device_attachment.key) else {
+        log::warn!("key of attachment with id {} cannot be decrypted", attachment.id);
+        return Ok(None);
+    };
+
+    crypto::FileCipher::try_from_key(&key)
+        .map(Some)
+        .map_err(|e| e.into())
+}
+
+pub async fn attachments(note_id: i32) -> Result<Vec<Attachment>, Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    let (folder, remote_id, client) = note_client(&mut conn, note_id).await?;
+
+    let remote_attachments = client
+        .clone()
+        .login_on_unauthorized(&|client| async move { client.fetch_attachments(remote_id).await }, &login)
+        .await?;
+
+    let mut attachments = Vec::with_capacity(remote_attachments.len());
+    for remote_attachment in remote_attachments {
+        if let Some(file_cipher) = attachment_file_cipher(&mut conn, &client, &folder, &remote_attachment).await? {
+            match attachment_name(&file_cipher.decrypt_str(&remote_attachment.name)?) {
+                Ok(name) => attachments.push(Attachment { id: remote_attachment.id, note_id, name }),
+                Err(_) => log::warn!("attachment with id {} has an invalid name", remote_attachment.id),
             }
         }
     }
 
-    if delete {
-        db::delete_note(&mut conn, note.local_id()).await?;
-    } else {
-        db::delete_note_local(&mut conn, note.local_id()).await?;
+    Ok(attachments)
+}
+
+pub async fn add_attachment(note_id: i32, path: String) -> Result<Attachment, Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    let (folder, remote_id, client) = note_client(&mut conn, note_id).await?;
+
+    if folder.role == Some(Role::Read) {
+        return Err(FOLDER_READ_ONLY);
+    }
+
+    let path = PathBuf::from(path);
+    let name = attachment_name(&path.to_string_lossy())?;
+    let bytes = tokio::fs::read(&path).await?;
+
+    let file_cipher = crypto::FileCipher::generate();
+    let file_key = file_cipher.key();
+
+    let identity = crypto::load_identity(&mut conn).await?;
+    let ciphers = folder_ciphers(&mut conn, &client, &folder, &identity).await?
+        .into_iter()
+        .filter(|cipher| cipher.trusted)
+        .collect::<Vec<_>>();
+    let nonces = db::unique_nonces(&mut conn, &ciphers.iter().map(|c| c.device_id).collect::<Vec<_>>()).await?;
+
+    let mut device_attachments = vec![];
+    for (cipher, nonce) in ciphers.iter().zip(nonces) {
+        device_attachments.push(DeviceAttachmentRequest { device_id: cipher.device_id, key: cipher.encrypt(&file_key, nonce)? });
+    }
+
+    let own_nonce = db::unique_nonces(&mut conn, &[0]).await?.remove(0);
+
+    let chunk_count = bytes.len().div_ceil(ATTACHMENT_CHUNK_SIZE).max(1) as i32;
+
+    let request = CreateAttachmentRequest {
+        name: file_cipher.encrypt_str(&name)?,
+        chunk_count,
+        key: own_cipher(&mut conn, &identity).await?.encrypt(&file_key, own_nonce)?,
+        device_attachments,
+    };
+
+    let request_ref = &request;
+    let created = match client.clone().login_on_unauthorized(&|client| async move { client.create_attachment(remote_id, request_ref).await }, &login).await {
+        Err(MavinoteError::Message(msg)) if msg == "devices_mismatch" => {
+            sync::sync_devices(&mut conn, folder.account_id).await?;
+            return Err(MavinoteError::Message(msg).into());
+        },
+        res => res?,
+    };
+
+    // An empty file is still uploaded as a single empty chunk
+    let chunks = if bytes.is_empty() { vec![&bytes[..]] } else { bytes.chunks(ATTACHMENT_CHUNK_SIZE).collect() };
+    for (index, chunk) in chunks.into_iter().enumerate() {
+        let data = file_cipher.encrypt_with_aad(chunk, &chunk_aad(created.id, index as i32, chunk_count))?;
+        let data_ref = &data;
+        client.clone().login_on_unauthorized(&|client| async move { client.upload_attachment_chunk(created.id, index as i32, data_ref.clone()).await }, &login).await?;
+    }
+
+    tokio::fs::create_dir_all(attachment_dir(created.id)).await?;
+    tokio::fs::write(attachment_file(created.id, &name), &bytes).await?;
+
+    Ok(Attachment { id: created.id, note_id, name })
+}
+
+/// Returns the path of decrypted attachment, downloading it into the cache if it is not cached yet
+pub async fn attachment_path(note_id: i32, attachment_id: i32) -> Result<String, Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    let (folder, remote_id, client) = note_client(&mut conn, note_id).await?;
+
+    let remote_attachment = client
+        .clone()
+        .login_on_unauthorized(&|client| async move { client.fetch_attachments(remote_id).await }, &login)
+        .await?
+        .into_iter()
+        .find(|attachment| attachment.id == attachment_id)
+        .ok_or(ATTACHMENT_NOT_FOUND)?;
+
+    let file_cipher = attachment_file_cipher(&mut conn, &client, &folder, &remote_attachment).await?
+        .ok_or(ATTACHMENT_NOT_FOUND)?;
+
+    let name = attachment_name(&file_cipher.decrypt_str(&remote_attachment.name)?)?;
+    let dir = attachment_dir(attachment_id);
+    let path = attachment_file(attachment_id, &name);
+
+    if tokio::fs::try_exists(&path).await? {
+        return Ok(path.to_string_lossy().to_string());
+    }
+
+    let mut bytes = vec![];
+    for chunk in 0..remote_attachment.chunk_count {
+        let data = client
+            .clone()
+            .login_on_unauthorized(&|client| async move { client.fetch_attachment_chunk(attachment_id, chunk).await }, &login)
+            .await?;
+
+        bytes.extend(file_cipher.decrypt_with_aad(&data, &chunk_aad(attachment_id, chunk, remote_attachment.chunk_count))?);
+    }
+
+    // File is written under a temporary name first so that a partially written file is never served from the cache
+    let tmp_path = dir.join(format!(".{attachment_id}.part"));
+    tokio::fs::create_dir_all(&dir).await?;
+    tokio::fs::write(&tmp_path, &bytes).await?;
+    tokio::fs::rename(&tmp_path, &path).await?;
+
+    Ok(path.to_string_lossy().to_string())
+}
+
+pub async fn delete_attachment(note_id: i32, attachment_id: i32) -> Result<(), Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    let (folder, _, client) = note_client(&mut conn, note_id).await?;
+
+    if folder.role == Some(Role::Read) {
+        return Err(FOLDER_READ_ONLY);
+    }
+
+    client
+        .login_on_unauthorized(&|client| async move { client.delete_attachment(attachment_id).await }, &login)
+        .await?;
+
+    let dir = attachment_dir(attachment_id);
+    if tokio::fs::try_exists(&dir).await? {
+        tokio::fs::remove_dir_all(&dir).await?;
     }
 
     Ok(())
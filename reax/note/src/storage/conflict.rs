@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use base::State;
+use once_cell::sync::OnceCell;
+use sqlx::{Pool, Sqlite, pool::PoolConnection};
+use tokio::sync::watch::Sender;
+
+use crate::Error;
+use crate::models::{Conflict, ConflictPolicy, LocalId, Note};
+use super::{db, note_name, update_send_notes, NOTE_NOT_FOUND};
+
+const CONFLICT_NOT_FOUND: Error = Error::Unreachable("ConflictNotFound");
+
+pub(crate) static CONFLICTS: OnceCell<Sender<State<Vec<Conflict>, Error>>> = OnceCell::new();
+
+/// Conflicted copies which are neither resolved nor discarded yet
+pub async fn conflicts() -> tokio::sync::watch::Receiver<State<Vec<Conflict>, Error>> {
+    let sender = CONFLICTS.get().unwrap();
+    let load = matches!(*sender.borrow(), State::Initial | State::Err(_));
+
+    if load {
+        sender.send_replace(State::Loading);
+
+        let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await.unwrap();
+
+        sender.send_replace(fetch_conflicts(&mut conn).await.into());
+    }
+
+    sender.subscribe()
+}
+
+pub(crate) async fn update_send_conflicts(conn: &mut PoolConnection<Sqlite>) {
+    let sender = CONFLICTS.get().unwrap();
+    // If nobody loaded the conflicts, then do not load the conflicts
+    let load = !matches!(*sender.borrow(), State::Initial);
+
+    if load {
+        sender.send_replace(State::Loading);
+
+        sender.send_replace(fetch_conflicts(conn).await.into());
+    }
+}
+
+async fn fetch_conflicts(conn: &mut PoolConnection<Sqlite>) -> Result<Vec<Conflict>, Error> {
+    let mut conflicts = Vec::new();
+
+    for conflict in db::fetch_note_conflicts(conn).await? {
+        let note = db::fetch_note(conn, LocalId(conflict.note_id)).await?.ok_or(NOTE_NOT_FOUND)?;
+        let copy = db::fetch_note(conn, LocalId(conflict.copy_id)).await?.ok_or(NOTE_NOT_FOUND)?;
+
+        conflicts.push(Conflict { id: conflict.id, note, copy, remote_commit: conflict.remote_commit, created_at: conflict.created_at });
+    }
+
+    Ok(conflicts)
+}
+
+pub async fn conflict_policy(account_id: i32) -> Result<ConflictPolicy, Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    db::fetch_conflict_policy(&mut conn, account_id).await
+        .map_err(|e| e.into())
+}
+
+pub async fn update_conflict_policy(account_id: i32, policy: ConflictPolicy) -> Result<(), Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    db::update_conflict_policy(&mut conn, account_id, policy).await
+        .map_err(|e| e.into())
+}
+
+/// Replaces the text of the note with the one in its conflicted copy, and moves the copy into the trash
+pub async fn resolve_conflict(conflict_id: i32) -> Result<(), Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    let conflict = db::fetch_note_conflict(&mut conn, conflict_id).await?
+        .ok_or(CONFLICT_NOT_FOUND)?;
+
+    let copy = db::fetch_note(&mut conn, LocalId(conflict.copy_id)).await?
+        .ok_or(NOTE_NOT_FOUND)?;
+
+    super::update_note(conflict.note_id, copy.text).await?;
+
+    discard(&mut conn, conflict_id, conflict.copy_id).await
+}
+
+/// Keeps the note as it is, and moves the conflicted copy into the trash
+pub async fn discard_conflict(conflict_id: i32) -> Result<(), Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    let conflict = db::fetch_note_conflict(&mut conn, conflict_id).await?
+        .ok_or(CONFLICT_NOT_FOUND)?;
+
+    discard(&mut conn, conflict_id, conflict.copy_id).await
+}
+
+async fn discard(conn: &mut PoolConnection<Sqlite>, conflict_id: i32, copy_id: i32) -> Result<(), Error> {
+    super::delete_note(copy_id).await?;
+
+    db::delete_note_conflict(conn, conflict_id).await?;
+
+    update_send_conflicts(conn).await;
+
+    Ok(())
+}
+
+/// Keeps the local text of the note in a new note next to it. Copy is created in remote on the next sync like the other
+/// local notes, so that the text is not lost if this device is gone before the conflict is handled.
+pub(crate) async fn create_copy(conn: &mut PoolConnection<Sqlite>, note: &Note, local_text: &str, remote_commit: i32) -> Result<(), Error> {
+    let name = format!("{} (conflicted copy)", note_name(local_text));
+
+    let copy = db::create_note(conn, LocalId(note.folder_id), None, name, local_text.to_owned(), 0).await?;
+
+    db::create_note_conflict(conn, note.local_id(), copy.local_id(), remote_commit).await?;
+
+    update_send_notes(conn, LocalId(note.folder_id)).await;
+    update_send_conflicts(conn).await;
+
+    Ok(())
+}
@@ -1,11 +1,18 @@
 use serde::de::DeserializeOwned;
 use sqlx::Connection;
 use sqlx::types::Json;
-use sqlx::{Sqlite, pool::PoolConnection, Error};
+use sqlx::{Row, Sqlite, SqliteConnection, pool::PoolConnection, sqlite::SqliteRow, Error, FromRow};
 
-use crate::models::{Folder, Note, State, RemoteId, LocalId, Account, AccountKind, Mavinote, Device, StoreValue, StoreKey};
+use crate::accounts::mavinote::responses;
+use crate::models::{Folder, Note, State, RemoteId, LocalId, Account, AccountKind, Mavinote, Device, DirectoryDevice, DirectoryRecord, StoreValue, StoreKey, Role, Trust, ConflictPolicy, NoteRevision, RevisionRetention, OperationKind};
+use super::vault;
+
+/// Days the trashed notes and folders are kept on this device if no retention is configured
+const TRASH_RETENTION_DAYS: i32 = 30;
 
 pub async fn store_value(conn: &mut PoolConnection<Sqlite>, key: StoreKey, value: &str) -> Result<(), Error> {
+    let value = seal_value(&key, value)?;
+
     sqlx::query("insert into store (key, value) values (?, ?) on conflict (key) do update set value = excluded.value")
         .bind(key)
         .bind(value)
@@ -15,10 +22,35 @@ pub async fn store_value(conn: &mut PoolConnection<Sqlite>, key: StoreKey, value
 }
 
 pub async fn fetch_value(conn: &mut PoolConnection<Sqlite>, key: StoreKey) -> Result<Option<StoreValue>, Error> {
-    sqlx::query_as("select key, value from store where key = ?")
+    let Some(mut store) = sqlx::query_as::<_, StoreValue>("select key, value from store where key = ?")
         .bind(key)
         .fetch_optional(conn)
+        .await? else {
+        return Ok(None);
+    };
+
+    if store.key.is_secret() {
+        store.value = vault::open(store.value)?;
+    }
+
+    Ok(Some(store))
+}
+
+/// Secret values are encrypted by the vault, the rest are stored as they are
+fn seal_value(key: &StoreKey, value: &str) -> Result<String, Error> {
+    if key.is_secret() {
+        Ok(vault::seal(value)?)
+    } else {
+        Ok(value.to_owned())
+    }
+}
+
+pub async fn delete_value(conn: &mut PoolConnection<Sqlite>, key: StoreKey) -> Result<(), Error> {
+    sqlx::query("delete from store where key = ?")
+        .bind(key)
+        .execute(conn)
         .await
+        .map(|_| ())
 }
 
 pub async fn fetch_accounts(conn: &mut PoolConnection<Sqlite>) -> Result<Vec<Account>, Error> {
@@ -34,6 +66,23 @@ pub async fn fetch_account(conn: &mut PoolConnection<Sqlite>, account_id: i32) -
         .await
 }
 
+pub async fn fetch_conflict_policy(conn: &mut PoolConnection<Sqlite>, account_id: i32) -> Result<ConflictPolicy, Error> {
+    sqlx::query_as::<_, (ConflictPolicy,)>("select conflict_policy from accounts where id = ?")
+        .bind(account_id)
+        .fetch_one(conn)
+        .await
+        .map(|(policy,)| policy)
+}
+
+pub async fn update_conflict_policy(conn: &mut PoolConnection<Sqlite>, account_id: i32, policy: ConflictPolicy) -> Result<(), Error> {
+    sqlx::query("update accounts set conflict_policy = ? where id = ?")
+        .bind(policy)
+        .bind(account_id)
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
 pub async fn fetch_account_data<T: DeserializeOwned + Unpin + Send + 'static>(conn: &mut PoolConnection<Sqlite>, account_id: i32) -> Result<Option<T>, Error> {
     sqlx::query_as::<Sqlite, (Json<T>,)>("select data from accounts where id = ?")
         .bind(account_id)
@@ -42,22 +91,101 @@ pub async fn fetch_account_data<T: DeserializeOwned + Unpin + Send + 'static>(co
         .map(|opt| opt.map(|json| json.0.0))
 }
 
+/// Note found by the full text search
+#[derive(FromRow)]
+pub struct NoteMatch {
+    pub id: i32,
+    pub folder_id: i32,
+    pub name: String,
+    pub snippet: String,
+}
+
+/// Copy of a note which keeps the local text that lost a conflict
+#[derive(FromRow)]
+pub struct NoteConflict {
+    pub id: i32,
+    pub note_id: i32,
+    pub copy_id: i32,
+    pub remote_commit: i32,
+    pub created_at: String,
+}
+
+/// Operation waiting in the outbox, it targets either a folder or a note
+#[derive(FromRow)]
+pub struct Operation {
+    pub id: i32,
+    pub account_id: i32,
+    pub kind: OperationKind,
+    pub folder_id: Option<i32>,
+    pub note_id: Option<i32>,
+    pub idempotency_key: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub retry_at: i64,
+    pub failed: bool,
+}
+
+/// CRDT document of a note, it is saved in base64
+pub struct NoteDocument {
+    pub note_id: i32,
+    pub actor: String,
+    pub document: String,
+    pub pushed_seq: i64,
+    pub update_id: Option<i32>,
+}
+
+impl<'r> FromRow<'r, SqliteRow> for NoteDocument {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, Error> {
+        Ok(NoteDocument {
+            note_id: row.try_get("note_id")?,
+            actor: row.try_get("actor")?,
+            document: vault::open(row.try_get("document")?)?,
+            pushed_seq: row.try_get("pushed_seq")?,
+            update_id: row.try_get("update_id")?,
+        })
+    }
+}
+
+/// Keys and the trust state of a device, which are needed to build its cipher
+#[derive(FromRow)]
+pub struct DeviceKey {
+    pub id: i32,
+    pub pubkey: String,
+    pub previous_pubkey: Option<String>,
+    pub trust: Trust,
+}
+
 pub async fn fetch_devices(conn: &mut PoolConnection<Sqlite>, account_id: i32) -> Result<Vec<Device>, Error> {
-    sqlx::query_as("select id, account_id, pubkey, created_at from devices where account_id = ?")
+    sqlx::query_as("select id, account_id, pubkey, created_at, trust from devices where account_id = ?")
+        .bind(account_id)
+        .fetch_all(conn)
+        .await
+}
+
+pub async fn fetch_device(conn: &mut PoolConnection<Sqlite>, account_id: i32, device_id: i32) -> Result<Option<Device>, Error> {
+    sqlx::query_as("select id, account_id, pubkey, created_at, trust from devices where account_id = ? and id = ?")
+        .bind(account_id)
+        .bind(device_id)
+        .fetch_optional(conn)
+        .await
+}
+
+pub async fn fetch_device_keys(conn: &mut PoolConnection<Sqlite>, account_id: i32) -> Result<Vec<DeviceKey>, Error> {
+    sqlx::query_as("select id, pubkey, previous_pubkey, trust from devices where account_id = ?")
         .bind(account_id)
         .fetch_all(conn)
         .await
 }
 
-pub async fn create_devices(conn: &mut PoolConnection<Sqlite>, account_id: i32, devices: &[crate::accounts::mavinote::Device]) -> Result<(), Error> {
-    let binds: String = itertools::Itertools::intersperse(devices.into_iter().map(|_| "(?, ?, ?, ?)"), ",")
+pub async fn create_devices(conn: &mut PoolConnection<Sqlite>, account_id: i32, devices: &[crate::accounts::mavinote::Device], trust: Trust) -> Result<(), Error> {
+    let binds: String = itertools::Itertools::intersperse(devices.into_iter().map(|_| "(?, ?, ?, ?, ?, ?)"), ",")
         .collect();
 
-    let query = format!("insert into devices (id, account_id, pubkey, created_at) values {}", binds);
+    let query = format!("insert into devices (id, account_id, pubkey, previous_pubkey, created_at, trust) values {}", binds);
     let mut query = sqlx::query(&query);
 
     for dev in devices {
-        query = query.bind(dev.id).bind(account_id).bind(&dev.pubkey).bind(&dev.created_at);
+        query = query.bind(dev.id).bind(account_id).bind(&dev.pubkey).bind(&dev.previous_pubkey).bind(&dev.created_at).bind(trust);
     }
 
     query.execute(conn)
@@ -65,6 +193,28 @@ pub async fn create_devices(conn: &mut PoolConnection<Sqlite>, account_id: i32,
         .map(|_| ())
 }
 
+pub async fn update_device_key(conn: &mut PoolConnection<Sqlite>, account_id: i32, device_id: i32, pubkey: &str, previous_pubkey: Option<&str>, trust: Trust) -> Result<(), Error> {
+    sqlx::query("update devices set pubkey = ?, previous_pubkey = ?, trust = ? where account_id = ? and id = ?")
+        .bind(pubkey)
+        .bind(previous_pubkey)
+        .bind(trust)
+        .bind(account_id)
+        .bind(device_id)
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
+pub async fn update_device_trust(conn: &mut PoolConnection<Sqlite>, account_id: i32, device_id: i32, trust: Trust) -> Result<(), Error> {
+    sqlx::query("update devices set trust = ? where account_id = ? and id = ?")
+        .bind(trust)
+        .bind(account_id)
+        .bind(device_id)
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
 pub async fn delete_devices(conn: &mut PoolConnection<Sqlite>, account_id: i32, device_ids: &[i32]) -> Result<(), Error> {
     let binds: String = itertools::Itertools::intersperse(device_ids.into_iter().map(|_| "(id = ? and account_id = ?)"), " or ")
         .collect();
@@ -91,12 +241,77 @@ pub async fn account_with_email_exists(conn: &mut PoolConnection<Sqlite>, email:
 }
 
 pub async fn fetch_account_folders(conn: &mut PoolConnection<Sqlite>, account_id: i32) -> Result<Vec<Folder>, Error> {
-    sqlx::query_as("select * from folders where account_id = ? order by id")
+    sqlx::query_as("select * from folders where account_id = ? and trashed_at is null order by id")
+        .bind(account_id)
+        .fetch_all(conn)
+        .await
+}
+
+pub async fn fetch_directory_devices(conn: &mut PoolConnection<Sqlite>, account_id: i32, email: &str) -> Result<Vec<DirectoryDevice>, Error> {
+    sqlx::query_as("select id, account_id, email, pubkey from directory_devices where account_id = ? and email = ? order by id")
         .bind(account_id)
+        .bind(email)
         .fetch_all(conn)
         .await
 }
 
+pub async fn fetch_directory_record(conn: &mut PoolConnection<Sqlite>, account_id: i32, email: &str) -> Result<Option<DirectoryRecord>, Error> {
+    sqlx::query_as("select seq, head, signer, signature from directory_records where account_id = ? and email = ?")
+        .bind(account_id)
+        .bind(email)
+        .fetch_optional(conn)
+        .await
+}
+
+pub async fn fetch_directory_signer(conn: &mut PoolConnection<Sqlite>, account_id: i32) -> Result<Option<String>, Error> {
+    sqlx::query_as::<Sqlite, (String,)>("select signer from directory_records where account_id = ? limit 1")
+        .bind(account_id)
+        .fetch_optional(conn)
+        .await
+        .map(|opt| opt.map(|row| row.0))
+}
+
+pub async fn store_directory(
+    conn: &mut PoolConnection<Sqlite>,
+    account_id: i32,
+    email: String,
+    record: responses::DirectoryRecord,
+    devices: Vec<responses::DirectoryDevice>,
+) -> Result<(), Error> {
+    conn.transaction(|conn| Box::pin(async move {
+        sqlx::query("delete from directory_devices where account_id = ? and email = ?")
+            .bind(account_id)
+            .bind(email.as_str())
+            .execute(&mut *conn)
+            .await?;
+
+        for device in devices {
+            sqlx::query("insert into directory_devices (id, account_id, email, pubkey) values (?, ?, ?, ?)")
+                .bind(device.id)
+                .bind(account_id)
+                .bind(email.as_str())
+                .bind(device.pubkey)
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        sqlx::query(
+            "insert into directory_records (account_id, email, seq, head, signer, signature) values (?, ?, ?, ?, ?, ?)
+            on conflict (account_id, email) do update set seq = excluded.seq, head = excluded.head, signer = excluded.signer, signature = excluded.signature"
+        )
+            .bind(account_id)
+            .bind(email.as_str())
+            .bind(record.seq)
+            .bind(record.head)
+            .bind(record.signer)
+            .bind(record.signature)
+            .execute(conn)
+            .await
+            .map(|_| ())
+    }))
+    .await
+}
+
 pub async fn create_account(conn: &mut PoolConnection<Sqlite>, name: String, kind: AccountKind, data: Option<Json<Mavinote>>) -> Result<Account, Error> {
     conn.transaction(|conn| Box::pin(async move {
         sqlx::query("insert into accounts (name, kind, data) values(?, ?, ?)")
@@ -124,6 +339,40 @@ pub async fn update_account_data(conn: &mut PoolConnection<Sqlite>, account_id:
         .map(|_| ())
 }
 
+/// Remote ids of the folders and notes of the account, including the trashed ones
+pub async fn fetch_account_remote_ids(conn: &mut PoolConnection<Sqlite>, account_id: i32) -> Result<(Vec<i32>, Vec<i32>), Error> {
+    let folder_ids = sqlx::query_as::<Sqlite, (i32,)>("select remote_id from folders where account_id = ? and remote_id is not null")
+        .bind(account_id)
+        .fetch_all(&mut *conn)
+        .await?;
+
+    let note_ids = sqlx::query_as::<Sqlite, (i32,)>(
+        "select notes.remote_id from notes inner join folders on folders.id = notes.folder_id where folders.account_id = ? and notes.remote_id is not null"
+    )
+        .bind(account_id)
+        .fetch_all(conn)
+        .await?;
+
+    Ok((folder_ids.into_iter().map(|row| row.0).collect(), note_ids.into_iter().map(|row| row.0).collect()))
+}
+
+pub async fn fetch_sync_cursor(conn: &mut PoolConnection<Sqlite>, account_id: i32) -> Result<i64, Error> {
+    sqlx::query_as::<Sqlite, (i64,)>("select sync_cursor from accounts where id = ?")
+        .bind(account_id)
+        .fetch_one(conn)
+        .await
+        .map(|row| row.0)
+}
+
+pub async fn update_sync_cursor(conn: &mut PoolConnection<Sqlite>, account_id: i32, cursor: i64) -> Result<(), Error> {
+    sqlx::query("update accounts set sync_cursor = ? where id = ?")
+        .bind(cursor)
+        .bind(account_id)
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
 pub async fn delete_account(conn: &mut PoolConnection<Sqlite>, account_id: i32) -> Result<(), Error> {
     sqlx::query("delete from accounts where id = ?")
         .bind(account_id)
@@ -133,13 +382,24 @@ pub async fn delete_account(conn: &mut PoolConnection<Sqlite>, account_id: i32)
 }
 
 pub async fn fetch_folders(conn: &mut PoolConnection<Sqlite>) -> Result<Vec<Folder>, Error> {
-    sqlx::query_as("select * from folders where state != ? order by id")
+    sqlx::query_as("select * from folders where state != ? and trashed_at is null order by id")
         .bind(State::Deleted)
         .fetch_all(conn)
         .await
 
 }
 
+/// Trashed folders whose parents are not in the trash, subfolders are listed alongside their parents
+pub async fn fetch_trashed_folders(conn: &mut PoolConnection<Sqlite>) -> Result<Vec<Folder>, Error> {
+    sqlx::query_as(
+        "select * from folders where trashed_at is not null
+        and (parent_id is null or parent_id not in (select id from folders where trashed_at is not null))
+        order by trashed_at desc, id desc"
+    )
+        .fetch_all(conn)
+        .await
+}
+
 pub async fn fetch_folder(conn: &mut PoolConnection<Sqlite>, local_id: LocalId) -> Result<Option<Folder>, Error> {
     sqlx::query_as("select * from folders where id = ?")
         .bind(local_id.0)
@@ -155,11 +415,19 @@ pub async fn fetch_folder_by_remote_id(conn: &mut PoolConnection<Sqlite>, remote
         .await
 }
 
-pub async fn create_folder(conn: &mut PoolConnection<Sqlite>, remote_id: Option<RemoteId>, account_id: i32, name: String) -> Result<Folder, Error> {
+pub async fn fetch_folder_subtree(conn: &mut PoolConnection<Sqlite>, local_id: LocalId) -> Result<Vec<Folder>, Error> {
+    sqlx::query_as("with recursive subtree(id) as (select ? union select folders.id from folders inner join subtree on folders.parent_id = subtree.id) select * from folders where id in subtree order by id")
+        .bind(local_id.0)
+        .fetch_all(conn)
+        .await
+}
+
+pub async fn create_folder(conn: &mut PoolConnection<Sqlite>, remote_id: Option<RemoteId>, account_id: i32, parent_id: Option<LocalId>, name: String) -> Result<Folder, Error> {
     conn.transaction(|conn| Box::pin(async move {
-        sqlx::query("insert into folders (remote_id, account_id, name) values(?, ?, ?)")
+        sqlx::query("insert into folders (remote_id, account_id, parent_id, name) values(?, ?, ?, ?)")
             .bind(remote_id.map(|id| id.0))
             .bind(account_id)
+            .bind(parent_id.map(|id| id.0))
             .bind(name.as_str())
             .execute(&mut *conn)
             .await
@@ -173,6 +441,35 @@ pub async fn create_folder(conn: &mut PoolConnection<Sqlite>, remote_id: Option<
      .await
 }
 
+pub async fn update_folder(conn: &mut PoolConnection<Sqlite>, local_id: LocalId, name: &str, state: State) -> Result<(), Error> {
+    sqlx::query("update folders set name = ?, state = ? where id = ?")
+        .bind(name)
+        .bind(state)
+        .bind(local_id.0)
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
+pub async fn update_folder_state(conn: &mut PoolConnection<Sqlite>, local_id: LocalId, state: State) -> Result<(), Error> {
+    sqlx::query("update folders set state = ? where id = ?")
+        .bind(state)
+        .bind(local_id.0)
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
+pub async fn update_folder_parent(conn: &mut PoolConnection<Sqlite>, local_id: LocalId, parent_id: Option<LocalId>, state: State) -> Result<(), Error> {
+    sqlx::query("update folders set parent_id = ?, state = ? where id = ?")
+        .bind(parent_id.map(|id| id.0))
+        .bind(state)
+        .bind(local_id.0)
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
 pub async fn update_folder_remote_id(conn: &mut PoolConnection<Sqlite>, local_id: LocalId, remote_id: RemoteId) -> Result<(), Error> {
     sqlx::query("update folders set remote_id = ? where id = ?")
         .bind(remote_id.0)
@@ -182,24 +479,123 @@ pub async fn update_folder_remote_id(conn: &mut PoolConnection<Sqlite>, local_id
         .map(|_| ())
 }
 
-pub async fn delete_folder(conn: &mut PoolConnection<Sqlite>, local_id: LocalId) -> Result<(), Error> {
-    sqlx::query("delete from folders where id = ?")
+/// Marks the synced folders of the account as modified, so that their names and keys are encrypted again
+pub async fn update_account_folders_modified(conn: &mut PoolConnection<Sqlite>, account_id: i32) -> Result<(), Error> {
+    sqlx::query("update folders set state = ? where account_id = ? and state = ? and remote_id is not null")
+        .bind(State::Modified)
+        .bind(account_id)
+        .bind(State::Clean)
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
+pub async fn fetch_folder_key(conn: &mut PoolConnection<Sqlite>, local_id: LocalId) -> Result<Option<String>, Error> {
+    sqlx::query_as::<_, (Option<String>,)>("select key from folders where id = ?")
+        .bind(local_id.0)
+        .fetch_optional(conn)
+        .await
+        .map(|opt| opt.and_then(|row| row.0))
+}
+
+pub async fn update_folder_key(conn: &mut PoolConnection<Sqlite>, local_id: LocalId, key: &str) -> Result<(), Error> {
+    sqlx::query("update folders set key = ? where id = ?")
+        .bind(key)
         .bind(local_id.0)
         .execute(conn)
         .await
         .map(|_| ())
 }
 
-pub async fn delete_folder_local(conn: &mut PoolConnection<Sqlite>, local_id: LocalId) -> Result<(), Error> {
-    sqlx::query("update folders set state = ? where id = ?")
-        .bind(State::Deleted)
+pub async fn update_folder_sharing(conn: &mut PoolConnection<Sqlite>, local_id: LocalId, shared: bool, role: Option<Role>) -> Result<(), Error> {
+    sqlx::query("update folders set shared = ?, role = ? where id = ?")
+        .bind(shared)
+        .bind(role)
         .bind(local_id.0)
-        .execute(&mut *conn)
-        .await?;
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
 
-    sqlx::query("delete from notes where folder_id = ?")
+pub async fn delete_folder(conn: &mut PoolConnection<Sqlite>, local_id: LocalId) -> Result<(), Error> {
+    sqlx::query("delete from folders where id = ?")
         .bind(local_id.0)
-        .execute(&mut *conn)
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
+/// Moves the folder and its subfolders into the trash, the ones trashed before keep their time. Queued operations of
+/// the folders and their notes are dropped since remote rejects the changes in the trash.
+pub async fn trash_folder(conn: &mut PoolConnection<Sqlite>, local_id: LocalId) -> Result<(), Error> {
+    conn.transaction(|conn| Box::pin(async move {
+        let subtree = "with recursive subtree(id) as (select ? union select folders.id from folders inner join subtree on folders.parent_id = subtree.id)";
+
+        sqlx::query(&format!("{subtree} update folders set trashed_at = current_timestamp where id in subtree and trashed_at is null"))
+            .bind(local_id.0)
+            .execute(&mut *conn)
+            .await?;
+
+        sqlx::query(&format!("{subtree} delete from operations where folder_id in subtree or note_id in (select id from notes where folder_id in subtree)"))
+            .bind(local_id.0)
+            .execute(&mut *conn)
+            .await
+            .map(|_| ())
+    }))
+    .await
+}
+
+/// Restores the folder alongside the subfolders trashed with it. Folder is moved to the top level if its parent is
+/// still in the trash.
+pub async fn restore_folder(conn: &mut PoolConnection<Sqlite>, local_id: LocalId) -> Result<(), Error> {
+    conn.transaction(|conn| Box::pin(async move {
+        sqlx::query(
+            "with recursive subtree(id, trashed_at) as (
+                select id, trashed_at from folders where id = ?
+                union select folders.id, folders.trashed_at from folders inner join subtree on folders.parent_id = subtree.id and folders.trashed_at = subtree.trashed_at
+            )
+            update folders set trashed_at = null where id in (select id from subtree)"
+        )
+            .bind(local_id.0)
+            .execute(&mut *conn)
+            .await?;
+
+        sqlx::query("update folders set parent_id = null where id = ? and parent_id in (select id from folders where trashed_at is not null)")
+            .bind(local_id.0)
+            .execute(&mut *conn)
+            .await
+            .map(|_| ())
+    }))
+    .await
+}
+
+/// Remote reports each folder of a trashed subtree on its own, so only the given folder is trashed
+pub async fn trash_folder_by_remote_id(conn: &mut PoolConnection<Sqlite>, remote_id: RemoteId, account_id: i32) -> Result<(), Error> {
+    let Some(folder) = fetch_folder_by_remote_id(conn, remote_id, account_id).await? else {
+        return Ok(());
+    };
+
+    conn.transaction(|conn| Box::pin(async move {
+        sqlx::query("update folders set trashed_at = coalesce(trashed_at, current_timestamp) where id = ?")
+            .bind(folder.id)
+            .execute(&mut *conn)
+            .await?;
+
+        sqlx::query("delete from operations where folder_id = ? or note_id in (select id from notes where folder_id = ?)")
+            .bind(folder.id)
+            .bind(folder.id)
+            .execute(&mut *conn)
+            .await
+            .map(|_| ())
+    }))
+    .await
+}
+
+pub async fn restore_folder_by_remote_id(conn: &mut PoolConnection<Sqlite>, remote_id: RemoteId, account_id: i32) -> Result<(), Error> {
+    sqlx::query("update folders set trashed_at = null where remote_id = ? and account_id = ? and trashed_at is not null")
+        .bind(remote_id.0)
+        .bind(account_id)
+        .execute(conn)
         .await
         .map(|_| ())
 }
@@ -221,9 +617,18 @@ pub async fn fetch_all_notes(conn: &mut PoolConnection<Sqlite>, folder_id: Local
 }
 
 pub async fn fetch_notes(conn: &mut PoolConnection<Sqlite>, folder_id: LocalId) -> Result<Vec<Note>, Error> {
-    sqlx::query_as("select * from notes where folder_id = ? and state != ? order by id")
+    sqlx::query_as("select * from notes where folder_id = ? and state not in (?, ?) order by id")
         .bind(folder_id.0)
         .bind(State::Deleted)
+        .bind(State::Trashed)
+        .fetch_all(conn)
+        .await
+}
+
+/// Notes of the trashed folders are restored alongside their folders, they are not listed on their own
+pub async fn fetch_trashed_notes(conn: &mut PoolConnection<Sqlite>) -> Result<Vec<Note>, Error> {
+    sqlx::query_as("select notes.* from notes inner join folders on folders.id = notes.folder_id where notes.state = ? and folders.trashed_at is null order by notes.id desc")
+        .bind(State::Trashed)
         .fetch_all(conn)
         .await
 }
@@ -243,32 +648,202 @@ pub async fn fetch_note_by_remote_id(conn: &mut PoolConnection<Sqlite>, note_id:
         .await
 }
 
+pub async fn fetch_account_note_by_remote_id(conn: &mut PoolConnection<Sqlite>, note_id: RemoteId, account_id: i32) -> Result<Option<Note>, Error> {
+    sqlx::query_as("select notes.* from notes inner join folders on folders.id = notes.folder_id where notes.remote_id = ? and folders.account_id = ?")
+        .bind(note_id.0)
+        .bind(account_id)
+        .fetch_optional(conn)
+        .await
+}
+
 pub async fn create_note(conn: &mut PoolConnection<Sqlite>, folder_id: LocalId, remote_id: Option<RemoteId>, name: String, text: String, commit: i32) -> Result<Note, Error> {
     conn.transaction(|conn| Box::pin(async move {
-        sqlx::query("insert into notes (folder_id, remote_id, name, text, 'commit', state) values(?, ?, ?, ?, ?, ?)")
+        let sealed_text = vault::seal(&text)?;
+
+        // Notes received from or created in remote are at their commit
+        sqlx::query("insert into notes (folder_id, remote_id, name, text, base, 'commit', state) values(?, ?, ?, ?, ?, ?, ?)")
             .bind(folder_id.0)
             .bind(remote_id.map(|id| id.0))
-            .bind(name.as_str())
-            .bind(text.as_str())
+            .bind(vault::seal(&name)?)
+            .bind(sealed_text.clone())
+            .bind(remote_id.map(|_| sealed_text))
             .bind(commit)
             .bind(State::Clean)
             .execute(&mut *conn)
             .await?;
 
-        sqlx::query_as("select * from notes order by id desc")
-            .fetch_optional(conn)
+        let note: Note = sqlx::query_as("select * from notes order by id desc")
+            .fetch_optional(&mut *conn)
             .await
-            .map(|opt| opt.unwrap())
+            .map(|opt| opt.unwrap())?;
+
+        if vault::searchable() {
+            sqlx::query("insert into note_search (rowid, name, text) values (?, ?, ?)")
+                .bind(note.id)
+                .bind(name.as_str())
+                .bind(text.as_str())
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        Ok(note)
     }))
      .await
 }
 
-pub async fn update_note(conn: &mut PoolConnection<Sqlite>, note_id: LocalId, name: &str, text: &str, commit: i32, state: State) -> Result<(), Error> {
-    sqlx::query("update notes set name=?, text=?, 'commit'=?, state=? where id=?")
-        .bind(name)
-        .bind(text)
+/// Copies the note into a folder of another account and moves the original into the given state at once, so that the
+/// note does not end up in both accounts. Operations creating the copy and deleting the original are queued alongside
+/// for the accounts whose (account id, idempotency key) pairs are given.
+pub async fn transfer_note(
+    conn: &mut PoolConnection<Sqlite>,
+    note: &Note,
+    folder_id: LocalId,
+    state: State,
+    create: Option<(i32, String)>,
+    delete: Option<(i32, String)>,
+) -> Result<Note, Error> {
+    let (sealed_name, sealed_text) = (vault::seal(&note.name)?, vault::seal(&note.text)?);
+    let search = vault::searchable().then(|| (note.name.clone(), note.text.clone()));
+    let note_id = note.id;
+    let trashed = state == State::Trashed;
+
+    conn.transaction(|conn| Box::pin(async move {
+        sqlx::query("insert into notes (folder_id, name, text, 'commit', state) values(?, ?, ?, 0, ?)")
+            .bind(folder_id.0)
+            .bind(sealed_name)
+            .bind(sealed_text)
+            .bind(State::Clean)
+            .execute(&mut *conn)
+            .await?;
+
+        let copy: Note = sqlx::query_as("select * from notes order by id desc")
+            .fetch_optional(&mut *conn)
+            .await
+            .map(|opt| opt.unwrap())?;
+
+        if let Some((name, text)) = search {
+            sqlx::query("insert into note_search (rowid, name, text) values (?, ?, ?)")
+                .bind(copy.id)
+                .bind(name)
+                .bind(text)
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        sqlx::query("update notes set state = ?, trashed_at = case when ? then current_timestamp end where id = ?")
+            .bind(state)
+            .bind(trashed)
+            .bind(note_id)
+            .execute(&mut *conn)
+            .await?;
+
+        for (kind, target_id, operation) in [(OperationKind::CreateNote, copy.id, create), (OperationKind::DeleteNote, note_id, delete)] {
+            let Some((account_id, idempotency_key)) = operation else {
+                continue;
+            };
+
+            sqlx::query("insert into operations (account_id, kind, note_id, idempotency_key) values (?, ?, ?, ?)")
+                .bind(account_id)
+                .bind(kind)
+                .bind(target_id)
+                .bind(idempotency_key)
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        Ok(copy)
+    }))
+    .await
+}
+
+/// Base is the text of the note at the given commit, it is kept as is if not given. Replaced text is kept as a revision.
+pub async fn update_note(conn: &mut PoolConnection<Sqlite>, note_id: LocalId, name: &str, text: &str, base: Option<&str>, commit: i32, state: State) -> Result<(), Error> {
+    let (sealed_name, sealed_text) = (vault::seal(name)?, vault::seal(text)?);
+    let sealed_base = base.map(vault::seal).transpose()?;
+    let search = vault::searchable().then(|| (name.to_owned(), text.to_owned()));
+    let retention = fetch_revision_retention(conn).await?;
+    let text_changed = fetch_note(conn, note_id).await?.is_some_and(|note| note.text != text);
+
+    conn.transaction(|conn| Box::pin(async move {
+        if text_changed {
+            // Name and text are copied as they are stored, so they are already sealed
+            sqlx::query("insert into note_revisions (note_id, name, text) select id, name, text from notes where id = ?")
+                .bind(note_id.0)
+                .execute(&mut *conn)
+                .await?;
+
+            prune_revisions(conn, Some(note_id), retention).await?;
+        }
+
+        sqlx::query("update notes set name=?, text=?, base=coalesce(?, base), 'commit'=?, state=? where id=?")
+            .bind(sealed_name)
+            .bind(sealed_text)
+            .bind(sealed_base)
+            .bind(commit)
+            .bind(state)
+            .bind(note_id.0)
+            .execute(&mut *conn)
+            .await?;
+
+        if let Some((name, text)) = search {
+            sqlx::query("update note_search set name = ?, text = ? where rowid = ?")
+                .bind(name)
+                .bind(text)
+                .bind(note_id.0)
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        Ok(())
+    }))
+     .await
+}
+
+/// Notes of the account that match the FTS5 query, best matches first. Matched terms in the snippet are wrapped with the
+/// given markers.
+pub async fn search_notes(conn: &mut PoolConnection<Sqlite>, query: &str, account_id: i32, markers: (&str, &str), limit: i32) -> Result<Vec<NoteMatch>, Error> {
+    sqlx::query_as(
+        "select notes.id, notes.folder_id, note_search.name, snippet(note_search, 1, ?, ?, '…', 16) as snippet from note_search
+        inner join notes on notes.id = note_search.rowid
+        inner join folders on folders.id = notes.folder_id
+        where note_search match ? and folders.account_id = ? and folders.trashed_at is null and notes.state not in (?, ?)
+        order by bm25(note_search, 2.0, 1.0) limit ?"
+    )
+        .bind(markers.0)
+        .bind(markers.1)
+        .bind(query)
+        .bind(account_id)
+        .bind(State::Deleted)
+        .bind(State::Trashed)
+        .bind(limit)
+        .fetch_all(conn)
+        .await
+}
+
+pub async fn fetch_account_notes(conn: &mut PoolConnection<Sqlite>, account_id: i32) -> Result<Vec<Note>, Error> {
+    sqlx::query_as("select notes.* from notes inner join folders on folders.id = notes.folder_id where folders.account_id = ? and folders.trashed_at is null and notes.state not in (?, ?) order by notes.id")
+        .bind(account_id)
+        .bind(State::Deleted)
+        .bind(State::Trashed)
+        .fetch_all(conn)
+        .await
+}
+
+pub async fn update_note_folder(conn: &mut PoolConnection<Sqlite>, note_id: LocalId, folder_id: LocalId) -> Result<(), Error> {
+    sqlx::query("update notes set folder_id = ? where id = ?")
+        .bind(folder_id.0)
+        .bind(note_id.0)
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
+/// Marks the current text as the one at the commit
+/// Note is created in remote with its current text, so the text becomes its base
+pub async fn update_note_remote_id(conn: &mut PoolConnection<Sqlite>, note_id: LocalId, remote_id: RemoteId, commit: i32) -> Result<(), Error> {
+    sqlx::query("update notes set remote_id = ?, 'commit' = ?, base = text where id = ?")
+        .bind(remote_id.0)
         .bind(commit)
-        .bind(state)
         .bind(note_id.0)
         .execute(conn)
         .await
@@ -276,7 +851,7 @@ pub async fn update_note(conn: &mut PoolConnection<Sqlite>, note_id: LocalId, na
 }
 
 pub async fn update_commit(conn: &mut PoolConnection<Sqlite>, note_id: LocalId, commit: i32) -> Result<(), Error> {
-    sqlx::query("update notes set 'commit' = ?, state = ? where id = ?")
+    sqlx::query("update notes set base = text, 'commit' = ?, state = ? where id = ?")
         .bind(commit)
         .bind(State::Clean)
         .bind(note_id.0)
@@ -285,6 +860,213 @@ pub async fn update_commit(conn: &mut PoolConnection<Sqlite>, note_id: LocalId,
         .map(|_| ())
 }
 
+pub async fn fetch_note_revisions(conn: &mut PoolConnection<Sqlite>, note_id: LocalId) -> Result<Vec<NoteRevision>, Error> {
+    sqlx::query_as("select * from note_revisions where note_id = ? order by id desc")
+        .bind(note_id.0)
+        .fetch_all(conn)
+        .await
+}
+
+pub async fn fetch_note_revision(conn: &mut PoolConnection<Sqlite>, revision_id: i32) -> Result<Option<NoteRevision>, Error> {
+    sqlx::query_as("select * from note_revisions where id = ?")
+        .bind(revision_id)
+        .fetch_optional(conn)
+        .await
+}
+
+pub async fn fetch_revision_retention(conn: &mut PoolConnection<Sqlite>) -> Result<RevisionRetention, Error> {
+    let Some(value) = fetch_value(conn, StoreKey::RevisionRetention).await? else {
+        return Ok(RevisionRetention::default());
+    };
+
+    serde_json::from_str(&value.value)
+        .map_err(|e| Error::Decode(Box::new(e)))
+}
+
+/// Stores the retention and applies it to the revisions of all notes
+pub async fn update_revision_retention(conn: &mut PoolConnection<Sqlite>, retention: RevisionRetention) -> Result<(), Error> {
+    let value = serde_json::to_string(&retention).unwrap();
+
+    conn.transaction(|conn| Box::pin(async move {
+        sqlx::query("insert into store (key, value) values (?, ?) on conflict (key) do update set value = excluded.value")
+            .bind(StoreKey::RevisionRetention)
+            .bind(value)
+            .execute(&mut *conn)
+            .await?;
+
+        prune_revisions(conn, None, retention).await
+    }))
+    .await
+}
+
+/// Deletes the revisions of the note, or of all notes if not given, which are out of the retention
+async fn prune_revisions(conn: &mut SqliteConnection, note_id: Option<LocalId>, retention: RevisionRetention) -> Result<(), Error> {
+    let query = match retention {
+        RevisionRetention::Count(count) => sqlx::query(
+            "delete from note_revisions where note_id = coalesce(?, note_id) and id in (
+                select id from (select id, row_number() over (partition by note_id order by id desc) as position from note_revisions)
+                where position > ?
+            )"
+        )
+            .bind(note_id.map(|id| id.0))
+            .bind(count),
+        RevisionRetention::Days(days) => sqlx::query("delete from note_revisions where note_id = coalesce(?, note_id) and created_at < datetime('now', ?)")
+            .bind(note_id.map(|id| id.0))
+            .bind(format!("-{days} days")),
+    };
+
+    query.execute(conn)
+        .await
+        .map(|_| ())
+}
+
+pub async fn fetch_trash_retention(conn: &mut PoolConnection<Sqlite>) -> Result<i32, Error> {
+    let Some(value) = fetch_value(conn, StoreKey::TrashRetention).await? else {
+        return Ok(TRASH_RETENTION_DAYS);
+    };
+
+    value.value.parse()
+        .map_err(|e| Error::Decode(Box::new(e)))
+}
+
+/// Stores the retention and purges the trash with it
+pub async fn update_trash_retention(conn: &mut PoolConnection<Sqlite>, days: i32) -> Result<(), Error> {
+    conn.transaction(|conn| Box::pin(async move {
+        sqlx::query("insert into store (key, value) values (?, ?) on conflict (key) do update set value = excluded.value")
+            .bind(StoreKey::TrashRetention)
+            .bind(days.to_string())
+            .execute(&mut *conn)
+            .await?;
+
+        purge_trash(conn, days).await
+    }))
+    .await
+}
+
+/// Deletes the notes and folders which stay in the trash longer than the given days. Notes of the deleted folders are
+/// deleted alongside them.
+pub async fn purge_trash(conn: &mut SqliteConnection, days: i32) -> Result<(), Error> {
+    let before = format!("-{days} days");
+
+    sqlx::query("delete from folders where trashed_at < datetime('now', ?)")
+        .bind(&before)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query("delete from notes where state = ? and trashed_at < datetime('now', ?)")
+        .bind(State::Trashed)
+        .bind(&before)
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
+pub async fn fetch_note_base(conn: &mut PoolConnection<Sqlite>, note_id: LocalId) -> Result<Option<String>, Error> {
+    let base = sqlx::query_as::<_, (Option<String>,)>("select base from notes where id = ?")
+        .bind(note_id.0)
+        .fetch_optional(conn)
+        .await?
+        .and_then(|(base,)| base);
+
+    base.map(vault::open)
+        .transpose()
+        .map_err(|e| e.into())
+}
+
+pub async fn create_note_merge(conn: &mut PoolConnection<Sqlite>, note_id: LocalId, base_commit: i32, remote_commit: i32, conflicts: usize) -> Result<(), Error> {
+    sqlx::query("insert into note_merges (note_id, base_commit, remote_commit, conflicts) values (?, ?, ?, ?)")
+        .bind(note_id.0)
+        .bind(base_commit)
+        .bind(remote_commit)
+        .bind(conflicts as i32)
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
+/// Conflicts whose notes are not deleted
+pub async fn fetch_note_conflicts(conn: &mut PoolConnection<Sqlite>) -> Result<Vec<NoteConflict>, Error> {
+    sqlx::query_as(
+        "select note_conflicts.* from note_conflicts
+        inner join notes as note on note.id = note_conflicts.note_id
+        inner join notes as copy on copy.id = note_conflicts.copy_id
+        where note.state not in (?, ?) and copy.state not in (?, ?)
+        order by note_conflicts.id"
+    )
+        .bind(State::Deleted)
+        .bind(State::Trashed)
+        .bind(State::Deleted)
+        .bind(State::Trashed)
+        .fetch_all(conn)
+        .await
+}
+
+pub async fn fetch_note_conflict(conn: &mut PoolConnection<Sqlite>, conflict_id: i32) -> Result<Option<NoteConflict>, Error> {
+    sqlx::query_as("select * from note_conflicts where id = ?")
+        .bind(conflict_id)
+        .fetch_optional(conn)
+        .await
+}
+
+pub async fn create_note_conflict(conn: &mut PoolConnection<Sqlite>, note_id: LocalId, copy_id: LocalId, remote_commit: i32) -> Result<(), Error> {
+    sqlx::query("insert into note_conflicts (note_id, copy_id, remote_commit) values (?, ?, ?)")
+        .bind(note_id.0)
+        .bind(copy_id.0)
+        .bind(remote_commit)
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
+pub async fn fetch_note_document(conn: &mut PoolConnection<Sqlite>, note_id: LocalId) -> Result<Option<NoteDocument>, Error> {
+    sqlx::query_as("select * from note_documents where note_id = ?")
+        .bind(note_id.0)
+        .fetch_optional(conn)
+        .await
+}
+
+pub async fn store_note_document(conn: &mut PoolConnection<Sqlite>, document: &NoteDocument) -> Result<(), Error> {
+    sqlx::query(
+        "insert into note_documents (note_id, actor, document, pushed_seq, update_id) values (?, ?, ?, ?, ?)
+        on conflict (note_id) do update set
+            actor = excluded.actor, document = excluded.document, pushed_seq = excluded.pushed_seq, update_id = excluded.update_id"
+    )
+        .bind(document.note_id)
+        .bind(&document.actor)
+        .bind(vault::seal(&document.document)?)
+        .bind(document.pushed_seq)
+        .bind(document.update_id)
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
+pub async fn update_note_document_pushed(conn: &mut PoolConnection<Sqlite>, note_id: LocalId, pushed_seq: i64, update_id: Option<i32>) -> Result<(), Error> {
+    sqlx::query("update note_documents set pushed_seq = ?, update_id = ? where note_id = ?")
+        .bind(pushed_seq)
+        .bind(update_id)
+        .bind(note_id.0)
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
+pub async fn delete_note_document(conn: &mut PoolConnection<Sqlite>, note_id: LocalId) -> Result<(), Error> {
+    sqlx::query("delete from note_documents where note_id = ?")
+        .bind(note_id.0)
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
+pub async fn delete_note_conflict(conn: &mut PoolConnection<Sqlite>, conflict_id: i32) -> Result<(), Error> {
+    sqlx::query("delete from note_conflicts where id = ?")
+        .bind(conflict_id)
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
 pub async fn delete_note(conn: &mut PoolConnection<Sqlite>, local_id: LocalId) -> Result<(), Error> {
     sqlx::query("delete from notes where id = ?")
         .bind(local_id.0)
@@ -293,9 +1075,12 @@ pub async fn delete_note(conn: &mut PoolConnection<Sqlite>, local_id: LocalId) -
         .map(|_| ())
 }
 
-pub async fn delete_note_local(conn: &mut PoolConnection<Sqlite>, local_id: LocalId) -> Result<(), Error> {
-    sqlx::query("update notes set state = ? where id = ?")
-        .bind(State::Deleted)
+pub async fn update_note_state(conn: &mut PoolConnection<Sqlite>, local_id: LocalId, state: State) -> Result<(), Error> {
+    let trashed = state == State::Trashed;
+
+    sqlx::query("update notes set state = ?, trashed_at = case when ? then coalesce(trashed_at, current_timestamp) end where id = ?")
+        .bind(state)
+        .bind(trashed)
         .bind(local_id.0)
         .execute(conn)
         .await
@@ -334,3 +1119,160 @@ pub async fn unique_nonces(conn: &mut PoolConnection<Sqlite>, device_ids: &[i32]
 
     Ok(nonce_ids)
 }
+
+/// Stores the pubkey of the rotated identity key, and marks the accounts whose servers are to be told about it
+pub async fn rotate_identity_key(conn: &mut PoolConnection<Sqlite>, values: Vec<(StoreKey, String)>, account_ids: Vec<i32>) -> Result<(), Error> {
+    conn.transaction(|conn| Box::pin(async move {
+        for (key, value) in values {
+            sqlx::query("insert into store (key, value) values (?, ?) on conflict (key) do update set value = excluded.value")
+                .bind(key)
+                .bind(value)
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        for account_id in account_ids {
+            sqlx::query("insert into pending_rotations (account_id) values (?) on conflict (account_id) do nothing")
+                .bind(account_id)
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        Ok(())
+    }))
+     .await
+}
+
+/// Replaces the pubkey of this device with the one restored from a recovery bundle, the rotation of the replaced key is
+/// forgotten
+pub async fn replace_identity(conn: &mut PoolConnection<Sqlite>, pubkey: String) -> Result<(), Error> {
+    conn.transaction(|conn| Box::pin(async move {
+        sqlx::query("insert into store (key, value) values (?, ?) on conflict (key) do update set value = excluded.value")
+            .bind(StoreKey::IdentityPubKey)
+            .bind(pubkey)
+            .execute(&mut *conn)
+            .await?;
+
+        sqlx::query("delete from store where key = ?")
+            .bind(StoreKey::IdentityRotatedAt)
+            .execute(&mut *conn)
+            .await?;
+
+        Ok(())
+    }))
+     .await
+}
+
+pub async fn fetch_pending_rotations(conn: &mut PoolConnection<Sqlite>) -> Result<Vec<i32>, Error> {
+    sqlx::query_as::<_, (i32,)>("select account_id from pending_rotations")
+        .fetch_all(conn)
+        .await
+        .map(|rows| rows.into_iter().map(|row| row.0).collect())
+}
+
+pub async fn pending_rotation_exists(conn: &mut PoolConnection<Sqlite>, account_id: i32) -> Result<bool, Error> {
+    sqlx::query_as::<_, (i32,)>("select account_id from pending_rotations where account_id = ?")
+        .bind(account_id)
+        .fetch_optional(conn)
+        .await
+        .map(|opt| opt.is_some())
+}
+
+pub async fn delete_pending_rotation(conn: &mut PoolConnection<Sqlite>, account_id: i32) -> Result<(), Error> {
+    sqlx::query("delete from pending_rotations where account_id = ?")
+        .bind(account_id)
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
+pub async fn fetch_operations(conn: &mut PoolConnection<Sqlite>, account_id: i32) -> Result<Vec<Operation>, Error> {
+    sqlx::query_as("select * from operations where account_id = ? and not failed order by id")
+        .bind(account_id)
+        .fetch_all(conn)
+        .await
+}
+
+pub async fn operations_exist(conn: &mut PoolConnection<Sqlite>, account_id: i32) -> Result<bool, Error> {
+    sqlx::query_as::<_, (i32,)>("select id from operations where account_id = ? and not failed limit 1")
+        .bind(account_id)
+        .fetch_optional(conn)
+        .await
+        .map(|opt| opt.is_some())
+}
+
+pub async fn failed_operations_exist(conn: &mut PoolConnection<Sqlite>, account_id: i32) -> Result<bool, Error> {
+    sqlx::query_as::<_, (i32,)>("select id from operations where account_id = ? and failed limit 1")
+        .bind(account_id)
+        .fetch_optional(conn)
+        .await
+        .map(|opt| opt.is_some())
+}
+
+pub async fn fetch_failed_operations(conn: &mut PoolConnection<Sqlite>) -> Result<Vec<Operation>, Error> {
+    sqlx::query_as("select * from operations where failed order by id")
+        .fetch_all(conn)
+        .await
+}
+
+pub async fn fetch_failed_operation(conn: &mut PoolConnection<Sqlite>, operation_id: i32) -> Result<Option<Operation>, Error> {
+    sqlx::query_as("select * from operations where id = ? and failed")
+        .bind(operation_id)
+        .fetch_optional(conn)
+        .await
+}
+
+pub async fn note_operation_exists(conn: &mut PoolConnection<Sqlite>, note_id: LocalId, kind: OperationKind) -> Result<bool, Error> {
+    sqlx::query_as::<_, (i32,)>("select id from operations where note_id = ? and kind = ? and not failed limit 1")
+        .bind(note_id.0)
+        .bind(kind)
+        .fetch_optional(conn)
+        .await
+        .map(|opt| opt.is_some())
+}
+
+pub async fn create_operation(conn: &mut PoolConnection<Sqlite>, account_id: i32, kind: OperationKind, target_id: LocalId, idempotency_key: &str) -> Result<(), Error> {
+    let (folder_id, note_id) = match kind.targets_note() {
+        true => (None, Some(target_id.0)),
+        false => (Some(target_id.0), None),
+    };
+
+    sqlx::query("insert into operations (account_id, kind, folder_id, note_id, idempotency_key) values (?, ?, ?, ?, ?)")
+        .bind(account_id)
+        .bind(kind)
+        .bind(folder_id)
+        .bind(note_id)
+        .bind(idempotency_key)
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
+/// Records the failure of the operation, it is not replayed again before the given time
+pub async fn fail_operation(conn: &mut PoolConnection<Sqlite>, operation_id: i32, error: &str, retry_at: i64) -> Result<(), Error> {
+    sqlx::query("update operations set attempts = attempts + 1, last_error = ?, retry_at = ? where id = ?")
+        .bind(error)
+        .bind(retry_at)
+        .bind(operation_id)
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
+/// Records the failure of the operation which remote refuses for good, it is not replayed again
+pub async fn fail_operation_permanently(conn: &mut PoolConnection<Sqlite>, operation_id: i32, error: &str) -> Result<(), Error> {
+    sqlx::query("update operations set attempts = attempts + 1, last_error = ?, failed = true where id = ?")
+        .bind(error)
+        .bind(operation_id)
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
+pub async fn delete_operation(conn: &mut PoolConnection<Sqlite>, operation_id: i32) -> Result<(), Error> {
+    sqlx::query("delete from operations where id = ?")
+        .bind(operation_id)
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
@@ -0,0 +1,135 @@
+use std::{collections::{BTreeMap, HashSet}, sync::Arc};
+
+use sqlx::{Pool, Sqlite, pool::PoolConnection};
+
+use crate::{
+    Error,
+    accounts::mavinote::{MavinoteClient, Error as MavinoteError, responses::{Directory, FolderDevice}},
+    crypto::{self, Error as CryptoError},
+    models::{DirectoryDevice, DirectoryRecord},
+};
+use super::{db, login, mavinote_client, NOT_MAVINOTE_ACCOUNT};
+
+/// Looks up the devices of the user with given email from the directory of the server. The returned devices are
+/// cached only if their key log extends the record that is seen last. Cached devices are returned when the server
+/// cannot be reached.
+pub async fn directory_devices(account_id: i32, email: String) -> Result<Vec<DirectoryDevice>, Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    let client = mavinote_client(&mut conn, account_id).await?
+        .ok_or(NOT_MAVINOTE_ACCOUNT)?;
+
+    match lookup(&mut conn, &client, account_id, &email).await {
+        Ok(()) | Err(Error::Mavinote(MavinoteError::NoConnection)) => {},
+        Err(e) => return Err(e),
+    }
+
+    db::fetch_directory_devices(&mut conn, account_id, &email).await
+        .map_err(|e| e.into())
+}
+
+/// Returns the ids of the devices that the verified directory lists with the same key. Directories of the users are
+/// looked up again if one of their devices is not cached yet, the devices which still cannot be verified are left out.
+pub(crate) async fn listed_devices(conn: &mut PoolConnection<Sqlite>, client: &MavinoteClient, account_id: i32, devices: &[&FolderDevice]) -> Result<HashSet<i32>, Error> {
+    let emails = devices.iter().map(|device| device.email.as_str()).collect::<HashSet<_>>();
+    let mut listed = HashSet::new();
+
+    for email in emails {
+        let user_devices = devices.iter().filter(|device| device.email == email).collect::<Vec<_>>();
+        let mut cached = db::fetch_directory_devices(conn, account_id, email).await?;
+
+        if user_devices.iter().any(|device| !is_listed(&cached, device)) {
+            match lookup(conn, client, account_id, email).await {
+                Ok(()) => cached = db::fetch_directory_devices(conn, account_id, email).await?,
+                Err(e) => log::warn!("failed to look up the directory of a folder member, {e:?}"),
+            }
+        }
+
+        listed.extend(user_devices.into_iter().filter(|device| is_listed(&cached, device)).map(|device| device.id));
+    }
+
+    Ok(listed)
+}
+
+fn is_listed(cached: &[DirectoryDevice], device: &FolderDevice) -> bool {
+    cached.iter().any(|cached| cached.id == device.id && cached.pubkey == device.pubkey)
+}
+
+/// Fetches the directory of the user and caches it once it is verified
+async fn lookup(conn: &mut PoolConnection<Sqlite>, client: &MavinoteClient, account_id: i32, email: &str) -> Result<(), Error> {
+    let directory = client.clone().login_on_unauthorized(&|client| async move { client.fetch_directory(email).await }, &login).await?;
+
+    let seen = db::fetch_directory_record(conn, account_id, email).await?;
+    let signer = db::fetch_directory_signer(conn, account_id).await?;
+
+    verify(&directory, email, seen.as_ref(), signer.as_deref())?;
+
+    db::store_directory(conn, account_id, email.to_owned(), directory.record, directory.devices).await
+        .map_err(|e| e.into())
+}
+
+/// Opts the account in or out of the directory. Users that are not listed cannot be looked up by others.
+pub async fn update_directory(account_id: i32, listed: bool) -> Result<(), Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    mavinote_client(&mut conn, account_id).await?
+        .ok_or(NOT_MAVINOTE_ACCOUNT)?
+        .login_on_unauthorized(&|client| async move { client.update_directory(listed).await }, &login)
+        .await
+        .map_err(|e| e.into())
+}
+
+fn verify(directory: &Directory, email: &str, seen: Option<&DirectoryRecord>, signer: Option<&str>) -> Result<(), CryptoError> {
+    let record = &directory.record;
+
+    // Records of all the users are signed with the same key, which is pinned on the first lookup
+    if signer.is_some_and(|signer| signer != record.signer) {
+        return Err(CryptoError::DirectoryMismatch);
+    }
+
+    crypto::verify_record(&record.signer, &format!("{}:{}:{}", email, record.seq, record.head), &record.signature)?;
+
+    let mut head = String::new();
+    let mut devices = BTreeMap::new();
+
+    for entry in &directory.log {
+        head = crypto::key_log_hash(&head, entry.device_id, &entry.pubkey, entry.added);
+
+        if head != entry.hash {
+            return Err(CryptoError::DirectoryMismatch);
+        }
+
+        if entry.added {
+            devices.insert(entry.device_id, entry.pubkey.as_str());
+        } else {
+            devices.remove(&entry.device_id);
+        }
+    }
+
+    if head != record.head || directory.log.len() != record.seq as usize {
+        return Err(CryptoError::DirectoryMismatch);
+    }
+
+    // Swapping a key without rewriting the seen part of the log is only possible by appending to it, which is visible
+    // in the log. Rewriting it changes the hash of the entry that is seen last.
+    if let Some(seen) = seen {
+        let extends = seen.seq == 0 || directory.log
+            .get(seen.seq as usize - 1)
+            .is_some_and(|entry| entry.hash == seen.head);
+
+        if !extends {
+            return Err(CryptoError::DirectoryMismatch);
+        }
+    }
+
+    let listed: BTreeMap<i32, &str> = directory.devices
+        .iter()
+        .map(|device| (device.id, device.pubkey.as_str()))
+        .collect();
+
+    if listed != devices {
+        return Err(CryptoError::DirectoryMismatch);
+    }
+
+    Ok(())
+}
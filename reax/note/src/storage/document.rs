@@ -0,0 +1,327 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use automerge::{ActorId, AutoCommit, AutomergeError, ObjId, ObjType, ReadDoc, ROOT, transaction::Transactable};
+use base64ct::{Base64, Encoding};
+use sqlx::{Pool, Sqlite, pool::PoolConnection};
+
+use crate::Error;
+use crate::accounts::mavinote::{MavinoteClient, Error as MavinoteError};
+use crate::crypto::FileCipher;
+use crate::models::{LocalId, Note, RemoteId, Role, State};
+use super::db::{self, NoteDocument};
+use super::{note_name, update_send_notes, FOLDER_READ_ONLY, NOTE_NOT_FOUND, NOTE_NOT_SYNCED};
+
+const INVALID_DOCUMENT: Error = Error::Unreachable("InvalidDocument");
+/// Key of the text object in the root of documents
+const TEXT: &str = "text";
+/// Number of updates in remote after which the device sending the last one replaces them with a snapshot
+const SNAPSHOT_AFTER: i64 = 50;
+
+/// Text of a note kept as an automerge document. Each device records its edits as the changes of its own actor,
+/// and changes are merged in any order, so the texts of devices converge without conflicts.
+struct Document {
+    note_id: i32,
+    doc: AutoCommit,
+    pushed_seq: u64,
+    update_id: Option<i32>,
+}
+
+impl Document {
+    fn create(note_id: i32, text: &str) -> Result<Self, Error> {
+        let mut doc = AutoCommit::new();
+        let obj = doc.put_object(ROOT, TEXT, ObjType::Text).map_err(invalid)?;
+        doc.update_text(&obj, text).map_err(invalid)?;
+
+        Ok(Document { note_id, doc, pushed_seq: 0, update_id: None })
+    }
+
+    fn empty(note_id: i32) -> Self {
+        Document { note_id, doc: AutoCommit::new(), pushed_seq: 0, update_id: None }
+    }
+
+    fn load(row: NoteDocument) -> Result<Self, Error> {
+        let bytes = Base64::decode_vec(&row.document).map_err(|_| INVALID_DOCUMENT)?;
+        let actor = row.actor.parse::<ActorId>().map_err(|_| INVALID_DOCUMENT)?;
+
+        Ok(Document {
+            note_id: row.note_id,
+            doc: AutoCommit::load(&bytes).map_err(invalid)?.with_actor(actor),
+            pushed_seq: row.pushed_seq as u64,
+            update_id: row.update_id,
+        })
+    }
+
+    fn row(&mut self) -> NoteDocument {
+        NoteDocument {
+            note_id: self.note_id,
+            actor: self.doc.get_actor().to_string(),
+            document: Base64::encode_string(&self.doc.save()),
+            pushed_seq: self.pushed_seq as i64,
+            update_id: self.update_id,
+        }
+    }
+
+    /// Text object is created by the device which converts the note, it is missing until its change is received
+    fn text_obj(&self) -> Result<Option<ObjId>, Error> {
+        self.doc.get(ROOT, TEXT)
+            .map(|value| value.map(|(_, obj)| obj))
+            .map_err(invalid)
+    }
+
+    fn text(&self) -> Result<String, Error> {
+        let obj = self.text_obj()?.ok_or(INVALID_DOCUMENT)?;
+
+        self.doc.text(&obj).map_err(invalid)
+    }
+
+    fn edit(&mut self, text: &str) -> Result<(), Error> {
+        let obj = self.text_obj()?.ok_or(INVALID_DOCUMENT)?;
+
+        self.doc.update_text(&obj, text).map_err(invalid)
+    }
+
+    /// Changes of this device which are not sent to remote yet, alongside the sequence number of the last one
+    fn pending(&mut self) -> (Vec<u8>, u64) {
+        let actor = self.doc.get_actor().clone();
+        let mut data = Vec::new();
+        let mut seq = self.pushed_seq;
+
+        for change in self.doc.get_changes(&[]) {
+            if change.actor_id() == &actor && change.seq() > self.pushed_seq {
+                data.extend_from_slice(change.raw_bytes());
+                seq = seq.max(change.seq());
+            }
+        }
+
+        (data, seq)
+    }
+}
+
+fn invalid(e: AutomergeError) -> Error {
+    log::error!("failed to read or write a note document, {e:?}");
+
+    INVALID_DOCUMENT
+}
+
+/// Converts the note into a document. Current text of the note becomes the first change of the document, other devices
+/// build the document from the changes in remote and apply their pending texts on it.
+pub async fn convert_note(note_id: i32) -> Result<(), Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    let note = db::fetch_note(&mut conn, LocalId(note_id)).await?
+        .ok_or(NOTE_NOT_FOUND)?;
+
+    if note.remote_id().is_none() {
+        return Err(NOTE_NOT_SYNCED);
+    }
+
+    if db::fetch_note_document(&mut conn, note.local_id()).await?.is_some() {
+        return Ok(());
+    }
+
+    let folder = db::fetch_folder(&mut conn, LocalId(note.folder_id)).await?.unwrap();
+
+    if folder.role == Some(Role::Read) {
+        return Err(FOLDER_READ_ONLY);
+    }
+
+    let row = Document::create(note.id, &note.text)?.row();
+    db::store_note_document(&mut conn, &row).await?;
+
+    // Document is sent by the sync if remote cannot be reached now
+    let mut state = State::Modified;
+
+    if let (Some(client), Some(key)) = (super::mavinote_client(&mut conn, folder.account_id).await?, db::fetch_folder_key(&mut conn, folder.local_id()).await?) {
+        let folder_cipher = FileCipher::try_from_key(&key)?;
+
+        match push(&mut conn, &client, folder.account_id, &folder_cipher, &note, row, &note.text).await {
+            Ok(true) => state = State::Clean,
+            Ok(false) => {},
+            Err(e) => log::debug!("failed to send the document of note with id {note_id}, {e:?}"),
+        }
+    }
+
+    db::update_note_state(&mut conn, note.local_id(), state).await?;
+
+    update_send_notes(&mut conn, LocalId(note.folder_id)).await;
+
+    Ok(())
+}
+
+/// Whether the text of the note is kept as a document
+pub async fn note_converted(note_id: i32) -> Result<bool, Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    Ok(db::fetch_note_document(&mut conn, LocalId(note_id)).await?.is_some())
+}
+
+/// Records the new text of the note as a change of this device
+pub(crate) async fn edit(conn: &mut PoolConnection<Sqlite>, row: NoteDocument, text: &str) -> Result<NoteDocument, Error> {
+    let mut document = Document::load(row)?;
+    document.edit(text)?;
+
+    let row = document.row();
+    db::store_note_document(conn, &row).await?;
+
+    Ok(row)
+}
+
+/// Records the new text of the note as a change of this device, and sends it to remote right away if possible.
+/// Returns the state of the note after the change.
+pub(crate) async fn update(conn: &mut PoolConnection<Sqlite>, client: &MavinoteClient, account_id: i32, folder_cipher: &FileCipher, note: &Note, row: NoteDocument, text: &str) -> Result<State, Error> {
+    let row = edit(conn, row, text).await?;
+
+    match push(conn, client, account_id, folder_cipher, note, row, text).await {
+        Ok(true) => Ok(State::Clean),
+        Ok(false) => Ok(State::Modified),
+        Err(e) => {
+            log::debug!("failed to send the document of note with id {}, {e:?}", note.id);
+
+            Ok(State::Modified)
+        }
+    }
+}
+
+/// Sends the changes of this device to remote, returns whether all of them are sent. If another device has converted
+/// the note first, the document is built from remote again and the text is applied on it, to be sent on next sync.
+pub(crate) async fn push(conn: &mut PoolConnection<Sqlite>, client: &MavinoteClient, account_id: i32, folder_cipher: &FileCipher, note: &Note, row: NoteDocument, text: &str) -> Result<bool, Error> {
+    let Some(remote_id) = note.remote_id() else {
+        return Err(Error::Unreachable("Document of a note without a remote id cannot exist"));
+    };
+
+    let mut document = Document::load(row)?;
+    let (changes, seq) = document.pending();
+
+    if changes.is_empty() {
+        return Ok(true);
+    }
+
+    let data = Base64::encode_string(&folder_cipher.encrypt(&changes)?);
+
+    match client.create_note_update(remote_id, &data, document.update_id, false).await {
+        Ok(created) => {
+            // Update which converts the note is the first one, otherwise updates of other devices may precede ours
+            // and they are still merged by the next pull
+            let update_id = document.update_id.or(Some(created.id));
+
+            db::update_note_document_pushed(conn, note.local_id(), seq as i64, update_id).await?;
+
+            if created.updates >= SNAPSHOT_AFTER {
+                if let Err(e) = snapshot(conn, client, folder_cipher, note, &mut document, seq, created.id).await {
+                    log::debug!("failed to send the snapshot of note with id {}, {e:?}", note.id);
+                }
+            }
+
+            Ok(true)
+        },
+        // Merged update is replaced by a snapshot, changes are sent again after the sync pulls the snapshot
+        Err(MavinoteError::Message(msg)) if msg == "unknown_previous_update" => Ok(false),
+        Err(MavinoteError::Message(msg)) if msg == "note_already_converted" => {
+            db::delete_note_document(conn, note.local_id()).await?;
+
+            pull(conn, client, account_id, note, None, Some(text)).await?;
+
+            Ok(false)
+        },
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Replaces the updates in remote with the whole document. Snapshot is only sent if no other device has sent an update
+/// since the document was last merged, otherwise it would drop their changes.
+async fn snapshot(conn: &mut PoolConnection<Sqlite>, client: &MavinoteClient, folder_cipher: &FileCipher, note: &Note, document: &mut Document, seq: u64, pushed_id: i32) -> Result<(), Error> {
+    let Some(remote_id) = note.remote_id() else {
+        return Ok(());
+    };
+
+    let updates = client.fetch_note_updates(remote_id, document.update_id.unwrap_or(0)).await?;
+
+    if updates.iter().any(|update| update.id != pushed_id) {
+        return Ok(());
+    }
+
+    let data = Base64::encode_string(&folder_cipher.encrypt(&document.doc.save())?);
+    let created = client.create_note_update(remote_id, &data, Some(pushed_id), true).await?;
+
+    db::update_note_document_pushed(conn, note.local_id(), seq as i64, Some(created.id)).await
+        .map_err(|e| e.into())
+}
+
+/// Merges the updates in remote into the document of the note, and replaces the text of the note with the merged one.
+/// Document is created if the note is converted by another device, then the given local text is applied on it as a
+/// change of this device.
+pub(crate) async fn pull(conn: &mut PoolConnection<Sqlite>, client: &MavinoteClient, account_id: i32, note: &Note, latest: Option<i32>, local_text: Option<&str>) -> Result<(), Error> {
+    let Some(remote_id) = note.remote_id() else {
+        return Ok(());
+    };
+
+    let (mut document, created) = match db::fetch_note_document(conn, note.local_id()).await? {
+        Some(row) => (Document::load(row)?, false),
+        None => (Document::empty(note.id), true),
+    };
+
+    if let (Some(latest), Some(update_id)) = (latest, document.update_id) {
+        if update_id >= latest {
+            return Ok(());
+        }
+    }
+
+    // Updates are encrypted with the key of the folder that the note was in
+    let mut ciphers = HashMap::new();
+
+    // Updates are fetched page by page until there is none left
+    'pages: loop {
+        let updates = client.fetch_note_updates(remote_id, document.update_id.unwrap_or(0)).await?;
+
+        if updates.is_empty() {
+            break;
+        }
+
+        for folder_id in updates.iter().map(|update| update.folder_id).collect::<HashSet<i32>>() {
+            if ciphers.contains_key(&folder_id) {
+                continue;
+            }
+
+            let key = match db::fetch_folder_by_remote_id(conn, RemoteId(folder_id), account_id).await? {
+                Some(folder) => db::fetch_folder_key(conn, folder.local_id()).await?,
+                None => None,
+            };
+
+            ciphers.insert(folder_id, key.map(|key| FileCipher::try_from_key(&key)).transpose()?);
+        }
+
+        for update in updates {
+            let Some(cipher) = &ciphers[&update.folder_id] else {
+                log::debug!("An update of note is received before the key of its folder");
+                break 'pages;
+            };
+
+            let data = Base64::decode_vec(&update.data).map_err(|_| INVALID_DOCUMENT)?;
+
+            document.doc.load_incremental(&cipher.decrypt(&data)?).map_err(invalid)?;
+            document.update_id = Some(update.id);
+        }
+    }
+
+    if document.text_obj()?.is_none() {
+        return Ok(());
+    }
+
+    let (text, state) = match local_text.filter(|_| created) {
+        Some(local_text) => {
+            document.edit(local_text)?;
+
+            (local_text.to_owned(), State::Modified)
+        },
+        None => (document.text()?, note.state.clone()),
+    };
+
+    db::store_note_document(conn, &document.row()).await?;
+
+    if text != note.text || state != note.state {
+        db::update_note(conn, note.local_id(), &note_name(&text), &text, None, note.commit, state).await?;
+    }
+
+    Ok(())
+}
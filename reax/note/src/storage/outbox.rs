@@ -0,0 +1,155 @@
+use std::sync::Arc;
+
+use base::State;
+use once_cell::sync::OnceCell;
+use rand::{Rng, thread_rng, distributions::Alphanumeric};
+use sqlx::{Pool, Sqlite, pool::PoolConnection};
+use tokio::sync::watch::Sender;
+
+use crate::Error;
+use crate::accounts::mavinote::MavinoteClient;
+use crate::models::{FailedOperation, LocalId, OperationKind, State as ModelState};
+use super::{db, update_send_folders, update_send_notes};
+
+const OPERATION_NOT_FOUND: Error = Error::Unreachable("OperationNotFound");
+
+pub(crate) static FAILED_OPERATIONS: OnceCell<Sender<State<Vec<FailedOperation>, Error>>> = OnceCell::new();
+
+/// Client to send a change to remote right away. There is none for the local accounts, nor while the earlier changes of
+/// the account wait in the outbox, then the change is queued after them instead of overtaking them.
+pub(crate) async fn client(conn: &mut PoolConnection<Sqlite>, account_id: i32) -> Result<Option<MavinoteClient>, Error> {
+    if db::operations_exist(conn, account_id).await? {
+        return Ok(None);
+    }
+
+    super::mavinote_client(conn, account_id).await
+}
+
+/// Queues the change to be replayed by the sync, returns whether it is queued. Local accounts have nothing to send.
+pub(crate) async fn enqueue(conn: &mut PoolConnection<Sqlite>, account_id: i32, kind: OperationKind, target_id: LocalId) -> Result<bool, Error> {
+    let Some(idempotency_key) = key(conn, account_id).await? else {
+        return Ok(false);
+    };
+
+    db::create_operation(conn, account_id, kind, target_id, &idempotency_key).await?;
+
+    Ok(true)
+}
+
+/// Idempotency key of a new operation of the account, there is none for the local accounts
+pub(crate) async fn key(conn: &mut PoolConnection<Sqlite>, account_id: i32) -> Result<Option<String>, Error> {
+    if super::mavinote_client(conn, account_id).await?.is_none() {
+        return Ok(None);
+    }
+
+    Ok(Some((0..32).map(|_| thread_rng().sample(Alphanumeric) as char).collect()))
+}
+
+/// Operations which remote refuses for good, they are neither retried nor discarded yet
+pub async fn failed_operations() -> tokio::sync::watch::Receiver<State<Vec<FailedOperation>, Error>> {
+    let sender = FAILED_OPERATIONS.get().unwrap();
+    let load = matches!(*sender.borrow(), State::Initial | State::Err(_));
+
+    if load {
+        sender.send_replace(State::Loading);
+
+        let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await.unwrap();
+
+        sender.send_replace(fetch_failed_operations(&mut conn).await.into());
+    }
+
+    sender.subscribe()
+}
+
+pub(crate) async fn update_send_failed_operations(conn: &mut PoolConnection<Sqlite>) {
+    let sender = FAILED_OPERATIONS.get().unwrap();
+    // If nobody loaded the failed operations, then do not load the failed operations
+    let load = !matches!(*sender.borrow(), State::Initial);
+
+    if load {
+        sender.send_replace(State::Loading);
+
+        sender.send_replace(fetch_failed_operations(conn).await.into());
+    }
+}
+
+async fn fetch_failed_operations(conn: &mut PoolConnection<Sqlite>) -> Result<Vec<FailedOperation>, Error> {
+    let mut failed_operations = Vec::new();
+
+    for operation in db::fetch_failed_operations(conn).await? {
+        let name = match (operation.folder_id, operation.note_id) {
+            (_, Some(note_id)) => db::fetch_note(conn, LocalId(note_id)).await?.map(|note| note.name),
+            (Some(folder_id), None) => db::fetch_folder(conn, LocalId(folder_id)).await?.map(|folder| folder.name),
+            (None, None) => None,
+        };
+
+        failed_operations.push(FailedOperation {
+            id: operation.id,
+            account_id: operation.account_id,
+            kind: operation.kind,
+            name: name.unwrap_or_default(),
+            error: operation.last_error.unwrap_or_default(),
+        });
+    }
+
+    Ok(failed_operations)
+}
+
+/// Queues the failed operation again after the ones waiting in the outbox, with a new idempotency key
+pub async fn retry_failed_operation(operation_id: i32) -> Result<(), Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    let operation = db::fetch_failed_operation(&mut conn, operation_id).await?
+        .ok_or(OPERATION_NOT_FOUND)?;
+
+    let target_id = operation.note_id.or(operation.folder_id).map(LocalId).ok_or(OPERATION_NOT_FOUND)?;
+
+    db::delete_operation(&mut conn, operation.id).await?;
+    enqueue(&mut conn, operation.account_id, operation.kind, target_id).await?;
+
+    update_send_failed_operations(&mut conn).await;
+
+    Ok(())
+}
+
+/// Drops the failed operation. Its target is brought back to the remote state if it exists in remote, so that the change
+/// is not sent again. Notes which cannot be pulled right now and the converted ones stay modified, their changes are
+/// sent by the local sync. Targets which are not created in remote yet are left to the local sync.
+pub async fn discard_failed_operation(operation_id: i32) -> Result<(), Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    let operation = db::fetch_failed_operation(&mut conn, operation_id).await?
+        .ok_or(OPERATION_NOT_FOUND)?;
+
+    if let Some(note_id) = operation.note_id {
+        if let Some(note) = db::fetch_note(&mut conn, LocalId(note_id)).await? {
+            let pulled = match (&note.remote_id, &note.state) {
+                (Some(_), ModelState::Modified) if db::fetch_note_document(&mut conn, note.local_id()).await?.is_none() => {
+                    super::sync::pull_note(&mut conn, operation.account_id, &note).await?
+                },
+                (Some(_), ModelState::Deleted) => {
+                    db::update_note_state(&mut conn, note.local_id(), ModelState::Clean).await?;
+                    true
+                },
+                _ => false,
+            };
+
+            if pulled {
+                update_send_notes(&mut conn, LocalId(note.folder_id)).await;
+            }
+        }
+    } else if let Some(folder_id) = operation.folder_id {
+        if let Some(folder) = db::fetch_folder(&mut conn, LocalId(folder_id)).await? {
+            if folder.remote_id.is_some() && matches!(folder.state, ModelState::Modified | ModelState::Deleted) {
+                db::update_folder_state(&mut conn, folder.local_id(), ModelState::Clean).await?;
+                update_send_folders(&mut conn).await;
+            }
+        }
+    }
+
+    db::delete_operation(&mut conn, operation.id).await?;
+
+    update_send_failed_operations(&mut conn).await;
+
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use std::sync::Arc;
+
+use base::Config;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite, types::Json};
+
+use crate::{Error, StorageError, crypto::{self, KdfParams, Sealed}};
+use crate::accounts::mavinote::{AuthClient, StoreRecoveryBundle};
+use crate::models::{AccountKind, Mavinote, StoreKey};
+use super::{db, login, mavinote_client, secrets, update_send_accounts, NOT_MAVINOTE_ACCOUNT};
+
+/// Identity of this device would be replaced by the recovered one, which the other accounts do not know
+const ACCOUNTS_EXIST: Error = Error::Unreachable("AccountsExist");
+
+/// Secrets that let a new device act as the device which created the bundle
+#[derive(Serialize, Deserialize)]
+struct Secrets {
+    privkey: String,
+    password: String,
+}
+
+/// Encrypts the identity key and the password of this device with the passphrase and stores them on the server of the
+/// account. The bundle has to be created again after the identity key is rotated.
+pub async fn create_recovery_bundle(account_id: i32, passphrase: String) -> Result<(), Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    let device_secrets = Secrets {
+        privkey: secrets::get(&mut conn, StoreKey::IdentityPrivKey).await?.unwrap(),
+        password: secrets::get(&mut conn, StoreKey::Password).await?.unwrap(),
+    };
+
+    let sealed = crypto::seal_with_passphrase(&passphrase, serde_json::to_string(&device_secrets).unwrap().as_bytes())?;
+    let request = &StoreRecoveryBundle {
+        salt: &sealed.salt,
+        algorithm: &sealed.kdf.algorithm,
+        memory_cost: sealed.kdf.memory_cost,
+        time_cost: sealed.kdf.time_cost,
+        parallelism: sealed.kdf.parallelism,
+        nonce: &sealed.nonce,
+        ciphertext: &sealed.ciphertext,
+    };
+
+    mavinote_client(&mut conn, account_id).await?
+        .ok_or(NOT_MAVINOTE_ACCOUNT)?
+        .login_on_unauthorized(&|client| async move { client.store_recovery_bundle(request).await }, &login)
+        .await
+        .map_err(|e| e.into())
+}
+
+pub async fn delete_recovery_bundle(account_id: i32) -> Result<(), Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    mavinote_client(&mut conn, account_id).await?
+        .ok_or(NOT_MAVINOTE_ACCOUNT)?
+        .login_on_unauthorized(&|client| async move { client.delete_recovery_bundle().await }, &login)
+        .await
+        .map_err(|e| e.into())
+}
+
+pub async fn send_recovery_code(email: String) -> Result<(), Error> {
+    let config = runtime::get::<Arc<Config>>().unwrap();
+
+    AuthClient::new(config.api_url.clone())
+        .send_recovery_code(&email)
+        .await
+        .map_err(|e| e.into())
+}
+
+/// Restores the identity of the device which created the recovery bundle and adds the account. Only a device without
+/// any Mavinote account can be recovered since its identity is replaced.
+pub async fn recover_account(email: String, code: String, passphrase: String) -> Result<(), Error> {
+    let config = runtime::get::<Arc<Config>>().unwrap();
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    if db::account_with_email_exists(&mut conn, &email).await? {
+        return Err(Error::Storage(StorageError::EmailAlreadyExists));
+    }
+
+    if db::fetch_accounts(&mut conn).await?.iter().any(|account| account.kind == AccountKind::Mavinote) {
+        return Err(ACCOUNTS_EXIST);
+    }
+
+    let client = AuthClient::new(config.api_url.clone());
+    let bundle = client.recover(&email, &code).await?;
+
+    let sealed = Sealed {
+        salt: bundle.salt,
+        kdf: KdfParams {
+            algorithm: bundle.algorithm,
+            memory_cost: bundle.memory_cost,
+            time_cost: bundle.time_cost,
+            parallelism: bundle.parallelism,
+        },
+        nonce: bundle.nonce,
+        ciphertext: bundle.ciphertext,
+    };
+    let recovered = crypto::open_with_passphrase(&passphrase, &sealed)?;
+    let recovered: Secrets = serde_json::from_slice(&recovered)
+        .map_err(|_| crypto::Error::Decrypt)?;
+
+    let pubkey = crypto::encode_pubkey(&crypto::decode_privkey(&recovered.privkey)?);
+
+    // Server is asked before the identity is replaced, so a bundle holding a rotated key does not break this device
+    let token = client.login(&email, &pubkey, &recovered.password).await?;
+
+    secrets::put(&mut conn, StoreKey::IdentityPrivKey, recovered.privkey).await?;
+    secrets::put(&mut conn, StoreKey::Password, recovered.password).await?;
+    secrets::delete(&mut conn, StoreKey::PreviousIdentityPrivKey).await?;
+    db::replace_identity(&mut conn, pubkey).await?;
+
+    db::create_account(&mut conn, email.clone(), AccountKind::Mavinote, Some(Json(Mavinote { email, token: token.token }))).await?;
+
+    update_send_accounts(&mut conn).await;
+
+    Ok(())
+}
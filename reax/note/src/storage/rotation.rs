@@ -0,0 +1,117 @@
+use std::sync::Arc;
+
+use base64ct::{Base64, Encoding};
+use chrono::{Duration, Utc};
+use rand::rngs::OsRng;
+use sqlx::{Pool, Sqlite, pool::PoolConnection};
+use x25519_dalek::StaticSecret;
+
+use crate::{Error, crypto, models::{AccountKind, StoreKey}};
+use super::{db, login, mavinote_client, secrets, NOT_MAVINOTE_ACCOUNT};
+
+const ROTATION_PENDING: Error = Error::Unreachable("RotationPending");
+
+/// How long the previous identity key is kept after the rotation. Other devices keep sending ciphertexts made for the
+/// previous key until they sync.
+const GRACE_PERIOD_DAYS: i64 = 30;
+
+/// Replaces the identity key of this device and tells the servers of the accounts about the new one. Servers that cannot
+/// be reached are told on the next sync, they keep knowing this device by the previous key until then.
+pub async fn rotate_identity_key() -> Result<(), Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    // The key a server knows this device by would be lost if it is rotated again before the server learns the last rotation
+    push_rotations(&mut conn).await?;
+
+    if !db::fetch_pending_rotations(&mut conn).await?.is_empty() {
+        return Err(ROTATION_PENDING);
+    }
+
+    let previous = secrets::get(&mut conn, StoreKey::IdentityPrivKey).await?.unwrap();
+    let privkey = StaticSecret::new(OsRng);
+
+    let account_ids = db::fetch_accounts(&mut conn).await?
+        .into_iter()
+        .filter(|account| account.kind == AccountKind::Mavinote)
+        .map(|account| account.id)
+        .collect();
+
+    // Secret store may be outside of the database, so the private keys are not stored in the same transaction. The previous
+    // key is stored first, so that it is not lost if the app is killed in between.
+    secrets::put(&mut conn, StoreKey::PreviousIdentityPrivKey, previous).await?;
+    secrets::put(&mut conn, StoreKey::IdentityPrivKey, Base64::encode_string(&privkey.to_bytes())).await?;
+
+    let values = vec![
+        (StoreKey::IdentityRotatedAt, Utc::now().timestamp().to_string()),
+        (StoreKey::IdentityPubKey, crypto::encode_pubkey(&privkey)),
+    ];
+
+    db::rotate_identity_key(&mut conn, values, account_ids).await?;
+
+    push_rotations(&mut conn).await
+}
+
+/// Tells the servers which have not learnt the last rotation yet about the new key, and drops the previous key once all of
+/// them learn it and the grace period passes
+pub(crate) async fn push_rotations(conn: &mut PoolConnection<Sqlite>) -> Result<(), Error> {
+    for account_id in db::fetch_pending_rotations(conn).await? {
+        push_rotation(conn, account_id).await?;
+    }
+
+    let Some(rotated_at) = db::fetch_value(conn, StoreKey::IdentityRotatedAt).await? else {
+        return Ok(());
+    };
+
+    let expired = rotated_at.value
+        .parse::<i64>()
+        .map(|timestamp| Utc::now().timestamp() - timestamp > Duration::days(GRACE_PERIOD_DAYS).num_seconds())
+        .unwrap_or(true);
+
+    if expired && db::fetch_pending_rotations(conn).await?.is_empty() {
+        secrets::delete(conn, StoreKey::PreviousIdentityPrivKey).await?;
+        db::delete_value(conn, StoreKey::IdentityRotatedAt).await?;
+    }
+
+    Ok(())
+}
+
+/// Returns the pubkey that the server of the account knows this device by
+pub(crate) async fn account_pubkey(conn: &mut PoolConnection<Sqlite>, account_id: i32) -> Result<String, Error> {
+    if db::pending_rotation_exists(conn, account_id).await? {
+        if let Some(previous) = crypto::load_identity(conn).await?.previous {
+            return Ok(crypto::encode_pubkey(&previous));
+        }
+    }
+
+    Ok(db::fetch_value(conn, StoreKey::IdentityPubKey).await?.unwrap().value)
+}
+
+async fn push_rotation(conn: &mut PoolConnection<Sqlite>, account_id: i32) -> Result<(), Error> {
+    let Some(previous) = crypto::load_identity(conn).await?.previous else {
+        return Err(Error::Unreachable("Pending rotation must have a previous key"));
+    };
+
+    let client = mavinote_client(conn, account_id).await?
+        .ok_or(NOT_MAVINOTE_ACCOUNT)?;
+
+    let pubkey = db::fetch_value(conn, StoreKey::IdentityPubKey).await?.unwrap().value;
+
+    let rotation_key = client
+        .clone()
+        .login_on_unauthorized(&|client| async move { client.fetch_rotation_key().await }, &login)
+        .await?;
+
+    let proof = crypto::rotation_proof(&previous, &rotation_key.pubkey, &pubkey)?;
+
+    let (pubkey, proof) = (pubkey.as_str(), proof.as_str());
+    client
+        .login_on_unauthorized(&|client| async move { client.rotate_pubkey(pubkey, proof).await }, &login)
+        .await?;
+
+    db::delete_pending_rotation(conn, account_id).await?;
+
+    // Names and keys of the folders are wrapped with the secrets shared with the previous key, they are wrapped again
+    // with the new one on the next sync
+    db::update_account_folders_modified(conn, account_id).await
+        .map_err(|e| e.into())
+}
@@ -0,0 +1,190 @@
+use std::sync::Arc;
+
+use sqlx::{Pool, Sqlite};
+
+use crate::Error;
+use crate::models::{Note, SearchResult, SnippetPart};
+use super::{db, vault};
+
+const LIMIT: usize = 50;
+/// Number of characters kept at both sides of the first match when the snippet is built without the index
+const SNIPPET_CONTEXT: usize = 40;
+const HIGHLIGHT_START: &str = "\u{2}";
+const HIGHLIGHT_END: &str = "\u{3}";
+
+/// Finds the notes of the account whose name or text contains every word of the query, best matches first. Words are
+/// matched as prefixes and case insensitively.
+pub async fn search(query: String, account_id: i32) -> Result<Vec<SearchResult>, Error> {
+    let terms = query
+        .split_whitespace()
+        .map(|term| term.to_lowercase())
+        .collect::<Vec<String>>();
+
+    if terms.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    if vault::searchable() {
+        let fts_query = terms
+            .iter()
+            .map(|term| format!("\"{}\"*", term.replace('"', "\"\"")))
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        let matches = db::search_notes(&mut conn, &fts_query, account_id, (HIGHLIGHT_START, HIGHLIGHT_END), LIMIT as i32).await?;
+
+        return Ok(matches
+            .into_iter()
+            .map(|m| SearchResult { note_id: m.id, folder_id: m.folder_id, name: m.name, snippet: parse_snippet(&m.snippet) })
+            .collect());
+    }
+
+    // Index is not kept while the local encryption is enabled, notes are decrypted and scanned instead
+    let mut results = db::fetch_account_notes(&mut conn, account_id)
+        .await?
+        .into_iter()
+        .filter_map(|note| scan(note, &terms))
+        .collect::<Vec<(usize, SearchResult)>>();
+
+    results.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+
+    Ok(results.into_iter().take(LIMIT).map(|(_, result)| result).collect())
+}
+
+fn parse_snippet(snippet: &str) -> Vec<SnippetPart> {
+    let mut parts = Vec::new();
+
+    for (i, part) in snippet.split(HIGHLIGHT_START).enumerate() {
+        // Every part except the first one starts with a highlighted text
+        let (highlighted, rest) = match part.split_once(HIGHLIGHT_END) {
+            Some((highlighted, rest)) if i > 0 => (highlighted, rest),
+            _ => ("", part),
+        };
+
+        if !highlighted.is_empty() {
+            parts.push(SnippetPart { text: highlighted.to_owned(), highlighted: true });
+        }
+
+        if !rest.is_empty() {
+            parts.push(SnippetPart { text: rest.to_owned(), highlighted: false });
+        }
+    }
+
+    parts
+}
+
+/// Scores the note by its matches, names are weighted more than texts like the index does
+fn scan(note: Note, terms: &[String]) -> Option<(usize, SearchResult)> {
+    let name = note.name.chars().collect::<Vec<char>>();
+    let text = note.text.chars().collect::<Vec<char>>();
+
+    let mut score = 0;
+    let mut matches = Vec::new();
+
+    for term in terms {
+        let term = term.chars().collect::<Vec<char>>();
+
+        let name_matches = find_all(&name, &term).len();
+        let text_matches = find_all(&text, &term);
+
+        if name_matches == 0 && text_matches.is_empty() {
+            return None;
+        }
+
+        score += name_matches * 2 + text_matches.len();
+        matches.extend(text_matches);
+    }
+
+    matches.sort();
+
+    Some((score, SearchResult { note_id: note.id, folder_id: note.folder_id, name: note.name, snippet: build_snippet(&text, &matches) }))
+}
+
+/// Ranges of the words starting with the term, the same words which the index would match as a prefix
+fn find_all(text: &[char], term: &[char]) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+
+    while start < text.len() {
+        let word_start = start == 0 || !text[start - 1].is_alphanumeric();
+
+        match match_len(&text[start..], term).filter(|_| word_start) {
+            Some(len) => {
+                let mut end = start + len;
+                while end < text.len() && text[end].is_alphanumeric() {
+                    end += 1;
+                }
+
+                ranges.push((start, end));
+                start = end.max(start + 1);
+            }
+            None => start += 1,
+        }
+    }
+
+    ranges
+}
+
+/// Number of characters of the text that match the term, a lowercased character can be longer than the original
+fn match_len(text: &[char], term: &[char]) -> Option<usize> {
+    let mut term = term.iter();
+    let mut len = 0;
+
+    for c in text {
+        let mut remaining = term.clone();
+
+        for lower in c.to_lowercase() {
+            if remaining.next() != Some(&lower) {
+                return None;
+            }
+        }
+
+        term = remaining;
+        len += 1;
+
+        if term.len() == 0 {
+            return Some(len);
+        }
+    }
+
+    None
+}
+
+fn build_snippet(text: &[char], matches: &[(usize, usize)]) -> Vec<SnippetPart> {
+    let (start, end) = match matches.first() {
+        Some((first, _)) => (first.saturating_sub(SNIPPET_CONTEXT), (first + SNIPPET_CONTEXT * 2).min(text.len())),
+        None => (0, (SNIPPET_CONTEXT * 2).min(text.len())),
+    };
+
+    let mut parts = Vec::new();
+    let mut plain = if start > 0 { String::from("…") } else { String::new() };
+    let mut cursor = start;
+
+    for &(match_start, match_end) in matches {
+        // Overlapping matches and the ones outside of the window are skipped
+        if match_start < cursor || match_end > end {
+            continue;
+        }
+
+        plain.extend(&text[cursor..match_start]);
+        if !plain.is_empty() {
+            parts.push(SnippetPart { text: std::mem::take(&mut plain), highlighted: false });
+        }
+
+        parts.push(SnippetPart { text: text[match_start..match_end].iter().collect(), highlighted: true });
+        cursor = match_end;
+    }
+
+    plain.extend(&text[cursor..end]);
+    if end < text.len() {
+        plain.push('…');
+    }
+
+    if !plain.is_empty() {
+        parts.push(SnippetPart { text: plain, highlighted: false });
+    }
+
+    parts
+}
@@ -0,0 +1,179 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use futures_util::future::BoxFuture;
+use once_cell::sync::OnceCell;
+use sqlx::{Pool, Sqlite, pool::PoolConnection};
+use tokio::sync::Mutex;
+
+use crate::{Error, crypto::{self, FileCipher}, models::{Encryption, StoreKey}};
+use super::{db, vault};
+
+const SECRET_STORE_SET: Error = Error::Unreachable("SecretStoreSet");
+
+static SECRET_STORE: OnceCell<Box<dyn SecretStore>> = OnceCell::new();
+
+/// Storage of the identity keys and the password of this device. Embedding apps can keep them outside of the notes
+/// database by choosing a store with [`set_secret_store`].
+pub trait SecretStore: Send + Sync {
+    fn get(&self, key: StoreKey) -> BoxFuture<'_, Result<Option<String>, Error>>;
+
+    fn put(&self, key: StoreKey, value: String) -> BoxFuture<'_, Result<(), Error>>;
+
+    fn delete(&self, key: StoreKey) -> BoxFuture<'_, Result<(), Error>>;
+}
+
+/// Replaces the default store, which keeps the secrets in the notes database. It must be called before the storage is
+/// initialized and only once.
+pub fn set_secret_store(store: impl SecretStore + 'static) -> Result<(), Error> {
+    SECRET_STORE.set(Box::new(store)).map_err(|_| SECRET_STORE_SET)
+}
+
+/// Keeps the secrets in the `store` table of the notes database, where they are encrypted only if the local encryption
+/// is enabled
+pub struct SqliteSecretStore;
+
+impl SecretStore for SqliteSecretStore {
+    fn get(&self, key: StoreKey) -> BoxFuture<'_, Result<Option<String>, Error>> {
+        Box::pin(async move {
+            let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+            Ok(db::fetch_value(&mut conn, key).await?.map(|store| store.value))
+        })
+    }
+
+    fn put(&self, key: StoreKey, value: String) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+            db::store_value(&mut conn, key, &value).await.map_err(|e| e.into())
+        })
+    }
+
+    fn delete(&self, key: StoreKey) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+            db::delete_value(&mut conn, key).await.map_err(|e| e.into())
+        })
+    }
+}
+
+/// Keeps the secrets in a file encrypted with the given key, which the host is expected to get from somewhere safer than
+/// the disk, like the keyring of the desktop
+pub struct FileSecretStore {
+    path: PathBuf,
+    cipher: FileCipher,
+    lock: Mutex<()>,
+}
+
+impl FileSecretStore {
+    /// Key is the base64 encoding of 32 random bytes
+    pub fn new(path: PathBuf, key: &str) -> Result<Self, Error> {
+        Ok(FileSecretStore { path, cipher: FileCipher::try_from_key(key)?, lock: Mutex::new(()) })
+    }
+
+    async fn read(&self) -> Result<BTreeMap<String, String>, Error> {
+        let bytes = match tokio::fs::read(&self.path).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        serde_json::from_slice(&self.cipher.decrypt(&bytes)?)
+            .map_err(|_| crypto::Error::Decrypt.into())
+    }
+
+    async fn write(&self, secrets: &BTreeMap<String, String>) -> Result<(), Error> {
+        let bytes = self.cipher.encrypt(&serde_json::to_vec(secrets).unwrap())?;
+
+        // File is replaced at once, so a crash while writing does not lose the secrets
+        let tmp_path = self.path.with_extension("tmp");
+        tokio::fs::write(&tmp_path, bytes).await?;
+        tokio::fs::rename(&tmp_path, &self.path).await
+            .map_err(|e| e.into())
+    }
+}
+
+impl SecretStore for FileSecretStore {
+    fn get(&self, key: StoreKey) -> BoxFuture<'_, Result<Option<String>, Error>> {
+        Box::pin(async move {
+            let _guard = self.lock.lock().await;
+
+            Ok(self.read().await?.remove(&format!("{key:?}")))
+        })
+    }
+
+    fn put(&self, key: StoreKey, value: String) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            let _guard = self.lock.lock().await;
+
+            let mut secrets = self.read().await?;
+            secrets.insert(format!("{key:?}"), value);
+
+            self.write(&secrets).await
+        })
+    }
+
+    fn delete(&self, key: StoreKey) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            let _guard = self.lock.lock().await;
+
+            let mut secrets = self.read().await?;
+            if secrets.remove(&format!("{key:?}")).is_some() {
+                self.write(&secrets).await?;
+            }
+
+            Ok(())
+        })
+    }
+}
+
+fn store() -> &'static dyn SecretStore {
+    SECRET_STORE.get_or_init(|| Box::new(SqliteSecretStore)).as_ref()
+}
+
+pub(crate) async fn get(conn: &mut PoolConnection<Sqlite>, key: StoreKey) -> Result<Option<String>, Error> {
+    if let Some(value) = store().get(key.clone()).await? {
+        return Ok(Some(value));
+    }
+
+    // Secrets which are written before the host chose another store are moved out of the database on their first use
+    let Some(store_value) = db::fetch_value(conn, key.clone()).await? else {
+        return Ok(None);
+    };
+
+    store().put(key.clone(), store_value.value.clone()).await?;
+    db::delete_value(conn, key).await?;
+
+    Ok(Some(store_value.value))
+}
+
+/// Moves the secrets left in the database to the chosen store. Sealed ones cannot be read while the vault is locked, they
+/// are moved on their first use instead.
+pub(crate) async fn migrate(conn: &mut PoolConnection<Sqlite>) -> Result<(), Error> {
+    if matches!(vault::encryption(), Encryption::Locked) {
+        return Ok(());
+    }
+
+    for key in [StoreKey::IdentityPrivKey, StoreKey::PreviousIdentityPrivKey, StoreKey::Password] {
+        get(conn, key).await?;
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn put(conn: &mut PoolConnection<Sqlite>, key: StoreKey, value: String) -> Result<(), Error> {
+    // A copy left in the database is moved first, otherwise it would stay there
+    get(conn, key.clone()).await?;
+
+    store().put(key, value).await
+}
+
+pub(crate) async fn delete(conn: &mut PoolConnection<Sqlite>, key: StoreKey) -> Result<(), Error> {
+    store().delete(key.clone()).await?;
+
+    db::delete_value(conn, key).await
+        .map_err(|e| e.into())
+}
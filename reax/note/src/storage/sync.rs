@@ -3,26 +3,54 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use base::Config;
+use chrono::Utc;
 use futures_util::{StreamExt, FutureExt, SinkExt};
 use sqlx::{Pool, Sqlite, pool::PoolConnection};
 use tokio::sync::watch::{channel, Receiver};
 use tokio::time::Instant;
 use tokio_tungstenite::connect_async;
-use x25519_dalek::StaticSecret;
 
-use super::db;
-use crate::accounts::mavinote::responses::Commit;
-use crate::crypto::{DeviceCipher, Error as CryptoError};
-use crate::{Error, crypto};
-use crate::accounts::mavinote::{CreateFolderRequest, CreateNoteRequest, MavinoteClient, Error as MavinoteError, RespondFolderRequest, RespondRequests, RespondNoteRequest, CreateRequests, DeviceMessage};
-use crate::models::{AccountKind, State as ModelState, RemoteId, Note, Mavinote, LocalId};
+use super::{db, document, send_trust_event};
+use crate::accounts::mavinote::responses::{Commit, Note as RemoteNote, NoteUpdate, Requests};
+use crate::crypto::{DeviceCipher, FileCipher, Identity, Error as CryptoError};
+use crate::{Error, crypto, merge::{self, Labels}};
+use crate::accounts::mavinote::{MavinoteClient, Error as MavinoteError, RespondFolderRequest, RespondRequests, RespondNoteRequest, CreateRequests, DeviceMessage};
+use crate::models::{AccountKind, ConflictPolicy, State as ModelState, RemoteId, Note, Mavinote, LocalId, Role, StoreKey, Trust, TrustEvent, OperationKind};
 
 const PING_INTERVAL: u64 = 30;
+/// Seconds that a failed operation waits before its first retry, the wait doubles with each attempt
+const RETRY_INTERVAL: i64 = 5;
+const MAX_RETRY_INTERVAL: i64 = 60 * 60;
+/// Errors of remote that sending the operation again cannot fix. Remote hides the folders which cannot be written, so a
+/// member who loses the write access gets item_not_found.
+const PERMANENT_ERRORS: [&str; 12] = [
+    "item_not_found",
+    "devices_mismatch",
+    "unknown_folder",
+    "unknown_note",
+    "folder_trashed",
+    "folder_shared",
+    "cannot_share_with_owner",
+    "invalid_chunk",
+    "invalid_idempotency_key",
+    "idempotency_key_reused",
+    "idempotency_outcome_unknown",
+    "unreadable_request",
+];
+
+/// Outcome of pulling a remote note
+enum Pull {
+    Applied,
+    /// Note cannot be decrypted until another device creates the device note of this device
+    Requested,
+    /// Note cannot be applied yet, it is pulled again on the next sync
+    Pending,
+}
 
 struct Sync<'a> {
     account_id: i32,
     client: MavinoteClient,
-    privkey: &'a StaticSecret,
+    identity: &'a Identity,
     ciphers: Vec<DeviceCipher>,
 }
 
@@ -32,7 +60,10 @@ impl<'a> Sync<'a> {
 
         self.remote(conn).await?;
 
-        self.local(conn).await?;
+        // Rows which are only marked as modified may depend on the operations in the outbox, they are sent afterwards
+        if self.outbox(conn).await? {
+            self.local(conn).await?;
+        }
 
         self.respond_device_requests(conn).await?;
 
@@ -42,62 +73,179 @@ impl<'a> Sync<'a> {
     async fn devices(&self, conn: &mut PoolConnection<Sqlite>) -> Result<Vec<DeviceCipher>, Error> {
         let new_devices = self.client.fetch_devices().await?;
 
-        // Verify and update the pubkeys
-        let ciphers = new_devices
-            .iter()
-            .map(|device| DeviceCipher::try_from_key(device.id, self.privkey, &device.pubkey))
-            .collect::<Result<Vec<_>, CryptoError>>()?;
+        // Verify the pubkeys before storing them
+        for device in &new_devices {
+            DeviceCipher::try_from_key(device.id, self.identity, &device.pubkey, device.previous_pubkey.as_deref())?;
+        }
 
-        let old_devices = db::fetch_devices(conn, self.account_id).await?;
+        let own_pubkey = db::fetch_value(conn, StoreKey::IdentityPubKey).await?.unwrap().value;
+        let old_devices = db::fetch_device_keys(conn, self.account_id).await?;
 
         let devices_to_delete = old_devices.iter()
-            .filter(|od| new_devices.iter().find(|nd| od.id == nd.id && od.pubkey == nd.pubkey).is_none())
+            .filter(|od| !new_devices.iter().any(|nd| od.id == nd.id))
             .map(|od| od.id)
             .collect::<Vec<_>>();
 
-        let devices_to_create = new_devices.into_iter()
-            .filter(|nd| old_devices.iter().find(|od| od.id == nd.id && od.pubkey == nd.pubkey).is_none())
-            .collect::<Vec<_>>();
-
         if !devices_to_delete.is_empty() {
             db::delete_devices(conn, self.account_id, &devices_to_delete).await?;
         }
 
+        let mut devices_to_create = Vec::new();
+        let mut changed = false;
+
+        for nd in new_devices {
+            let Some(od) = old_devices.iter().find(|od| od.id == nd.id) else {
+                send_trust_event(TrustEvent {
+                    account_id: self.account_id,
+                    device_id: nd.id,
+                    trust: Trust::Unverified,
+                    fingerprint: crypto::fingerprint(&own_pubkey, &nd.pubkey)?,
+                });
+
+                devices_to_create.push(nd);
+                continue;
+            };
+
+            if od.pubkey != nd.pubkey {
+                // Nothing is encrypted for the new key until the user compares the fingerprints again
+                db::update_device_key(conn, self.account_id, nd.id, &nd.pubkey, nd.previous_pubkey.as_deref(), Trust::Changed).await?;
+
+                send_trust_event(TrustEvent {
+                    account_id: self.account_id,
+                    device_id: nd.id,
+                    trust: Trust::Changed,
+                    fingerprint: crypto::fingerprint(&own_pubkey, &nd.pubkey)?,
+                });
+
+                changed = true;
+            } else if od.previous_pubkey != nd.previous_pubkey {
+                db::update_device_key(conn, self.account_id, nd.id, &nd.pubkey, nd.previous_pubkey.as_deref(), od.trust).await?;
+            }
+        }
+
         if !devices_to_create.is_empty() {
-            db::create_devices(conn, self.account_id, &devices_to_create).await?;
+            db::create_devices(conn, self.account_id, &devices_to_create, Trust::Unverified).await?;
         }
 
-        Ok(ciphers)
+        // Folder keys wrapped for the previous key of a changed device are not sent to it anymore
+        if changed {
+            db::update_account_folders_modified(conn, self.account_id).await?;
+        }
+
+        Self::load_device_ciphers(conn, self.identity, self.account_id).await
     }
 
     async fn remote(&self, conn: &mut PoolConnection<Sqlite>) -> Result<(), Error> {
         let mut requests = CreateRequests::default();
 
-        let remote_folders = self.client.fetch_folders().await?;
-        for remote_folder in remote_folders {
-            let reqs = self.remote_folder(conn, remote_folder).await?;
+        let cursor = db::fetch_sync_cursor(conn, self.account_id).await?;
+
+        // Folders and notes are known before the changes are fetched, so that the ones created meanwhile are not dropped
+        let (folder_ids, note_ids) = db::fetch_account_remote_ids(conn, self.account_id).await?;
+
+        let changes = self.client.fetch_changes(cursor).await?;
+
+        // Deletions which are purged in remote are not returned, the folders and notes that are left out of a resync are
+        // dropped instead
+        if changes.resync {
+            let returned_folder_ids = changes.folders.iter().map(|folder| folder.id).collect::<HashSet<_>>();
+            let returned_note_ids = changes.folders
+                .iter()
+                .flat_map(|folder| folder.commits.iter().map(|commit| commit.note_id))
+                .collect::<HashSet<_>>();
+
+            for note_id in note_ids.into_iter().filter(|id| !returned_note_ids.contains(id)) {
+                if let Some(note) = db::fetch_account_note_by_remote_id(conn, RemoteId(note_id), self.account_id).await? {
+                    db::delete_note(conn, note.local_id()).await?;
+                }
+            }
+
+            for folder_id in folder_ids.into_iter().filter(|id| !returned_folder_ids.contains(id)) {
+                db::delete_folder_by_remote_id(conn, RemoteId(folder_id), self.account_id).await?;
+            }
+        }
+
+        // Cursor is held right before the first change that cannot be applied yet, so that it is pulled again
+        let mut cursor = changes.cursor;
+
+        // Parents are assigned after all the folders are handled since a parent may come after its subfolders
+        let parents = changes.folders
+            .iter()
+            .filter(|folder| folder.state == ModelState::Clean)
+            .map(|folder| (folder.id(), folder.parent_id.map(RemoteId), folder.seq))
+            .collect::<Vec<_>>();
+
+        for remote_folder in changes.folders {
+            let seq = remote_folder.seq;
+            let (reqs, applied) = self.remote_folder(conn, remote_folder).await?;
             requests.folder_ids.extend(reqs.folder_ids);
             requests.note_ids.extend(reqs.note_ids);
+
+            if !applied {
+                cursor = cursor.min(seq - 1);
+            }
+        }
+
+        for (folder_id, parent_id, seq) in parents {
+            if !self.remote_folder_parent(conn, folder_id, parent_id).await? {
+                cursor = cursor.min(seq - 1);
+            }
         }
 
         if !requests.folder_ids.is_empty() || !requests.note_ids.is_empty() {
             self.client.create_requests(&requests).await?;
         }
 
-        Ok(())
+        // Cursor is only advanced after all the changes are applied, a failed sync starts over from the same cursor
+        db::update_sync_cursor(conn, self.account_id, cursor).await
+            .map_err(|e| e.into())
     }
 
-    async fn remote_folder(&self, conn: &mut PoolConnection<Sqlite>, remote_folder: crate::accounts::mavinote::responses::Folder) -> Result<CreateRequests, Error> {
-        if let ModelState::Deleted = remote_folder.state {
-            return db::delete_folder_by_remote_id(conn, remote_folder.id(), self.account_id)
+    /// Applies the folder and its notes, returns the requests to send and whether all of them are applied
+    async fn remote_folder(&self, conn: &mut PoolConnection<Sqlite>, remote_folder: crate::accounts::mavinote::responses::Folder) -> Result<(CreateRequests, bool), Error> {
+        // Trashed folders are kept in the local trash so that they can be restored until they are purged
+        match remote_folder.state {
+            ModelState::Trashed => return db::trash_folder_by_remote_id(conn, remote_folder.id(), self.account_id)
+                .await
+                .map(|_| (CreateRequests::default(), true))
+                .map_err(|e| e.into()),
+            ModelState::Deleted => return db::delete_folder_by_remote_id(conn, remote_folder.id(), self.account_id)
                 .await
-                .map(|_| CreateRequests::default())
-                .map_err(|e| e.into());
+                .map(|_| (CreateRequests::default(), true))
+                .map_err(|e| e.into()),
+            _ => {},
         }
 
         let mut requests = CreateRequests::default();
+        let mut applied = true;
+
+        let shared_ciphers = self.shared_ciphers(conn, remote_folder.id(), remote_folder.shared).await?;
+        let ciphers = shared_ciphers.as_deref().unwrap_or(&self.ciphers);
 
         let folder = match db::fetch_folder_by_remote_id(conn, remote_folder.id(), self.account_id).await? {
+            // Locally renamed folders keep their name, it is pushed to remote by local sync
+            Some(folder) if folder.state == ModelState::Clean => {
+                // Folder may be restored from the trash by another device
+                db::restore_folder_by_remote_id(conn, remote_folder.id(), self.account_id).await?;
+
+                let name = match &remote_folder.device_folder {
+                    Some(device_folder) => match ciphers.iter().find(|cipher| cipher.device_id == device_folder.sender_device_id) {
+                        Some(cipher) => Some(cipher.decrypt(&device_folder.name)?),
+                        None => {
+                            log::warn!("A folder with unknown sender is received");
+                            applied = false;
+                            None
+                        }
+                    },
+                    None => None,
+                };
+
+                if let Some(name) = name.filter(|name| *name != folder.name) {
+                    db::update_folder(conn, folder.local_id(), &name, ModelState::Clean).await?;
+                }
+
+                folder
+            },
             Some(folder) => folder,
             None => {
                 let Some(device_folder) = &remote_folder.device_folder else {
@@ -106,87 +254,199 @@ impl<'a> Sync<'a> {
                     requests.folder_ids.push(remote_folder.id);
                     requests.note_ids.extend(remote_folder.commits.into_iter().map(|commit| commit.note_id));
 
-                    return Ok(requests);
+                    return Ok((requests, false));
                 };
 
-                let Some(cipher) = self.ciphers.iter().find(|cipher| cipher.device_id == device_folder.sender_device_id) else {
+                let Some(cipher) = ciphers.iter().find(|cipher| cipher.device_id == device_folder.sender_device_id) else {
                     log::warn!("A folder with unknown sender is received");
-                    return Ok(requests);
+                    return Ok((requests, false));
                 };
 
                 db::create_folder(
                     conn,
                     Some(remote_folder.id()),
                     self.account_id,
+                    None,
                     cipher.decrypt(&device_folder.name)?
                 ).await?
             }
         };
 
+        if folder.shared != remote_folder.shared || folder.role != remote_folder.role {
+            db::update_folder_sharing(conn, folder.local_id(), remote_folder.shared, remote_folder.role).await?;
+        }
+
+        let wrapped_key = remote_folder.device_folder
+            .as_ref()
+            .and_then(|device_folder| Some((device_folder.sender_device_id, device_folder.key.as_ref()?)));
+
+        if let Some((sender_device_id, wrapped_key)) = wrapped_key {
+            match ciphers.iter().find(|cipher| cipher.device_id == sender_device_id) {
+                Some(cipher) => {
+                    let key = cipher.decrypt(wrapped_key)?;
+
+                    // Key rotated by this device may not be sent yet, the one it is rotated from is not taken back
+                    let rotated = match db::fetch_folder_key(conn, folder.local_id()).await? {
+                        Some(local_key) => FileCipher::try_from_key(&local_key)?.rotated_from(&FileCipher::try_from_key(&key)?),
+                        None => false,
+                    };
+
+                    if !rotated {
+                        db::update_folder_key(conn, folder.local_id(), &key).await?;
+                    }
+                },
+                None => {
+                    log::warn!("A folder key with unknown sender is received");
+                    applied = false;
+                },
+            }
+        }
+
+        let mut folder_cipher = db::fetch_folder_key(conn, folder.local_id()).await?
+            .map(|key| FileCipher::try_from_key(&key))
+            .transpose()?;
+
+        // Folders which are created before the folder keys are given a key only if no device has one, otherwise this
+        // device would replace the key that the others use. New key is wrapped for the devices by local sync.
+        if folder_cipher.is_none() && !remote_folder.keyed && folder.role != Some(Role::Read) {
+            let cipher = FileCipher::generate();
+
+            db::update_folder_key(conn, folder.local_id(), &cipher.key()).await?;
+            db::update_folder_state(conn, folder.local_id(), ModelState::Modified).await?;
+
+            folder_cipher = Some(cipher);
+        }
+
         for commit in remote_folder.commits {
             let note_id = commit.note_id;
-            if self.remote_note(conn, commit, folder.local_id()).await? {
-                requests.note_ids.push(note_id);
+            match self.remote_note(conn, ciphers, folder_cipher.as_ref(), commit, folder.local_id()).await? {
+                Pull::Applied => {},
+                Pull::Requested => {
+                    requests.note_ids.push(note_id);
+                    applied = false;
+                },
+                Pull::Pending => applied = false,
             }
         }
 
-        Ok(requests)
+        Ok((requests, applied))
     }
 
-    async fn remote_note(&self, conn: &mut PoolConnection<Sqlite>, commit: Commit, folder_id: LocalId) -> Result<bool, Error> {
-        let local_note = db::fetch_note_by_remote_id(conn, RemoteId(commit.note_id), folder_id).await?;
+    /// Links the folder to its parent, returns whether it is linked. A folder is not linked while it or its parent is
+    /// not pulled yet, then its change is pulled again on the next sync.
+    async fn remote_folder_parent(&self, conn: &mut PoolConnection<Sqlite>, folder_id: RemoteId, parent_id: Option<RemoteId>) -> Result<bool, Error> {
+        let Some(folder) = db::fetch_folder_by_remote_id(conn, folder_id, self.account_id).await? else {
+            return Ok(false);
+        };
+
+        // Locally moved folders keep their parent, it is pushed to remote by local sync
+        if folder.state != ModelState::Clean {
+            return Ok(true);
+        }
+
+        let parent_id = match parent_id {
+            Some(parent_id) => match db::fetch_folder_by_remote_id(conn, parent_id, self.account_id).await? {
+                Some(parent) => Some(parent.local_id()),
+                None => {
+                    log::debug!("parent of folder with remote id {} is not available yet", folder_id.0);
+                    return Ok(false);
+                }
+            },
+            None => None,
+        };
+
+        if parent_id.map(|id| id.0) != folder.parent_id {
+            db::update_folder_parent(conn, folder.local_id(), parent_id, ModelState::Clean).await?;
+        }
+
+        Ok(true)
+    }
+
+    async fn remote_note(&self, conn: &mut PoolConnection<Sqlite>, ciphers: &[DeviceCipher], folder_cipher: Option<&FileCipher>, commit: Commit, folder_id: LocalId) -> Result<Pull, Error> {
+        // Note may have been moved from another folder, hence it is searched in the whole account
+        let mut local_note = db::fetch_account_note_by_remote_id(conn, RemoteId(commit.note_id), self.account_id).await?;
+
+        if let Some(note) = local_note.as_mut().filter(|note| note.folder_id != folder_id.0) {
+            // Locally moved notes keep their folder, the move is sent by the outbox
+            if !db::note_operation_exists(conn, note.local_id(), OperationKind::MoveNote).await? {
+                db::update_note_folder(conn, note.local_id(), folder_id).await?;
+                note.folder_id = folder_id.0;
+            }
+        }
 
         if commit.state == ModelState::Deleted {
             if let Some(note) = &local_note {
                 db::delete_note(conn, note.local_id()).await?;
             }
 
-            return Ok(false);
+            return Ok(Pull::Applied);
+        }
+
+        if commit.state == ModelState::Trashed {
+            if let Some(note) = local_note.filter(|note| note.state != ModelState::Trashed) {
+                db::update_note_state(conn, note.local_id(), ModelState::Trashed).await?;
+            }
+
+            return Ok(Pull::Applied);
+        }
+
+        // Locally trashed notes have already reached the trash of remote, so the note is restored by another device
+        if let Some(note) = local_note.as_mut().filter(|note| note.state == ModelState::Trashed) {
+            db::update_note_state(conn, note.local_id(), ModelState::Clean).await?;
+            note.state = ModelState::Clean;
         }
 
         if let Some(note) = &local_note {
+            // Commits of the converted notes are not written anymore, their texts are merged from the updates
+            if let (Some(update_id), true) = (commit.update_id, note.state != ModelState::Deleted) {
+                let local_text = (note.state == ModelState::Modified).then_some(note.text.as_str());
+
+                document::pull(conn, &self.client, self.account_id, note, Some(update_id), local_text).await?;
+            }
+
             // Having same commit means there is no need to pull fresh note from the server.
             // Deleted state will be handled by local sync
             if note.state == ModelState::Deleted || note.commit >= commit.commit {
-                return Ok(false);
+                return Ok(Pull::Applied);
             }
         }
 
         let Some(remote_note) = self.client.fetch_note(RemoteId(commit.note_id)).await? else {
             log::warn!("note with remote id {} does not exist on remote", commit.note_id);
-            return Ok(false);
+            return Ok(Pull::Applied);
         };
 
-        let Some(device_note) = remote_note.device_note else {
+        if remote_note.content.is_none() && remote_note.device_note.is_none() {
             log::debug!("A note with no device note is received. Some other devices must create our device note");
-            return Ok(true);
-        };
+            return Ok(Pull::Requested);
+        }
 
-        let Some(cipher) = self.ciphers.iter().find(|cipher| cipher.device_id == device_note.sender_device_id) else {
-            log::warn!("A note with unknown sender device is received");
-            return Ok(false);
+        // Note is pulled again once the key of its folder is received
+        let Some((name, text)) = super::decrypt_remote_note(folder_cipher, ciphers, &remote_note)? else {
+            return Ok(Pull::Pending);
         };
 
-        let name = cipher.decrypt(&device_note.name)?;
-        let mut text = cipher.decrypt(&device_note.text)?;
-
         if let Some(note) = local_note {
-            // Merge local and remote text to resolve the conflict
-            if note.state == ModelState::Modified {
-                text += "\n___CONFLICT_RESOLVING___\n";
-                text += &note.text;
-            }
+            let (name, merged, state) = if note.state == ModelState::Modified {
+                match resolve_conflict(conn, self.account_id, &note, &note.text, &text, remote_note.commit).await? {
+                    Resolution::Merged(merged) => (super::note_name(&merged), merged, note.state.clone()),
+                    Resolution::Copied => (name, text.clone(), ModelState::Clean),
+                }
+            } else {
+                (name, text.clone(), note.state.clone())
+            };
 
             db::update_note(
                 conn,
                 note.local_id(),
                 &name,
-                &text,
+                &merged,
+                Some(&text),
                 remote_note.commit,
-                note.state,
+                state,
             ).await?
         } else {
-            db::create_note(
+            let note = db::create_note(
                 conn,
                 folder_id,
                 Some(RemoteId(commit.note_id)),
@@ -194,13 +454,233 @@ impl<'a> Sync<'a> {
                 text,
                 remote_note.commit
             ).await?;
+
+            if let Some(update_id) = commit.update_id {
+                document::pull(conn, &self.client, self.account_id, &note, Some(update_id), None).await?;
+            }
+        }
+
+        Ok(Pull::Applied)
+    }
+
+    /// Replays the operations in the outbox in the order they are made, returns whether the outbox is emptied. A failed
+    /// operation holds back the later ones until its retry time, since they may depend on it. An operation that remote
+    /// refuses for good is put aside as failed instead, the outbox is not emptied until it is retried or discarded.
+    async fn outbox(&self, conn: &mut PoolConnection<Sqlite>) -> Result<bool, Error> {
+        let operations = db::fetch_operations(conn, self.account_id).await?;
+        let now = Utc::now().timestamp();
+
+        for (index, operation) in operations.iter().enumerate() {
+            if operation.retry_at > now {
+                log::debug!("operation with id {} waits for its retry, {:?}", operation.id, operation.last_error);
+
+                return Ok(false);
+            }
+
+            let later = &operations[index + 1..];
+
+            match self.replay(conn, operation, later).await {
+                Ok(()) => db::delete_operation(conn, operation.id).await?,
+                Err(Error::Mavinote(MavinoteError::Message(msg))) if PERMANENT_ERRORS.contains(&msg.as_str()) => {
+                    log::error!("operation with id {} is refused by remote, {msg}", operation.id);
+
+                    db::fail_operation_permanently(conn, operation.id, &msg).await?;
+
+                    super::outbox::update_send_failed_operations(conn).await;
+                },
+                Err(e) => {
+                    // Operation is retried on next sync once the connection is back
+                    let retry_at = match e {
+                        Error::Mavinote(MavinoteError::NoConnection) => now,
+                        _ => now + (RETRY_INTERVAL << operation.attempts.min(16)).min(MAX_RETRY_INTERVAL),
+                    };
+
+                    db::fail_operation(conn, operation.id, &format!("{e:?}"), retry_at).await?;
+
+                    return Err(e);
+                }
+            }
+        }
+
+        // Local sync would send the changes of the failed operations again
+        Ok(!db::failed_operations_exist(conn, self.account_id).await?)
+    }
+
+    /// Sends the change of the operation with its idempotency key. Payload is read from the current state of the target,
+    /// operations whose targets are gone or are superseded by the later ones complete without sending anything.
+    async fn replay(&self, conn: &mut PoolConnection<Sqlite>, operation: &db::Operation, later: &[db::Operation]) -> Result<(), Error> {
+        let client = self.client.clone().with_idempotency_key(operation.idempotency_key.clone());
+        let same_target = |op: &&db::Operation| op.folder_id == operation.folder_id && op.note_id == operation.note_id;
+        let superseded = later.iter().filter(same_target).any(|op| op.kind == operation.kind);
+        // Target stays modified until its last operation is sent
+        let pending = later.iter().any(|op| same_target(&op));
+
+        if let Some(note_id) = operation.note_id {
+            let Some(note) = db::fetch_note(conn, LocalId(note_id)).await? else {
+                return Ok(());
+            };
+
+            return self.replay_note(conn, &client, operation.kind, note, superseded).await;
+        }
+
+        let Some(folder) = db::fetch_folder(conn, LocalId(operation.folder_id.unwrap_or_default())).await? else {
+            return Ok(());
+        };
+
+        // Parent is none if it is not created in remote yet, then local sync creates or moves the folder after its parent
+        let parent_remote_id = match folder.parent_id() {
+            Some(parent_id) => db::fetch_folder(conn, parent_id).await?.and_then(|parent| parent.remote_id()).map(Some),
+            None => Some(None),
+        };
+
+        let remote_id = match (operation.kind, folder.remote_id(), parent_remote_id) {
+            (OperationKind::CreateFolder, None, Some(parent_remote_id)) => {
+                let folder_cipher = super::load_folder_cipher(conn, &folder).await?.ok_or(super::FOLDER_KEY_PENDING)?;
+                let request = super::encrypt_device_folders(conn, &self.ciphers, &folder.name, &folder_cipher).await?;
+
+                let remote_folder = client.create_folder(parent_remote_id, &request).await?;
+
+                return db::update_folder_remote_id(conn, folder.local_id(), remote_folder.id()).await.map_err(|e| e.into());
+            },
+            (OperationKind::DeleteFolder, Some(remote_id), _) if folder.state == ModelState::Deleted => {
+                match client.delete_folder(remote_id).await {
+                    // Folder may already be deleted by another device
+                    Ok(_) => {},
+                    Err(MavinoteError::Message(msg)) if msg == "item_not_found" => {},
+                    Err(e) => return Err(e.into()),
+                }
+
+                // Folder has reached the trash of remote, it is restored from there from now on
+                db::trash_folder(conn, folder.local_id()).await?;
+
+                return db::update_folder_state(conn, folder.local_id(), ModelState::Clean).await.map_err(|e| e.into());
+            },
+            (OperationKind::RenameFolder | OperationKind::MoveFolder, Some(remote_id), _) if !superseded && folder.state != ModelState::Deleted => remote_id,
+            _ => return Ok(()),
+        };
+
+        match operation.kind {
+            OperationKind::RenameFolder => {
+                let shared_ciphers = self.shared_ciphers(conn, remote_id, folder.shared).await?;
+                let ciphers = shared_ciphers.as_deref().unwrap_or(&self.ciphers);
+                let folder_cipher = super::load_folder_cipher(conn, &folder).await?.ok_or(super::FOLDER_KEY_PENDING)?;
+
+                let request = super::encrypt_device_folders(conn, ciphers, &folder.name, &folder_cipher).await?;
+
+                client.rename_folder(remote_id, &request).await?;
+            },
+            // Only the owner can move a folder
+            OperationKind::MoveFolder if folder.role.is_none() => {
+                let Some(parent_remote_id) = parent_remote_id else {
+                    return Ok(());
+                };
+
+                match client.move_folder(remote_id, parent_remote_id).await {
+                    Ok(_) => {},
+                    // Another device moved the new parent under this folder meanwhile, remote parent is kept
+                    Err(MavinoteError::Message(msg)) if msg == "folder_cycle" => {
+                        if let Some(remote_folder) = self.client.fetch_folder(remote_id).await? {
+                            self.remote_folder_parent(conn, remote_id, remote_folder.parent_id.map(RemoteId)).await?;
+                        }
+                    },
+                    Err(e) => return Err(e.into()),
+                }
+            },
+            _ => {},
         }
 
-        Ok(false)
+        if !pending {
+            db::update_folder_state(conn, folder.local_id(), ModelState::Clean).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn replay_note(&self, conn: &mut PoolConnection<Sqlite>, client: &MavinoteClient, kind: OperationKind, note: Note, superseded: bool) -> Result<(), Error> {
+        let folder = db::fetch_folder(conn, LocalId(note.folder_id)).await?.unwrap();
+
+        match (kind, note.remote_id(), folder.remote_id()) {
+            // Notes are created in their current folders, local sync creates the notes of the folders it creates
+            (OperationKind::CreateNote, None, Some(folder_remote_id)) if note.state == ModelState::Clean || note.state == ModelState::Modified => {
+                let folder_cipher = super::load_folder_cipher(conn, &folder).await?.ok_or(super::FOLDER_KEY_PENDING)?;
+                let request = super::encrypt_note(&folder_cipher, &note.name, &note.text)?;
+
+                let remote_note = client.create_note(folder_remote_id, &request).await?;
+
+                db::update_note_remote_id(conn, note.local_id(), remote_note.id(), remote_note.commit).await?;
+            },
+            // Note may be cleaned by an earlier replay while it is edited again, so its state is not checked
+            (OperationKind::UpdateNote, Some(remote_id), _) if !superseded && note.state != ModelState::Deleted && note.state != ModelState::Trashed => {
+                let folder_cipher = super::load_folder_cipher(conn, &folder).await?.ok_or(super::FOLDER_KEY_PENDING)?;
+
+                if let Some(document) = db::fetch_note_document(conn, note.local_id()).await? {
+                    if document::push(conn, client, self.account_id, &folder_cipher, &note, document, &note.text).await? {
+                        db::update_note_state(conn, note.local_id(), ModelState::Clean).await?;
+                    }
+
+                    return Ok(());
+                }
+
+                let request = super::encrypt_note(&folder_cipher, &note.name, &note.text)?;
+
+                match client.update_note(remote_id, note.commit, &request).await? {
+                    NoteUpdate::Committed(commit) => db::update_commit(conn, note.local_id(), commit.commit).await?,
+                    NoteUpdate::Mismatch(remote_note) => {
+                        let shared_ciphers = match folder.remote_id() {
+                            Some(id) => self.shared_ciphers(conn, id, folder.shared).await?,
+                            None => None,
+                        };
+                        let ciphers = shared_ciphers.as_deref().unwrap_or(&self.ciphers);
+
+                        self.merge_remote_note(conn, ciphers, &folder_cipher, note, remote_note).await?;
+                    },
+                }
+            },
+            (OperationKind::MoveNote, Some(remote_id), Some(folder_remote_id)) if !superseded && note.state != ModelState::Deleted && note.state != ModelState::Trashed => {
+                let folder_cipher = super::load_folder_cipher(conn, &folder).await?.ok_or(super::FOLDER_KEY_PENDING)?;
+
+                let request = super::encrypt_note(&folder_cipher, &note.name, &note.text)?;
+
+                match client.move_note(remote_id, folder_remote_id, &request).await {
+                    Ok(()) => {},
+                    // Note is trashed in remote meanwhile, its state is pulled by remote sync
+                    Err(MavinoteError::Message(msg)) if msg == "item_not_found" => {},
+                    Err(e) => return Err(e.into()),
+                }
+            },
+            (OperationKind::DeleteNote, Some(remote_id), _) if note.state == ModelState::Deleted => {
+                match client.delete_note(remote_id).await {
+                    // Note may already be deleted by another device
+                    Ok(_) => {},
+                    Err(MavinoteError::Message(msg)) if msg == "item_not_found" => {},
+                    Err(e) => return Err(e.into()),
+                }
+
+                db::update_note_state(conn, note.local_id(), ModelState::Trashed).await?;
+            },
+            _ => {},
+        }
+
+        Ok(())
     }
 
     async fn local(&self, conn: &mut PoolConnection<Sqlite>) -> Result<(), Error> {
-        let local_folders = db::fetch_account_folders(conn, self.account_id).await?;
+        let mut local_folders = db::fetch_account_folders(conn, self.account_id).await?;
+
+        // Parents are synced before their subfolders so that subfolders can refer to them in remote
+        let parents = local_folders.iter().map(|f| (f.id, f.parent_id)).collect::<HashMap<_, _>>();
+        local_folders.sort_by_key(|folder| {
+            let mut depth = 0;
+            let mut id = folder.id;
+            // Depth is bounded since a pending local move can form a cycle with a remote one until it is resolved
+            while let (Some(Some(parent_id)), true) = (parents.get(&id), depth < parents.len()) {
+                depth += 1;
+                id = *parent_id;
+            }
+
+            depth
+        });
+
         for local_folder in local_folders {
             self.local_folder(conn, local_folder).await?;
         }
@@ -216,19 +696,65 @@ impl<'a> Sync<'a> {
 
             self.client.delete_folder(remote_id).await?;
 
-            return db::delete_folder(conn, local_folder.local_id()).await.map_err(|e| e.into());
+            db::trash_folder(conn, local_folder.local_id()).await?;
+
+            return db::update_folder_state(conn, local_folder.local_id(), ModelState::Clean).await.map_err(|e| e.into());
         }
 
+        // Changes of the members which can only read the folder are never pushed
+        if local_folder.role == Some(Role::Read) {
+            return Ok(());
+        }
+
+        let parent_remote_id = match local_folder.parent_id() {
+            Some(parent_id) => db::fetch_folder(conn, parent_id).await?.and_then(|parent| parent.remote_id()),
+            None => None,
+        };
+
+        let shared_ciphers = match local_folder.remote_id() {
+            Some(id) => self.shared_ciphers(conn, id, local_folder.shared).await?,
+            None => None,
+        };
+        let ciphers = shared_ciphers.as_deref().unwrap_or(&self.ciphers);
+
+        // Folder is synced once its key is received
+        let Some(folder_cipher) = super::load_folder_cipher(conn, &local_folder).await? else {
+            log::debug!("key of folder with id {} is not received yet", local_folder.id);
+            return Ok(());
+        };
+
         let remote_folder_id = match local_folder.remote_id() {
+            Some(id) if local_folder.state == ModelState::Modified => {
+                let request = super::encrypt_device_folders(conn, ciphers, &local_folder.name, &folder_cipher).await?;
+
+                self.client.rename_folder(id, &request).await?;
+
+                // Only the owner can move a folder, members see the shared folders at the top level
+                let moved = match local_folder.role {
+                    Some(_) => Ok(()),
+                    None => self.client.move_folder(id, parent_remote_id).await,
+                };
+
+                match moved {
+                    Ok(_) => db::update_folder(conn, local_folder.local_id(), &local_folder.name, ModelState::Clean).await?,
+                    // Another device moved the new parent under this folder meanwhile, remote parent is kept
+                    Err(MavinoteError::Message(msg)) if msg == "folder_cycle" => {
+                        db::update_folder(conn, local_folder.local_id(), &local_folder.name, ModelState::Clean).await?;
+
+                        if let Some(remote_folder) = self.client.fetch_folder(id).await? {
+                            self.remote_folder_parent(conn, id, remote_folder.parent_id.map(RemoteId)).await?;
+                        }
+                    },
+                    Err(e) => return Err(e.into()),
+                }
+
+                id
+            },
             Some(id) => id,
             None => {
-                let mut request = vec![];
-                let nonces = db::unique_nonces(conn, &self.ciphers.iter().map(|c| c.device_id).collect::<Vec<_>>()).await?;
-                for (cipher, nonce) in self.ciphers.iter().zip(nonces) {
-                    request.push(CreateFolderRequest{ device_id: cipher.device_id, name: cipher.encrypt(&local_folder.name, nonce)? });
-                }
+                let request = super::encrypt_device_folders(conn, &self.ciphers, &local_folder.name, &folder_cipher).await?;
 
-                let remote_folder = self.client.create_folder(&request).await?;
+                let remote_folder = self.client.create_folder(parent_remote_id, &request).await?;
 
                 db::update_folder_remote_id(conn, local_folder.local_id(), remote_folder.id()).await?;
 
@@ -246,58 +772,94 @@ impl<'a> Sync<'a> {
 
                 self.client.delete_note(remote_id).await?;
 
-                db::delete_note(conn, local_note.local_id()).await?;
+                db::update_note_state(conn, local_note.local_id(), ModelState::Trashed).await?;
 
                 continue;
             }
 
+            // Notes without a remote id are created in remote only if they are restored from the trash
+            if local_note.state == ModelState::Trashed {
+                continue;
+            }
+
             // This state does not need sync
             if local_note.state != ModelState::Modified && local_note.remote_id().is_some() {
                 continue;
             }
-            let mut device_notes = vec![];
-            let name_nonces = db::unique_nonces(conn, &self.ciphers.iter().map(|c| c.device_id).collect::<Vec<_>>()).await?;
-            let text_nonces = db::unique_nonces(conn, &self.ciphers.iter().map(|c| c.device_id).collect::<Vec<_>>()).await?;
+            // Changes of the converted notes are sent as updates instead of commits
+            if let Some(document) = db::fetch_note_document(conn, local_note.local_id()).await? {
+                if document::push(conn, &self.client, self.account_id, &folder_cipher, &local_note, document, &local_note.text).await? {
+                    db::update_note_state(conn, local_note.local_id(), ModelState::Clean).await?;
+                }
 
-            for (cipher, (name_nonce, text_nonce)) in self.ciphers.iter().zip(name_nonces.into_iter().zip(text_nonces)) {
-                device_notes.push(CreateNoteRequest{
-                    device_id: cipher.device_id,
-                    name: cipher.encrypt(&local_note.name, name_nonce)?,
-                    text: cipher.encrypt(&local_note.text, text_nonce)?
-                });
+                continue;
             }
 
+            let request = super::encrypt_note(&folder_cipher, &local_note.name, &local_note.text)?;
+
             if let Some(remote_id) = local_note.remote_id() {
-                let commit = self.client.update_note(remote_id, local_note.commit, &device_notes).await?;
-                db::update_commit(conn, local_note.local_id(), commit.commit).await?;
+                match self.client.update_note(remote_id, local_note.commit, &request).await? {
+                    NoteUpdate::Committed(commit) => db::update_commit(conn, local_note.local_id(), commit.commit).await?,
+                    NoteUpdate::Mismatch(remote_note) => self.merge_remote_note(conn, ciphers, &folder_cipher, local_note, remote_note).await?,
+                }
             } else {
-                let remote_note = self.client.create_note(remote_folder_id, &device_notes).await?;
-                sqlx::query("update notes set remote_id = ?, 'commit' = ? where id = ?")
-                    .bind(remote_note.id)
-                    .bind(remote_note.commit)
-                    .bind(local_note.id)
-                    .execute(&mut *conn)
-                    .await?;
+                let remote_note = self.client.create_note(remote_folder_id, &request).await?;
+                db::update_note_remote_id(conn, local_note.local_id(), remote_note.id(), remote_note.commit).await?;
             }
         }
 
         Ok(())
     }
 
+    async fn merge_remote_note(&self, conn: &mut PoolConnection<Sqlite>, ciphers: &[DeviceCipher], folder_cipher: &FileCipher, local_note: Note, remote_note: RemoteNote) -> Result<(), Error> {
+        let Some(remote_id) = local_note.remote_id() else {
+            return Err(Error::Unreachable("Merged note without a remote id cannot exist"));
+        };
+
+        let Some((remote_name, remote_text)) = super::decrypt_remote_note(Some(folder_cipher), ciphers, &remote_note)? else {
+            log::debug!("A note which cannot be decrypted is received while merging. Note will be merged on next sync");
+            return Ok(());
+        };
+
+        let text = match resolve_conflict(conn, self.account_id, &local_note, &local_note.text, &remote_text, remote_note.commit).await? {
+            Resolution::Merged(text) => text,
+            Resolution::Copied => {
+                return db::update_note(conn, local_note.local_id(), &remote_name, &remote_text, Some(&remote_text), remote_note.commit, ModelState::Clean).await
+                    .map_err(|e| e.into());
+            }
+        };
+        let name = super::note_name(&text);
+
+        db::update_note(conn, local_note.local_id(), &name, &text, Some(&remote_text), remote_note.commit, ModelState::Modified).await?;
+
+        let request = super::encrypt_note(folder_cipher, &name, &text)?;
+
+        // If the note is updated again in the meantime, merged note stays modified and it is merged on next sync
+        if let NoteUpdate::Committed(commit) = self.client.update_note(remote_id, remote_note.commit, &request).await? {
+            db::update_commit(conn, local_note.local_id(), commit.commit).await?;
+        }
+
+        Ok(())
+    }
+
     async fn respond_device_requests(&self, conn: &mut PoolConnection<Sqlite>) -> Result<(), Error> {
         let requests = self.client.fetch_requests().await?;
 
+        let member_ciphers = self.member_ciphers(conn, &requests).await?;
+        // Devices whose keys changed get nothing until they are verified again
+        let ciphers = self.ciphers.iter().chain(&member_ciphers).filter(|c| c.trusted);
+
         let mut note_ids: HashMap<i32, HashSet<&DeviceCipher>> = HashMap::new();
         let mut folder_ids: HashMap<i32, HashSet<&DeviceCipher>> = HashMap::new();
 
         for req in &requests.folder_requests {
-            if let Some(cipher) = self.ciphers.iter().find(|c| c.device_id == req.device_id) {
+            if let Some(cipher) = ciphers.clone().find(|c| c.device_id == req.device_id) {
                 folder_ids.entry(req.folder_id).or_insert(HashSet::new()).insert(cipher);
             }
         }
 
         for req in &requests.note_requests {
-            if let Some(cipher) = self.ciphers.iter().find(|c| c.device_id == req.device_id) {
+            if let Some(cipher) = ciphers.clone().find(|c| c.device_id == req.device_id) {
                 note_ids.entry(req.note_id).or_insert(HashSet::new()).insert(cipher);
             }
         }
@@ -308,26 +870,28 @@ impl<'a> Sync<'a> {
             let folder = db::fetch_folder_by_remote_id(conn, RemoteId(folder_id), self.account_id).await?;
 
             if let Some(folder) = folder {
-                let nonces = db::unique_nonces(conn, &self.ciphers.iter().map(|c| c.device_id).collect::<Vec<_>>()).await?;
+                let nonces = db::unique_nonces(conn, &ciphers.iter().map(|c| c.device_id).collect::<Vec<_>>()).await?;
+                let key_nonces = db::unique_nonces(conn, &ciphers.iter().map(|c| c.device_id).collect::<Vec<_>>()).await?;
+                // Members which can only read the folder may not have received its key yet
+                let key = db::fetch_folder_key(conn, folder.local_id()).await?;
+
+                for (cipher, (nonce, key_nonce)) in ciphers.iter().zip(nonces.into_iter().zip(key_nonces)) {
+                    let key = match &key {
+                        Some(key) => Some(cipher.encrypt(key, key_nonce)?),
+                        None => None,
+                    };
 
-                for (cipher, nonce) in ciphers.into_iter().zip(nonces) {
                     device_responses
                         .entry(cipher.device_id)
                         .or_insert(RespondRequests { device_id: cipher.device_id, folders: Vec::new(), notes: Vec::new() })
                         .folders
-                        .push(RespondFolderRequest { folder_id, name: cipher.encrypt(&folder.name, nonce)? });
+                        .push(RespondFolderRequest { folder_id, name: cipher.encrypt(&folder.name, nonce)?, key });
                 }
             }
         }
 
         for (note_id, ciphers) in note_ids {
-            let note: Option<Note> = sqlx::query_as("select notes.* from notes inner join folders on folders.id = notes.folder_id where notes.remote_id = ? and folders.account_id = ?")
-                .bind(note_id)
-                .bind(self.account_id)
-                .fetch_optional(&mut *conn)
-                .await?;
-
-            if let Some(note) = note {
+            if let Some(note) = db::fetch_account_note_by_remote_id(conn, RemoteId(note_id), self.account_id).await? {
                 let name_nonces = db::unique_nonces(conn, &ciphers.iter().map(|c| c.device_id).collect::<Vec<_>>()).await?;
                 let text_nonces = db::unique_nonces(conn, &ciphers.iter().map(|c| c.device_id).collect::<Vec<_>>()).await?;
 
@@ -348,20 +912,131 @@ impl<'a> Sync<'a> {
         Ok(())
     }
 
-    async fn load_device_ciphers(conn: &mut PoolConnection<Sqlite>, privkey: &StaticSecret, account_id: i32) -> Result<Vec<DeviceCipher>, Error> {
-        let devices = db::fetch_devices(conn, account_id).await?;
+    /// Ciphers of the devices of all the members are needed for the shared folders. Devices of the other members are
+    /// trusted only if the verified directory lists them with the same key.
+    async fn shared_ciphers(&self, conn: &mut PoolConnection<Sqlite>, folder_id: RemoteId, shared: bool) -> Result<Option<Vec<DeviceCipher>>, Error> {
+        if !shared {
+            return Ok(None);
+        }
+
+        let devices = self.client.fetch_folder_devices(folder_id).await?;
+
+        let members = devices.iter().filter(|device| !self.ciphers.iter().any(|c| c.device_id == device.id)).collect::<Vec<_>>();
+        let listed = super::directory::listed_devices(conn, &self.client, self.account_id, &members).await?;
+
+        devices
+            .iter()
+            .map(|device| {
+                let mut cipher = DeviceCipher::try_from_key(device.id, self.identity, &device.pubkey, device.previous_pubkey.as_deref())?;
+
+                // Trust is only known for the devices of this account
+                cipher.trusted = match self.ciphers.iter().find(|c| c.device_id == device.id) {
+                    Some(own) => own.trusted,
+                    None => listed.contains(&device.id),
+                };
+
+                Ok(cipher)
+            })
+            .collect::<Result<Vec<_>, CryptoError>>()
+            .map(Some)
+            .map_err(|e| e.into())
+    }
+
+    /// Returns the ciphers of the requesting devices which belong to the other members of the shared folders
+    async fn member_ciphers(&self, conn: &mut PoolConnection<Sqlite>, requests: &Requests) -> Result<Vec<DeviceCipher>, Error> {
+        let is_unknown = |device_id: i32| !self.ciphers.iter().any(|c| c.device_id == device_id);
+
+        let mut folders = HashSet::new();
+
+        for req in requests.folder_requests.iter().filter(|req| is_unknown(req.device_id)) {
+            folders.insert(req.folder_id);
+        }
+
+        for req in requests.note_requests.iter().filter(|req| is_unknown(req.device_id)) {
+            if let Some(note) = db::fetch_account_note_by_remote_id(conn, RemoteId(req.note_id), self.account_id).await? {
+                if let Some(remote_id) = db::fetch_folder(conn, LocalId(note.folder_id)).await?.and_then(|folder| folder.remote_id) {
+                    folders.insert(remote_id);
+                }
+            }
+        }
+
+        let mut ciphers: Vec<DeviceCipher> = Vec::new();
+
+        for folder_id in folders {
+            let shared = db::fetch_folder_by_remote_id(conn, RemoteId(folder_id), self.account_id).await?
+                .is_some_and(|folder| folder.shared);
+
+            for cipher in self.shared_ciphers(conn, RemoteId(folder_id), shared).await?.unwrap_or_default() {
+                if is_unknown(cipher.device_id) && !ciphers.contains(&cipher) {
+                    ciphers.push(cipher);
+                }
+            }
+        }
+
+        Ok(ciphers)
+    }
+
+    async fn load_device_ciphers(conn: &mut PoolConnection<Sqlite>, identity: &Identity, account_id: i32) -> Result<Vec<DeviceCipher>, Error> {
+        let devices = db::fetch_device_keys(conn, account_id).await?;
 
         devices
             .into_iter()
-            .map(|device| DeviceCipher::try_from_key(device.id, privkey, &device.pubkey))
+            .map(|device| DeviceCipher::try_from_key(device.id, identity, &device.pubkey, device.previous_pubkey.as_deref()).map(|cipher| cipher.with_trust(device.trust)))
             .collect::<Result<Vec<_>, CryptoError>>()
             .map_err(|e| e.into())
     }
 }
 
+/// How a note which is modified on both this device and remote is handled
+pub(crate) enum Resolution {
+    Merged(String),
+    /// Note takes the remote text, local text is moved to a conflicted copy
+    Copied,
+}
+
+/// Handles the conflict by the policy of the account
+pub(crate) async fn resolve_conflict(conn: &mut PoolConnection<Sqlite>, account_id: i32, note: &Note, local_text: &str, remote_text: &str, remote_commit: i32) -> Result<Resolution, Error> {
+    // Local changes may have reached remote already, then there is nothing to keep
+    if local_text == remote_text {
+        return Ok(Resolution::Merged(remote_text.to_owned()));
+    }
+
+    match db::fetch_conflict_policy(conn, account_id).await? {
+        ConflictPolicy::Merge => merge_note(conn, note, local_text, remote_text, remote_commit).await.map(Resolution::Merged),
+        ConflictPolicy::Copy => {
+            super::conflict::create_copy(conn, note, local_text, remote_commit).await?;
+
+            Ok(Resolution::Copied)
+        }
+    }
+}
+
+/// Merges the changes made on this device and in remote since the commit of the note, the text at that commit is their
+/// common ancestor. Merges are recorded with the commits they merge.
+async fn merge_note(conn: &mut PoolConnection<Sqlite>, note: &Note, local_text: &str, remote_text: &str, remote_commit: i32) -> Result<String, Error> {
+    // Notes synced before the base was kept have none, their whole texts conflict unless they are the same
+    let base = db::fetch_note_base(conn, note.local_id()).await?.unwrap_or_default();
+
+    let remote_label = format!("commit {remote_commit}");
+    let merged = merge::merge(&base, local_text, remote_text, &Labels { local: "this device", remote: &remote_label });
+
+    if merged.conflicts > 0 {
+        log::debug!("note with id {} has {} conflicts after merging commit {remote_commit}", note.id, merged.conflicts);
+    }
+
+    db::create_note_merge(conn, note.local_id(), note.commit, remote_commit, merged.conflicts).await?;
+
+    Ok(merged.text)
+}
+
 pub async fn sync() -> Result<(), Error> {
     let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
-    let privkey = crypto::load_privkey(&mut conn).await?;
+
+    if let Err(e) = super::rotation::push_rotations(&mut conn).await {
+        log::error!("failed to push the rotation of identity key, {e:?}");
+    }
+
+    let identity = crypto::load_identity(&mut conn).await?;
 
     let mavinote_accounts = db::fetch_accounts(&mut conn).await?
         .into_iter()
@@ -377,8 +1052,8 @@ pub async fn sync() -> Result<(), Error> {
         let sync = Sync {
             account_id: account.id,
             client,
-            privkey: &privkey,
-            ciphers: Sync::load_device_ciphers(&mut conn, &privkey, account.id).await?
+            identity: &identity,
+            ciphers: Sync::load_device_ciphers(&mut conn, &identity, account.id).await?
         };
 
         if let Err(e) = sync.sync(&mut conn).await {
@@ -397,6 +1072,7 @@ pub async fn sync() -> Result<(), Error> {
     }
 
     super::update_send_folders(&mut conn).await;
+    super::conflict::update_send_conflicts(&mut conn).await;
 
     Ok(())
 }
@@ -509,7 +1185,7 @@ pub async fn listen_notifications(account_id: i32) -> Result<Receiver<()>, Error
 }
 
 pub(crate) async fn sync_devices(conn: &mut PoolConnection<Sqlite>, account_id: i32) -> Result<(), Error> {
-    let privkey = crypto::load_privkey(conn).await?;
+    let identity = crypto::load_identity(conn).await?;
 
     let Some(client) = super::mavinote_client(conn, account_id).await? else {
         return Err(Error::Unreachable("Mavinote account must have a client"));
@@ -518,7 +1194,7 @@ pub(crate) async fn sync_devices(conn: &mut PoolConnection<Sqlite>, account_id:
     let sync = Sync {
         account_id,
         client,
-        privkey: &privkey,
+        identity: &identity,
         ciphers: Vec::new()
     };
 
@@ -532,6 +1208,7 @@ async fn handle_message(msg: &DeviceMessage, account_id: i32) -> Result<bool, Er
         DeviceMessage::RefreshRemote => refresh_remote(account_id).await?,
         DeviceMessage::RefreshFolder(folder_id) => refresh_folder(account_id, *folder_id).await?,
         DeviceMessage::RefreshNote { folder_id, note_id, commit, deleted } => refresh_note(account_id, *folder_id, *note_id, *commit, *deleted).await?,
+        DeviceMessage::RefreshNoteUpdates { note_id, update_id, .. } => refresh_note_updates(account_id, *note_id, *update_id).await?,
         DeviceMessage::Timeout => return Ok(true),
         _ => log::debug!("message is unhandled"),
     };
@@ -542,7 +1219,7 @@ async fn handle_message(msg: &DeviceMessage, account_id: i32) -> Result<bool, Er
 async fn refresh_respond_requests(account_id: i32) -> Result<(), Error> {
     let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
 
-    let privkey = crypto::load_privkey(&mut conn).await?;
+    let identity = crypto::load_identity(&mut conn).await?;
 
     let Some(client) = super::mavinote_client(&mut conn, account_id).await? else {
         return Err(Error::Unreachable("Mavinote account must have a client"));
@@ -551,8 +1228,8 @@ async fn refresh_respond_requests(account_id: i32) -> Result<(), Error> {
     let sync = Sync {
         account_id,
         client,
-        privkey: &privkey,
-        ciphers: Sync::load_device_ciphers(&mut conn, &privkey, account_id).await.unwrap()
+        identity: &identity,
+        ciphers: Sync::load_device_ciphers(&mut conn, &identity, account_id).await.unwrap()
     };
 
     sync.respond_device_requests(&mut conn).await
@@ -561,7 +1238,7 @@ async fn refresh_respond_requests(account_id: i32) -> Result<(), Error> {
 async fn refresh_remote(account_id: i32) -> Result<(), Error> {
     let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
 
-    let privkey = crypto::load_privkey(&mut conn).await?;
+    let identity = crypto::load_identity(&mut conn).await?;
 
     let Some(client) = super::mavinote_client(&mut conn, account_id).await? else {
         return Err(Error::Unreachable("Mavinote account must have a client"));
@@ -570,8 +1247,8 @@ async fn refresh_remote(account_id: i32) -> Result<(), Error> {
     let sync = Sync {
         account_id,
         client,
-        privkey: &privkey,
-        ciphers: Sync::load_device_ciphers(&mut conn, &privkey, account_id).await.unwrap()
+        identity: &identity,
+        ciphers: Sync::load_device_ciphers(&mut conn, &identity, account_id).await.unwrap()
     };
 
     sync.remote(&mut conn).await?;
@@ -594,16 +1271,19 @@ async fn refresh_folder(account_id: i32, folder_id: i32) -> Result<(), Error> {
         return Ok(());
     };
 
-    let privkey = crypto::load_privkey(&mut conn).await?;
+    let identity = crypto::load_identity(&mut conn).await?;
 
     let sync = Sync {
         account_id,
         client,
-        privkey: &privkey,
-        ciphers: Sync::load_device_ciphers(&mut conn, &privkey, account_id).await.unwrap()
+        identity: &identity,
+        ciphers: Sync::load_device_ciphers(&mut conn, &identity, account_id).await.unwrap()
     };
 
+    let parent_id = remote_folder.parent_id.map(RemoteId);
+
     sync.remote_folder(&mut conn, remote_folder).await?;
+    sync.remote_folder_parent(&mut conn, RemoteId(folder_id), parent_id).await?;
 
     super::update_send_folders(&mut conn).await;
 
@@ -623,20 +1303,188 @@ async fn refresh_note(account_id: i32, folder_id: i32, note_id: i32, commit: i32
         return Ok(());
     };
 
-    let privkey = crypto::load_privkey(&mut conn).await?;
+    let identity = crypto::load_identity(&mut conn).await?;
 
     let sync = Sync {
         account_id,
         client,
-        privkey: &privkey,
-        ciphers: Sync::load_device_ciphers(&mut conn, &privkey, account_id).await.unwrap()
+        identity: &identity,
+        ciphers: Sync::load_device_ciphers(&mut conn, &identity, account_id).await.unwrap()
     };
 
-    let state = if deleted { ModelState::Deleted } else { ModelState::Clean };
+    // Deleted notes are moved into the trash in remote
+    let state = if deleted { ModelState::Trashed } else { ModelState::Clean };
+
+    let shared_ciphers = sync.shared_ciphers(&mut conn, RemoteId(folder_id), folder.shared).await?;
+    let ciphers = shared_ciphers.as_deref().unwrap_or(&sync.ciphers);
+
+    let folder_cipher = db::fetch_folder_key(&mut conn, folder.local_id()).await?
+        .map(|key| FileCipher::try_from_key(&key))
+        .transpose()?;
 
-    sync.remote_note(&mut conn, Commit { note_id, commit, state }, folder.local_id()).await?;
+    sync.remote_note(&mut conn, ciphers, folder_cipher.as_ref(), Commit { note_id, commit, state, update_id: None }, folder.local_id()).await?;
 
     super::update_send_notes(&mut conn, folder.local_id()).await;
 
     Ok(())
 }
+
+/// Replaces the text of the note with its remote version, returns whether it is replaced. The local text is kept in
+/// the revisions of the note. Notes which cannot be fetched or decrypted right now are left as they are.
+pub(crate) async fn pull_note(conn: &mut PoolConnection<Sqlite>, account_id: i32, note: &Note) -> Result<bool, Error> {
+    let Some(remote_id) = note.remote_id() else {
+        return Ok(false);
+    };
+
+    let Some(client) = super::mavinote_client(conn, account_id).await? else {
+        return Err(Error::Unreachable("Mavinote account must have a client"));
+    };
+
+    let Some(folder) = db::fetch_folder(conn, LocalId(note.folder_id)).await? else {
+        return Err(Error::Unreachable("Folder belonging to note is not found"));
+    };
+
+    let remote_note = match client.fetch_note(remote_id).await {
+        Ok(Some(remote_note)) => remote_note,
+        Ok(None) | Err(MavinoteError::NoConnection) => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+
+    let identity = crypto::load_identity(conn).await?;
+
+    let sync = Sync {
+        account_id,
+        client,
+        identity: &identity,
+        ciphers: Sync::load_device_ciphers(conn, &identity, account_id).await?
+    };
+
+    let shared_ciphers = match folder.remote_id() {
+        Some(folder_id) => sync.shared_ciphers(conn, folder_id, folder.shared).await?,
+        None => None,
+    };
+    let ciphers = shared_ciphers.as_deref().unwrap_or(&sync.ciphers);
+
+    let folder_cipher = db::fetch_folder_key(conn, folder.local_id()).await?
+        .map(|key| FileCipher::try_from_key(&key))
+        .transpose()?;
+
+    let Some((name, text)) = super::decrypt_remote_note(folder_cipher.as_ref(), ciphers, &remote_note)? else {
+        return Ok(false);
+    };
+
+    db::update_note(conn, note.local_id(), &name, &text, Some(&text), remote_note.commit, ModelState::Clean).await?;
+
+    Ok(true)
+}
+
+async fn refresh_note_updates(account_id: i32, note_id: i32, update_id: i32) -> Result<(), Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    let Some(client) = super::mavinote_client(&mut conn, account_id).await? else {
+        return Err(Error::Unreachable("Mavinote account must have a client"));
+    };
+
+    let Some(note) = db::fetch_account_note_by_remote_id(&mut conn, RemoteId(note_id), account_id).await? else {
+        log::debug!("Updates of a note which does not exist locally are received, note is pulled on next sync");
+
+        return Ok(());
+    };
+
+    if note.state == ModelState::Deleted {
+        return Ok(());
+    }
+
+    let local_text = (note.state == ModelState::Modified).then_some(note.text.as_str());
+
+    document::pull(&mut conn, &client, account_id, &note, Some(update_id), local_text).await?;
+
+    super::update_send_notes(&mut conn, LocalId(note.folder_id)).await;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::{Pool, Sqlite, pool::PoolConnection, sqlite::SqlitePoolOptions, types::Json};
+
+    use crate::models::{AccountKind, Mavinote, Note, RemoteId};
+    use super::{db, resolve_conflict, Resolution};
+
+    async fn create_pool() -> Pool<Sqlite> {
+        let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+
+        sqlx::migrate!("../migrations").run(&pool).await.unwrap();
+
+        pool
+    }
+
+    /// Creates a note at commit 1 of a Mavinote account, whose base is the given text
+    async fn create_note(conn: &mut PoolConnection<Sqlite>, text: &str) -> (i32, Note) {
+        let mavinote = Mavinote { email: "email".to_string(), token: "token".to_string() };
+        let account = db::create_account(conn, "email".to_string(), AccountKind::Mavinote, Some(Json(mavinote))).await.unwrap();
+        let folder = db::create_folder(conn, Some(RemoteId(1)), account.id, None, "folder".to_string()).await.unwrap();
+        let note = db::create_note(conn, folder.local_id(), Some(RemoteId(1)), "note".to_string(), text.to_string(), 1).await.unwrap();
+
+        (account.id, note)
+    }
+
+    async fn fetch_merges(conn: &mut PoolConnection<Sqlite>, note: &Note) -> Vec<(i32, i32, i32)> {
+        sqlx::query_as("select base_commit, remote_commit, conflicts from note_merges where note_id = ? order by id")
+            .bind(note.id)
+            .fetch_all(conn)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn it_merges_changes_of_both_sides_and_records_the_merged_commits_when_resolve_conflict_is_called() {
+        let pool = create_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+
+        let (account_id, note) = create_note(&mut conn, "first\nsecond\nthird\n").await;
+
+        let resolution = resolve_conflict(&mut conn, account_id, &note, "FIRST\nsecond\nthird\n", "first\nsecond\nTHIRD\n", 2).await.unwrap();
+
+        let Resolution::Merged(text) = resolution else {
+            panic!("note must be merged");
+        };
+
+        assert_eq!("FIRST\nsecond\nTHIRD\n", text);
+        assert_eq!(vec![(1, 2, 0)], fetch_merges(&mut conn, &note).await);
+    }
+
+    #[tokio::test]
+    async fn it_records_the_number_of_conflicts_if_hunks_overlap_when_resolve_conflict_is_called() {
+        let pool = create_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+
+        let (account_id, note) = create_note(&mut conn, "first\nsecond\n").await;
+
+        let resolution = resolve_conflict(&mut conn, account_id, &note, "first\nlocal\n", "first\nremote\n", 3).await.unwrap();
+
+        let Resolution::Merged(text) = resolution else {
+            panic!("note must be merged");
+        };
+
+        assert_eq!("first\n<<<<<<< this device\nlocal\n=======\nremote\n>>>>>>> commit 3\n", text);
+        assert_eq!(vec![(1, 3, 1)], fetch_merges(&mut conn, &note).await);
+    }
+
+    #[tokio::test]
+    async fn it_does_not_record_a_merge_if_texts_are_the_same_when_resolve_conflict_is_called() {
+        let pool = create_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+
+        let (account_id, note) = create_note(&mut conn, "first\n").await;
+
+        let resolution = resolve_conflict(&mut conn, account_id, &note, "changed\n", "changed\n", 2).await.unwrap();
+
+        let Resolution::Merged(text) = resolution else {
+            panic!("note must be merged");
+        };
+
+        assert_eq!("changed\n", text);
+        assert!(fetch_merges(&mut conn, &note).await.is_empty());
+    }
+}
@@ -0,0 +1,277 @@
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, RwLock};
+
+use sqlx::{Connection, Pool, Sqlite, pool::PoolConnection};
+
+use crate::crypto::{self, FileCipher, Sealed};
+use crate::models::{Encryption, StoreKey};
+use super::{db, update_send_notes};
+
+const ENCRYPTION_ENABLED: crate::Error = crate::Error::Unreachable("EncryptionEnabled");
+const ENCRYPTION_DISABLED: crate::Error = crate::Error::Unreachable("EncryptionDisabled");
+
+/// Key which encrypts the names and texts of notes, their revisions and documents, and the secret store values in the
+/// database
+#[derive(Clone)]
+enum Vault {
+    Disabled,
+    Locked,
+    Unlocked(Box<FileCipher>),
+}
+
+static VAULT: RwLock<Vault> = RwLock::new(Vault::Disabled);
+
+#[derive(Debug)]
+pub enum Error {
+    Locked,
+    Crypto(crypto::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Locked => write!(f, "local encryption is locked"),
+            Error::Crypto(e) => write!(f, "failed to encrypt or decrypt a local value, {e:?}"),
+        }
+    }
+}
+
+impl std::error::Error for Error { }
+
+impl From<Error> for sqlx::Error {
+    fn from(e: Error) -> Self {
+        sqlx::Error::Decode(Box::new(e))
+    }
+}
+
+/// Encrypts the value before it is written to the database, the value is kept as is if the encryption is disabled
+pub(crate) fn seal(value: &str) -> Result<String, Error> {
+    match &*VAULT.read().unwrap() {
+        Vault::Disabled => Ok(value.to_owned()),
+        Vault::Locked => Err(Error::Locked),
+        Vault::Unlocked(cipher) => cipher.encrypt_str(value).map_err(Error::Crypto),
+    }
+}
+
+pub(crate) fn open(value: String) -> Result<String, Error> {
+    match &*VAULT.read().unwrap() {
+        Vault::Disabled => Ok(value),
+        Vault::Locked => Err(Error::Locked),
+        Vault::Unlocked(cipher) => cipher.decrypt_str(&value).map_err(Error::Crypto),
+    }
+}
+
+/// Whether the plain names and texts of notes can be written to the search index, which is not encrypted
+pub(crate) fn searchable() -> bool {
+    matches!(*VAULT.read().unwrap(), Vault::Disabled)
+}
+
+/// Starts in the locked state if the encryption is enabled previously
+pub(crate) async fn init(conn: &mut PoolConnection<Sqlite>) -> Result<(), crate::Error> {
+    if db::fetch_value(conn, StoreKey::DataKey).await?.is_some() {
+        *VAULT.write().unwrap() = Vault::Locked;
+    }
+
+    Ok(())
+}
+
+pub fn encryption() -> Encryption {
+    match &*VAULT.read().unwrap() {
+        Vault::Disabled => Encryption::Disabled,
+        Vault::Locked => Encryption::Locked,
+        Vault::Unlocked(_) => Encryption::Unlocked,
+    }
+}
+
+/// Encrypts the existing notes and secrets with a random key, which is sealed with the pin. Argon2 slows down guessing
+/// the pin of a stolen database, still a passphrase should be preferred over a few digits.
+pub async fn enable_encryption(pin: String) -> Result<(), crate::Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    if db::fetch_value(&mut conn, StoreKey::DataKey).await?.is_some() {
+        return Err(ENCRYPTION_ENABLED);
+    }
+
+    let cipher = FileCipher::generate();
+    let sealed = crypto::seal_with_passphrase(&pin, cipher.key().as_bytes())?;
+
+    reseal(&mut conn, None, Some(cipher), Some(serde_json::to_string(&sealed).unwrap())).await
+}
+
+/// Decrypts the notes and secrets back, the pin is required even if the vault is unlocked
+pub async fn disable_encryption(pin: String) -> Result<(), crate::Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    let Some(data_key) = db::fetch_value(&mut conn, StoreKey::DataKey).await? else {
+        return Err(ENCRYPTION_DISABLED);
+    };
+
+    let cipher = open_data_key(&pin, &data_key.value)?;
+
+    reseal(&mut conn, Some(cipher), None, None).await
+}
+
+/// Unlocks the vault, a wrong pin fails with [`crypto::Error::Decrypt`]
+pub async fn unlock(pin: String) -> Result<(), crate::Error> {
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    let Some(data_key) = db::fetch_value(&mut conn, StoreKey::DataKey).await? else {
+        return Err(ENCRYPTION_DISABLED);
+    };
+
+    *VAULT.write().unwrap() = Vault::Unlocked(Box::new(open_data_key(&pin, &data_key.value)?));
+
+    // Notes which are observed while the vault is locked only hold the error
+    update_send_all_notes(&mut conn).await
+}
+
+/// Forgets the key, so that it has to be unlocked again with the pin
+pub async fn lock() -> Result<(), crate::Error> {
+    {
+        let mut vault = VAULT.write().unwrap();
+        if let Vault::Unlocked(_) = *vault {
+            *vault = Vault::Locked;
+        }
+    }
+
+    let mut conn = runtime::get::<Arc<Pool<Sqlite>>>().unwrap().acquire().await?;
+
+    // Decrypted notes are not kept by the observers either
+    update_send_all_notes(&mut conn).await
+}
+
+fn open_data_key(pin: &str, data_key: &str) -> Result<FileCipher, crypto::Error> {
+    let sealed: Sealed = serde_json::from_str(data_key)
+        .map_err(|_| crypto::Error::Decrypt)?;
+
+    let key = String::from_utf8(crypto::open_with_passphrase(pin, &sealed)?)
+        .map_err(|_| crypto::Error::Decrypt)?;
+
+    FileCipher::try_from_key(&key)
+}
+
+/// Encrypts or decrypts all the protected values in a transaction, and stores or deletes the sealed data key
+async fn reseal(conn: &mut PoolConnection<Sqlite>, from: Option<FileCipher>, to: Option<FileCipher>, data_key: Option<String>) -> Result<(), crate::Error> {
+    let previous = VAULT.read().unwrap().clone();
+
+    let res = conn.transaction(|conn| Box::pin(async move {
+        let notes = sqlx::query_as::<_, (i32, String, String, Option<String>)>("select id, name, text, base from notes")
+            .fetch_all(&mut *conn)
+            .await?;
+
+        // Search index holds the plain names and texts, it is dropped while the encryption is enabled
+        sqlx::query("delete from note_search")
+            .execute(&mut *conn)
+            .await?;
+
+        for (id, name, text, base) in notes {
+            let name = convert(from.as_ref(), to.as_ref(), name)?;
+            let text = convert(from.as_ref(), to.as_ref(), text)?;
+            let base = base.map(|base| convert(from.as_ref(), to.as_ref(), base)).transpose()?;
+
+            if to.is_none() {
+                sqlx::query("insert into note_search (rowid, name, text) values (?, ?, ?)")
+                    .bind(id)
+                    .bind(name.as_str())
+                    .bind(text.as_str())
+                    .execute(&mut *conn)
+                    .await?;
+            }
+
+            sqlx::query("update notes set name = ?, text = ?, base = ? where id = ?")
+                .bind(name)
+                .bind(text)
+                .bind(base)
+                .bind(id)
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        let revisions = sqlx::query_as::<_, (i32, String, String)>("select id, name, text from note_revisions")
+            .fetch_all(&mut *conn)
+            .await?;
+
+        for (id, name, text) in revisions {
+            sqlx::query("update note_revisions set name = ?, text = ? where id = ?")
+                .bind(convert(from.as_ref(), to.as_ref(), name)?)
+                .bind(convert(from.as_ref(), to.as_ref(), text)?)
+                .bind(id)
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        let documents = sqlx::query_as::<_, (i32, String)>("select note_id, document from note_documents")
+            .fetch_all(&mut *conn)
+            .await?;
+
+        for (note_id, document) in documents {
+            sqlx::query("update note_documents set document = ? where note_id = ?")
+                .bind(convert(from.as_ref(), to.as_ref(), document)?)
+                .bind(note_id)
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        let values = sqlx::query_as::<_, (StoreKey, String)>("select key, value from store where key in (?, ?, ?)")
+            .bind(StoreKey::IdentityPrivKey)
+            .bind(StoreKey::PreviousIdentityPrivKey)
+            .bind(StoreKey::Password)
+            .fetch_all(&mut *conn)
+            .await?;
+
+        for (key, value) in values {
+            sqlx::query("update store set value = ? where key = ?")
+                .bind(convert(from.as_ref(), to.as_ref(), value)?)
+                .bind(key)
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        match data_key {
+            Some(data_key) => sqlx::query("insert into store (key, value) values (?, ?)")
+                .bind(StoreKey::DataKey)
+                .bind(data_key)
+                .execute(&mut *conn)
+                .await?,
+            None => sqlx::query("delete from store where key = ?")
+                .bind(StoreKey::DataKey)
+                .execute(&mut *conn)
+                .await?,
+        };
+
+        // Vault is switched before the commit, so that the writes waiting for this transaction use the new key
+        *VAULT.write().unwrap() = match to {
+            Some(cipher) => Vault::Unlocked(Box::new(cipher)),
+            None => Vault::Disabled,
+        };
+
+        Ok::<(), crate::Error>(())
+    }))
+    .await;
+
+    if res.is_err() {
+        *VAULT.write().unwrap() = previous;
+    }
+
+    res
+}
+
+fn convert(from: Option<&FileCipher>, to: Option<&FileCipher>, value: String) -> Result<String, crypto::Error> {
+    let value = match from {
+        Some(cipher) => cipher.decrypt_str(&value)?,
+        None => value,
+    };
+
+    match to {
+        Some(cipher) => cipher.encrypt_str(&value),
+        None => Ok(value),
+    }
+}
+
+async fn update_send_all_notes(conn: &mut PoolConnection<Sqlite>) -> Result<(), crate::Error> {
+    for folder in db::fetch_folders(conn).await? {
+        update_send_notes(conn, folder.local_id()).await;
+    }
+
+    Ok(())
+}
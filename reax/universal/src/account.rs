@@ -1,6 +1,7 @@
 use base::State;
-use note::Error;
+use note::{Error, models::{ConflictPolicy, Verification}};
 use serde::Serialize;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::task::JoinHandle;
 
 use crate::{spawn, Message};
@@ -97,11 +98,18 @@ pub fn request_verification(once_id: i32, email: String) -> *mut JoinHandle<()>
     Box::into_raw(Box::new(handle))
 }
 
-pub fn wait_verification(once_id: i32, token: String) -> *mut JoinHandle<()> {
+pub fn wait_verification(stream_id: i32, token: String) -> *mut JoinHandle<()> {
     let handle = spawn(async move {
-        let res = note::storage::wait_verification(token).await;
+        let res = note::storage::wait_verification(token, |code| {
+            send_stream(stream_id, Message::Value(Ok(Verification::Code(code))));
+        }).await;
 
-        send_once(once_id, res);
+        match res {
+            Ok(()) => send_stream(stream_id, Message::Value(Ok(Verification::Accepted))),
+            Err(e) => send_stream::<()>(stream_id, Message::Value(Err(e))),
+        }
+
+        send_stream::<()>(stream_id, Message::Complete);
     });
 
     Box::into_raw(Box::new(handle))
@@ -219,3 +227,183 @@ pub fn update_welcome_shown(once_id: i32, shown: bool) -> * mut JoinHandle<()> {
 
     Box::into_raw(Box::new(handle))
 }
+
+pub fn directory_devices(once_id: i32, account_id: i32, email: String) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::directory::directory_devices(account_id, email).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn update_directory(once_id: i32, account_id: i32, listed: bool) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::directory::update_directory(account_id, listed).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn rotate_identity_key(once_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::rotation::rotate_identity_key().await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn verify_device(once_id: i32, account_id: i32, device_id: i32, fingerprint: String) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::verify_device(account_id, device_id, fingerprint).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn trust_events(stream_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let mut rx = note::storage::trust_events();
+
+        loop {
+            match rx.recv().await {
+                Ok(event) => send_stream(stream_id, Message::Value(Ok(event))),
+                // Missed events are not important, devices are fetched again with their latest trust
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+        }
+
+        send_stream::<()>(stream_id, Message::Complete);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn start_device_verification(once_id: i32, account_id: i32, pubkey: String) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::start_device_verification(account_id, pubkey).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn create_recovery_bundle(once_id: i32, account_id: i32, passphrase: String) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::recovery::create_recovery_bundle(account_id, passphrase).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn delete_recovery_bundle(once_id: i32, account_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::recovery::delete_recovery_bundle(account_id).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn send_recovery_code(once_id: i32, email: String) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::recovery::send_recovery_code(email).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn recover_account(once_id: i32, email: String, code: String, passphrase: String) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::recovery::recover_account(email, code, passphrase).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn encryption(once_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = Result::<_, note::Error>::Ok(note::storage::vault::encryption());
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn enable_encryption(once_id: i32, pin: String) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::vault::enable_encryption(pin).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn disable_encryption(once_id: i32, pin: String) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::vault::disable_encryption(pin).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn unlock(once_id: i32, pin: String) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::unlock(pin).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn lock(once_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::vault::lock().await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn conflict_policy(once_id: i32, account_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::conflict::conflict_policy(account_id).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn update_conflict_policy(once_id: i32, account_id: i32, keep_copies: bool) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let policy = if keep_copies { ConflictPolicy::Copy } else { ConflictPolicy::Merge };
+        let res = note::storage::conflict::update_conflict_policy(account_id, policy).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
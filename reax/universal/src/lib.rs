@@ -8,6 +8,7 @@ use base::Config;
 
 pub mod account;
 pub mod note;
+pub mod secrets;
 
 static ASYNC_RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
 static HANDLER: OnceLock<Mutex<Sender<(i32, Vec<u8>)>>> = OnceLock::new();
@@ -1,5 +1,5 @@
 use base::State;
-use note::Error;
+use note::{Error, models::{RevisionRetention, Role}};
 use serde::Serialize;
 use tokio::task::JoinHandle;
 
@@ -53,6 +53,30 @@ pub fn folders(stream_id: i32) -> * mut JoinHandle<()> {
     Box::into_raw(Box::new(handle))
 }
 
+pub fn conflicts(stream_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let mut rx = note::storage::conflicts().await;
+
+        match &*rx.borrow() {
+            State::Ok(ok) => send_stream(stream_id, Message::Value(Ok(ok))),
+            State::Err(e) => send_stream::<()>(stream_id, Message::Value(Err(e.clone()))),
+            _ => {},
+        };
+
+        while rx.changed().await.is_ok() {
+            match &*rx.borrow() {
+                State::Ok(ok) => send_stream(stream_id, Message::Value(Ok(ok))),
+                State::Err(e) => send_stream::<()>(stream_id, Message::Value(Err(e.clone()))),
+                _ => {},
+            };
+        }
+
+        send_stream::<Vec<note::models::Conflict>>(stream_id, Message::Complete);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
 pub fn folder(once_id: i32, folder_id: i32) -> * mut JoinHandle<()> {
     let handle = spawn(async move {
         let res = note::storage::folder(folder_id).await;
@@ -63,9 +87,29 @@ pub fn folder(once_id: i32, folder_id: i32) -> * mut JoinHandle<()> {
     Box::into_raw(Box::new(handle))
 }
 
-pub fn create_folder(once_id: i32, account_id: i32, name: String) -> * mut JoinHandle<()> {
+pub fn create_folder(once_id: i32, account_id: i32, parent_id: Option<i32>, name: String) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::create_folder(account_id, parent_id, name).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn rename_folder(once_id: i32, folder_id: i32, name: String) -> * mut JoinHandle<()> {
     let handle = spawn(async move {
-        let res = note::storage::create_folder(account_id, name).await;
+        let res = note::storage::rename_folder(folder_id, name).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn move_folder(once_id: i32, folder_id: i32, parent_id: Option<i32>) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::move_folder(folder_id, parent_id).await;
 
         send_once(once_id, res);
     });
@@ -144,3 +188,316 @@ pub fn delete_note(once_id: i32, note_id: i32) -> * mut JoinHandle<()> {
 
     Box::into_raw(Box::new(handle))
 }
+
+pub fn move_note(once_id: i32, note_id: i32, folder_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::move_note(note_id, folder_id).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn note_commits(once_id: i32, note_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::note_commits(note_id).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn note_commit(once_id: i32, note_id: i32, commit: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::note_commit(note_id, commit).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn restore_commit(once_id: i32, note_id: i32, commit: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::restore_commit(note_id, commit).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn attachments(once_id: i32, note_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::attachments(note_id).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn add_attachment(once_id: i32, note_id: i32, path: String) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::add_attachment(note_id, path).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn attachment_path(once_id: i32, note_id: i32, attachment_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::attachment_path(note_id, attachment_id).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn delete_attachment(once_id: i32, note_id: i32, attachment_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::delete_attachment(note_id, attachment_id).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn trash(once_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::trash().await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn restore_note(once_id: i32, note_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::restore_note(note_id).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn folder_members(once_id: i32, folder_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::folder_members(folder_id).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn share_folder(once_id: i32, folder_id: i32, email: String, write: bool) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let role = if write { Role::Write } else { Role::Read };
+        let res = note::storage::share_folder(folder_id, email, role).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn remove_folder_member(once_id: i32, folder_id: i32, user_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::remove_folder_member(folder_id, user_id).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn search(stream_id: i32, query: String, account_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        match note::storage::search(query, account_id).await {
+            Ok(results) => {
+                for result in results {
+                    send_stream(stream_id, Message::Value(Ok(result)));
+                }
+            }
+            Err(e) => send_stream::<()>(stream_id, Message::Value(Err(e))),
+        }
+
+        send_stream::<()>(stream_id, Message::Complete);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn resolve_conflict(once_id: i32, conflict_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::resolve_conflict(conflict_id).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn discard_conflict(once_id: i32, conflict_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::discard_conflict(conflict_id).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn failed_operations(stream_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let mut rx = note::storage::failed_operations().await;
+
+        match &*rx.borrow() {
+            State::Ok(ok) => send_stream(stream_id, Message::Value(Ok(ok))),
+            State::Err(e) => send_stream::<()>(stream_id, Message::Value(Err(e.clone()))),
+            _ => {},
+        };
+
+        while rx.changed().await.is_ok() {
+            match &*rx.borrow() {
+                State::Ok(ok) => send_stream(stream_id, Message::Value(Ok(ok))),
+                State::Err(e) => send_stream::<()>(stream_id, Message::Value(Err(e.clone()))),
+                _ => {},
+            };
+        }
+
+        send_stream::<Vec<note::models::FailedOperation>>(stream_id, Message::Complete);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn retry_failed_operation(once_id: i32, operation_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::retry_failed_operation(operation_id).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn discard_failed_operation(once_id: i32, operation_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::discard_failed_operation(operation_id).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn note_revisions(once_id: i32, note_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::note_revisions(note_id).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn restore_revision(once_id: i32, revision_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::restore_revision(revision_id).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn revision_retention(once_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::revision_retention().await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn update_revision_retention(once_id: i32, value: i32, days: bool) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let retention = if days { RevisionRetention::Days(value) } else { RevisionRetention::Count(value) };
+        let res = note::storage::update_revision_retention(retention).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn convert_note(once_id: i32, note_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::convert_note(note_id).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn note_converted(once_id: i32, note_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::note_converted(note_id).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn trashed_folders(once_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::trashed_folders().await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn restore_folder(once_id: i32, folder_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::restore_folder(folder_id).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn trash_retention(once_id: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::trash_retention().await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
+
+pub fn update_trash_retention(once_id: i32, days: i32) -> * mut JoinHandle<()> {
+    let handle = spawn(async move {
+        let res = note::storage::update_trash_retention(days).await;
+
+        send_once(once_id, res);
+    });
+
+    Box::into_raw(Box::new(handle))
+}
@@ -0,0 +1,58 @@
+use std::{path::PathBuf, sync::Arc};
+
+use futures_util::future::BoxFuture;
+use note::{Error, StorageError, models::StoreKey, storage::secrets::{self, FileSecretStore, SecretStore}};
+
+/// Secret storage of the host platform, such as the Keychain on iOS or the Keystore on Android. Keys are the names of
+/// the [`StoreKey`] variants. Calls may block, they are run off the async runtime.
+pub trait SecretHook: Send + Sync + 'static {
+    fn get(&self, key: &str) -> Result<Option<String>, String>;
+
+    fn put(&self, key: &str, value: &str) -> Result<(), String>;
+
+    fn delete(&self, key: &str) -> Result<(), String>;
+}
+
+struct HostSecretStore<H: SecretHook>(Arc<H>);
+
+impl<H: SecretHook> HostSecretStore<H> {
+    fn run<T, F>(&self, f: F) -> BoxFuture<'_, Result<T, Error>>
+    where
+        T: Send + 'static,
+        F: FnOnce(&H) -> Result<T, String> + Send + 'static,
+    {
+        let hook = self.0.clone();
+
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || f(&hook))
+                .await
+                .map_err(|e| Error::Storage(StorageError::SecretStore(e.to_string())))?
+                .map_err(|e| Error::Storage(StorageError::SecretStore(e)))
+        })
+    }
+}
+
+impl<H: SecretHook> SecretStore for HostSecretStore<H> {
+    fn get(&self, key: StoreKey) -> BoxFuture<'_, Result<Option<String>, Error>> {
+        self.run(move |hook| hook.get(&format!("{key:?}")))
+    }
+
+    fn put(&self, key: StoreKey, value: String) -> BoxFuture<'_, Result<(), Error>> {
+        self.run(move |hook| hook.put(&format!("{key:?}"), &value))
+    }
+
+    fn delete(&self, key: StoreKey) -> BoxFuture<'_, Result<(), Error>> {
+        self.run(move |hook| hook.delete(&format!("{key:?}")))
+    }
+}
+
+/// Keeps the secrets of this device in the storage of the host. It must be called before the note module is initialized.
+pub fn use_host_secret_store(hook: impl SecretHook) -> Result<(), Error> {
+    secrets::set_secret_store(HostSecretStore(Arc::new(hook)))
+}
+
+/// Keeps the secrets of this device in a file encrypted with the given key instead of the notes database. It must be
+/// called before the note module is initialized.
+pub fn use_file_secret_store(path: String, key: String) -> Result<(), Error> {
+    secrets::set_secret_store(FileSecretStore::new(PathBuf::from(path), &key)?)
+}
@@ -38,7 +38,8 @@ export function deserializeNote(deserializer: Deserializer): Note {
 export enum State {
     Clean,
     Modified,
-    Deleted
+    Deleted,
+    Trashed
 }
 
 function deserializeState(deserializer: Deserializer): State {
@@ -51,6 +52,8 @@ function deserializeState(deserializer: Deserializer): State {
             return State.Modified;
         case 2:
             return State.Deleted;
+        case 3:
+            return State.Trashed;
         default:
             throw new Error('Unknown index for State');
     }